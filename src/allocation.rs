@@ -0,0 +1,93 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    static NUM_ALLOCATIONS: Cell<usize> = Cell::new(0);
+}
+
+fn count_allocation() {
+    // ignore the allocations while the thread local storage is destroyed
+    let _ = NUM_ALLOCATIONS.try_with(|num| num.set(num.get() + 1));
+}
+
+/// Global allocator of `System` counting the heap allocations of each thread
+///
+/// It is available with the `assert-no-alloc` feature. If it is the `#[global_allocator]`,
+/// the solves which must not allocate, e.g. `JacobianIkSolver::solve()` after
+/// `preallocate()`, panic when they allocate. It is used to verify that the solves in the
+/// real-time threads are allocation-free.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// #[global_allocator]
+/// static ALLOCATOR: k::CountingAllocator = k::CountingAllocator;
+///
+/// fn main() {
+///     let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+///     let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+///     arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+///     let mut target = arm.end_transform();
+///     target.translation.vector.z += 0.02;
+///
+///     let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+///     solver.preallocate(&arm);
+///     let num_allocations = k::num_allocations();
+///     // panics if it allocates
+///     solver.solve(&arm, &target).unwrap();
+///     assert_eq!(k::num_allocations(), num_allocations);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// The number of the heap allocations of the current thread by `CountingAllocator`
+///
+/// It is always zero if `CountingAllocator` is not the global allocator.
+pub fn num_allocations() -> usize {
+    NUM_ALLOCATIONS.with(|num| num.get())
+}
+
+/// Panic if the current thread allocated after `num_allocations()` returned `before`
+pub(crate) fn assert_no_allocations_since(before: usize, name: &str) {
+    let allocated = num_allocations() - before;
+    assert!(allocated == 0, "{} allocated {} times", name, allocated);
+}
@@ -0,0 +1,445 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::angles;
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use super::joint::*;
+use super::node::*;
+use na::{Isometry3, Matrix3, RealField, Translation3, UnitQuaternion, Vector3};
+use nalgebra as na;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+use simba::scalar::SubsetOf;
+
+/// IK solver which calculates the solutions in closed form
+///
+/// The target is in the frame of the root of the arm, same as `SerialChain::end_transform()`.
+pub trait AnalyticalIkSolver<T: RealField> {
+    /// All the solution branches of the joint positions for `target`
+    ///
+    /// It is empty if `target` is out of reach.
+    fn solve_all(&self, target: &Isometry3<T>) -> Vec<Vec<T>>;
+
+    /// The solution closest to `current`, `None` if `target` is out of reach
+    fn solve_nearest(&self, target: &Isometry3<T>, current: &[T]) -> Option<Vec<T>> {
+        self.solve_all(target)
+            .into_iter()
+            .map(|solution| {
+                let distance = solution
+                    .iter()
+                    .zip(current)
+                    .fold(T::zero(), |sum, (a, b)| sum + (*a - *b) * (*a - *b));
+                (distance, solution)
+            })
+            .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(_, solution)| solution)
+    }
+}
+
+/// Geometric parameters of an ortho-parallel 6-DoF arm with a spherical wrist
+///
+/// It is the kinematics of the most industrial arms (PUMA, KUKA, ABB, FANUC, ...),
+/// described by the OPW parameters of Brandstötter et al., "An Analytical Solution
+/// of the Inverse Kinematics Problem of Industrial Serial Manipulators with an
+/// Ortho-parallel Basis and a Spherical Wrist" (2014).
+///
+/// At the zero positions, the joint 1 rotates around Z at the origin, the joints 2 and 3
+/// rotate around Y, and the wrist joints 4, 5 and 6 rotate around Z, Y and Z.
+///
+/// * `c1`, `a1`, `b`: the joint 2 is at `(a1, b, c1)` in the frame of the joint 1
+/// * `c2`: the joint 3 is `c2` above the joint 2
+/// * `a2`, `c3`: the wrist center is at `(a2, 0, c3)` from the joint 3
+/// * `c4`: the end is `c4` ahead of the wrist center along the Z axis of the end
+///
+/// The angle of the model is `sign_corrections[i] * q[i] + offsets[i]` for the
+/// joint position `q[i]`, to fit the zero positions and the directions of a robot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct OpwParameters<T: RealField> {
+    pub a1: T,
+    pub a2: T,
+    pub b: T,
+    pub c1: T,
+    pub c2: T,
+    pub c3: T,
+    pub c4: T,
+    /// added to the joint positions, in radians
+    pub offsets: [T; 6],
+    /// direction of the joints, `1` or `-1`
+    pub sign_corrections: [i8; 6],
+}
+
+impl<T> OpwParameters<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create the parameters without offsets and sign corrections
+    pub fn new(a1: T, a2: T, b: T, c1: T, c2: T, c3: T, c4: T) -> Self {
+        Self {
+            a1,
+            a2,
+            b,
+            c1,
+            c2,
+            c3,
+            c4,
+            offsets: [T::zero(); 6],
+            sign_corrections: [1; 6],
+        }
+    }
+
+    fn sign(&self, i: usize) -> T {
+        na::convert(f64::from(self.sign_corrections[i]))
+    }
+
+    fn model_angles(&self, positions: &[T]) -> [T; 6] {
+        let mut angles = [T::zero(); 6];
+        for (i, angle) in angles.iter_mut().enumerate() {
+            *angle = self.sign(i) * positions[i] + self.offsets[i];
+        }
+        angles
+    }
+
+    fn joint_positions_of(&self, angles: &[T; 6]) -> Vec<T> {
+        (0..6)
+            .map(|i| angles::wrap_angle((angles[i] - self.offsets[i]) * self.sign(i)))
+            .collect()
+    }
+
+    /// Forward kinematics in closed form
+    ///
+    /// It returns `Err` if the length of `positions` is not 6.
+    pub fn end_transform(&self, positions: &[T]) -> Result<Isometry3<T>, Error> {
+        if positions.len() != 6 {
+            return Err(Error::SizeMismatchError {
+                input: positions.len(),
+                required: 6,
+            });
+        }
+        let q = self.model_angles(positions);
+        let base = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), q[0]);
+        let elbow = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), q[1] + q[2]);
+        let center = base
+            * (Vector3::new(self.a1, self.b, self.c1)
+                + UnitQuaternion::from_axis_angle(&Vector3::y_axis(), q[1])
+                    * Vector3::new(T::zero(), T::zero(), self.c2)
+                + elbow * Vector3::new(self.a2, T::zero(), self.c3));
+        let rotation = base
+            * elbow
+            * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), q[3])
+            * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), q[4])
+            * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), q[5]);
+        let position = center + rotation * Vector3::new(T::zero(), T::zero(), self.c4);
+        Ok(Isometry3::from_parts(
+            Translation3::from(position),
+            rotation,
+        ))
+    }
+
+    /// Create the chain of this model, the joints are named `"opw_joint1"` to `"opw_joint6"`
+    ///
+    /// The end node is a fixed joint named `"opw_tool"`.
+    pub fn to_serial_chain(&self) -> SerialChain<T> {
+        let zero = T::zero();
+        let translations = [
+            Vector3::zeros(),
+            Vector3::new(self.a1, self.b, self.c1),
+            Vector3::new(zero, zero, self.c2),
+            Vector3::new(self.a2, zero, self.c3),
+            Vector3::zeros(),
+            Vector3::zeros(),
+        ];
+        let axes = [
+            Vector3::z_axis(),
+            Vector3::y_axis(),
+            Vector3::y_axis(),
+            Vector3::z_axis(),
+            Vector3::y_axis(),
+            Vector3::z_axis(),
+        ];
+        let mut nodes = (0..6)
+            .map(|i| {
+                let origin = Isometry3::from_parts(
+                    Translation3::from(translations[i]),
+                    UnitQuaternion::from_axis_angle(&axes[i], self.offsets[i]),
+                );
+                let axis = if self.sign_corrections[i] < 0 {
+                    -axes[i]
+                } else {
+                    axes[i]
+                };
+                NodeBuilder::new()
+                    .name(&format!("opw_joint{}", i + 1))
+                    .origin(origin)
+                    .joint_type(JointType::Rotational { axis })
+                    .into_node()
+            })
+            .collect::<Vec<_>>();
+        nodes.push(
+            NodeBuilder::new()
+                .name("opw_tool")
+                .translation(Translation3::new(zero, zero, self.c4))
+                .into_node(),
+        );
+        for i in 1..nodes.len() {
+            nodes[i].set_parent(&nodes[i - 1]);
+        }
+        SerialChain::new_unchecked(Chain::from_root(nodes.remove(0)))
+    }
+}
+
+/// Closed-form IK solver of the arms described by `OpwParameters`
+///
+/// It returns up to eight solutions (shoulder front/back, elbow up/down, wrist flip),
+/// and it is fast enough for the control loops of high rates.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+/// use k::{OpwIkSolver, OpwParameters};
+///
+/// // KUKA KR 6 R700 sixx
+/// let mut parameters = OpwParameters::new(0.025, -0.035, 0.0, 0.4, 0.315, 0.365, 0.08);
+/// parameters.offsets[1] = -std::f64::consts::FRAC_PI_2;
+/// parameters.sign_corrections = [-1, 1, 1, -1, 1, -1];
+/// let solver = OpwIkSolver::new(parameters);
+/// let arm = parameters.to_serial_chain();
+///
+/// let positions = [0.2, -0.3, 0.4, 0.5, -0.6, 0.7];
+/// arm.set_joint_positions(&positions).unwrap();
+/// let target = arm.end_transform();
+///
+/// let solutions = solver.solve_all(&target);
+/// assert_eq!(solutions.len(), 8);
+/// for solution in &solutions {
+///     arm.set_joint_positions_unchecked(solution);
+///     let end = arm.end_transform();
+///     assert!((end.to_homogeneous() - target.to_homogeneous()).norm() < 1e-6);
+/// }
+/// let nearest = solver.solve_nearest(&target, &positions).unwrap();
+/// assert!(nearest.iter().zip(&positions).all(|(a, b)| (a - b).abs() < 1e-6));
+///
+/// // it also works as `InverseKinematicsSolver`
+/// arm.set_joint_positions(&[0.0; 6]).unwrap();
+/// solver.solve(&arm, &target).unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct OpwIkSolver<T: RealField> {
+    pub parameters: OpwParameters<T>,
+}
+
+impl<T> OpwIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    pub fn new(parameters: OpwParameters<T>) -> Self {
+        Self { parameters }
+    }
+}
+
+impl<T> AnalyticalIkSolver<T> for OpwIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn solve_all(&self, target: &Isometry3<T>) -> Vec<Vec<T>> {
+        let p = &self.parameters;
+        let two: T = na::convert(2.0);
+        let pi = T::pi();
+        let m: Matrix3<T> = target.rotation.to_rotation_matrix().into_inner();
+        let c = target.translation.vector - m.column(2) * p.c4;
+
+        // joint 1, the shoulder at front or back
+        let nx1 = (c.x * c.x + c.y * c.y - p.b * p.b).sqrt() - p.a1;
+        let tmp1 = c.y.atan2(c.x);
+        let tmp2 = p.b.atan2(nx1 + p.a1);
+        let theta1 = [tmp1 - tmp2, tmp1 + tmp2 - pi];
+
+        // joints 2 and 3, the elbow up or down for each shoulder
+        let tmp3 = c.z - p.c1;
+        let s1_2 = nx1 * nx1 + tmp3 * tmp3;
+        let tmp4 = nx1 + two * p.a1;
+        let s2_2 = tmp4 * tmp4 + tmp3 * tmp3;
+        let kappa_2 = p.a2 * p.a2 + p.c3 * p.c3;
+        let c2_2 = p.c2 * p.c2;
+        let tmp13 = ((s1_2 + c2_2 - kappa_2) / (two * s1_2.sqrt() * p.c2)).acos();
+        let tmp14 = nx1.atan2(tmp3);
+        let tmp15 = ((s2_2 + c2_2 - kappa_2) / (two * s2_2.sqrt() * p.c2)).acos();
+        let tmp16 = tmp4.atan2(tmp3);
+        let theta2 = [-tmp13 + tmp14, tmp13 + tmp14, -tmp15 - tmp16, tmp15 - tmp16];
+        let tmp9 = two * p.c2 * kappa_2.sqrt();
+        let psi3 = p.a2.atan2(p.c3);
+        let tmp7 = ((s1_2 - c2_2 - kappa_2) / tmp9).acos();
+        let tmp8 = ((s2_2 - c2_2 - kappa_2) / tmp9).acos();
+        let theta3 = [tmp7 - psi3, -tmp7 - psi3, tmp8 - psi3, -tmp8 - psi3];
+
+        // the wrist, and its flip
+        let mut solutions = Vec::with_capacity(8);
+        for flip in 0..2 {
+            for i in 0..4 {
+                let t1 = theta1[i / 2];
+                let (s1, c1) = (t1.sin(), t1.cos());
+                let (s23, c23) = ((theta2[i] + theta3[i]).sin(), (theta2[i] + theta3[i]).cos());
+                let mp = m[(0, 2)] * s23 * c1 + m[(1, 2)] * s23 * s1 + m[(2, 2)] * c23;
+                let mut t5 = (T::one() - mp * mp).max(T::zero()).sqrt().atan2(mp);
+                let mut t4 = (m[(1, 2)] * c1 - m[(0, 2)] * s1)
+                    .atan2(m[(0, 2)] * c23 * c1 + m[(1, 2)] * c23 * s1 - m[(2, 2)] * s23);
+                let mut t6 = (m[(0, 1)] * s23 * c1 + m[(1, 1)] * s23 * s1 + m[(2, 1)] * c23)
+                    .atan2(-m[(0, 0)] * s23 * c1 - m[(1, 0)] * s23 * s1 - m[(2, 0)] * c23);
+                if flip == 1 {
+                    t4 += pi;
+                    t5 = -t5;
+                    t6 -= pi;
+                }
+                let angles = [t1, theta2[i], theta3[i], t4, t5, t6];
+                let is_finite = angles.iter().all(|a| {
+                    let a: f64 = na::convert(*a);
+                    a.is_finite()
+                });
+                if is_finite {
+                    solutions.push(p.joint_positions_of(&angles));
+                }
+            }
+        }
+        solutions
+    }
+}
+
+impl<T> InverseKinematicsSolver<T> for OpwIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Set the solution nearest to the current positions within the limits
+    ///
+    /// `arm` must have the kinematics of `OpwParameters::to_serial_chain()`.
+    /// The full pose is always solved, so it returns `Error::InvalidParameterError` if
+    /// `constraints` frees any coordinate or ignores any joint. If no solution is within
+    /// the limits, it returns `Error::NoAnalyticalSolutionError`.
+    fn solve_with_constraints(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error> {
+        if arm.dof() != 6 {
+            return Err(Error::SizeMismatchError {
+                input: arm.dof(),
+                required: 6,
+            });
+        }
+        let is_full_pose = constraints.position_x
+            && constraints.position_y
+            && constraints.position_z
+            && constraints.rotation_x
+            && constraints.rotation_y
+            && constraints.rotation_z
+            && constraints.ignored_joint_names.is_empty();
+        if !is_full_pose {
+            return Err(Error::InvalidParameterError {
+                reason: "OpwIkSolver supports only the constraints of the full pose".to_owned(),
+            });
+        }
+        let current = arm.joint_positions();
+        let distance = |solution: &[T]| {
+            solution
+                .iter()
+                .zip(&current)
+                .fold(T::zero(), |sum, (a, b)| sum + (*a - *b) * (*a - *b))
+        };
+        let mut solutions = self.solve_all(target_pose);
+        solutions.sort_by(|a, b| {
+            distance(a)
+                .partial_cmp(&distance(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        for solution in &solutions {
+            if arm.set_joint_positions(solution).is_ok() {
+                return Ok(());
+            }
+        }
+        Err(Error::NoAnalyticalSolutionError {
+            num_solutions: solutions.len(),
+        })
+    }
+}
+
+#[test]
+fn test_opw_random_positions() {
+    use super::bench::Rng;
+    let mut parameters = OpwParameters::new(0.15, -0.1, 0.05, 0.5, 0.6, 0.7, 0.1);
+    parameters.offsets = [0.1, -0.2, 0.3, 0.0, 0.0, 0.4];
+    parameters.sign_corrections = [1, -1, 1, 1, -1, 1];
+    let solver = OpwIkSolver::new(parameters);
+    let arm = parameters.to_serial_chain();
+    let mut rng = Rng::new(3);
+    for _ in 0..50 {
+        let positions = (0..6)
+            .map(|_| (rng.next_f64() - 0.5) * 5.0)
+            .collect::<Vec<f64>>();
+        arm.set_joint_positions_unchecked(&positions);
+        let target = arm.end_transform();
+        let closed_form = parameters.end_transform(&positions).unwrap();
+        assert!((closed_form.to_homogeneous() - target.to_homogeneous()).norm() < 1e-10);
+        let solutions = solver.solve_all(&target);
+        assert!(!solutions.is_empty());
+        for solution in &solutions {
+            let end = parameters.end_transform(solution).unwrap();
+            assert!((end.to_homogeneous() - target.to_homogeneous()).norm() < 1e-6);
+        }
+    }
+    let far = Isometry3::translation(10.0, 0.0, 0.0);
+    assert!(solver.solve_all(&far).is_empty());
+}
+
+#[test]
+fn test_opw_solve_with_constraints() {
+    let parameters = OpwParameters::new(0.15, -0.1, 0.05, 0.5, 0.6, 0.7, 0.1);
+    let solver = OpwIkSolver::new(parameters);
+    let arm = parameters.to_serial_chain();
+    let positions = [0.1, 0.2, -0.3, 0.4, 0.5, -0.6];
+    arm.set_joint_positions(&positions).unwrap();
+    let target = arm.end_transform();
+    arm.set_joint_positions(&[0.0; 6]).unwrap();
+
+    let constraints = Constraints {
+        rotation_z: false,
+        ..Default::default()
+    };
+    assert!(matches!(
+        solver.solve_with_constraints(&arm, &target, &constraints),
+        Err(Error::InvalidParameterError { .. })
+    ));
+    solver.solve(&arm, &target).unwrap();
+    let end = arm.end_transform();
+    assert!((end.to_homogeneous() - target.to_homogeneous()).norm() < 1e-6);
+
+    // no branch is within the limits
+    arm.set_joint_positions(&[0.0; 6]).unwrap();
+    for node in arm.iter() {
+        node.lock().joint.limits = Some(Range::new(-0.01, 0.01));
+    }
+    assert!(matches!(
+        solver.solve(&arm, &target),
+        Err(Error::NoAnalyticalSolutionError { num_solutions }) if num_solutions > 0
+    ));
+    let far = Isometry3::translation(10.0, 0.0, 0.0);
+    assert!(matches!(
+        solver.solve(&arm, &far),
+        Err(Error::NoAnalyticalSolutionError { num_solutions: 0 })
+    ));
+}
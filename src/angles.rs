@@ -0,0 +1,461 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+//! Conversions between roll-pitch-yaw, axis-angle and quaternion
+//!
+//! Roll-pitch-yaw is the same convention as URDF: rotation around fixed X, Y and Z axes,
+//! in that order. Axis-angle is represented by a scaled axis, whose norm is the angle.
+use crate::errors::*;
+use na::{DMatrix, Quaternion, RealField, UnitQuaternion, Vector3, Vector4};
+use nalgebra as na;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// Create quaternion from roll, pitch and yaw
+///
+/// # Examples
+///
+/// ```
+/// let q = k::angles::quaternion_from_rpy(0.1, 0.2, 0.3);
+/// let rpy = k::angles::rpy_from_quaternion(&q);
+/// assert!((rpy[0] - 0.1f64).abs() < 1e-10);
+/// assert!((rpy[1] - 0.2f64).abs() < 1e-10);
+/// assert!((rpy[2] - 0.3f64).abs() < 1e-10);
+/// ```
+pub fn quaternion_from_rpy<T: RealField>(roll: T, pitch: T, yaw: T) -> UnitQuaternion<T> {
+    UnitQuaternion::from_euler_angles(roll, pitch, yaw)
+}
+
+/// Get roll, pitch and yaw from quaternion
+///
+/// The pitch is in [-π/2, π/2], roll and yaw are in [-π, π].
+pub fn rpy_from_quaternion<T: RealField>(quaternion: &UnitQuaternion<T>) -> [T; 3] {
+    let (roll, pitch, yaw) = quaternion.euler_angles();
+    [roll, pitch, yaw]
+}
+
+/// Create quaternion from axis-angle (scaled axis)
+///
+/// # Examples
+///
+/// ```
+/// use k::Vector3;
+///
+/// let q = k::angles::quaternion_from_axis_angle(&Vector3::new(0.0, 0.0, 0.5));
+/// assert!((q.angle() - 0.5f64).abs() < 1e-10);
+/// let axis_angle = k::angles::axis_angle_from_quaternion(&q);
+/// assert!((axis_angle - Vector3::new(0.0, 0.0, 0.5)).norm() < 1e-10);
+/// ```
+pub fn quaternion_from_axis_angle<T: RealField>(axis_angle: &Vector3<T>) -> UnitQuaternion<T> {
+    UnitQuaternion::from_scaled_axis(*axis_angle)
+}
+
+/// Get axis-angle (scaled axis) from quaternion
+///
+/// The angle is always in [0, π], so `q` and `-q` give the same result.
+///
+/// # Examples
+///
+/// ```
+/// use k::{UnitQuaternion, Vector3};
+///
+/// let q = UnitQuaternion::new_unchecked(-k::angles::quaternion_from_axis_angle(
+///     &Vector3::new(0.2, 0.0, 0.0)).into_inner());
+/// let axis_angle = k::angles::axis_angle_from_quaternion(&q);
+/// assert!((axis_angle - Vector3::new(0.2, 0.0, 0.0)).norm() < 1e-10);
+/// ```
+pub fn axis_angle_from_quaternion<T: RealField>(quaternion: &UnitQuaternion<T>) -> Vector3<T> {
+    quaternion.scaled_axis()
+}
+
+/// Create quaternion from roll, pitch and yaw in an array
+pub fn quaternion_from_rpy_array<T: RealField>(rpy: &[T; 3]) -> UnitQuaternion<T> {
+    quaternion_from_rpy(rpy[0], rpy[1], rpy[2])
+}
+
+/// Calculate the rotation from `b` to `a` as axis-angle (scaled axis)
+///
+/// It is expressed in the frame which `a` and `b` are expressed in.
+///
+/// # Examples
+///
+/// ```
+/// use k::{UnitQuaternion, Vector3};
+///
+/// let a = UnitQuaternion::from_euler_angles(0.0, 0.0, 0.3);
+/// let b = UnitQuaternion::from_euler_angles(0.0, 0.0, 0.1);
+/// let diff = k::angles::rotation_diff(&a, &b);
+/// assert!((diff - Vector3::new(0.0, 0.0, 0.2)).norm() < 1e-10);
+/// ```
+pub fn rotation_diff<T: RealField>(a: &UnitQuaternion<T>, b: &UnitQuaternion<T>) -> Vector3<T> {
+    axis_angle_from_quaternion(&b.rotation_to(a))
+}
+
+/// Wrap the angle into [-π, π)
+///
+/// # Examples
+///
+/// ```
+/// use std::f64::consts::PI;
+/// assert!((k::angles::wrap_angle(3.0 * PI) + PI).abs() < 1e-10);
+/// assert!((k::angles::wrap_angle(-0.5f64) + 0.5).abs() < 1e-10);
+/// ```
+pub fn wrap_angle<T: RealField>(angle: T) -> T {
+    let two_pi = T::two_pi();
+    angle - two_pi * ((angle + T::pi()) / two_pi).floor()
+}
+
+/// Signed angle of the shortest rotation from `from` to `to`, in [-π, π)
+///
+/// # Examples
+///
+/// ```
+/// use std::f64::consts::PI;
+/// let d = k::angles::shortest_angular_distance(0.9 * PI, -0.9 * PI);
+/// assert!((d - 0.2 * PI).abs() < 1e-10);
+/// ```
+pub fn shortest_angular_distance<T: RealField>(from: T, to: T) -> T {
+    wrap_angle(to - from)
+}
+
+/// The angle equivalent to `angle` (modulo 2π) which is nearest to `reference`
+///
+/// Used for the continuous joints to avoid unnecessary full turns.
+///
+/// # Examples
+///
+/// ```
+/// use std::f64::consts::PI;
+/// let a = k::angles::nearest_equivalent_angle(-0.9 * PI, 2.9 * PI);
+/// assert!((a - 3.1 * PI).abs() < 1e-10);
+/// ```
+pub fn nearest_equivalent_angle<T: RealField>(angle: T, reference: T) -> T {
+    reference + shortest_angular_distance(reference, angle)
+}
+
+/// Remove the jumps larger than π from the sequence of the angles
+///
+/// The first angle is kept, and the others are shifted by multiples of 2π
+/// to be continuous.
+///
+/// # Examples
+///
+/// ```
+/// use std::f64::consts::PI;
+/// let unwrapped = k::angles::unwrap_angles(&[PI - 0.1, -PI + 0.1, -PI + 0.3]);
+/// assert!((unwrapped[1] - (PI + 0.1)).abs() < 1e-10);
+/// assert!((unwrapped[2] - (PI + 0.3)).abs() < 1e-10);
+/// ```
+pub fn unwrap_angles<T: RealField>(angles: &[T]) -> Vec<T> {
+    let mut unwrapped: Vec<T> = Vec::with_capacity(angles.len());
+    for angle in angles {
+        let next = match unwrapped.last() {
+            Some(prev) => *prev + wrap_angle(*angle - *prev),
+            None => *angle,
+        };
+        unwrapped.push(next);
+    }
+    unwrapped
+}
+
+/// Make the sequence of roll, pitch and yaw continuous
+///
+/// Roll-pitch-yaw has two representations for a rotation, `(r, p, y)` and
+/// `(r + π, π - p, y + π)`. The closer one to the previous value is selected
+/// and then the angles are unwrapped, so the result is continuous
+/// even around the singularity of pitch = ±π/2.
+///
+/// # Examples
+///
+/// ```
+/// use std::f64::consts::PI;
+/// let rpys = [[0.1, 0.2, PI - 0.05], [0.1, 0.2, -PI + 0.05]];
+/// let unwrapped = k::angles::unwrap_rpy_sequence(&rpys);
+/// assert!((unwrapped[1][2] - (PI + 0.05)).abs() < 1e-10);
+/// ```
+pub fn unwrap_rpy_sequence<T: RealField>(rpys: &[[T; 3]]) -> Vec<[T; 3]> {
+    let mut unwrapped: Vec<[T; 3]> = Vec::with_capacity(rpys.len());
+    for rpy in rpys {
+        let next = match unwrapped.last() {
+            Some(prev) => {
+                let candidates = [*rpy, [rpy[0] + T::pi(), T::pi() - rpy[1], rpy[2] + T::pi()]];
+                let mut best: Option<([T; 3], T)> = None;
+                for candidate in candidates.iter() {
+                    let mut shifted = [T::zero(); 3];
+                    let mut distance = T::zero();
+                    for i in 0..3 {
+                        shifted[i] = prev[i] + wrap_angle(candidate[i] - prev[i]);
+                        distance += (shifted[i] - prev[i]).abs();
+                    }
+                    match best {
+                        Some((_, d)) if d <= distance => {}
+                        _ => best = Some((shifted, distance)),
+                    }
+                }
+                best.expect("candidates are not empty").0
+            }
+            None => *rpy,
+        };
+        unwrapped.push(next);
+    }
+    unwrapped
+}
+
+/// Parameterization of a rotation by an array of values, e.g. for optimizers and file formats
+///
+/// The identity rotation is all zeros (`[0, 0, 0, 1]` for `Quaternion`),
+/// and it is converted back to the identity exactly.
+///
+/// # Examples
+///
+/// ```
+/// use k::angles::RotationParameterization;
+/// use k::UnitQuaternion;
+///
+/// let q = UnitQuaternion::from_euler_angles(0.1, -0.2, 0.3);
+/// for p in [
+///     RotationParameterization::Quaternion,
+///     RotationParameterization::ExponentialCoordinates,
+///     RotationParameterization::Rpy,
+/// ]
+/// .iter()
+/// {
+///     let parameters = p.parameters_of(&q);
+///     assert_eq!(parameters.len(), p.num_parameters());
+///     assert!(p.rotation_of(&parameters).unwrap().angle_to(&q) < 1e-10);
+///     assert_eq!(p.rotation_of(&p.parameters_of(&UnitQuaternion::<f64>::identity())).unwrap(),
+///                UnitQuaternion::identity());
+/// }
+/// assert!(RotationParameterization::Rpy.rotation_of(&[0.0f64; 4]).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum RotationParameterization {
+    /// `[x, y, z, w]` of the unit quaternion, same order as `UnitQuaternion::coords`
+    ///
+    /// It is normalized when it is converted to the rotation, and all zeros is
+    /// regarded as the identity.
+    Quaternion,
+    /// Axis-angle (scaled axis) `[x, y, z]`, whose norm is the angle
+    ExponentialCoordinates,
+    /// `[roll, pitch, yaw]`, same as `quaternion_from_rpy()`
+    Rpy,
+}
+
+impl Default for RotationParameterization {
+    fn default() -> Self {
+        RotationParameterization::Quaternion
+    }
+}
+
+impl RotationParameterization {
+    /// The number of the parameters
+    pub fn num_parameters(self) -> usize {
+        match self {
+            RotationParameterization::Quaternion => 4,
+            RotationParameterization::ExponentialCoordinates | RotationParameterization::Rpy => 3,
+        }
+    }
+
+    /// Convert the rotation to the parameters
+    pub fn parameters_of<T: RealField>(self, rotation: &UnitQuaternion<T>) -> Vec<T> {
+        match self {
+            RotationParameterization::Quaternion => rotation.coords.iter().cloned().collect(),
+            RotationParameterization::ExponentialCoordinates => {
+                axis_angle_from_quaternion(rotation)
+                    .iter()
+                    .cloned()
+                    .collect()
+            }
+            RotationParameterization::Rpy => rpy_from_quaternion(rotation).to_vec(),
+        }
+    }
+
+    /// Convert the parameters to the rotation
+    ///
+    /// It returns `Error::SizeMismatchError` if the length of `parameters` is not
+    /// `num_parameters()`.
+    pub fn rotation_of<T: RealField>(self, parameters: &[T]) -> Result<UnitQuaternion<T>, Error> {
+        self.check_len(parameters)?;
+        Ok(match self {
+            RotationParameterization::Quaternion => UnitQuaternion::try_new(
+                Quaternion::from(Vector4::from_column_slice(parameters)),
+                T::zero(),
+            )
+            .unwrap_or_else(UnitQuaternion::identity),
+            RotationParameterization::ExponentialCoordinates => {
+                quaternion_from_axis_angle(&Vector3::from_column_slice(parameters))
+            }
+            RotationParameterization::Rpy => {
+                quaternion_from_rpy(parameters[0], parameters[1], parameters[2])
+            }
+        })
+    }
+
+    /// The 3 x `num_parameters()` matrix which maps the derivative of the parameters to
+    /// the angular velocity, in the frame which the rotation is expressed in
+    ///
+    /// Multiply it to the rotational rows of a jacobian to get the jacobian
+    /// with respect to the parameters.
+    ///
+    /// It returns `Error::SizeMismatchError` if the length of `parameters` is not
+    /// `num_parameters()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::angles::RotationParameterization;
+    ///
+    /// let p = RotationParameterization::Rpy;
+    /// // the rotation around Z is the yaw
+    /// let jacobi = p.angular_velocity_jacobian(&[0.0f64, 0.0, 0.0]).unwrap();
+    /// assert_eq!(jacobi.column(2).as_slice(), &[0.0, 0.0, 1.0]);
+    /// ```
+    pub fn angular_velocity_jacobian<T: RealField>(
+        self,
+        parameters: &[T],
+    ) -> Result<DMatrix<T>, Error> {
+        self.check_len(parameters)?;
+        let mut jacobi = DMatrix::zeros(3, self.num_parameters());
+        for i in 0..self.num_parameters() {
+            jacobi
+                .fixed_slice_mut::<3, 1>(0, i)
+                .copy_from(&self.angular_velocity_column(parameters, i));
+        }
+        Ok(jacobi)
+    }
+
+    /// The `index`-th column of `angular_velocity_jacobian()` without allocation
+    ///
+    /// The length of `parameters` must be `num_parameters()`.
+    pub(crate) fn angular_velocity_column<T: RealField>(
+        self,
+        parameters: &[T],
+        index: usize,
+    ) -> Vector3<T> {
+        let two: T = na::convert(2.0);
+        match self {
+            RotationParameterization::Quaternion => {
+                // ω = 2 (w dv - dw v + v × dv) for the unit quaternion (v, w),
+                // divided by the norm for the derivative of the normalized one
+                let q = Vector4::from_column_slice(parameters);
+                let norm = q.norm();
+                let (v, w, norm) = if norm > T::zero() {
+                    (q.xyz() / norm, q.w / norm, norm)
+                } else {
+                    (Vector3::zeros(), T::one(), T::one())
+                };
+                let column = if index < 3 {
+                    let mut dv = Vector3::zeros();
+                    dv[index] = T::one();
+                    dv * w + v.cross(&dv)
+                } else {
+                    -v
+                };
+                column * (two / norm)
+            }
+            RotationParameterization::ExponentialCoordinates => {
+                // the left jacobian of SO(3)
+                let r = Vector3::from_column_slice(parameters);
+                let angle = r.norm();
+                let (a, b) = if angle < na::convert(1e-4) {
+                    let angle2 = angle * angle;
+                    (
+                        (T::one() - angle2 / na::convert(12.0)) / two,
+                        (T::one() - angle2 / na::convert(20.0)) / na::convert(6.0),
+                    )
+                } else {
+                    let angle2 = angle * angle;
+                    (
+                        (T::one() - angle.cos()) / angle2,
+                        (angle - angle.sin()) / (angle2 * angle),
+                    )
+                };
+                let mut e = Vector3::zeros();
+                e[index] = T::one();
+                let cross = r.cross(&e);
+                e + cross * a + r.cross(&cross) * b
+            }
+            RotationParameterization::Rpy => {
+                // ω = R_z(y) R_y(p) e_x droll + R_z(y) e_y dpitch + e_z dyaw
+                let (sp, cp) = parameters[1].sin_cos();
+                let (sy, cy) = parameters[2].sin_cos();
+                match index {
+                    0 => Vector3::new(cp * cy, cp * sy, -sp),
+                    1 => Vector3::new(-sy, cy, T::zero()),
+                    _ => Vector3::z(),
+                }
+            }
+        }
+    }
+
+    fn check_len<T>(self, parameters: &[T]) -> Result<(), Error> {
+        if parameters.len() == self.num_parameters() {
+            Ok(())
+        } else {
+            Err(Error::SizeMismatchError {
+                input: parameters.len(),
+                required: self.num_parameters(),
+            })
+        }
+    }
+}
+
+#[test]
+fn test_unwrap_rpy_sequence_around_singularity() {
+    use std::f64::consts::FRAC_PI_2;
+    let before = quaternion_from_rpy(0.3, FRAC_PI_2 - 0.01, -0.2);
+    let after = quaternion_from_rpy(0.3, FRAC_PI_2 + 0.01, -0.2);
+    let rpys = [rpy_from_quaternion(&before), rpy_from_quaternion(&after)];
+    let unwrapped = unwrap_rpy_sequence(&rpys);
+    assert!((unwrapped[0][1] - (FRAC_PI_2 - 0.01)).abs() < 1e-8);
+    assert!((unwrapped[1][1] - (FRAC_PI_2 + 0.01)).abs() < 1e-8);
+    assert!((unwrapped[1][0] - 0.3).abs() < 1e-8);
+    assert!((unwrapped[1][2] + 0.2).abs() < 1e-8);
+    for (rpy, original) in unwrapped.iter().zip(&[before, after]) {
+        assert!(quaternion_from_rpy_array(rpy).angle_to(original) < 1e-8);
+    }
+}
+
+#[test]
+fn test_angular_velocity_jacobian_finite_difference() {
+    let rotation = UnitQuaternion::from_euler_angles(0.3, -0.4, 0.5);
+    let eps = 1e-6;
+    for p in [
+        RotationParameterization::Quaternion,
+        RotationParameterization::ExponentialCoordinates,
+        RotationParameterization::Rpy,
+    ]
+    .iter()
+    {
+        for parameters in [
+            p.parameters_of(&rotation),
+            p.parameters_of(&UnitQuaternion::identity()),
+            // not normalized for the quaternion
+            p.parameters_of(&rotation).iter().map(|x| x * 1.5).collect(),
+        ]
+        .iter()
+        {
+            let jacobi = p.angular_velocity_jacobian(parameters).unwrap();
+            let base = p.rotation_of(parameters).unwrap();
+            for i in 0..p.num_parameters() {
+                let mut moved = parameters.clone();
+                moved[i] += eps;
+                let omega = rotation_diff(&p.rotation_of(&moved).unwrap(), &base) / eps;
+                assert!((omega - jacobi.column(i)).norm() < 1e-5, "{:?} {}", p, i);
+            }
+        }
+    }
+}
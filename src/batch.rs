@@ -0,0 +1,215 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::joint::*;
+use na::{DMatrix, Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Where the position of a `FlatJoint` comes from
+#[derive(Debug, Clone)]
+pub enum FlatJointPosition<T: RealField> {
+    /// Fixed joint, it has no position
+    Fixed,
+    /// The first column of the configuration matrix, the joint uses
+    /// `JointType::num_positions()` columns from it
+    Input(usize),
+    /// Calculated from the column of the mimic parent
+    Mimic { index: usize, mimic: Mimic<T> },
+    /// The mimic parent is not in the chain, so the current position is used
+    Constant(T),
+}
+
+/// A node of `FlatChain`
+#[derive(Debug, Clone)]
+pub struct FlatJoint<T: RealField> {
+    /// index of the parent in `FlatChain::joints`, `None` if the parent is not in the chain
+    pub parent: Option<usize>,
+    /// local origin transform of the joint
+    pub origin: Isometry3<T>,
+    /// type of the joint
+    pub joint_type: JointType<T>,
+    /// where the position comes from
+    pub position: FlatJointPosition<T>,
+}
+
+/// Plain description of a `Chain` without any locks or references, created by `Chain::to_flat()`
+///
+/// It is in the same order as `Chain::iter()`, so a parent is always before its children.
+#[derive(Debug, Clone)]
+pub struct FlatChain<T: RealField> {
+    /// all the nodes of the chain
+    pub joints: Vec<FlatJoint<T>>,
+    /// degree of freedom, the number of the columns of the configuration matrix
+    pub dof: usize,
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create the flattened description of this chain for `BatchFkBackend`
+    pub fn to_flat(&self) -> FlatChain<T> {
+        let nodes = self.iter().collect::<Vec<_>>();
+        let movable = nodes
+            .iter()
+            .filter(|node| node.joint().is_movable())
+            .collect::<Vec<_>>();
+        // the first column of each movable node
+        let columns = movable
+            .iter()
+            .scan(0, |column, node| {
+                let first = *column;
+                *column += node.joint().num_positions();
+                Some(first)
+            })
+            .collect::<Vec<_>>();
+        let joints = nodes
+            .iter()
+            .map(|node| {
+                let parent = node
+                    .parent()
+                    .and_then(|parent| nodes.iter().position(|n| **n == parent));
+                let position = if !node.joint().is_movable() {
+                    FlatJointPosition::Fixed
+                } else {
+                    match (node.mimic_parent(), node.mimic()) {
+                        (Some(mimic_parent), Some(mimic)) => {
+                            match movable.iter().position(|n| ***n == mimic_parent) {
+                                Some(index) => FlatJointPosition::Mimic {
+                                    index: columns[index],
+                                    mimic,
+                                },
+                                None => FlatJointPosition::Constant(
+                                    node.joint_position()
+                                        .expect("Must be a bug: movable joint must have position"),
+                                ),
+                            }
+                        }
+                        _ => FlatJointPosition::Input(
+                            columns[movable
+                                .iter()
+                                .position(|n| **n == *node)
+                                .expect("movable node must be found")],
+                        ),
+                    }
+                };
+                let joint = node.joint();
+                FlatJoint {
+                    parent,
+                    origin: *joint.origin(),
+                    joint_type: joint.joint_type,
+                    position,
+                }
+            })
+            .collect();
+        FlatChain {
+            joints,
+            dof: self.dof(),
+        }
+    }
+}
+
+/// Backend to calculate forward kinematics of many configurations at once
+///
+/// Implement this trait to offload the calculation to GPU or SIMD.
+/// `CpuBatchFkBackend` is the reference implementation.
+pub trait BatchFkBackend<T>
+where
+    T: RealField,
+{
+    /// Calculate the transforms of all the joints of `chain` for each row of `configurations`
+    ///
+    /// `configurations` is (number of configurations) x `chain.dof` matrix.
+    /// The result is in the same order as the rows, and each element is in the same order
+    /// as `chain.joints`. The transforms are in the frame of the root of the chain.
+    fn batch_transforms(
+        &self,
+        chain: &FlatChain<T>,
+        configurations: &DMatrix<T>,
+    ) -> Result<Vec<Vec<Isometry3<T>>>, Error>;
+}
+
+/// Reference implementation of `BatchFkBackend`, which calculates on CPU sequentially
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let flat = chain.to_flat();
+/// let configurations = nalgebra::DMatrix::from_fn(3, chain.dof(), |r, c| 0.1 * (r + c) as f64);
+/// let poses = CpuBatchFkBackend.batch_transforms(&flat, &configurations).unwrap();
+/// assert_eq!(poses.len(), 3);
+///
+/// // The same as `update_transforms()`
+/// chain.set_joint_positions(configurations.row(2).transpose().as_slice()).unwrap();
+/// let transforms = chain.update_transforms();
+/// for (a, b) in transforms.iter().zip(poses[2].iter()) {
+///     assert!((a.translation.vector - b.translation.vector).norm() < 1e-10);
+///     assert!(a.rotation.angle_to(&b.rotation) < 1e-10);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuBatchFkBackend;
+
+impl<T> BatchFkBackend<T> for CpuBatchFkBackend
+where
+    T: RealField,
+{
+    fn batch_transforms(
+        &self,
+        chain: &FlatChain<T>,
+        configurations: &DMatrix<T>,
+    ) -> Result<Vec<Vec<Isometry3<T>>>, Error> {
+        if configurations.ncols() != chain.dof {
+            return Err(Error::SizeMismatchError {
+                input: configurations.ncols(),
+                required: chain.dof,
+            });
+        }
+        Ok(configurations
+            .row_iter()
+            .map(|row| {
+                let mut transforms: Vec<Isometry3<T>> = Vec::with_capacity(chain.joints.len());
+                let mut positions = Vec::new();
+                for joint in &chain.joints {
+                    positions.clear();
+                    match joint.position {
+                        FlatJointPosition::Fixed => {}
+                        FlatJointPosition::Input(index) => positions.extend(
+                            (index..index + joint.joint_type.num_positions()).map(|i| row[i]),
+                        ),
+                        FlatJointPosition::Mimic { index, ref mimic } => {
+                            positions.push(mimic.mimic_position(row[index]))
+                        }
+                        FlatJointPosition::Constant(position) => positions.push(position),
+                    }
+                    let parent_transform = match joint.parent {
+                        Some(parent) => transforms[parent],
+                        None => Isometry3::identity(),
+                    };
+                    transforms.push(
+                        parent_transform * joint.origin * joint.joint_type.transform(&positions),
+                    );
+                }
+                transforms
+            })
+            .collect())
+    }
+}
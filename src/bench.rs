@@ -0,0 +1,276 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+//! Benchmark of IK solvers with reproducible random targets
+//!
+//! # Examples
+//!
+//! ```
+//! use k::bench::*;
+//!
+//! let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+//! let arm = k::SerialChain::from_end(chain.find("r_wrist_pitch").unwrap());
+//! let config = IkBenchConfig {
+//!     num_targets: 10,
+//!     ..Default::default()
+//! };
+//! let report = run_ik_bench(&k::JacobianIkSolver::default(), &arm, &config).unwrap();
+//! assert_eq!(report.num_targets, 10);
+//! println!("{}", report);
+//! ```
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use na::RealField;
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+/// Settings of `run_ik_bench()`
+#[derive(Debug, Clone)]
+pub struct IkBenchConfig {
+    /// number of the random targets
+    pub num_targets: usize,
+    /// seed of the random numbers. The same seed generates the same targets.
+    pub seed: u64,
+    /// constraints passed to the solver
+    pub constraints: Constraints,
+}
+
+impl Default for IkBenchConfig {
+    fn default() -> Self {
+        Self {
+            num_targets: 100,
+            seed: 0,
+            constraints: Constraints::default(),
+        }
+    }
+}
+
+/// Result of `run_ik_bench()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IkBenchReport {
+    pub num_targets: usize,
+    pub num_success: usize,
+    /// mean of the iterations of the successful solves,
+    /// `None` if the solver does not count the iterations or nothing succeeded
+    pub mean_iterations: Option<f64>,
+    /// median of the time of the solves
+    pub time_p50: Duration,
+    /// 90th percentile of the time of the solves
+    pub time_p90: Duration,
+    /// 99th percentile of the time of the solves
+    pub time_p99: Duration,
+    pub time_max: Duration,
+}
+
+impl IkBenchReport {
+    /// Ratio of the successful solves, in [0, 1]
+    pub fn success_rate(&self) -> f64 {
+        if self.num_targets == 0 {
+            0.0
+        } else {
+            self.num_success as f64 / self.num_targets as f64
+        }
+    }
+}
+
+impl Display for IkBenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "success: {}/{} ({:.1}%)",
+            self.num_success,
+            self.num_targets,
+            self.success_rate() * 100.0
+        )?;
+        if let Some(iterations) = self.mean_iterations {
+            write!(f, ", mean iterations: {:.2}", iterations)?;
+        }
+        write!(
+            f,
+            ", time p50: {:?}, p90: {:?}, p99: {:?}, max: {:?}",
+            self.time_p50, self.time_p90, self.time_p99, self.time_max
+        )
+    }
+}
+
+/// xorshift64*, enough to generate reproducible targets without dependencies
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // the state must not be zero
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+    /// uniform in [0, 1)
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Generate reachable targets by forward kinematics of random joint positions
+///
+/// The positions are uniform within the limits, or in [-π, π] for the joints without limits.
+/// The joint positions of `arm` are restored.
+pub fn generate_random_targets<T>(
+    arm: &SerialChain<T>,
+    num_targets: usize,
+    seed: u64,
+) -> Vec<na::Isometry3<T>>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let ranges = arm
+        .iter_position_nodes()
+        .map(|node| match node.joint().limits {
+            Some(ref range) => (
+                na::convert::<T, f64>(range.min),
+                na::convert::<T, f64>(range.max),
+            ),
+            None => (-std::f64::consts::PI, std::f64::consts::PI),
+        })
+        .collect::<Vec<_>>();
+    let orig_positions = arm.joint_positions();
+    let mut rng = Rng::new(seed);
+    let targets = (0..num_targets)
+        .map(|_| {
+            let positions = ranges
+                .iter()
+                .map(|(min, max)| na::convert(min + (max - min) * rng.next_f64()))
+                .collect::<Vec<T>>();
+            arm.set_joint_positions_unchecked(&positions);
+            arm.end_transform()
+        })
+        .collect();
+    arm.set_joint_positions_unchecked(&orig_positions);
+    targets
+}
+
+/// `p`th percentile of the sorted `times` by the nearest-rank method
+fn nearest_rank_percentile(times: &[Duration], p: usize) -> Duration {
+    if times.is_empty() {
+        Duration::default()
+    } else {
+        times[((p * times.len() + 99) / 100).max(1) - 1]
+    }
+}
+
+/// Run `solver` for random reachable targets of `arm` and report the statistics
+///
+/// Every solve starts from the joint positions of `arm` at the call, and
+/// they are restored at the end.
+pub fn run_ik_bench<T, S>(
+    solver: &S,
+    arm: &SerialChain<T>,
+    config: &IkBenchConfig,
+) -> Result<IkBenchReport, Error>
+where
+    T: RealField + SubsetOf<f64>,
+    S: InverseKinematicsSolver<T>,
+{
+    let targets = generate_random_targets(arm, config.num_targets, config.seed);
+    let orig_positions = arm.joint_positions();
+    let mut times = Vec::with_capacity(targets.len());
+    let mut num_success = 0;
+    let mut iterations = Vec::new();
+    for target in &targets {
+        arm.set_joint_positions(&orig_positions)?;
+        let start = Instant::now();
+        let result = solver.solve_with_iterations(arm, target, &config.constraints);
+        times.push(start.elapsed());
+        if let Ok(num) = result {
+            num_success += 1;
+            iterations.extend(num);
+        }
+    }
+    arm.set_joint_positions(&orig_positions)?;
+    times.sort();
+    let percentile = |p: usize| nearest_rank_percentile(&times, p);
+    let mean_iterations = if iterations.is_empty() || iterations.len() != num_success {
+        None
+    } else {
+        Some(iterations.iter().sum::<usize>() as f64 / iterations.len() as f64)
+    };
+    Ok(IkBenchReport {
+        num_targets: targets.len(),
+        num_success,
+        mean_iterations,
+        time_p50: percentile(50),
+        time_p90: percentile(90),
+        time_p99: percentile(99),
+        time_max: times.last().copied().unwrap_or_default(),
+    })
+}
+
+#[test]
+fn test_random_targets_are_reproducible() {
+    let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    let arm = SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    let positions = arm.joint_positions();
+    let a = generate_random_targets(&arm, 5, 1);
+    let b = generate_random_targets(&arm, 5, 1);
+    let c = generate_random_targets(&arm, 5, 2);
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(arm.joint_positions(), positions);
+
+    let report = run_ik_bench(
+        &JacobianIkSolver::new(0.001, 0.005, 0.5, 100),
+        &arm,
+        &IkBenchConfig {
+            num_targets: 5,
+            seed: 1,
+            constraints: Constraints {
+                rotation_x: false,
+                rotation_y: false,
+                rotation_z: false,
+                ..Default::default()
+            },
+        },
+    )
+    .unwrap();
+    assert_eq!(report.num_targets, 5);
+    assert_eq!(report.num_success, 2);
+    assert_eq!(report.mean_iterations, Some(12.5));
+    assert!(report.time_p50 <= report.time_p90);
+    // the 90th and 99th of five samples are the largest one
+    assert_eq!(report.time_p90, report.time_max);
+    assert_eq!(report.time_p99, report.time_max);
+    assert_eq!(arm.joint_positions(), positions);
+}
+
+#[test]
+fn test_nearest_rank_percentile() {
+    let times = (1..=10).map(Duration::from_millis).collect::<Vec<_>>();
+    assert_eq!(
+        nearest_rank_percentile(&times, 50),
+        Duration::from_millis(5)
+    );
+    assert_eq!(
+        nearest_rank_percentile(&times, 90),
+        Duration::from_millis(9)
+    );
+    assert_eq!(
+        nearest_rank_percentile(&times, 99),
+        Duration::from_millis(10)
+    );
+    assert_eq!(nearest_rank_percentile(&times, 0), Duration::from_millis(1));
+    assert_eq!(nearest_rank_percentile(&[], 50), Duration::default());
+}
@@ -0,0 +1,288 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::angles::wrap_angle;
+use super::chain::*;
+use super::errors::*;
+use super::joint::*;
+use na::RealField;
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// A joint clamped by `Chain::clamp_positions()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClampedJoint<T: RealField> {
+    /// index in the joint positions
+    pub index: usize,
+    pub joint_name: String,
+    /// the position before clamping
+    pub original: T,
+    /// the position after clamping
+    pub clamped: T,
+}
+
+impl<T: RealField> ClampedJoint<T> {
+    /// The change by clamping, `clamped - original`
+    pub fn correction(&self) -> T {
+        self.clamped - self.original
+    }
+}
+
+/// Result of `Chain::clamp_positions()`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ClampReport<T: RealField> {
+    /// the clamped joints in the order of the joint positions
+    pub clamped: Vec<ClampedJoint<T>>,
+}
+
+impl<T: RealField> ClampReport<T> {
+    /// Returns true if no joint is clamped
+    pub fn is_empty(&self) -> bool {
+        self.clamped.is_empty()
+    }
+
+    /// The largest absolute value of the corrections, zero if nothing is clamped
+    pub fn max_correction(&self) -> T {
+        self.clamped
+            .iter()
+            .fold(T::zero(), |max, c| max.max(c.correction().abs()))
+    }
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Get the lower and upper bounds of the positions of the movable joints
+    ///
+    /// The limits are used if they exist. Rotational joints without limits and the
+    /// continuous joints are bounded by [-π, π]. Linear joints without limits
+    /// have no bounds and `Error::UnboundedJointError` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .limits(Some((-1.0..=2.0).into()))
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// j1.set_parent(&j0);
+    /// let chain = Chain::<f64>::from_root(j0);
+    /// let (lower, upper) = chain.position_bounds().unwrap();
+    /// assert_eq!(lower, vec![-1.0, -std::f64::consts::PI]);
+    /// assert_eq!(upper, vec![2.0, std::f64::consts::PI]);
+    /// ```
+    pub fn position_bounds(&self) -> Result<(Vec<T>, Vec<T>), Error> {
+        let mut lower = Vec::with_capacity(self.dof());
+        let mut upper = Vec::with_capacity(self.dof());
+        for joint in self.iter_joints() {
+            for i in 0..joint.num_positions() {
+                let (min, max) = joint_bounds(&joint, i)?;
+                lower.push(min);
+                upper.push(max);
+            }
+        }
+        Ok((lower, upper))
+    }
+
+    /// Map the joint positions into [0, 1] for each joint by `position_bounds()`
+    ///
+    /// The positions of continuous joints are wrapped into [-π, π) before the mapping,
+    /// so they are in [0, 1). The positions out of the limits are not clamped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Linear{axis: Vector3::x_axis()})
+    ///     .limits(Some((0.0..=0.5).into()))
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// j1.set_parent(&j0);
+    /// let chain = Chain::<f64>::from_root(j0);
+    /// let u = chain.normalize(&[0.25, 2.0 * std::f64::consts::PI]).unwrap();
+    /// assert!((u[0] - 0.5).abs() < 1e-10);
+    /// assert!((u[1] - 0.5).abs() < 1e-10);
+    /// let q = chain.denormalize(&u).unwrap();
+    /// assert!((q[0] - 0.25).abs() < 1e-10);
+    /// assert!(q[1].abs() < 1e-10);
+    /// ```
+    pub fn normalize(&self, positions: &[T]) -> Result<Vec<T>, Error> {
+        self.check_bounds_input_size(positions)?;
+        let (lower, upper) = self.position_bounds()?;
+        Ok(self
+            .iter_position_nodes()
+            .zip(positions)
+            .zip(lower.into_iter().zip(upper))
+            .map(|((node, position), (min, max))| {
+                let position = if node.joint().effective_limits().is_none() {
+                    wrap_angle(*position)
+                } else {
+                    *position
+                };
+                let width = max - min;
+                if width > T::zero() {
+                    (position - min) / width
+                } else {
+                    T::zero()
+                }
+            })
+            .collect())
+    }
+
+    /// Map the normalized values in [0, 1] back to the joint positions
+    ///
+    /// This is the inverse of `normalize()`.
+    pub fn denormalize(&self, normalized: &[T]) -> Result<Vec<T>, Error> {
+        self.check_bounds_input_size(normalized)?;
+        let (lower, upper) = self.position_bounds()?;
+        Ok(normalized
+            .iter()
+            .zip(lower.into_iter().zip(upper))
+            .map(|(u, (min, max))| min + (max - min) * *u)
+            .collect())
+    }
+
+    /// Saturate `positions` to the limits in place, and report the clamped joints
+    ///
+    /// The joints without limits are not changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .name("j0")
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .limits(Some((-1.0..=1.0).into()))
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .name("j1")
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .limits(Some((-1.0..=1.0).into()))
+    ///     .into_node();
+    /// j1.set_parent(&j0);
+    /// let chain = Chain::<f64>::from_root(j0);
+    /// let mut positions = [0.5, 1.5];
+    /// let report = chain.clamp_positions(&mut positions).unwrap();
+    /// assert_eq!(positions, [0.5, 1.0]);
+    /// assert_eq!(report.clamped.len(), 1);
+    /// assert_eq!(report.clamped[0].joint_name, "j1");
+    /// assert_eq!(report.clamped[0].correction(), -0.5);
+    /// chain.set_joint_positions(&positions).unwrap();
+    /// ```
+    pub fn clamp_positions(&self, positions: &mut [T]) -> Result<ClampReport<T>, Error> {
+        self.check_bounds_input_size(positions)?;
+        let mut clamped = Vec::new();
+        for (index, (node, position)) in self
+            .iter_position_nodes()
+            .zip(positions.iter_mut())
+            .enumerate()
+        {
+            let joint = node.joint();
+            if let Some(ref range) = joint.effective_limits() {
+                let value = range.clamp(*position);
+                if value != *position {
+                    clamped.push(ClampedJoint {
+                        index,
+                        joint_name: joint.name.clone(),
+                        original: *position,
+                        clamped: value,
+                    });
+                    *position = value;
+                }
+            }
+        }
+        Ok(ClampReport { clamped })
+    }
+
+    fn check_bounds_input_size(&self, input: &[T]) -> Result<(), Error> {
+        if input.len() != self.dof() {
+            return Err(Error::SizeMismatchError {
+                input: input.len(),
+                required: self.dof(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Bounds of the `index`-th position of `joint`
+fn joint_bounds<T: RealField + SubsetOf<f64>>(
+    joint: &Joint<T>,
+    index: usize,
+) -> Result<(T, T), Error> {
+    match joint.effective_limits() {
+        Some(range) => Ok((range.min, range.max)),
+        None if joint.joint_type.is_angle(index) => Ok((-T::pi(), T::pi())),
+        None => Err(Error::UnboundedJointError {
+            joint_name: joint.name.clone(),
+        }),
+    }
+}
+
+#[test]
+fn test_normalize_errors() {
+    use super::node::*;
+    let j0 = NodeBuilder::new()
+        .name("j0")
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::x_axis(),
+        })
+        .into_node();
+    let chain = Chain::<f64>::from_root(j0);
+    assert!(matches!(
+        chain.position_bounds(),
+        Err(Error::UnboundedJointError { .. })
+    ));
+    assert!(chain.normalize(&[0.0]).is_err());
+    assert!(matches!(
+        chain.denormalize(&[0.0, 0.0]),
+        Err(Error::SizeMismatchError { .. })
+    ));
+}
+
+#[test]
+fn test_continuous_joint_bounds() {
+    use super::node::*;
+    let j0 = NodeBuilder::new()
+        .name("j0")
+        .joint_type(JointType::Rotational {
+            axis: na::Vector3::z_axis(),
+        })
+        .limits(Some((-1.0..=1.0).into()))
+        .continuous(true)
+        .into_node();
+    let chain = Chain::<f64>::from_root(j0);
+    let (lower, upper) = chain.position_bounds().unwrap();
+    assert_eq!(lower, [-std::f64::consts::PI]);
+    assert_eq!(upper, [std::f64::consts::PI]);
+    let mut positions = [2.0];
+    assert!(chain.clamp_positions(&mut positions).unwrap().is_empty());
+    assert_eq!(positions, [2.0]);
+    // not snapped to the limits
+    chain.set_joint_positions(&[1.0 + 1e-14]).unwrap();
+    assert_eq!(chain.joint_positions(), [1.0 + 1e-14]);
+}
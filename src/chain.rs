@@ -13,14 +13,20 @@
   See the License for the specific language governing permissions and
   limitations under the License.
 */
+use super::coupled_limits::*;
 use super::errors::*;
 use super::joint::*;
+use super::metadata::*;
 use super::node::*;
+use super::profile::*;
+use super::tolerances::*;
+use super::update_hooks::*;
 use na::{Isometry3, RealField};
 use nalgebra as na;
 use simba::scalar::SubsetOf;
 use std::fmt::{self, Display};
 use std::ops::Deref;
+use std::sync::Arc;
 
 /// Kinematic Chain using `Node`
 ///
@@ -86,8 +92,26 @@ pub struct Chain<T: RealField> {
     nodes: Vec<Node<T>>,
     movable_nodes: Vec<Node<T>>,
     dof: usize,
+    tolerances: Tolerances<T>,
+    profiler: Option<Profiler>,
+    update_hooks: UpdateHooks<T>,
+    metadata: ChainMetadata,
+    coupled_limits: Vec<CoupledLimit<T>>,
 }
 
+/// `Chain` of `f32`, the default tolerances of the solvers are tuned for it
+///
+/// # Examples
+///
+/// ```
+/// let chain = k::ChainF32::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChainF32::from_end(chain.find("l_wrist_pitch").unwrap());
+/// assert_eq!(arm.dof(), 6);
+/// ```
+pub type ChainF32 = Chain<f32>;
+/// `Chain` of `f64`
+pub type ChainF64 = Chain<f64>;
+
 impl<T: RealField + SubsetOf<f64>> Chain<T> {
     fn fmt_with_indent_level(
         &self,
@@ -107,7 +131,7 @@ impl<T: RealField + SubsetOf<f64>> Chain<T> {
 
 impl<T: RealField + SubsetOf<f64>> Display for Chain<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.fmt_with_indent_level(&self.iter().next().unwrap(), 0, f)
+        self.fmt_with_indent_level(self.iter().next().unwrap(), 0, f)
     }
 }
 
@@ -130,6 +154,84 @@ impl<T: RealField + SubsetOf<f64>> Chain<T> {
         Self::from_nodes(nodes)
     }
 
+    /// Create `Chain` from the joints and the indices of their parents
+    ///
+    /// `parents[i]` is the index of the parent of `joints[i]`, and exactly one joint
+    /// must be the root (`None`). If the parents come before their children, the order
+    /// of `iter()` is the same as `joints`, so the indices are kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let joints = vec![
+    ///     Joint::new("root", JointType::Fixed),
+    ///     Joint::new("a", JointType::Rotational { axis: Vector3::z_axis() }),
+    ///     Joint::new("b", JointType::Rotational { axis: Vector3::y_axis() }),
+    /// ];
+    /// let chain = Chain::<f64>::from_flat(joints, vec![None, Some(0), Some(0)]).unwrap();
+    /// assert_eq!(chain.dof(), 2);
+    /// let names = chain.iter().map(|n| n.joint().name.clone()).collect::<Vec<_>>();
+    /// assert_eq!(names, vec!["root", "a", "b"]);
+    /// assert_eq!(chain.find("b").unwrap().parent().unwrap().joint().name, "root");
+    ///
+    /// // cycle
+    /// let joints = vec![Joint::new("a", JointType::Fixed), Joint::new("b", JointType::Fixed)];
+    /// assert!(Chain::<f64>::from_flat(joints, vec![Some(1), Some(0)]).is_err());
+    /// ```
+    pub fn from_flat(joints: Vec<Joint<T>>, parents: Vec<Option<usize>>) -> Result<Self, Error> {
+        if joints.len() != parents.len() {
+            return Err(Error::SizeMismatchError {
+                input: parents.len(),
+                required: joints.len(),
+            });
+        }
+        let num_roots = parents.iter().filter(|p| p.is_none()).count();
+        if num_roots != 1 {
+            return Err(Error::InvalidStructureError {
+                reason: format!("{} roots are found, but it must be one", num_roots),
+            });
+        }
+        for (i, parent) in parents.iter().enumerate() {
+            if let Some(p) = parent {
+                if *p >= joints.len() {
+                    return Err(Error::InvalidStructureError {
+                        reason: format!("parent index {} of joint {} is out of range", p, i),
+                    });
+                }
+            }
+            // a cycle doesn't reach the root within the number of the joints
+            let mut current = *parent;
+            let mut depth = 0;
+            while let Some(p) = current {
+                depth += 1;
+                if depth > joints.len() {
+                    return Err(Error::InvalidStructureError {
+                        reason: format!("joint {} is in a cycle", i),
+                    });
+                }
+                current = parents[p];
+            }
+        }
+        let nodes = joints.into_iter().map(Node::new).collect::<Vec<_>>();
+        for (node, parent) in nodes.iter().zip(&parents) {
+            if let Some(p) = parent {
+                node.set_parent(&nodes[*p]);
+            }
+        }
+        let sorted = parents.iter().enumerate().all(|(i, parent)| match parent {
+            Some(p) => *p < i,
+            None => true,
+        });
+        Ok(if sorted {
+            Self::from_nodes(nodes)
+        } else {
+            let root = parents.iter().position(|p| p.is_none()).unwrap();
+            Self::from_root(nodes[root].clone())
+        })
+    }
+
     /// Create `Chain` from end joint. It has any branches.
     ///
     /// Do not discard root joint before create Chain.
@@ -172,15 +274,16 @@ impl<T: RealField + SubsetOf<f64>> Chain<T> {
     /// let chain = Chain::<f64>::from_nodes(vec![l0, l1]);
     /// ```
     pub fn from_nodes(nodes: Vec<Node<T>>) -> Chain<T> {
-        let movable_nodes = nodes
-            .iter()
-            .filter(|joint| joint.joint().is_movable())
-            .cloned()
-            .collect::<Vec<_>>();
+        let movable_nodes = collect_movable_nodes(&nodes);
         Chain {
-            dof: movable_nodes.len(),
+            dof: num_positions(&movable_nodes),
             movable_nodes,
             nodes,
+            tolerances: Tolerances::default(),
+            profiler: None,
+            update_hooks: UpdateHooks::default(),
+            metadata: ChainMetadata::default(),
+            coupled_limits: Vec::new(),
         }
     }
 
@@ -271,12 +374,58 @@ impl<T: RealField + SubsetOf<f64>> Chain<T> {
     ///
     /// Fixed joints are ignored. If you want to manipulate on Fixed,
     /// use `iter()` instead of `iter_joints()`
-    pub fn iter_joints(&self) -> impl Iterator<Item = JointRefGuard<T>> {
+    pub fn iter_joints(&self) -> impl Iterator<Item = JointRefGuard<'_, T>> {
         self.movable_nodes.iter().map(|node| node.joint())
     }
 
+    /// Iterate the movable nodes once for each of their positions
+    ///
+    /// The i-th node owns the i-th value of `joint_positions()`, so it can be zipped with
+    /// the positions. The node of a joint which has several positions is repeated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// assert_eq!(chain.iter_position_nodes().count(), chain.dof());
+    /// ```
+    pub fn iter_position_nodes(&self) -> impl Iterator<Item = &Node<T>> {
+        self.movable_nodes
+            .iter()
+            .flat_map(|node| std::iter::repeat(node).take(node.joint().num_positions()))
+    }
+
+    /// Pairs of the movable nodes and their parts of `positions`
+    ///
+    /// `positions` is sliced by `Joint::num_positions()`. If it is shorter than `dof()`,
+    /// the last slices are short or empty.
+    fn iter_movable_positions<'a>(
+        &'a self,
+        positions: &'a [T],
+    ) -> impl Iterator<Item = (&'a Node<T>, &'a [T])> {
+        let mut rest = positions;
+        self.movable_nodes.iter().map(move |node| {
+            let len = node.joint().num_positions().min(rest.len());
+            let (head, tail) = rest.split_at(len);
+            rest = tail;
+            (node, head)
+        })
+    }
+
+    /// The index of the first position of `node` in `joint_positions()`
+    pub(crate) fn position_offset(&self, node: &Node<T>) -> Option<usize> {
+        let mut offset = 0;
+        for movable in &self.movable_nodes {
+            if movable == node {
+                return Some(offset);
+            }
+            offset += movable.joint().num_positions();
+        }
+        None
+    }
+
     /// Iterate for links
-    pub fn iter_links(&self) -> impl Iterator<Item = LinkRefGuard<T>> {
+    pub fn iter_links(&self) -> impl Iterator<Item = LinkRefGuard<'_, T>> {
         self.nodes.iter().filter_map(|node| {
             if node.link().is_some() {
                 Some(LinkRefGuard { guard: node.lock() })
@@ -330,22 +479,95 @@ impl<T: RealField + SubsetOf<f64>> Chain<T> {
     pub fn find(&self, joint_name: &str) -> Option<&Node<T>> {
         self.iter().find(|joint| joint.joint().name == joint_name)
     }
+    /// Change the type of the joint at the current pose, and update the DoF
+    ///
+    /// See `Node::convert_to()`. It is useful to fix a joint at the current angle for
+    /// model reduction, or to free a fixed joint by giving it an axis.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let mut chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// chain.set_joint_positions(&[0.2; 12]).unwrap();
+    /// chain.update_transforms();
+    /// let end = chain.find("l_wrist_pitch").unwrap().world_transform().unwrap();
+    /// chain.convert_joint("l_elbow_pitch", JointType::Fixed).unwrap();
+    /// assert_eq!(chain.dof(), 11);
+    /// chain.update_transforms();
+    /// let converted = chain.find("l_wrist_pitch").unwrap().world_transform().unwrap();
+    /// assert!((converted.translation.vector - end.translation.vector).norm() < 1e-10);
+    /// ```
+    pub fn convert_joint(
+        &mut self,
+        joint_name: &str,
+        joint_type: JointType<T>,
+    ) -> Result<(), Error> {
+        let node = self
+            .find(joint_name)
+            .ok_or_else(|| Error::JointNotFoundError {
+                joint_name: joint_name.to_owned(),
+            })?
+            .clone();
+        node.convert_to(joint_type);
+        self.update_movable_nodes();
+        Ok(())
+    }
+
+    /// Update the movable joints and the DoF after changing the types of the joints
+    pub fn update_movable_nodes(&mut self) {
+        self.movable_nodes = collect_movable_nodes(&self.nodes);
+        self.dof = num_positions(&self.movable_nodes);
+    }
+
     /// Get the positions of the joints
     ///
-    /// `FixedJoint` is ignored. the length is the same with `dof()`
+    /// `FixedJoint` is ignored. the length is the same with `dof()`.
+    /// It is the concatenation of `Joint::joint_positions()` of the movable joints.
     pub fn joint_positions(&self) -> Vec<T> {
         self.iter_joints()
-            .map(|joint| {
-                joint
-                    .joint_position()
-                    .expect("Must be a bug: movable joint must have position")
-            })
+            .flat_map(|joint| joint.joint_positions().to_vec())
             .collect()
     }
 
+    /// Copy `joint_positions()` into `positions` without allocating a vector
+    pub(crate) fn copy_joint_positions(&self, positions: &mut [T]) {
+        let mut index = 0;
+        for joint in self.iter_joints() {
+            for position in joint.joint_positions() {
+                positions[index] = *position;
+                index += 1;
+            }
+        }
+    }
+
     /// Set the positions of the joints
     ///
-    /// `FixedJoints` are ignored. the input number must be equal with `dof()`
+    /// `FixedJoints` are ignored. the input number must be equal with `dof()`.
+    /// The limits of the joints are handled by `limit_policy()`, after the positions
+    /// slightly out of the limits are clamped by `tolerances().joint_limit`.
+    ///
+    /// It is transactional: if it returns `Err`, the positions of the joints are not changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let l0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// let l1 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .limits(Some((-1.0..=1.0).into()))
+    ///     .into_node();
+    /// l1.set_parent(&l0);
+    /// let chain = Chain::<f64>::from_root(l0);
+    /// assert!(chain.set_joint_positions(&[0.5, 2.0]).is_err());
+    /// // the first joint is not modified
+    /// assert_eq!(chain.joint_positions(), vec![0.0, 0.0]);
+    /// ```
     pub fn set_joint_positions(&self, positions_vec: &[T]) -> Result<(), Error> {
         if positions_vec.len() != self.dof {
             return Err(Error::SizeMismatchError {
@@ -353,39 +575,573 @@ impl<T: RealField + SubsetOf<f64>> Chain<T> {
                 required: self.dof,
             });
         }
-        for (joint, position) in self.movable_nodes.iter().zip(positions_vec.iter()) {
-            joint.set_joint_position(*position)?;
+        let orig_positions = self.joint_positions();
+        let orig_commanded = self
+            .movable_nodes
+            .iter()
+            .map(|node| node.lock().commanded_position)
+            .collect::<Vec<_>>();
+        let snapped_positions = self
+            .iter_position_nodes()
+            .zip(positions_vec)
+            .map(|(node, position)| match node.joint().effective_limits() {
+                Some(range) => self
+                    .tolerances
+                    .snap_to_limits(*position, range.min, range.max),
+                None => *position,
+            })
+            .collect::<Vec<_>>();
+        for (joint, positions) in self.iter_movable_positions(&snapped_positions) {
+            if let Err(err) = joint.set_joint_positions(positions) {
+                self.restore_joint_positions(&orig_positions, &orig_commanded);
+                return Err(err);
+            }
+        }
+        let policy = self.limit_policy();
+        if self.coupled_limits.is_empty() || policy == LimitPolicy::Ignore {
+            return Ok(());
+        }
+        let mut positions = self.joint_positions();
+        if policy == LimitPolicy::Clamp {
+            if self.project_to_coupled_limits(&mut positions) {
+                self.set_joint_positions_clamped_per_joint(&positions);
+            }
+            return Ok(());
+        }
+        for (limit, indices) in self.coupled_limits_with_indices() {
+            let group = indices.iter().map(|i| positions[*i]).collect::<Vec<_>>();
+            let violation = limit.violation(&group);
+            if violation > self.tolerances.coupled_limit {
+                self.restore_joint_positions(&orig_positions, &orig_commanded);
+                return Err(Error::CoupledLimitError {
+                    joint_names: limit.joint_names().to_vec(),
+                    violation: na::convert(violation),
+                });
+            }
         }
         Ok(())
     }
 
+    /// Get the positions of the joints converted by `Joint::units`
+    ///
+    /// The joints without the units are in radians or meters.
+    pub fn joint_positions_in_units(&self) -> Vec<T> {
+        self.iter_joints()
+            .flat_map(|joint| {
+                joint
+                    .joint_positions()
+                    .iter()
+                    .map(|position| match joint.units {
+                        Some(ref units) => units.to_hardware(*position),
+                        None => *position,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Set the positions of the joints from the values in `Joint::units`, e.g. encoder counts
+    ///
+    /// The values are converted, and set by `set_joint_positions()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use k::joint::JointUnits;
+    ///
+    /// let l0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .units(Some(JointUnits::new("counts", 1000.0, 2048.0).unwrap()))
+    ///     .into_node();
+    /// let l1 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// l1.set_parent(&l0);
+    /// let chain = Chain::<f64>::from_root(l0);
+    /// chain.set_joint_positions_in_units(&[2548.0, 0.3]).unwrap();
+    /// assert_eq!(chain.joint_positions(), vec![0.5, 0.3]);
+    /// assert_eq!(chain.joint_positions_in_units(), vec![2548.0, 0.3]);
+    /// ```
+    pub fn set_joint_positions_in_units(&self, values: &[T]) -> Result<(), Error> {
+        if values.len() != self.dof {
+            return Err(Error::SizeMismatchError {
+                input: values.len(),
+                required: self.dof,
+            });
+        }
+        let positions = self
+            .iter_position_nodes()
+            .zip(values)
+            .map(|(node, value)| node.joint().position_from_units(*value))
+            .collect::<Vec<_>>();
+        self.set_joint_positions(&positions)
+    }
+
+    /// Set how the positions out of the limits are handled
+    ///
+    /// The policy is set to `Joint::limit_policy` of all the joints, so it is applied by
+    /// `set_joint_positions()` of the chain and by `Node::set_joint_position()` and
+    /// `Node::set_joint_positions()` of each node. The clamped and unchecked setters,
+    /// e.g. `set_joint_positions_clamped()`, explicitly override it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use k::joint::LimitPolicy;
+    ///
+    /// let l0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .limits(Some((-1.0..=1.0).into()))
+    ///     .into_node();
+    /// let chain = Chain::<f64>::from_root(l0.clone());
+    /// assert_eq!(chain.limit_policy(), LimitPolicy::Error);
+    /// assert!(chain.set_joint_positions(&[2.0]).is_err());
+    ///
+    /// chain.set_limit_policy(LimitPolicy::Clamp);
+    /// chain.set_joint_positions(&[2.0]).unwrap();
+    /// assert_eq!(chain.joint_positions(), vec![1.0]);
+    /// l0.set_joint_position(-2.0).unwrap();
+    /// assert_eq!(l0.joint_position(), Some(-1.0));
+    /// ```
+    pub fn set_limit_policy(&self, policy: LimitPolicy) {
+        for node in self.iter() {
+            node.lock().joint.limit_policy = policy;
+        }
+    }
+
+    /// Get how the positions out of the limits are handled
+    ///
+    /// It is the policy of the first movable joint, or the default if there are none.
+    pub fn limit_policy(&self) -> LimitPolicy {
+        self.movable_nodes
+            .first()
+            .map(|node| node.joint().limit_policy)
+            .unwrap_or_default()
+    }
+
+    /// Set the numeric tolerances
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let l0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .limits(Some((-1.0..=1.0).into()))
+    ///     .into_node();
+    /// let mut chain = Chain::<f64>::from_root(l0);
+    /// assert!(chain.set_joint_positions(&[1.001]).is_err());
+    ///
+    /// chain.set_tolerances(Tolerances {
+    ///     joint_limit: 0.01,
+    ///     ..Default::default()
+    /// });
+    /// chain.set_joint_positions(&[1.001]).unwrap();
+    /// assert_eq!(chain.joint_positions(), vec![1.0]);
+    /// ```
+    pub fn set_tolerances(&mut self, tolerances: Tolerances<T>) {
+        self.tolerances = tolerances;
+    }
+
+    /// Get the numeric tolerances
+    pub fn tolerances(&self) -> &Tolerances<T> {
+        &self.tolerances
+    }
+
+    /// Calculate the degree of freedom of the independently actuated joints
+    ///
+    /// Joints which mimic other joints are not counted.
+    pub fn actuated_dof(&self) -> usize {
+        self.movable_nodes
+            .iter()
+            .filter(|node| node.mimic_parent().is_none())
+            .map(|node| node.joint().num_positions())
+            .sum()
+    }
+
+    /// Get the positions of the independently actuated joints
+    ///
+    /// This is `joint_positions()` without the joints which mimic other joints.
+    /// The order is kept, so the i-th value of the actuated space is the
+    /// i-th joint of `iter_joints()` which does not have a mimic parent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Linear{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .joint_type(JointType::Linear{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// let j2 = NodeBuilder::new()
+    ///     .joint_type(JointType::Linear{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// connect![j0 => j1 => j2];
+    /// j1.set_mimic_parent(&j0, k::joint::Mimic::new(2.0, 0.1));
+    /// let chain = Chain::<f64>::from_root(j0);
+    /// assert_eq!(chain.dof(), 3);
+    /// assert_eq!(chain.actuated_dof(), 2);
+    ///
+    /// chain.set_joint_positions(&[0.5, 0.0, 0.3]).unwrap();
+    /// assert_eq!(chain.joint_positions(), vec![0.5, 1.1, 0.3]);
+    /// assert_eq!(chain.actuated_positions(), vec![0.5, 0.3]);
+    /// assert_eq!(chain.expanded_positions(&[0.5, 0.3]).unwrap(), vec![0.5, 1.1, 0.3]);
+    /// ```
+    pub fn actuated_positions(&self) -> Vec<T> {
+        self.movable_nodes
+            .iter()
+            .filter(|node| node.mimic_parent().is_none())
+            .flat_map(|node| node.joint_positions())
+            .collect()
+    }
+
+    /// Map the positions of the actuated space into the positions of all the movable joints
+    ///
+    /// The input length must be equal with `actuated_dof()`, and the output length is `dof()`.
+    /// The positions of the mimic joints are calculated from their mimic parents.
+    /// If the mimic parent is not in this chain, its current position is used.
+    pub fn expanded_positions(&self, actuated_positions: &[T]) -> Result<Vec<T>, Error> {
+        let actuated_dof = self.actuated_dof();
+        if actuated_positions.len() != actuated_dof {
+            return Err(Error::SizeMismatchError {
+                input: actuated_positions.len(),
+                required: actuated_dof,
+            });
+        }
+        let mut actuated_iter = actuated_positions.iter();
+        let mut positions = self
+            .iter_position_nodes()
+            .map(|node| match node.mimic_parent() {
+                None => actuated_iter.next().cloned(),
+                Some(_) => None,
+            })
+            .collect::<Vec<_>>();
+        for i in 0..positions.len() {
+            if positions[i].is_none() {
+                positions[i] = Some(self.mimic_position_at(i, &positions)?);
+            }
+        }
+        Ok(positions
+            .into_iter()
+            .map(|p| p.expect("all positions are resolved"))
+            .collect())
+    }
+
+    /// Set the positions of the independently actuated joints
+    ///
+    /// The mimic joints are updated by their mimic parents.
+    pub fn set_actuated_positions(&self, actuated_positions: &[T]) -> Result<(), Error> {
+        self.set_joint_positions(&self.expanded_positions(actuated_positions)?)
+    }
+
+    /// Resolve the position of the mimic joint at `index` of `joint_positions()`
+    fn mimic_position_at(&self, index: usize, positions: &[Option<T>]) -> Result<T, Error> {
+        let node = self
+            .iter_position_nodes()
+            .nth(index)
+            .expect("index must be less than dof");
+        let mimic_parent = node.mimic_parent().expect("must have mimic parent");
+        let mimic_error = || Error::MimicError {
+            from: mimic_parent.joint().name.to_owned(),
+            to: node.joint().name.to_owned(),
+        };
+        let mimic = node.lock().mimic.clone();
+        let mimic = mimic.ok_or_else(mimic_error)?;
+        if node.joint().num_positions() != 1 || mimic_parent.joint().num_positions() != 1 {
+            return Err(mimic_error());
+        }
+        let parent_position = match self.position_offset(&mimic_parent) {
+            Some(parent_index) => match positions[parent_index] {
+                Some(position) => position,
+                None => self.mimic_position_at(parent_index, positions)?,
+            },
+            None => mimic_parent.joint_position().ok_or_else(mimic_error)?,
+        };
+        Ok(mimic.mimic_position(parent_position))
+    }
+
     /// Set the clamped positions of the joints
     ///
     /// This function is safe, in contrast to `set_joint_positions_unchecked`.
+    ///
+    /// The positions are also moved into the coupled limits added by `add_coupled_limit()`,
+    /// so the IK solvers respect them.
     pub fn set_joint_positions_clamped(&self, positions_vec: &[T]) {
-        for (joint, position) in self.movable_nodes.iter().zip(positions_vec.iter()) {
-            joint.set_joint_position_clamped(*position);
+        self.set_joint_positions_clamped_per_joint(positions_vec);
+        if self.coupled_limits.is_empty() {
+            return;
+        }
+        let mut positions = self.joint_positions();
+        if self.project_to_coupled_limits(&mut positions) {
+            self.set_joint_positions_clamped_per_joint(&positions);
+        }
+    }
+
+    /// Roll back `set_joint_positions()`, including the commanded positions of the nodes
+    fn restore_joint_positions(&self, positions: &[T], commanded: &[Option<T>]) {
+        self.set_joint_positions_unchecked(positions);
+        for (node, commanded) in self.movable_nodes.iter().zip(commanded) {
+            node.lock().commanded_position = *commanded;
+        }
+    }
+
+    fn set_joint_positions_clamped_per_joint(&self, positions_vec: &[T]) {
+        for (joint, positions) in self.iter_movable_positions(positions_vec) {
+            joint.set_joint_positions_clamped(positions);
         }
     }
 
+    /// Pairs of the coupled limits and the indices of their joints in `joint_positions()`
+    ///
+    /// The limits whose joints are not movable in this chain any more are skipped.
+    fn coupled_limits_with_indices(&self) -> Vec<(&CoupledLimit<T>, Vec<usize>)> {
+        self.coupled_limits
+            .iter()
+            .filter_map(|limit| {
+                limit
+                    .joint_names()
+                    .iter()
+                    .map(|name| {
+                        let node = self
+                            .movable_nodes
+                            .iter()
+                            .find(|node| node.joint().name == *name)?;
+                        self.position_offset(node)
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .map(|indices| (limit, indices))
+            })
+            .collect()
+    }
+
+    /// Project `positions` into the coupled limits, returns true if modified
+    ///
+    /// The limits sharing joints can be violated again by the projection to the others,
+    /// so the projections are repeated until all of them are satisfied within the tolerance.
+    fn project_to_coupled_limits(&self, positions: &mut [T]) -> bool {
+        const MAX_ROUNDS: usize = 100;
+        let limits = self.coupled_limits_with_indices();
+        let mut modified = false;
+        for _ in 0..MAX_ROUNDS {
+            let mut satisfied = true;
+            for (limit, indices) in &limits {
+                let mut group = indices.iter().map(|i| positions[*i]).collect::<Vec<_>>();
+                if limit.violation(&group) <= T::zero() {
+                    continue;
+                }
+                limit.project(&mut group);
+                for (i, position) in indices.iter().zip(group) {
+                    positions[*i] = position;
+                }
+                modified = true;
+            }
+            for (limit, indices) in &limits {
+                let group = indices.iter().map(|i| positions[*i]).collect::<Vec<_>>();
+                if limit.violation(&group) > self.tolerances.coupled_limit {
+                    satisfied = false;
+                }
+            }
+            if satisfied {
+                break;
+            }
+        }
+        modified
+    }
+
     /// Fast, but without check, dangerous `set_joint_positions`
     #[inline]
     pub fn set_joint_positions_unchecked(&self, positions_vec: &[T]) {
-        for (joint, position) in self.movable_nodes.iter().zip(positions_vec.iter()) {
-            joint.set_joint_position_unchecked(*position);
+        for (joint, positions) in self.iter_movable_positions(positions_vec) {
+            joint.set_joint_positions_unchecked(positions);
         }
     }
 
     /// Update world_transform() of the joints
+    ///
+    /// The nodes are updated in the order of `update_order()`, the parent first.
+    /// The hooks added by `add_pre_update_hook()` and `add_post_update_hook()` are
+    /// called before and after the update of each node.
+    ///
+    /// If a profiler is set by `set_profiler()`, the time of each node is recorded
+    /// with the joint name.
+    ///
+    /// The transforms of the nodes marked by `Node::set_output_suppressed()` are
+    /// updated, but not contained in the returned vector.
+    ///
+    /// The poses are published to the sink set by `set_pose_sink()`.
     pub fn update_transforms(&self) -> Vec<Isometry3<T>> {
-        self.iter()
-            .map(|node| {
-                let parent_transform = node.parent_world_transform().expect("cache must exist");
-                let trans = parent_transform * node.joint().local_transform();
-                node.joint().set_world_transform(trans);
-                trans
-            })
-            .collect()
+        let transforms = self.update_transforms_internal(false);
+        if self.update_hooks.has_sink() {
+            let names = self
+                .iter()
+                .filter(|node| !node.is_output_suppressed())
+                .map(|node| node.joint().name.clone())
+                .collect::<Vec<_>>();
+            self.update_hooks.publish(&names, &transforms);
+        }
+        transforms
+    }
+
+    /// Update `world_transform()` of the nodes only, without the hooks, the profiler and
+    /// the pose sink of `update_transforms()`
+    ///
+    /// It doesn't allocate, so it is used in the allocation-free IK solve.
+    pub(crate) fn update_world_transforms(&self) {
+        for node in self.iter() {
+            let parent_transform = node.parent_world_transform().expect("cache must exist");
+            let trans = parent_transform * node.joint().local_transform();
+            node.joint().set_world_transform(trans);
+        }
+    }
+
+    /// Same as `update_transforms()`, but returns the transforms of all the nodes
+    /// in the order of `iter()`
+    pub(crate) fn update_all_transforms(&self) -> Vec<Isometry3<T>> {
+        self.update_transforms_internal(true)
+    }
+
+    fn update_transforms_internal(&self, include_suppressed: bool) -> Vec<Isometry3<T>> {
+        let update = |node: &Node<T>| {
+            self.update_hooks.call_pre(node);
+            let parent_transform = node.parent_world_transform().expect("cache must exist");
+            let trans = parent_transform * node.joint().local_transform();
+            node.joint().set_world_transform(trans);
+            self.update_hooks.call_post(node);
+            trans
+        };
+        let update = |node: &Node<T>| {
+            let trans = match self.profiler {
+                Some(ref profiler) => {
+                    let name = node.joint().name.clone();
+                    profiler.measure(&name, None, || update(node))
+                }
+                None => update(node),
+            };
+            if include_suppressed || !node.is_output_suppressed() {
+                Some(trans)
+            } else {
+                None
+            }
+        };
+        self.iter().filter_map(update).collect()
+    }
+
+    /// Set the profiler to record the time of `update_transforms()`, `None` to disable it
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    /// Get the profiler set by `set_profiler()`
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// The name, version and other information of the model
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// assert_eq!(chain.metadata().name, "robo");
+    /// chain.metadata_mut().version = Some(k::ModelVersion::new(0, 1, 0));
+    /// assert_eq!(chain.clone().metadata().version, Some(k::ModelVersion::new(0, 1, 0)));
+    /// ```
+    pub fn metadata(&self) -> &ChainMetadata {
+        &self.metadata
+    }
+
+    /// Mutable access to `metadata()`
+    pub fn metadata_mut(&mut self) -> &mut ChainMetadata {
+        &mut self.metadata
+    }
+
+    /// Set the metadata
+    pub fn set_metadata(&mut self, metadata: ChainMetadata) {
+        self.metadata = metadata;
+    }
+
+    /// Add a limit over a group of joints
+    ///
+    /// `set_joint_positions()` returns `Error::CoupledLimitError` if the positions violate it
+    /// (or moves them into it if the limit policy is `LimitPolicy::Clamp`), and
+    /// `set_joint_positions_clamped()` moves the positions into it.
+    /// Returns `Error::JointNotFoundError` if a joint is not a movable joint of this chain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let pitch = NodeBuilder::new()
+    ///     .name("pitch")
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .limits(Some((-2.0..=2.0).into()))
+    ///     .into_node();
+    /// let roll = NodeBuilder::new()
+    ///     .name("roll")
+    ///     .joint_type(JointType::Rotational{axis: Vector3::x_axis()})
+    ///     .limits(Some((-2.0..=2.0).into()))
+    ///     .into_node();
+    /// roll.set_parent(&pitch);
+    /// let mut chain = Chain::<f64>::from_root(pitch);
+    /// chain
+    ///     .add_coupled_limit(
+    ///         CoupledLimit::ellipsoid(
+    ///             vec!["pitch".to_owned(), "roll".to_owned()],
+    ///             vec![0.0, 0.0],
+    ///             vec![2.0, 1.0],
+    ///         )
+    ///         .unwrap(),
+    ///     )
+    ///     .unwrap();
+    /// // each position is in its own limits, but the pair is not in the ellipsoid
+    /// assert!(chain.set_joint_positions(&[1.5, 1.0]).is_err());
+    /// assert_eq!(chain.joint_positions(), vec![0.0, 0.0]);
+    ///
+    /// chain.set_joint_positions_clamped(&[0.0, 1.5]);
+    /// assert!((chain.joint_positions()[1] - 1.0).abs() < 1e-9);
+    /// ```
+    pub fn add_coupled_limit(&mut self, limit: CoupledLimit<T>) -> Result<(), Error> {
+        for name in limit.joint_names() {
+            let node = self
+                .movable_nodes
+                .iter()
+                .find(|node| node.joint().name == *name)
+                .ok_or_else(|| Error::JointNotFoundError {
+                    joint_name: name.clone(),
+                })?;
+            if node.joint().num_positions() != 1 {
+                return Err(Error::InvalidParameterError {
+                    reason: format!("coupled limit of {} which has several positions", name),
+                });
+            }
+        }
+        self.coupled_limits.push(limit);
+        Ok(())
+    }
+
+    /// The limits added by `add_coupled_limit()`
+    pub fn coupled_limits(&self) -> &[CoupledLimit<T>] {
+        &self.coupled_limits
+    }
+
+    /// Remove all the limits added by `add_coupled_limit()`
+    pub fn clear_coupled_limits(&mut self) {
+        self.coupled_limits.clear();
+    }
+
+    pub(crate) fn update_hooks(&self) -> &UpdateHooks<T> {
+        &self.update_hooks
+    }
+
+    pub(crate) fn update_hooks_mut(&mut self) -> &mut UpdateHooks<T> {
+        &mut self.update_hooks
     }
 
     /// Update world_velocity() of the joints
@@ -399,38 +1155,29 @@ impl<T: RealField + SubsetOf<f64>> Chain<T> {
                 let parent_velocity = node
                     .parent_world_velocity()
                     .expect("velocity cache must exist");
-                let velocity = match node.joint().joint_type {
-                    JointType::Fixed => parent_velocity,
-                    JointType::Rotational { axis } => {
-                        let parent = node.parent().expect("parent must exist");
-                        let parent_vel = parent.joint().origin().translation.vector;
-                        Velocity::from_parts(
-                            parent_velocity.translation
-                                + parent_velocity.rotation.cross(
-                                    &(parent_transform.rotation.to_rotation_matrix() * parent_vel),
-                                ),
-                            parent_velocity.rotation
-                                + node
-                                    .world_transform()
-                                    .expect("cache must exist")
-                                    .rotation
-                                    .to_rotation_matrix()
-                                    * (axis.into_inner() * node.joint().joint_velocity().unwrap()),
-                        )
-                    }
-                    JointType::Linear { axis } => Velocity::from_parts(
+                let joint = node.joint();
+                let velocity = if joint.is_movable() {
+                    let parent = node.parent().expect("parent must exist");
+                    let parent_vel = parent.joint().origin().translation.vector;
+                    let mut velocity = Velocity::from_parts(
                         parent_velocity.translation
-                            + node
-                                .world_transform()
-                                .expect("cache must exist")
-                                .rotation
-                                .to_rotation_matrix()
-                                * (axis.into_inner() * node.joint().joint_velocity().unwrap()),
-                        // TODO: Is this true??
+                            + parent_velocity.rotation.cross(
+                                &(parent_transform.rotation.to_rotation_matrix() * parent_vel),
+                            ),
                         parent_velocity.rotation,
-                    ),
+                    );
+                    let transform = joint.world_transform().expect("cache must exist");
+                    for (i, joint_velocity) in joint.joint_velocities().iter().enumerate() {
+                        let motion =
+                            joint.world_motion(&transform, i, &transform.translation.vector);
+                        velocity.translation += motion.translation * *joint_velocity;
+                        velocity.rotation += motion.rotation * *joint_velocity;
+                    }
+                    velocity
+                } else {
+                    parent_velocity
                 };
-                node.joint().set_world_velocity(velocity);
+                joint.set_world_velocity(velocity);
                 velocity
             })
             .collect()
@@ -469,6 +1216,11 @@ where
                 nodes: vec![],
                 movable_nodes: vec![],
                 dof: 0,
+                tolerances: self.tolerances,
+                profiler: self.profiler.clone(),
+                update_hooks: self.update_hooks.clone(),
+                metadata: self.metadata.clone(),
+                coupled_limits: self.coupled_limits.clone(),
             };
         }
         assert!(self.nodes[0].is_root());
@@ -479,6 +1231,8 @@ where
             .map(|n| {
                 let node = Node::new(n.joint().clone());
                 node.set_link(n.link().clone());
+                node.set_output_suppressed(n.is_output_suppressed());
+                node.lock().commanded_position = n.lock().commanded_position;
                 node
             })
             .collect::<Vec<_>>();
@@ -500,18 +1254,63 @@ where
         //
         // first node must be root
         assert!(new_nodes[0].is_root());
-        Chain::from_root(new_nodes.remove(0))
+        let mut chain = Chain::from_root(new_nodes.remove(0));
+        chain.tolerances = self.tolerances;
+        chain.profiler = self.profiler.clone();
+        chain.update_hooks = self.update_hooks.clone();
+        chain.metadata = self.metadata.clone();
+        chain.coupled_limits = self.coupled_limits.clone();
+        chain
     }
 }
 
+/// The length of the positions of the chain which has `movable_nodes`
+fn num_positions<T>(movable_nodes: &[Node<T>]) -> usize
+where
+    T: RealField + SubsetOf<f64>,
+{
+    movable_nodes
+        .iter()
+        .map(|node| node.joint().num_positions())
+        .sum()
+}
+
+fn collect_movable_nodes<T>(nodes: &[Node<T>]) -> Vec<Node<T>>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    nodes
+        .iter()
+        .filter(|joint| joint.joint().is_movable())
+        .cloned()
+        .collect()
+}
+
 #[derive(Debug)]
 /// Kinematic chain without any branch.
 ///
 /// All joints are connected sequentially.
 pub struct SerialChain<T: RealField> {
     inner: Chain<T>,
+    end_effector: Option<EndEffector<T>>,
+}
+
+/// Named frame of the tool center point, fixed to the last node of a `SerialChain`
+///
+/// It is set by `SerialChain::set_end_effector()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndEffector<T: RealField> {
+    /// name of the frame
+    pub name: String,
+    /// transform from the frame of the last node (flange) to the tool center point
+    pub transform: Isometry3<T>,
 }
 
+/// `SerialChain` of `f32`
+pub type SerialChainF32 = SerialChain<f32>;
+/// `SerialChain` of `f64`
+pub type SerialChainF64 = SerialChain<f64>;
+
 impl<T> SerialChain<T>
 where
     T: RealField + SubsetOf<f64>,
@@ -521,7 +1320,10 @@ where
     /// If the input Chain has any branches it causes serious bugs.
     ///
     pub fn new_unchecked(inner: Chain<T>) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            end_effector: None,
+        }
     }
     /// Convert Chain into SerialChain
     ///
@@ -553,7 +1355,7 @@ where
                 }
             }
         }
-        Some(Self { inner })
+        Some(Self::new_unchecked(inner))
     }
     /// Create SerialChain from the end `Node`
     ///
@@ -564,9 +1366,7 @@ where
     /// let s_chain = k::SerialChain::from_end(&node);
     /// ```
     pub fn from_end(end_joint: &Node<T>) -> SerialChain<T> {
-        SerialChain {
-            inner: Chain::from_end(end_joint),
-        }
+        SerialChain::new_unchecked(Chain::from_end(end_joint))
     }
 
     /// Create SerialChain from the end `Node` and root `Node`.
@@ -584,21 +1384,226 @@ where
     /// assert_eq!(s_chain.iter().count(), 2);
     /// ```
     pub fn from_end_to_root(end_joint: &Node<T>, root_joint: &Node<T>) -> SerialChain<T> {
-        SerialChain {
-            inner: Chain::from_end_to_root(end_joint, root_joint),
-        }
+        SerialChain::new_unchecked(Chain::from_end_to_root(end_joint, root_joint))
     }
 
     /// Safely unwrap and returns inner `Chain` instance
     pub fn unwrap(self) -> Chain<T> {
         self.inner
     }
-    /// Calculate transform of the end joint
+    /// Set how the positions out of the limits are handled
+    ///
+    /// See `Chain::set_limit_policy()`.
+    pub fn set_limit_policy(&self, policy: LimitPolicy) {
+        self.inner.set_limit_policy(policy);
+    }
+    /// Set the numeric tolerances
+    ///
+    /// See `Chain::set_tolerances()`.
+    pub fn set_tolerances(&mut self, tolerances: Tolerances<T>) {
+        self.inner.set_tolerances(tolerances);
+    }
+    /// Add a limit over a group of joints
+    ///
+    /// See `Chain::add_coupled_limit()`.
+    pub fn add_coupled_limit(&mut self, limit: CoupledLimit<T>) -> Result<(), Error> {
+        self.inner.add_coupled_limit(limit)
+    }
+    /// Remove all the coupled limits
+    ///
+    /// See `Chain::clear_coupled_limits()`.
+    pub fn clear_coupled_limits(&mut self) {
+        self.inner.clear_coupled_limits();
+    }
+    /// Change the type of the joint at the current pose, and update the DoF
+    ///
+    /// See `Chain::convert_joint()`.
+    pub fn convert_joint(
+        &mut self,
+        joint_name: &str,
+        joint_type: JointType<T>,
+    ) -> Result<(), Error> {
+        self.inner.convert_joint(joint_name, joint_type)
+    }
+    /// Set the profiler to record the time of `update_transforms()`
+    ///
+    /// See `Chain::set_profiler()`.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.inner.set_profiler(profiler);
+    }
+    /// Add a function called before the update of the joint by `update_transforms()`
+    ///
+    /// See `Chain::add_pre_update_hook()`.
+    pub fn add_pre_update_hook<F>(&mut self, joint_name: &str, hook: F) -> Result<(), Error>
+    where
+        F: Fn(&Node<T>) + Send + Sync + 'static,
+    {
+        self.inner.add_pre_update_hook(joint_name, hook)
+    }
+    /// Add a function called after the update of the joint by `update_transforms()`
+    ///
+    /// See `Chain::add_post_update_hook()`.
+    pub fn add_post_update_hook<F>(&mut self, joint_name: &str, hook: F) -> Result<(), Error>
+    where
+        F: Fn(&Node<T>) + Send + Sync + 'static,
+    {
+        self.inner.add_post_update_hook(joint_name, hook)
+    }
+    /// Remove all the hooks of `update_transforms()`
+    pub fn clear_update_hooks(&mut self) {
+        self.inner.clear_update_hooks();
+    }
+    /// Publish the poses calculated by `update_transforms()` to `sink`
+    ///
+    /// See `Chain::set_pose_sink()`.
+    pub fn set_pose_sink(&mut self, sink: Option<Arc<dyn PoseSink<T>>>) {
+        self.inner.set_pose_sink(sink);
+    }
+    /// Calculate transform of the end effector
+    ///
+    /// It is the transform of the end joint (flange) multiplied by the transform of the
+    /// end effector if it is set by `set_end_effector()`. The solvers and the jacobian
+    /// use it as the end of the arm.
     pub fn end_transform(&self) -> Isometry3<T> {
+        match self.end_effector {
+            Some(ref end_effector) => self.flange_transform() * end_effector.transform,
+            None => self.flange_transform(),
+        }
+    }
+
+    /// Calculate transform of the end joint, without the end effector
+    pub fn flange_transform(&self) -> Isometry3<T> {
         self.iter().fold(Isometry3::identity(), |trans, joint| {
             trans * joint.joint().local_transform()
         })
     }
+
+    /// Set the named tool center point at `transform` from the end joint
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let mut arm = SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// assert_eq!(arm.end_effector_name(), "l_wrist_pitch");
+    ///
+    /// arm.set_end_effector("l_gripper_tcp", Isometry3::translation(0.0, 0.0, -0.15));
+    /// assert_eq!(arm.end_effector_name(), "l_gripper_tcp");
+    /// let tcp = arm.flange_transform() * Isometry3::translation(0.0, 0.0, -0.15);
+    /// assert_eq!(arm.end_transform(), tcp);
+    /// assert_eq!(arm.frame_transform("l_gripper_tcp"), Some(tcp));
+    /// assert_eq!(arm.frame_transform("l_wrist_pitch"), Some(arm.flange_transform()));
+    ///
+    /// arm.clear_end_effector();
+    /// assert_eq!(arm.end_transform(), arm.flange_transform());
+    /// ```
+    pub fn set_end_effector(&mut self, name: &str, transform: Isometry3<T>) {
+        self.end_effector = Some(EndEffector {
+            name: name.to_owned(),
+            transform,
+        });
+    }
+
+    /// Remove the end effector, the end joint becomes the end
+    pub fn clear_end_effector(&mut self) {
+        self.end_effector = None;
+    }
+
+    /// Get the end effector set by `set_end_effector()`
+    pub fn end_effector(&self) -> Option<&EndEffector<T>> {
+        self.end_effector.as_ref()
+    }
+
+    /// Name of the end, the end effector or the end joint if it is not set
+    pub fn end_effector_name(&self) -> String {
+        match self.end_effector {
+            Some(ref end_effector) => end_effector.name.clone(),
+            None => self
+                .iter()
+                .last()
+                .map(|node| node.joint().name.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Calculate the transform of the named frame, the end effector or a joint, from the
+    /// root of the chain at the current positions
+    ///
+    /// It returns `None` if no frame has the name.
+    pub fn frame_transform(&self, name: &str) -> Option<Isometry3<T>> {
+        if let Some(ref end_effector) = self.end_effector {
+            if end_effector.name == name {
+                return Some(self.end_transform());
+            }
+        }
+        let mut trans = Isometry3::identity();
+        for node in self.iter() {
+            let joint = node.joint();
+            trans *= joint.local_transform();
+            if joint.name == name {
+                return Some(trans);
+            }
+        }
+        None
+    }
+
+    /// Calculate the lengths of the links, the distances between the origins of the joints
+    ///
+    /// The i-th value is the length of the translation of the origin of the (i + 1)-th node,
+    /// so the length is `iter().count() - 1`. The extension of the prismatic joints is
+    /// not included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .translation(Translation3::new(0.0, 0.3, 0.4))
+    ///     .joint_type(JointType::Linear{axis: Vector3::z_axis()})
+    ///     .limits(Some((-0.1..=0.2).into()))
+    ///     .into_node();
+    /// let j2 = NodeBuilder::new()
+    ///     .translation(Translation3::new(0.0, 0.0, 1.0))
+    ///     .into_node();
+    /// connect![j0 => j1 => j2];
+    /// let arm = SerialChain::<f64>::from_end(&j2);
+    /// assert_eq!(arm.link_lengths(), vec![0.5, 1.0]);
+    /// assert_eq!(arm.max_reach(), Some(1.7));
+    /// ```
+    pub fn link_lengths(&self) -> Vec<T> {
+        self.iter()
+            .skip(1)
+            .map(|node| node.joint().origin().translation.vector.norm())
+            .collect()
+    }
+
+    /// Calculate the upper bound of the distance from the first joint to the end
+    ///
+    /// It is the sum of `link_lengths()` and the maximum extensions of the prismatic joints.
+    /// If any prismatic joint has no limits, it returns `None`.
+    pub fn max_reach(&self) -> Option<T> {
+        let mut reach = self
+            .link_lengths()
+            .into_iter()
+            .fold(T::zero(), |a, b| a + b);
+        for joint in self.iter_joints() {
+            for i in 0..joint.num_positions() {
+                // the positions which translate the joint
+                let motion = joint.joint_type.motion(joint.joint_positions(), i);
+                if motion.translation != na::Vector3::zeros() {
+                    let range = joint.limits?;
+                    reach += range.min.abs().max(range.max.abs());
+                }
+            }
+        }
+        Some(reach)
+    }
 }
 
 impl<T> Clone for SerialChain<T>
@@ -608,6 +1613,7 @@ where
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            end_effector: self.end_effector.clone(),
         }
     }
 }
@@ -774,3 +1780,71 @@ fn test_mimic() {
     assert_eq!(positions[1], 0.2);
     assert_eq!(positions[2], 0.9);
 }
+
+#[test]
+fn test_overlapping_coupled_limits() {
+    use super::joint::*;
+    use super::node::*;
+
+    let j0 = NodeBuilder::new()
+        .name("j0")
+        .joint_type(JointType::Rotational {
+            axis: na::Vector3::y_axis(),
+        })
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .name("j1")
+        .joint_type(JointType::Rotational {
+            axis: na::Vector3::x_axis(),
+        })
+        .into_node();
+    j1.set_parent(&j0);
+    let mut chain = Chain::<f64>::from_root(j0);
+    let names = vec!["j0".to_owned(), "j1".to_owned()];
+    // a wedge around the positive j0 axis, both limits contain both joints
+    chain
+        .add_coupled_limit(CoupledLimit::linear(names.clone(), vec![-0.5, 1.0], 0.0).unwrap())
+        .unwrap();
+    chain
+        .add_coupled_limit(CoupledLimit::linear(names, vec![-0.5, -1.0], 0.0).unwrap())
+        .unwrap();
+    assert!(chain.set_joint_positions(&[1.0, 0.1]).is_ok());
+    assert!(chain.set_joint_positions(&[-1.0, 0.0]).is_err());
+
+    // the projection to the second limit violates the first one again
+    chain.set_limit_policy(LimitPolicy::Clamp);
+    chain.set_joint_positions(&[-1.0, 0.0]).unwrap();
+    let positions = chain.joint_positions();
+    for limit in chain.coupled_limits() {
+        assert!(limit.violation(&positions) <= chain.tolerances().coupled_limit);
+    }
+    assert!(positions[0].abs() < 1e-6 && positions[1].abs() < 1e-6);
+}
+
+#[test]
+fn test_commanded_positions_of_failed_set() {
+    let j0 = NodeBuilder::new()
+        .joint_type(JointType::Rotational {
+            axis: na::Vector3::y_axis(),
+        })
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .joint_type(JointType::Rotational {
+            axis: na::Vector3::y_axis(),
+        })
+        .limits(Some((-1.0..=1.0).into()))
+        .into_node();
+    j1.set_parent(&j0);
+    let chain = Chain::<f64>::from_root(j0.clone());
+    chain.set_limit_policy(LimitPolicy::Clamp);
+    chain.set_joint_positions(&[0.5, 1.5]).unwrap();
+    chain.set_limit_policy(LimitPolicy::Error);
+    assert!(j1.set_joint_position(2.0).is_err());
+    assert_eq!(j1.commanded_position(), Some(1.5));
+
+    // the rollback restores the commands, not the clamped positions
+    assert!(chain.set_joint_positions(&[0.7, 2.0]).is_err());
+    assert_eq!(j0.commanded_position(), Some(0.5));
+    assert_eq!(j1.commanded_position(), Some(1.5));
+    assert_eq!(chain.joint_positions(), vec![0.5, 1.0]);
+}
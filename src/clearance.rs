@@ -0,0 +1,131 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use na::RealField;
+use nalgebra as na;
+use std::fmt;
+
+/// Minimum distance between the robot and the obstacles at a configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clearance<T: RealField> {
+    /// the minimum distance, negative if penetrating
+    pub distance: T,
+    /// the gradient of `distance` by the joint positions, if it is available
+    pub gradient: Option<Vec<T>>,
+}
+
+impl<T: RealField> Clearance<T> {
+    /// Create a clearance without gradient
+    pub fn new(distance: T) -> Self {
+        Self {
+            distance,
+            gradient: None,
+        }
+    }
+
+    /// Create a clearance with the gradient by the joint positions
+    pub fn with_gradient(distance: T, gradient: Vec<T>) -> Self {
+        Self {
+            distance,
+            gradient: Some(gradient),
+        }
+    }
+}
+
+type ClearanceFunction<T> = Box<dyn Fn(&[T]) -> Clearance<T> + Send + Sync>;
+
+/// Keep the clearance from the obstacles while solving IK, used by `JacobianIkSolver`
+///
+/// The user supplies the function to calculate the clearance of joint positions, e.g.
+/// using a collision library, so this crate does not depend on any of them.
+/// A step of the iterations which makes the clearance smaller than `threshold` is
+/// changed as below.
+///
+/// 1. If the gradient is available, the joints are pushed along it by
+///    `gain * (threshold - distance)`.
+/// 2. If the clearance still gets smaller, the step is halved up to
+///    `max_backtracks` times, and rejected at last.
+pub struct ClearanceConstraint<T: RealField> {
+    function: ClearanceFunction<T>,
+    /// the minimum clearance to keep
+    pub threshold: T,
+    /// multiplier of the push along the gradient
+    pub gain: T,
+    /// how many times the step is halved before it is rejected
+    pub max_backtracks: usize,
+}
+
+impl<T: RealField> ClearanceConstraint<T> {
+    /// Create the constraint from the function of the joint positions
+    pub fn new<F>(function: F, threshold: T) -> Self
+    where
+        F: Fn(&[T]) -> Clearance<T> + Send + Sync + 'static,
+    {
+        Self {
+            function: Box::new(function),
+            threshold,
+            gain: T::one(),
+            max_backtracks: 5,
+        }
+    }
+
+    /// Calculate the clearance of the joint positions
+    pub fn clearance(&self, positions: &[T]) -> Clearance<T> {
+        (self.function)(positions)
+    }
+
+    fn is_acceptable(&self, current: &Clearance<T>, next: &Clearance<T>) -> bool {
+        next.distance >= self.threshold || next.distance >= current.distance
+    }
+
+    /// Change the step from `current` to `next` not to reduce the clearance below the threshold
+    pub(crate) fn filter_step(&self, current: &[T], next: Vec<T>) -> Vec<T> {
+        let current_clearance = self.clearance(current);
+        let next_clearance = self.clearance(&next);
+        if self.is_acceptable(&current_clearance, &next_clearance) {
+            return next;
+        }
+        let mut next = next;
+        if let Some(ref gradient) = current_clearance.gradient {
+            let push = self.gain * (self.threshold - current_clearance.distance);
+            for (q, g) in next.iter_mut().zip(gradient) {
+                *q += push * *g;
+            }
+            if self.is_acceptable(&current_clearance, &self.clearance(&next)) {
+                return next;
+            }
+        }
+        let half: T = na::convert(0.5);
+        for _ in 0..self.max_backtracks {
+            for (q, c) in next.iter_mut().zip(current) {
+                *q = *c + (*q - *c) * half;
+            }
+            if self.is_acceptable(&current_clearance, &self.clearance(&next)) {
+                return next;
+            }
+        }
+        current.to_vec()
+    }
+}
+
+impl<T: RealField> fmt::Debug for ClearanceConstraint<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClearanceConstraint")
+            .field("threshold", &self.threshold)
+            .field("gain", &self.gain)
+            .field("max_backtracks", &self.max_backtracks)
+            .finish()
+    }
+}
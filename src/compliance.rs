@@ -0,0 +1,167 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::funcs::*;
+use na::{DVector, Matrix6, RealField, Vector6};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+impl<T> SerialChain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Expected deviation of the end pose under `wrench` by the backlash and the stiffness
+    /// of the joints at the current positions
+    ///
+    /// `wrench` is the force (x, y, z) and the torque (x, y, z) applied to the end in the
+    /// world frame. The result is the deviation of the position (x, y, z) and the rotation
+    /// (x, y, z) in the world frame, the same rows as `jacobian()`.
+    ///
+    /// Each joint is deflected by `τ / stiffness` with the joint torque `τ = Jᵀ wrench`,
+    /// and pushed by the half of the backlash to the direction of `τ`.
+    /// The joints without `stiffness` or `backlash` are rigid or without play.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .stiffness(Some(100.0))
+    ///     .backlash(Some(0.002))
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .translation(Translation3::new(1.0, 0.0, 0.0))
+    ///     .into_node();
+    /// j1.set_parent(&j0);
+    /// let arm = SerialChain::from_end(&j1);
+    /// // 10 N to y at the end
+    /// let wrench = nalgebra::Vector6::<f64>::new(0.0, 10.0, 0.0, 0.0, 0.0, 0.0);
+    /// let deviation = arm.end_deviation_under_load(&wrench);
+    /// // 10 Nm / 100 Nm/rad + 0.001 rad
+    /// assert!((deviation[5] - 0.101).abs() < 1e-10);
+    /// assert!((deviation[1] - 0.101).abs() < 1e-10);
+    /// ```
+    pub fn end_deviation_under_load(&self, wrench: &Vector6<T>) -> Vector6<T> {
+        let jacobi = jacobian(self);
+        let torques = jacobi.transpose() * wrench;
+        let deflections = self
+            .iter_position_nodes()
+            .zip(torques.iter())
+            .map(|(node, torque)| {
+                let joint = node.joint();
+                let mut deflection = T::zero();
+                if let Some(stiffness) = joint.stiffness {
+                    deflection += *torque / stiffness;
+                }
+                if let Some(backlash) = joint.backlash {
+                    if *torque != T::zero() {
+                        deflection += torque.signum() * backlash * na::convert(0.5);
+                    }
+                }
+                deflection
+            })
+            .collect::<Vec<_>>();
+        let deviation = jacobi * DVector::from_vec(deflections);
+        Vector6::from_iterator(deviation.iter().cloned())
+    }
+
+    /// Task-space compliance matrix `J K⁻¹ Jᵀ` at the current positions
+    ///
+    /// `joint_stiffness_diag` is the stiffness of each joint, which must be positive.
+    /// The rows and the columns are the same as `jacobian()`.
+    pub fn cartesian_compliance(&self, joint_stiffness_diag: &[T]) -> Result<Matrix6<T>, Error> {
+        if joint_stiffness_diag.len() != self.dof() {
+            return Err(Error::SizeMismatchError {
+                input: joint_stiffness_diag.len(),
+                required: self.dof(),
+            });
+        }
+        let jacobi = jacobian(self);
+        let mut scaled = jacobi.clone();
+        for (mut column, stiffness) in scaled.column_iter_mut().zip(joint_stiffness_diag) {
+            column /= *stiffness;
+        }
+        let compliance = scaled * jacobi.transpose();
+        Ok(Matrix6::from_iterator(compliance.iter().cloned()))
+    }
+
+    /// Task-space stiffness matrix `(J K⁻¹ Jᵀ)⁻¹` at the current positions
+    ///
+    /// It is for the design of impedance control, or choosing the stiff posture for
+    /// contact tasks. It returns `Error::InverseMatrixError` if the arm has less than
+    /// six DoF or it is at a singular posture.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let stiffness = arm.cartesian_stiffness(&[1000.0; 6]).unwrap();
+    /// let compliance = arm.cartesian_compliance(&[1000.0; 6]).unwrap();
+    /// assert!((stiffness * compliance - k::nalgebra::Matrix6::identity()).norm() < 1e-6);
+    /// ```
+    pub fn cartesian_stiffness(&self, joint_stiffness_diag: &[T]) -> Result<Matrix6<T>, Error> {
+        self.cartesian_compliance(joint_stiffness_diag)?
+            .try_inverse()
+            .ok_or(Error::InverseMatrixError)
+    }
+
+    /// Covariance of the end pose `J Σ Jᵀ` propagated from the uncertainty of the joint positions
+    ///
+    /// `joint_sigmas` is the standard deviation of the position of each joint, which are
+    /// regarded as independent. The rows and the columns are the position (x, y, z) and the
+    /// rotation (x, y, z) in the world frame, the same as `jacobian()`. The square roots of
+    /// the diagonal elements are the standard deviations of the end pose.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .translation(Translation3::new(2.0, 0.0, 0.0))
+    ///     .into_node();
+    /// j1.set_parent(&j0);
+    /// let arm = SerialChain::<f64>::from_end(&j1);
+    /// let covariance = arm.end_pose_covariance(&[0.01]).unwrap();
+    /// // 2 m * 0.01 rad in y
+    /// assert!((covariance[(1, 1)].sqrt() - 0.02).abs() < 1e-10);
+    /// assert!((covariance[(5, 5)].sqrt() - 0.01).abs() < 1e-10);
+    /// assert_eq!(covariance[(0, 0)], 0.0);
+    /// ```
+    pub fn end_pose_covariance(&self, joint_sigmas: &[T]) -> Result<Matrix6<T>, Error> {
+        if joint_sigmas.len() != self.dof() {
+            return Err(Error::SizeMismatchError {
+                input: joint_sigmas.len(),
+                required: self.dof(),
+            });
+        }
+        let jacobi = jacobian(self);
+        let mut scaled = jacobi.clone();
+        for (mut column, sigma) in scaled.column_iter_mut().zip(joint_sigmas) {
+            column *= *sigma * *sigma;
+        }
+        let covariance = scaled * jacobi.transpose();
+        Ok(Matrix6::from_iterator(covariance.iter().cloned()))
+    }
+}
@@ -0,0 +1,160 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::funcs::*;
+use super::trajectory::*;
+use na::RealField;
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Kinematic condition of an arm at a point of a trajectory
+#[derive(Debug, Clone, PartialEq)]
+pub struct KinematicCondition<T: RealField> {
+    /// time of the point [sec]
+    pub time_from_start: T,
+    /// Yoshikawa's manipulability, the product of the singular values of the jacobian
+    pub manipulability: T,
+    /// the smallest singular value of the jacobian, zero at singular postures
+    pub min_singular_value: T,
+    /// the smallest distance to the limits normalized by the width of the limits,
+    /// in [0, 0.5] if in the limits. `None` if no joints have limits.
+    pub limit_distance: Option<T>,
+}
+
+/// Kinematic conditions of all the points of a trajectory, by `JointTrajectory::condition_profile()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionProfile<T: RealField> {
+    pub conditions: Vec<KinematicCondition<T>>,
+}
+
+impl<T: RealField> ConditionProfile<T> {
+    /// The smallest manipulability in the trajectory, `None` if it is empty
+    pub fn min_manipulability(&self) -> Option<T> {
+        self.min_by(|c| Some(c.manipulability))
+    }
+
+    /// The smallest singular value in the trajectory, `None` if it is empty
+    pub fn min_singular_value(&self) -> Option<T> {
+        self.min_by(|c| Some(c.min_singular_value))
+    }
+
+    /// The smallest normalized distance to the limits in the trajectory
+    pub fn min_limit_distance(&self) -> Option<T> {
+        self.min_by(|c| c.limit_distance)
+    }
+
+    fn min_by(&self, f: impl Fn(&KinematicCondition<T>) -> Option<T>) -> Option<T> {
+        self.conditions
+            .iter()
+            .filter_map(f)
+            .fold(None, |min, v| Some(min.map_or(v, |m: T| m.min(v))))
+    }
+}
+
+impl<T> SerialChain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Kinematic condition at the current positions, `time_from_start` is zero
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let bent = arm.kinematic_condition();
+    /// // the elbow is stretched
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, 0.0, 0.0, -0.3]).unwrap();
+    /// let stretched = arm.kinematic_condition();
+    /// assert!(stretched.min_singular_value < bent.min_singular_value);
+    /// ```
+    pub fn kinematic_condition(&self) -> KinematicCondition<T> {
+        let singular_values = jacobian(self).singular_values();
+        let manipulability = singular_values.iter().fold(T::one(), |p, s| p * *s);
+        let min_singular_value = singular_values.min();
+        let limit_distance = self
+            .iter_position_nodes()
+            .zip(self.joint_positions())
+            .filter_map(|(node, position)| {
+                let range = node.joint().effective_limits()?;
+                let width = range.max - range.min;
+                if width <= T::zero() {
+                    return None;
+                }
+                Some((position - range.min).min(range.max - position) / width)
+            })
+            .fold(None, |min, v| Some(min.map_or(v, |m: T| m.min(v))));
+        KinematicCondition {
+            time_from_start: T::zero(),
+            manipulability,
+            min_singular_value,
+            limit_distance,
+        }
+    }
+}
+
+impl<T> JointTrajectory<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Evaluate the kinematic condition of `arm` at each point
+    ///
+    /// The positions of `arm` are restored after the evaluation. The positions out of
+    /// the limits are evaluated as they are, and their `limit_distance` is negative.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// let trajectory = JointTrajectory::new(vec![
+    ///     TrajectoryPoint::new(vec![0.1, 0.2, 0.0, -0.5, 0.0, -0.3], 0.0),
+    ///     TrajectoryPoint::new(vec![0.1, 0.2, 0.0, -0.1, 0.0, -0.3], 1.0),
+    /// ]);
+    /// let profile = trajectory.condition_profile(&arm).unwrap();
+    /// assert_eq!(profile.conditions.len(), 2);
+    /// assert_eq!(profile.conditions[1].time_from_start, 1.0);
+    /// assert_eq!(profile.min_singular_value(), Some(profile.conditions[1].min_singular_value));
+    /// ```
+    pub fn condition_profile(&self, arm: &SerialChain<T>) -> Result<ConditionProfile<T>, Error> {
+        for point in &self.points {
+            if point.positions.len() != arm.dof() {
+                return Err(Error::SizeMismatchError {
+                    input: point.positions.len(),
+                    required: arm.dof(),
+                });
+            }
+        }
+        let orig_positions = arm.joint_positions();
+        let conditions = self
+            .points
+            .iter()
+            .map(|point| {
+                arm.set_joint_positions_unchecked(&point.positions);
+                KinematicCondition {
+                    time_from_start: point.time_from_start,
+                    ..arm.kinematic_condition()
+                }
+            })
+            .collect();
+        arm.set_joint_positions_unchecked(&orig_positions);
+        Ok(ConditionProfile { conditions })
+    }
+}
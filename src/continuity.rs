@@ -0,0 +1,167 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+use std::sync::{Arc, Mutex};
+
+/// IK solver which keeps the solutions continuous across the frames of target tracking
+///
+/// Each solve starts from the previous solution, and the deviation from it is minimized
+/// in the nullspace with the gain `continuity_weight`, combined with the nullspace
+/// objective of `solver` if any. A solution which moves any joint more than
+/// `max_joint_jump` from the previous one (e.g. a flip of the elbow) is rejected with
+/// `Error::SolutionJumpError`. The joint positions are restored if it fails, and the
+/// previous solution is kept.
+///
+/// The first solve, or the first one after `reset()`, uses the current positions as
+/// the previous solution.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let start = arm.end_transform();
+///
+/// let solver = k::ContinuousIkSolver::new(k::JacobianIkSolver::default(), 0.2, 0.1);
+/// for i in 1..=5 {
+///     let mut target = start;
+///     target.translation.vector.z += 0.005 * i as f64;
+///     solver.solve(&arm, &target).unwrap();
+/// }
+/// assert_eq!(solver.previous_solution().unwrap(), arm.joint_positions());
+///
+/// // a target far away needs a large jump
+/// let mut target = start;
+/// target.translation.vector.z += 0.2;
+/// let positions = arm.joint_positions();
+/// assert!(matches!(
+///     solver.solve(&arm, &target),
+///     Err(k::Error::SolutionJumpError { .. })
+/// ));
+/// assert_eq!(arm.joint_positions(), positions);
+/// ```
+pub struct ContinuousIkSolver<T: RealField> {
+    solver: JacobianIkSolver<T>,
+    /// maximum difference of each joint from the previous solution
+    pub max_joint_jump: T,
+    reference: Arc<Mutex<Vec<T>>>,
+    previous: Mutex<Option<Vec<T>>>,
+}
+
+impl<T> ContinuousIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create a solver with the allowed jump and the gain of the continuity cost
+    pub fn new(mut solver: JacobianIkSolver<T>, max_joint_jump: T, continuity_weight: T) -> Self {
+        let reference = Arc::new(Mutex::new(Vec::new()));
+        let shared = reference.clone();
+        let continuity = NullspaceObjective::new(
+            move |positions: &[T]| {
+                positions
+                    .iter()
+                    .zip(shared.lock().unwrap().iter())
+                    .map(|(q, r)| *q - *r)
+                    .collect()
+            },
+            continuity_weight,
+        );
+        let objective = match solver.take_nullspace_objective() {
+            Some(objective) => objective.combine(continuity),
+            None => continuity,
+        };
+        solver.set_nullspace_objective(Some(objective));
+        Self {
+            solver,
+            max_joint_jump,
+            reference,
+            previous: Mutex::new(None),
+        }
+    }
+
+    /// The solution of the last successful solve
+    pub fn previous_solution(&self) -> Option<Vec<T>> {
+        self.previous.lock().unwrap().clone()
+    }
+
+    /// Forget the previous solution, e.g. when the tracking restarts from another pose
+    pub fn reset(&self) {
+        *self.previous.lock().unwrap() = None;
+    }
+}
+
+impl<T> InverseKinematicsSolver<T> for ContinuousIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn solve_with_constraints(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error> {
+        let orig_positions = arm.joint_positions();
+        // the previous solution of another arm is ignored
+        let previous = self
+            .previous_solution()
+            .filter(|previous| previous.len() == arm.dof());
+        let reference = match previous {
+            Some(previous) => {
+                arm.set_joint_positions_clamped(&previous);
+                previous
+            }
+            None => orig_positions.clone(),
+        };
+        *self.reference.lock().unwrap() = reference.clone();
+        if let Err(error) = self
+            .solver
+            .solve_with_constraints(arm, target_pose, constraints)
+        {
+            arm.set_joint_positions_unchecked(&orig_positions);
+            return Err(error);
+        }
+        let positions = arm.joint_positions();
+        let jump = positions
+            .iter()
+            .zip(&reference)
+            .map(|(q, r)| (*q - *r).abs())
+            .enumerate()
+            .find(|(_, jump)| *jump > self.max_joint_jump);
+        if let Some((index, jump)) = jump {
+            arm.set_joint_positions_unchecked(&orig_positions);
+            return Err(Error::SolutionJumpError {
+                joint_name: arm
+                    .iter_position_nodes()
+                    .nth(index)
+                    .unwrap()
+                    .joint()
+                    .name
+                    .clone(),
+                jump: na::convert(jump),
+            });
+        }
+        *self.previous.lock().unwrap() = Some(positions);
+        Ok(())
+    }
+}
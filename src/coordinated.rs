@@ -0,0 +1,165 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use super::trajectory::*;
+use super::world::current_arm_root_transform;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Motion of two arms holding a shared object, the follower keeps the grasp
+/// while the leader moves
+///
+/// The grasp is the transform of the end of the follower from the end of the leader.
+/// The arms must not share joints, because the follower is solved with the leader fixed.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let leader = SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// let follower = SerialChain::from_end(chain.find("r_wrist_pitch").unwrap());
+/// leader.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// follower.set_joint_positions(&[-0.1, -0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let motion = CoordinatedMotion::new(&leader, &follower);
+///
+/// let leader_trajectory = JointTrajectory::new(vec![
+///     TrajectoryPoint::new(vec![0.1, 0.2, 0.0, -0.5, 0.0, -0.3], 0.0),
+///     TrajectoryPoint::new(vec![0.12, 0.2, 0.0, -0.55, 0.0, -0.3], 0.5),
+///     TrajectoryPoint::new(vec![0.14, 0.2, 0.0, -0.6, 0.0, -0.3], 1.0),
+/// ]);
+/// let solver = JacobianIkSolver::default();
+/// let follower_trajectory = motion
+///     .follower_trajectory(&solver, &leader_trajectory, &Constraints::default(), Some(0.2))
+///     .unwrap();
+/// assert_eq!(follower_trajectory.points.len(), 3);
+///
+/// // the grasp is kept at the end
+/// leader.set_joint_positions(&leader_trajectory.points[2].positions).unwrap();
+/// follower.set_joint_positions(&follower_trajectory.points[2].positions).unwrap();
+/// let grasp = leader.end_transform().inverse() * follower.end_transform();
+/// assert!((grasp.translation.vector - motion.grasp().translation.vector).norm() < 0.001);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CoordinatedMotion<'a, T: RealField> {
+    leader: &'a SerialChain<T>,
+    follower: &'a SerialChain<T>,
+    grasp: Isometry3<T>,
+}
+
+impl<'a, T> CoordinatedMotion<'a, T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create the motion with the grasp of the current joint positions
+    pub fn new(leader: &'a SerialChain<T>, follower: &'a SerialChain<T>) -> Self {
+        let grasp = (current_arm_root_transform(leader) * leader.end_transform()).inverse()
+            * current_arm_root_transform(follower)
+            * follower.end_transform();
+        Self::with_grasp(leader, follower, grasp)
+    }
+
+    /// Create the motion with the transform of the end of `follower` from the end of `leader`
+    pub fn with_grasp(
+        leader: &'a SerialChain<T>,
+        follower: &'a SerialChain<T>,
+        grasp: Isometry3<T>,
+    ) -> Self {
+        Self {
+            leader,
+            follower,
+            grasp,
+        }
+    }
+
+    /// The transform of the end of the follower from the end of the leader
+    pub fn grasp(&self) -> Isometry3<T> {
+        self.grasp
+    }
+
+    /// Calculate the pose of the end of the follower for the current positions of the
+    /// leader, in the frame of the follower
+    pub fn follower_target(&self) -> Isometry3<T> {
+        current_arm_root_transform(self.follower).inverse()
+            * current_arm_root_transform(self.leader)
+            * self.leader.end_transform()
+            * self.grasp
+    }
+
+    /// Generate the trajectory of the follower which keeps the grasp while the leader
+    /// moves along `leader_trajectory`
+    ///
+    /// The points have the same times as `leader_trajectory`. Each IK is started from
+    /// the solution of the previous point (from the current positions for the first one).
+    /// It returns `Err(Error::InvalidTrajectoryError)` if a joint of the follower moves
+    /// more than `max_joint_step` between the points, and the error of the solver if IK
+    /// fails. The joint positions of both arms are restored.
+    pub fn follower_trajectory<S>(
+        &self,
+        solver: &S,
+        leader_trajectory: &JointTrajectory<T>,
+        constraints: &Constraints,
+        max_joint_step: Option<T>,
+    ) -> Result<JointTrajectory<T>, Error>
+    where
+        S: InverseKinematicsSolver<T>,
+    {
+        let leader_positions = self.leader.joint_positions();
+        let follower_positions = self.follower.joint_positions();
+        let result = self.track(solver, leader_trajectory, constraints, max_joint_step);
+        self.leader.set_joint_positions_unchecked(&leader_positions);
+        self.follower
+            .set_joint_positions_unchecked(&follower_positions);
+        result
+    }
+
+    fn track<S>(
+        &self,
+        solver: &S,
+        leader_trajectory: &JointTrajectory<T>,
+        constraints: &Constraints,
+        max_joint_step: Option<T>,
+    ) -> Result<JointTrajectory<T>, Error>
+    where
+        S: InverseKinematicsSolver<T>,
+    {
+        let mut points: Vec<TrajectoryPoint<T>> =
+            Vec::with_capacity(leader_trajectory.points.len());
+        for (i, leader_point) in leader_trajectory.points.iter().enumerate() {
+            self.leader.set_joint_positions(&leader_point.positions)?;
+            solver.solve_with_constraints(self.follower, &self.follower_target(), constraints)?;
+            let positions = self.follower.joint_positions();
+            if let (Some(max), Some(prev)) = (max_joint_step, points.last()) {
+                let jumped = positions
+                    .iter()
+                    .zip(&prev.positions)
+                    .any(|(a, b)| (*a - *b).abs() > max);
+                if jumped {
+                    return Err(Error::InvalidTrajectoryError { point_index: i });
+                }
+            }
+            points.push(TrajectoryPoint::new(
+                positions,
+                leader_point.time_from_start,
+            ));
+        }
+        Ok(JointTrajectory::new(points))
+    }
+}
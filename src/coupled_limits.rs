@@ -0,0 +1,233 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::errors::*;
+use na::RealField;
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Limit over a group of joints, which can not be expressed by the range of each joint
+///
+/// For example, the combined envelope of the shoulder pitch and roll of a humanoid.
+/// It is registered to the chain by `Chain::add_coupled_limit()`, and enforced by
+/// `Chain::set_joint_positions()` and `Chain::set_joint_positions_clamped()`.
+///
+/// # Examples
+///
+/// ```
+/// // q0 + q1 <= 1.0
+/// let limit = k::CoupledLimit::linear(
+///     vec!["j0".to_owned(), "j1".to_owned()],
+///     vec![1.0, 1.0],
+///     1.0,
+/// )
+/// .unwrap();
+/// assert!(limit.is_satisfied(&[0.5, 0.5]));
+/// assert!(!limit.is_satisfied(&[0.8, 0.5]));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoupledLimit<T: RealField> {
+    /// `sum(coefficients[i] * q[i]) <= bound`
+    Linear {
+        /// names of the joints
+        joint_names: Vec<String>,
+        /// coefficient of each joint
+        coefficients: Vec<T>,
+        /// upper bound of the weighted sum
+        bound: T,
+    },
+    /// `sum(((q[i] - center[i]) / radii[i])^2) <= 1`
+    Ellipsoid {
+        /// names of the joints
+        joint_names: Vec<String>,
+        /// center of the ellipsoid
+        center: Vec<T>,
+        /// radius of each joint, must be positive
+        radii: Vec<T>,
+    },
+}
+
+impl<T> CoupledLimit<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create a linear inequality limit `sum(coefficients[i] * q[i]) <= bound`
+    ///
+    /// Returns `Error::SizeMismatchError` if the lengths are different.
+    pub fn linear(joint_names: Vec<String>, coefficients: Vec<T>, bound: T) -> Result<Self, Error> {
+        if coefficients.len() != joint_names.len() {
+            return Err(Error::SizeMismatchError {
+                input: coefficients.len(),
+                required: joint_names.len(),
+            });
+        }
+        Ok(CoupledLimit::Linear {
+            joint_names,
+            coefficients,
+            bound,
+        })
+    }
+
+    /// Create an ellipsoidal limit `sum(((q[i] - center[i]) / radii[i])^2) <= 1`
+    ///
+    /// Returns `Error::SizeMismatchError` if the lengths are different, and
+    /// `Error::InvalidParameterError` if a radius is not positive and finite.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let limit = k::CoupledLimit::ellipsoid(
+    ///     vec!["pitch".to_owned(), "roll".to_owned()],
+    ///     vec![0.0, 0.0],
+    ///     vec![2.0, 1.0],
+    /// )
+    /// .unwrap();
+    /// assert!(limit.is_satisfied(&[1.5, 0.5]));
+    /// assert!(!limit.is_satisfied(&[1.5, 1.0]));
+    /// ```
+    pub fn ellipsoid(
+        joint_names: Vec<String>,
+        center: Vec<T>,
+        radii: Vec<T>,
+    ) -> Result<Self, Error> {
+        for len in &[center.len(), radii.len()] {
+            if *len != joint_names.len() {
+                return Err(Error::SizeMismatchError {
+                    input: *len,
+                    required: joint_names.len(),
+                });
+            }
+        }
+        for radius in &radii {
+            let r: f64 = na::convert(*radius);
+            if r <= 0.0 || !r.is_finite() {
+                return Err(Error::InvalidParameterError {
+                    reason: format!("radius of the ellipsoid must be positive, but {}", radius),
+                });
+            }
+        }
+        Ok(CoupledLimit::Ellipsoid {
+            joint_names,
+            center,
+            radii,
+        })
+    }
+
+    /// Names of the joints in the group
+    pub fn joint_names(&self) -> &[String] {
+        match self {
+            CoupledLimit::Linear { joint_names, .. } => joint_names,
+            CoupledLimit::Ellipsoid { joint_names, .. } => joint_names,
+        }
+    }
+
+    /// How much the positions of the group violate the limit, zero if they satisfy it
+    ///
+    /// It is `sum(coefficients[i] * q[i]) - bound` for `Linear`, and
+    /// `sum(((q[i] - center[i]) / radii[i])^2) - 1` for `Ellipsoid`.
+    pub fn violation(&self, positions: &[T]) -> T {
+        let value = match self {
+            CoupledLimit::Linear {
+                coefficients,
+                bound,
+                ..
+            } => {
+                coefficients
+                    .iter()
+                    .zip(positions)
+                    .fold(T::zero(), |sum, (c, q)| sum + *c * *q)
+                    - *bound
+            }
+            CoupledLimit::Ellipsoid { center, radii, .. } => {
+                center
+                    .iter()
+                    .zip(radii)
+                    .zip(positions)
+                    .fold(T::zero(), |sum, ((c, r), q)| {
+                        let x = (*q - *c) / *r;
+                        sum + x * x
+                    })
+                    - T::one()
+            }
+        };
+        value.max(T::zero())
+    }
+
+    /// Returns true if the positions of the group satisfy the limit
+    pub fn is_satisfied(&self, positions: &[T]) -> bool {
+        self.violation(positions) <= T::zero()
+    }
+
+    /// Move the positions of the group into the limit
+    ///
+    /// `Linear` is projected to the closest point on the boundary, and `Ellipsoid`
+    /// is scaled toward the center.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let limit = k::CoupledLimit::ellipsoid(
+    ///     vec!["pitch".to_owned(), "roll".to_owned()],
+    ///     vec![0.0, 0.0],
+    ///     vec![2.0, 1.0],
+    /// )
+    /// .unwrap();
+    /// let mut positions = [4.0f64, 0.0];
+    /// limit.project(&mut positions);
+    /// assert!((positions[0] - 2.0).abs() < 1e-9);
+    /// assert!(limit.is_satisfied(&positions));
+    /// ```
+    pub fn project(&self, positions: &mut [T]) {
+        let violation = self.violation(positions);
+        if violation <= T::zero() {
+            return;
+        }
+        match self {
+            CoupledLimit::Linear { coefficients, .. } => {
+                let norm_squared = coefficients.iter().fold(T::zero(), |sum, c| sum + *c * *c);
+                if norm_squared <= T::zero() {
+                    return;
+                }
+                let scale = violation / norm_squared;
+                for (q, c) in positions.iter_mut().zip(coefficients) {
+                    *q -= *c * scale;
+                }
+            }
+            CoupledLimit::Ellipsoid { center, .. } => {
+                let scale = T::one() / (violation + T::one()).sqrt();
+                for (q, c) in positions.iter_mut().zip(center) {
+                    *q = *c + (*q - *c) * scale;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ellipsoid_invalid_radii() {
+    let names = vec!["a".to_owned(), "b".to_owned()];
+    for radii in &[
+        [1.0, 0.0],
+        [-1.0, 1.0],
+        [1.0, f64::INFINITY],
+        [f64::NAN, 1.0],
+    ] {
+        assert!(matches!(
+            CoupledLimit::ellipsoid(names.clone(), vec![0.0, 0.0], radii.to_vec()),
+            Err(Error::InvalidParameterError { .. })
+        ));
+    }
+    assert!(CoupledLimit::ellipsoid(names, vec![0.0, 0.0], vec![1.0, 2.0]).is_ok());
+}
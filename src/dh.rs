@@ -0,0 +1,388 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::joint::*;
+use super::node::*;
+use na::{Isometry3, RealField, Translation3, Unit, UnitQuaternion, Vector3};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Type of the variable of a `DhParameter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhJointType {
+    /// the joint position is added to `theta`
+    Revolute,
+    /// the joint position is added to `d`
+    Prismatic,
+}
+
+/// Modified (Craig's) Denavit-Hartenberg parameters of a joint
+///
+/// The transform from the previous frame is
+/// `Rx(alpha) * Tx(a) * Rz(theta) * Tz(d)`.
+#[derive(Debug, Clone)]
+pub struct DhParameter<T: RealField> {
+    pub name: String,
+    pub joint_type: DhJointType,
+    pub a: T,
+    pub alpha: T,
+    pub d: T,
+    /// `theta` at the joint position zero
+    pub theta: T,
+    pub limits: Option<Range<T>>,
+}
+
+impl<T> DhParameter<T>
+where
+    T: RealField,
+{
+    /// Transform from the previous frame, without the joint motion
+    fn origin(&self) -> Isometry3<T> {
+        Isometry3::rotation(Vector3::x() * self.alpha)
+            * Translation3::new(self.a, T::zero(), T::zero())
+            * Isometry3::rotation(Vector3::z() * self.theta)
+            * Translation3::new(T::zero(), T::zero(), self.d)
+    }
+
+    fn joint_type(&self) -> JointType<T> {
+        match self.joint_type {
+            DhJointType::Revolute => JointType::Rotational {
+                axis: Vector3::z_axis(),
+            },
+            DhJointType::Prismatic => JointType::Linear {
+                axis: Vector3::z_axis(),
+            },
+        }
+    }
+}
+
+/// Serial chain described by DH parameters
+///
+/// `base` is the pose of the first joint frame at zero position in the chain frame,
+/// and `tool` is the pose of the end in the last joint frame.
+#[derive(Debug, Clone)]
+pub struct DhChain<T: RealField> {
+    pub base: Isometry3<T>,
+    pub parameters: Vec<DhParameter<T>>,
+    pub tool: Isometry3<T>,
+}
+
+impl<T> DhChain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Forward kinematics by the DH parameters
+    pub fn end_transform(&self, positions: &[T]) -> Result<Isometry3<T>, Error> {
+        if positions.len() != self.parameters.len() {
+            return Err(Error::SizeMismatchError {
+                input: positions.len(),
+                required: self.parameters.len(),
+            });
+        }
+        let mut pose = self.base;
+        for (i, (param, position)) in self.parameters.iter().zip(positions).enumerate() {
+            if i > 0 {
+                pose *= param.origin();
+            }
+            pose *= param.joint_type().transform(std::slice::from_ref(position));
+        }
+        Ok(pose * self.tool)
+    }
+
+    /// Create a chain whose joints have z axes and origins from the DH parameters
+    ///
+    /// The end node is a fixed joint named `"dh_tool"`.
+    pub fn to_serial_chain(&self) -> SerialChain<T> {
+        let mut nodes = Vec::new();
+        for (i, param) in self.parameters.iter().enumerate() {
+            let origin = if i == 0 { self.base } else { param.origin() };
+            nodes.push(
+                NodeBuilder::new()
+                    .name(&param.name)
+                    .origin(origin)
+                    .joint_type(param.joint_type())
+                    .limits(param.limits)
+                    .into_node(),
+            );
+        }
+        nodes.push(
+            NodeBuilder::new()
+                .name("dh_tool")
+                .origin(self.tool)
+                .into_node(),
+        );
+        for i in 1..nodes.len() {
+            nodes[i].set_parent(&nodes[i - 1]);
+        }
+        SerialChain::new_unchecked(Chain::from_root(nodes.remove(0)))
+    }
+}
+
+/// Unit vector perpendicular to `z`, close to `hint` if possible
+fn perpendicular<T: RealField>(z: &Vector3<T>, hint: &Vector3<T>) -> Unit<Vector3<T>> {
+    let eps = na::convert(1e-9);
+    let projected = hint - z * z.dot(hint);
+    if let Some(x) = Unit::try_new(projected, eps) {
+        return x;
+    }
+    let other = if z.x.abs() < na::convert(0.9) {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    Unit::new_normalize(other - z * z.dot(&other))
+}
+
+fn frame_from<T: RealField>(
+    origin: Vector3<T>,
+    x: &Unit<Vector3<T>>,
+    z: &Unit<Vector3<T>>,
+) -> Isometry3<T> {
+    let y = z.cross(x);
+    let rotation = na::Rotation3::from_matrix_unchecked(na::Matrix3::from_columns(&[
+        x.into_inner(),
+        y,
+        z.into_inner(),
+    ]));
+    Isometry3::from_parts(
+        Translation3::from(origin),
+        UnitQuaternion::from_rotation_matrix(&rotation),
+    )
+}
+
+impl<T> SerialChain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Convert to the modified DH parameters
+    ///
+    /// The joint positions are kept. The result is validated by comparing the forward
+    /// kinematics of some configurations with this chain, and an error is returned if
+    /// the difference is larger than `tolerance`. Mimic joints are not supported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = SerialChain::from_end(chain.find("r_wrist_pitch").unwrap());
+    /// let dh = arm.to_dh(1e-9).unwrap();
+    /// assert_eq!(dh.parameters.len(), 6);
+    ///
+    /// let positions = [0.1, 0.2, 0.3, -0.4, 0.5, -0.6];
+    /// arm.set_joint_positions(&positions).unwrap();
+    /// let expected = arm.end_transform();
+    /// let from_dh = dh.to_serial_chain();
+    /// from_dh.set_joint_positions(&positions).unwrap();
+    /// let actual = from_dh.end_transform();
+    /// assert!((expected.translation.vector - actual.translation.vector).norm() < 1e-9);
+    /// assert!(expected.rotation.angle_to(&actual.rotation) < 1e-9);
+    /// ```
+    pub fn to_dh(&self, tolerance: T) -> Result<DhChain<T>, Error> {
+        if self.iter().any(|node| node.mimic_parent().is_some()) {
+            return Err(Error::ConventionConversionError {
+                reason: "mimic joints cannot be expressed by DH parameters".to_owned(),
+            });
+        }
+        // joint axes at zero positions
+        let mut pose = Isometry3::identity();
+        let mut axes = Vec::new();
+        let mut names = Vec::new();
+        let mut types = Vec::new();
+        let mut limits = Vec::new();
+        for node in self.iter() {
+            let joint = node.joint();
+            pose *= joint.origin();
+            let (axis, joint_type) = match joint.joint_type {
+                JointType::Fixed => continue,
+                JointType::Rotational { axis } => (axis, DhJointType::Revolute),
+                JointType::Linear { axis } => (axis, DhJointType::Prismatic),
+                JointType::Universal { .. }
+                | JointType::Planar { .. }
+                | JointType::Floating { .. } => {
+                    return Err(Error::ConventionConversionError {
+                        reason: format!("{} has several positions", joint.name),
+                    })
+                }
+            };
+            axes.push((pose.translation.vector, pose.rotation * axis));
+            names.push(joint.name.clone());
+            types.push(joint_type);
+            limits.push(joint.limits);
+        }
+        let n = axes.len();
+        if n == 0 {
+            return Err(Error::ConventionConversionError {
+                reason: "no movable joints".to_owned(),
+            });
+        }
+
+        // frame i: z along axis i, x along the common normal to axis i + 1
+        let eps: T = na::convert(1e-9);
+        let mut frames: Vec<Isometry3<T>> = Vec::with_capacity(n);
+        for i in 0..n {
+            let (p, z) = axes[i];
+            let prev_origin = frames.last().map(|f| f.translation.vector);
+            let prev_x = frames
+                .last()
+                .map(|f| f.rotation * Vector3::x())
+                .unwrap_or_else(Vector3::x);
+            let on_line = |q: Vector3<T>| p + z.into_inner() * z.dot(&(q - p));
+            let (origin, x) = if i + 1 < n {
+                let (p2, z2) = axes[i + 1];
+                let normal = z.cross(&z2);
+                let diff = p2 - p;
+                match Unit::try_new(normal, eps) {
+                    Some(normal) => {
+                        // closest point on axis i to axis i + 1
+                        let t = diff.cross(&z2).dot(&normal) / normal.dot(&z.cross(&z2));
+                        let origin = p + z.into_inner() * t;
+                        let sign = if normal.dot(&diff) < T::zero() {
+                            -T::one()
+                        } else {
+                            T::one()
+                        };
+                        (origin, Unit::new_unchecked(normal.into_inner() * sign))
+                    }
+                    None => {
+                        // parallel axes
+                        let origin = on_line(prev_origin.unwrap_or(p));
+                        (origin, perpendicular(&z, &(p2 - origin)))
+                    }
+                }
+            } else {
+                (
+                    on_line(prev_origin.unwrap_or(p)),
+                    perpendicular(&z, &prev_x),
+                )
+            };
+            frames.push(frame_from(origin, &x, &z));
+        }
+
+        let mut parameters = Vec::with_capacity(n);
+        for i in 0..n {
+            let (alpha, a, theta, d) = if i == 0 {
+                (T::zero(), T::zero(), T::zero(), T::zero())
+            } else {
+                let relative = frames[i - 1].inverse() * frames[i];
+                let m = relative.rotation.to_rotation_matrix();
+                let m = m.matrix();
+                let t = relative.translation.vector;
+                let alpha = (-m[(1, 2)]).atan2(m[(2, 2)]);
+                let theta = (-m[(0, 1)]).atan2(m[(0, 0)]);
+                let z = m.column(2).into_owned();
+                (alpha, t.x, theta, t.dot(&z))
+            };
+            parameters.push(DhParameter {
+                name: names[i].clone(),
+                joint_type: types[i],
+                a,
+                alpha,
+                d,
+                theta,
+                limits: limits[i],
+            });
+        }
+        let dh = DhChain {
+            base: frames[0],
+            parameters,
+            tool: frames[n - 1].inverse() * pose_at_zero(self),
+        };
+        self.validate_dh(&dh, tolerance)?;
+        Ok(dh)
+    }
+
+    fn validate_dh(&self, dh: &DhChain<T>, tolerance: T) -> Result<(), Error> {
+        let orig_positions = self.joint_positions();
+        let n = orig_positions.len();
+        let samples = [0.0, 0.3, -0.7, 1.1];
+        let mut max_error = T::zero();
+        for (k, sample) in samples.iter().enumerate() {
+            let positions = (0..n)
+                .map(|i| na::convert(sample * ((i + k) % 3 + 1) as f64))
+                .collect::<Vec<T>>();
+            self.set_joint_positions_unchecked(&positions);
+            let expected = self.end_transform();
+            let actual = dh.end_transform(&positions)?;
+            let error = (expected.translation.vector - actual.translation.vector)
+                .norm()
+                .max(expected.rotation.angle_to(&actual.rotation));
+            max_error = max_error.max(error);
+        }
+        self.set_joint_positions_unchecked(&orig_positions);
+        if max_error > tolerance {
+            return Err(Error::ConventionConversionError {
+                reason: format!(
+                    "forward kinematics differs by {}",
+                    na::convert::<T, f64>(max_error)
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// End transform at zero positions
+fn pose_at_zero<T>(arm: &SerialChain<T>) -> Isometry3<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    arm.iter().fold(Isometry3::identity(), |pose, node| {
+        pose * node.joint().origin()
+    })
+}
+
+#[test]
+fn test_dh_roundtrip_with_parallel_and_prismatic() {
+    let j0 = NodeBuilder::new()
+        .name("j0")
+        .translation(Translation3::new(0.1, 0.2, 0.3))
+        .joint_type(JointType::Rotational {
+            axis: Vector3::y_axis(),
+        })
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .name("j1")
+        .translation(Translation3::new(0.0, 0.0, 0.5))
+        .joint_type(JointType::Rotational {
+            axis: Vector3::y_axis(),
+        })
+        .into_node();
+    let j2 = NodeBuilder::new()
+        .name("j2")
+        .translation(Translation3::new(0.2, 0.0, 0.0))
+        .rotation(UnitQuaternion::from_euler_angles(0.3, 0.0, 0.2))
+        .joint_type(JointType::Linear {
+            axis: Vector3::x_axis(),
+        })
+        .into_node();
+    let end = NodeBuilder::new()
+        .name("end")
+        .translation(Translation3::new(0.0, 0.1, 0.0))
+        .into_node();
+    j1.set_parent(&j0);
+    j2.set_parent(&j1);
+    end.set_parent(&j2);
+    let arm = SerialChain::new_unchecked(Chain::<f64>::from_root(j0));
+    let dh = arm.to_dh(1e-9).unwrap();
+    assert_eq!(dh.parameters[2].joint_type, DhJointType::Prismatic);
+    // parallel axes
+    assert!(dh.parameters[1].alpha.abs() < 1e-9);
+    assert!((dh.parameters[1].a.abs() - 0.5).abs() < 1e-9);
+    assert!(dh.end_transform(&[0.0]).is_err());
+}
@@ -0,0 +1,108 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use na::RealField;
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Estimate the efforts of the joints to overcome the damping and the friction
+    ///
+    /// The effort of each joint is `damping * velocity + friction * sign(velocity)`,
+    /// by `Joint::damping` and `Joint::friction` (`<dynamics>` of URDF). The joints
+    /// without them have no effort. Gravity and inertia are not included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .damping(Some(0.5))
+    ///     .friction(Some(0.1))
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// j1.set_parent(&j0);
+    /// let chain = Chain::<f64>::from_root(j0);
+    /// let efforts = chain.estimate_friction_efforts(&[-2.0, 1.0]).unwrap();
+    /// assert_eq!(efforts, vec![-1.1, 0.0]);
+    /// ```
+    pub fn estimate_friction_efforts(&self, velocities: &[T]) -> Result<Vec<T>, Error> {
+        if velocities.len() != self.dof() {
+            return Err(Error::SizeMismatchError {
+                input: velocities.len(),
+                required: self.dof(),
+            });
+        }
+        Ok(self
+            .iter_position_nodes()
+            .zip(velocities)
+            .map(|(node, velocity)| {
+                let joint = node.joint();
+                let mut effort = T::zero();
+                if let Some(damping) = joint.damping {
+                    effort += damping * *velocity;
+                }
+                if let Some(friction) = joint.friction {
+                    if *velocity != T::zero() {
+                        effort += friction * velocity.signum();
+                    }
+                }
+                effort
+            })
+            .collect())
+    }
+
+    /// Differences of the measured efforts from `estimate_friction_efforts()`
+    ///
+    /// It is for health monitoring: the joints with large differences may have
+    /// mechanical problems.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .damping(Some(0.5))
+    ///     .into_node();
+    /// let chain = Chain::<f64>::from_root(j0);
+    /// let deviations = chain.effort_deviations(&[1.0], &[0.8]).unwrap();
+    /// assert!((deviations[0] - 0.3).abs() < 1e-10);
+    /// ```
+    pub fn effort_deviations(&self, velocities: &[T], measured: &[T]) -> Result<Vec<T>, Error> {
+        if measured.len() != self.dof() {
+            return Err(Error::SizeMismatchError {
+                input: measured.len(),
+                required: self.dof(),
+            });
+        }
+        Ok(self
+            .estimate_friction_efforts(velocities)?
+            .into_iter()
+            .zip(measured)
+            .map(|(estimated, measured)| *measured - estimated)
+            .collect())
+    }
+}
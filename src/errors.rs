@@ -70,6 +70,85 @@ pub enum Error {
         position_diff: na::Vector3<f64>,
         rotation_diff: na::Vector3<f64>,
     },
+    /// The joint is not found in the chain
+    #[error("joint {} is not found", joint_name)]
+    JointNotFoundError {
+        /// name of the joint
+        joint_name: String,
+    },
+    /// The time of the trajectory point is not increasing
+    #[error("time of trajectory point {} is not increasing", point_index)]
+    InvalidTrajectoryError {
+        /// index of the invalid point
+        point_index: usize,
+    },
+    /// The solution moves a joint from the previous one more than allowed
+    #[error("joint {} jumps {} from the previous solution", joint_name, jump)]
+    SolutionJumpError {
+        /// name of the joint
+        joint_name: String,
+        /// absolute difference of the position
+        jump: f64,
+    },
+    /// The joint has no limits where finite bounds are necessary
+    #[error("joint {} has no limits", joint_name)]
+    UnboundedJointError {
+        /// name of the joint
+        joint_name: String,
+    },
+    /// Failed to convert the chain into another convention
+    #[error("failed to convert the convention: {}", reason)]
+    ConventionConversionError {
+        /// description of the reason
+        reason: String,
+    },
+    /// The structure of the nodes is not a tree
+    #[error("invalid structure: {}", reason)]
+    InvalidStructureError {
+        /// description of the reason
+        reason: String,
+    },
+    /// Failed to load or resolve the SRDF
+    #[error("SRDF error: {}", reason)]
+    SrdfError {
+        /// description of the reason
+        reason: String,
+    },
+    /// Failed to read or write `WorkspaceMap`
+    #[error("workspace map error: {}", reason)]
+    WorkspaceMapError {
+        /// description of the reason
+        reason: String,
+    },
+    /// The metadata of the model is invalid
+    #[error("metadata error: {}", reason)]
+    MetadataError {
+        /// description of the reason
+        reason: String,
+    },
+    /// The parameter given to a constructor is out of its valid range
+    #[error("invalid parameter: {}", reason)]
+    InvalidParameterError {
+        /// description of the reason
+        reason: String,
+    },
+    /// The positions violate a `CoupledLimit` of the chain
+    #[error("coupled limit of {:?} is violated by {}", joint_names, violation)]
+    CoupledLimitError {
+        /// names of the joints in the limit
+        joint_names: Vec<String>,
+        /// value of `CoupledLimit::violation()`
+        violation: f64,
+    },
+    /// No solution of the analytical IK is within the limits of the joints
+    #[error(
+        "no analytical solution within the limits, out of {} solutions",
+        num_solutions
+    )]
+    NoAnalyticalSolutionError {
+        /// number of the solutions regardless of the limits, zero if the target is out of reach
+        num_solutions: usize,
+    },
     #[error("inverse matrix error")]
     InverseMatrixError,
     #[error(
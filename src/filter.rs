@@ -0,0 +1,209 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use na::{Isometry3, RealField, Translation3};
+use nalgebra as na;
+use std::sync::Mutex;
+
+/// Exponential (first order low-pass) filter of poses
+///
+/// The translation is interpolated linearly and the rotation is interpolated
+/// by slerp, so the output is always a valid pose.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// let mut filter = PoseFilter::new(0.5).unwrap();
+/// let first = filter.filter(&Isometry3::translation(1.0, 0.0, 0.0));
+/// // the first input is passed through
+/// assert_eq!(first.translation.vector.x, 1.0);
+/// let second = filter.filter(&Isometry3::translation(2.0, 0.0, 0.0));
+/// assert_eq!(second.translation.vector.x, 1.5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PoseFilter<T: RealField> {
+    alpha: T,
+    state: Option<Isometry3<T>>,
+}
+
+impl<T> PoseFilter<T>
+where
+    T: RealField,
+{
+    /// Create filter with the smoothing factor `alpha` in (0, 1]
+    ///
+    /// The output is `alpha * input + (1 - alpha) * previous output`.
+    /// `alpha = 1` means no filtering. It returns `Error::InvalidParameterError`
+    /// if `alpha` is out of the range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// assert!(k::PoseFilter::new(0.5).is_ok());
+    /// assert!(k::PoseFilter::new(0.0).is_err());
+    /// assert!(k::PoseFilter::new(1.5).is_err());
+    /// ```
+    pub fn new(alpha: T) -> Result<Self, Error> {
+        if alpha > T::zero() && alpha <= T::one() {
+            Ok(Self { alpha, state: None })
+        } else {
+            Err(Error::InvalidParameterError {
+                reason: format!("alpha of PoseFilter must be in (0, 1], but {}", alpha),
+            })
+        }
+    }
+
+    /// Create filter from the cutoff frequency [Hz] and the sampling period [sec]
+    ///
+    /// Both must be positive, otherwise it returns `Error::InvalidParameterError`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let filter = k::PoseFilter::<f64>::from_cutoff_frequency(5.0, 0.01).unwrap();
+    /// assert!((filter.alpha() - 0.2391).abs() < 1e-4);
+    /// ```
+    pub fn from_cutoff_frequency(cutoff_frequency: T, period: T) -> Result<Self, Error> {
+        if cutoff_frequency <= T::zero() || period <= T::zero() {
+            return Err(Error::InvalidParameterError {
+                reason: format!(
+                    "cutoff frequency {} and period {} of PoseFilter must be positive",
+                    cutoff_frequency, period
+                ),
+            });
+        }
+        let time_constant = T::one() / (T::two_pi() * cutoff_frequency);
+        Self::new(period / (period + time_constant))
+    }
+
+    /// Get the smoothing factor
+    pub fn alpha(&self) -> T {
+        self.alpha
+    }
+
+    /// Input a new pose and get the filtered pose
+    pub fn filter(&mut self, pose: &Isometry3<T>) -> Isometry3<T> {
+        let filtered = match self.state {
+            Some(prev) => {
+                let translation = prev
+                    .translation
+                    .vector
+                    .lerp(&pose.translation.vector, self.alpha);
+                let rotation = prev
+                    .rotation
+                    .try_slerp(&pose.rotation, self.alpha, T::default_epsilon())
+                    .unwrap_or(pose.rotation);
+                Isometry3::from_parts(Translation3::from(translation), rotation)
+            }
+            None => *pose,
+        };
+        self.state = Some(filtered);
+        filtered
+    }
+
+    /// Get the last output
+    pub fn state(&self) -> Option<Isometry3<T>> {
+        self.state
+    }
+
+    /// Forget the history. The next input is passed through.
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+/// IK solver which filters the target poses by `PoseFilter` before solving
+///
+/// It is useful for noisy targets from teleoperation or perception.
+/// Call `reset()` when the target jumps intentionally.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("r_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let target = arm.end_transform();
+///
+/// let solver = k::FilteredIkSolver::new(k::JacobianIkSolver::default(), k::PoseFilter::new(0.5).unwrap());
+/// solver.solve(&arm, &target).unwrap();
+/// ```
+pub struct FilteredIkSolver<T, S>
+where
+    T: RealField,
+    S: InverseKinematicsSolver<T>,
+{
+    solver: S,
+    filter: Mutex<PoseFilter<T>>,
+}
+
+impl<T, S> FilteredIkSolver<T, S>
+where
+    T: RealField,
+    S: InverseKinematicsSolver<T>,
+{
+    pub fn new(solver: S, filter: PoseFilter<T>) -> Self {
+        Self {
+            solver,
+            filter: Mutex::new(filter),
+        }
+    }
+
+    /// Forget the history of the targets
+    pub fn reset(&self) {
+        self.filter.lock().unwrap().reset();
+    }
+
+    /// Get the inner solver
+    pub fn solver(&self) -> &S {
+        &self.solver
+    }
+}
+
+impl<T, S> InverseKinematicsSolver<T> for FilteredIkSolver<T, S>
+where
+    T: RealField,
+    S: InverseKinematicsSolver<T>,
+{
+    fn solve_with_constraints(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error> {
+        let filtered = self.filter.lock().unwrap().filter(target_pose);
+        self.solver
+            .solve_with_constraints(arm, &filtered, constraints)
+    }
+}
+
+#[test]
+fn test_pose_filter_rotation() {
+    let mut filter = PoseFilter::new(0.5).unwrap();
+    filter.filter(&Isometry3::identity());
+    let target = Isometry3::rotation(na::Vector3::new(0.0, 0.0, 1.0));
+    let filtered = filter.filter(&target);
+    assert!((filtered.rotation.angle() - 0.5f64).abs() < 1e-10);
+    filter.reset();
+    assert!(filter.state().is_none());
+    assert_eq!(filter.filter(&target), target);
+}
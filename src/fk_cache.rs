@@ -0,0 +1,206 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+use std::collections::{BTreeMap, HashMap};
+
+/// Statistics of `FkCache`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FkCacheStats {
+    /// number of the queries which were found in the cache
+    pub hits: usize,
+    /// number of the queries which were calculated
+    pub misses: usize,
+}
+
+impl FkCacheStats {
+    /// Ratio of the hits in all queries. It is zero if nothing is queried.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// LRU cache of the end transforms of a `SerialChain`
+///
+/// The joint positions are quantized by `resolution`, and the positions in the same
+/// bucket share the cached end transform, which is calculated with the first positions
+/// of the bucket. When the cache is full, the least recently used entry is removed.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// let j0 = NodeBuilder::new()
+///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+///     .into_node();
+/// let j1 = NodeBuilder::new()
+///     .translation(Translation3::new(1.0, 0.0, 0.0))
+///     .into_node();
+/// connect![j0 => j1];
+/// let arm = SerialChain::<f64>::from_end(&j1);
+///
+/// let mut cache = FkCache::new(arm, 0.001, 100);
+/// let pose = cache.end_transform(&[0.5]).unwrap();
+/// assert!((pose.translation.vector.x - 0.5f64.cos()).abs() < 1e-10);
+/// // near-identical positions hit the cache
+/// cache.end_transform(&[0.5001]).unwrap();
+/// cache.end_transform(&[0.7]).unwrap();
+/// assert_eq!(cache.stats(), FkCacheStats { hits: 1, misses: 2 });
+/// assert!((cache.stats().hit_rate() - 1.0 / 3.0).abs() < 1e-10);
+/// assert_eq!(cache.len(), 2);
+/// ```
+#[derive(Debug)]
+pub struct FkCache<T: RealField> {
+    chain: SerialChain<T>,
+    resolution: T,
+    capacity: usize,
+    // key -> (end transform, last used tick)
+    entries: HashMap<Vec<i64>, (Isometry3<T>, u64)>,
+    // last used tick -> key, to find the least recently used entry
+    ticks: BTreeMap<u64, Vec<i64>>,
+    tick: u64,
+    stats: FkCacheStats,
+}
+
+impl<T> FkCache<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create `FkCache` of `chain`, which stores `capacity` transforms at most
+    pub fn new(chain: SerialChain<T>, resolution: T, capacity: usize) -> Self {
+        Self {
+            chain,
+            resolution,
+            capacity,
+            entries: HashMap::new(),
+            ticks: BTreeMap::new(),
+            tick: 0,
+            stats: FkCacheStats::default(),
+        }
+    }
+
+    /// Get the end transform of the chain with `positions`
+    ///
+    /// If it is not cached, the joint positions of the chain are set and the
+    /// end transform is calculated.
+    pub fn end_transform(&mut self, positions: &[T]) -> Result<Isometry3<T>, Error> {
+        if positions.len() != self.chain.dof() {
+            return Err(Error::SizeMismatchError {
+                input: positions.len(),
+                required: self.chain.dof(),
+            });
+        }
+        let key = self.quantize(positions);
+        self.tick += 1;
+        if let Some((transform, tick)) = self.entries.get_mut(&key) {
+            self.ticks.remove(tick);
+            *tick = self.tick;
+            self.ticks.insert(self.tick, key);
+            self.stats.hits += 1;
+            return Ok(*transform);
+        }
+        self.stats.misses += 1;
+        self.chain.set_joint_positions(positions)?;
+        let transform = self.chain.end_transform();
+        if self.capacity == 0 {
+            return Ok(transform);
+        }
+        if self.entries.len() >= self.capacity {
+            let oldest = self.ticks.keys().next().cloned();
+            if let Some(oldest) = oldest {
+                let oldest_key = self.ticks.remove(&oldest).expect("tick must exist");
+                self.entries.remove(&oldest_key);
+            }
+        }
+        self.ticks.insert(self.tick, key.clone());
+        self.entries.insert(key, (transform, self.tick));
+        Ok(transform)
+    }
+
+    fn quantize(&self, positions: &[T]) -> Vec<i64> {
+        positions
+            .iter()
+            .map(|p| {
+                let bucket: f64 = na::convert((*p / self.resolution).round());
+                bucket as i64
+            })
+            .collect()
+    }
+
+    /// Get the statistics of the hits and misses
+    pub fn stats(&self) -> FkCacheStats {
+        self.stats
+    }
+
+    /// Number of the cached transforms
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if nothing is cached
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove all cached transforms and reset the statistics
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.ticks.clear();
+        self.stats = FkCacheStats::default();
+    }
+
+    /// Get the wrapped chain
+    pub fn chain(&self) -> &SerialChain<T> {
+        &self.chain
+    }
+
+    /// Unwrap and returns the inner `SerialChain`
+    pub fn into_inner(self) -> SerialChain<T> {
+        self.chain
+    }
+}
+
+#[test]
+fn test_fk_cache_lru() {
+    use super::joint::*;
+    use super::node::*;
+    let j0 = NodeBuilder::new()
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::x_axis(),
+        })
+        .into_node();
+    let mut cache = FkCache::new(SerialChain::<f64>::from_end(&j0), 0.01, 2);
+    cache.end_transform(&[0.1]).unwrap();
+    cache.end_transform(&[0.2]).unwrap();
+    // use 0.1 to make 0.2 the least recently used
+    cache.end_transform(&[0.1]).unwrap();
+    cache.end_transform(&[0.3]).unwrap();
+    assert_eq!(cache.len(), 2);
+    cache.end_transform(&[0.1]).unwrap();
+    assert_eq!(cache.stats(), FkCacheStats { hits: 2, misses: 3 });
+    cache.end_transform(&[0.2]).unwrap();
+    assert_eq!(cache.stats(), FkCacheStats { hits: 2, misses: 4 });
+    assert!(cache.end_transform(&[0.1, 0.2]).is_err());
+}
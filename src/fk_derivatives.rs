@@ -0,0 +1,192 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::funcs::*;
+use super::pose_buffer::*;
+use na::{DMatrix, RealField, Vector3};
+use nalgebra as na;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+use simba::scalar::SubsetOf;
+
+/// Derivatives of the columns of `jacobian()` by the joint positions
+///
+/// The i-th matrix is `∂J/∂q_i`, with the same shape as `jacobian()`. They are
+/// calculated from the columns of the jacobian analytically. The rotation of
+/// `JointType::Floating` changes its own axes, which is not included, so it is
+/// approximate for the chains which have it.
+///
+/// # Examples
+///
+/// ```
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// let positions = vec![0.1, 0.2, 0.0, -0.5, 0.0, -0.3];
+/// arm.set_joint_positions(&positions).unwrap();
+/// let hessian = k::jacobian_hessian(&arm);
+/// let jacobi = k::jacobian(&arm);
+///
+/// // compare with the finite difference
+/// let eps = 1e-6;
+/// let mut moved = positions.clone();
+/// moved[1] += eps;
+/// arm.set_joint_positions(&moved).unwrap();
+/// let diff = (k::jacobian(&arm) - jacobi) / eps;
+/// assert!((diff - &hessian[1]).norm() < 1e-4);
+/// ```
+pub fn jacobian_hessian<T>(arm: &SerialChain<T>) -> Vec<DMatrix<T>>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    hessian_from_jacobian(&jacobian(arm))
+}
+
+fn hessian_from_jacobian<T: RealField>(jacobi: &DMatrix<T>) -> Vec<DMatrix<T>> {
+    let dof = jacobi.ncols();
+    let linear = |j: usize| Vector3::new(jacobi[(0, j)], jacobi[(1, j)], jacobi[(2, j)]);
+    let angular = |j: usize| Vector3::new(jacobi[(3, j)], jacobi[(4, j)], jacobi[(5, j)]);
+    (0..dof)
+        .map(|i| {
+            let mut h = DMatrix::zeros(6, dof);
+            for j in 0..dof {
+                // the joint i moves the axis of j if it is closer to the root,
+                // otherwise it moves only the end
+                let (dv, dw) = if i <= j {
+                    (angular(i).cross(&linear(j)), angular(i).cross(&angular(j)))
+                } else {
+                    (angular(j).cross(&linear(i)), Vector3::zeros())
+                };
+                h.fixed_slice_mut::<3, 1>(0, j).copy_from(&dv);
+                h.fixed_slice_mut::<3, 1>(3, j).copy_from(&dw);
+            }
+            h
+        })
+        .collect()
+}
+
+/// Forward kinematics with its derivatives in flat row-major buffers
+///
+/// It is for the learning pipelines which take the derivatives through FFI, e.g. as
+/// numpy arrays, without deriving them again by autodiff.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct FkDerivatives<T: RealField> {
+    /// number of the movable joints
+    pub dof: usize,
+    /// the end pose, translation (x, y, z) and quaternion (x, y, z, w),
+    /// the same layout as the pose buffers
+    pub pose: Vec<T>,
+    /// `jacobian()` of the shape `(6, dof)`, `jacobian[r * dof + j]`
+    pub jacobian: Vec<T>,
+    /// `jacobian_hessian()` of the shape `(dof, 6, dof)`, `hessian[(i * 6 + r) * dof + j]`
+    /// is `∂J[r][j]/∂q_i`, only if it is requested
+    pub hessian: Option<Vec<T>>,
+}
+
+/// Calculate the end pose and its derivatives of `arm` at the current positions
+///
+/// The second order derivatives are calculated if `second_order` is true.
+///
+/// # Examples
+///
+/// ```
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+///
+/// let fk = k::fk_with_derivatives(&arm, true);
+/// assert_eq!(fk.pose.len(), k::POSE_BUFFER_STRIDE);
+/// assert_eq!(fk.pose[0], arm.end_transform().translation.vector.x);
+/// let jacobi = k::jacobian(&arm);
+/// assert_eq!(fk.jacobian[2 * fk.dof + 3], jacobi[(2, 3)]);
+/// let hessian = k::jacobian_hessian(&arm);
+/// assert_eq!(fk.hessian.unwrap()[(4 * 6 + 1) * fk.dof + 5], hessian[4][(1, 5)]);
+/// ```
+pub fn fk_with_derivatives<T>(arm: &SerialChain<T>, second_order: bool) -> FkDerivatives<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let dof = arm.dof();
+    let end = arm.end_transform();
+    let t = &end.translation.vector;
+    let q = &end.rotation.coords;
+    let pose = vec![t.x, t.y, t.z, q.x, q.y, q.z, q.w];
+    debug_assert_eq!(pose.len(), POSE_BUFFER_STRIDE);
+    let jacobi = jacobian(arm);
+    // nalgebra is column-major
+    let jacobian = jacobi.transpose().as_slice().to_vec();
+    let hessian = if second_order {
+        let mut flat = Vec::with_capacity(dof * 6 * dof);
+        for h in hessian_from_jacobian(&jacobi) {
+            flat.extend_from_slice(h.transpose().as_slice());
+        }
+        Some(flat)
+    } else {
+        None
+    };
+    FkDerivatives {
+        dof,
+        pose,
+        jacobian,
+        hessian,
+    }
+}
+
+#[test]
+fn test_hessian_finite_difference() {
+    use super::joint::*;
+    use super::node::*;
+    let j0 = NodeBuilder::new()
+        .joint_type(JointType::Rotational {
+            axis: Vector3::z_axis(),
+        })
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .translation(na::Translation3::new(0.0, 0.1, 0.3))
+        .joint_type(JointType::Linear {
+            axis: Vector3::x_axis(),
+        })
+        .into_node();
+    let j2 = NodeBuilder::new()
+        .translation(na::Translation3::new(0.2, 0.0, 0.1))
+        .joint_type(JointType::Rotational {
+            axis: Vector3::y_axis(),
+        })
+        .into_node();
+    let j3 = NodeBuilder::new()
+        .translation(na::Translation3::new(0.0, 0.0, 0.4))
+        .joint_type(JointType::Rotational {
+            axis: Vector3::x_axis(),
+        })
+        .into_node();
+    let end = NodeBuilder::new()
+        .translation(na::Translation3::new(0.1, 0.0, 0.2))
+        .into_node();
+    crate::connect![j0 => j1 => j2 => j3 => end];
+    let arm = SerialChain::<f64>::from_end(&end);
+    let positions = vec![0.3, 0.2, -0.4, 0.7];
+    arm.set_joint_positions(&positions).unwrap();
+    let jacobi = jacobian(&arm);
+    let hessian = jacobian_hessian(&arm);
+    let eps = 1e-7;
+    for (i, h) in hessian.iter().enumerate() {
+        let mut moved = positions.clone();
+        moved[i] += eps;
+        arm.set_joint_positions(&moved).unwrap();
+        let diff = (jacobian(&arm) - &jacobi) / eps;
+        assert!((diff - h).norm() < 1e-5, "joint {}", i);
+    }
+}
@@ -0,0 +1,140 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::node::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+use std::ops::{Deref, Index};
+
+/// A joint frame of `SerialChain::frames()`
+#[derive(Debug, Clone)]
+pub struct ChainFrame<T: RealField> {
+    /// name of the joint
+    pub name: String,
+    /// the node of this frame
+    pub node: Node<T>,
+    /// the world transform of the frame
+    pub transform: Isometry3<T>,
+}
+
+/// Ordered joint frames from the root to the end, returned by `SerialChain::frames()`
+///
+/// It derefs to a slice of `ChainFrame`, so it can be indexed and iterated.
+#[derive(Debug, Clone)]
+pub struct ChainFrames<T: RealField> {
+    frames: Vec<ChainFrame<T>>,
+}
+
+impl<T: RealField> ChainFrames<T> {
+    /// Find the frame by the joint name
+    pub fn find(&self, name: &str) -> Option<&ChainFrame<T>> {
+        self.frames.iter().find(|f| f.name == name)
+    }
+
+    /// Index of the frame with the joint name
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.frames.iter().position(|f| f.name == name)
+    }
+
+    /// The transform of the `to`-th frame seen from the `from`-th frame
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of range.
+    pub fn relative_transform(&self, from: usize, to: usize) -> Isometry3<T> {
+        self.frames[from].transform.inverse() * self.frames[to].transform
+    }
+
+    /// The last frame, `None` if the chain is empty
+    pub fn end(&self) -> Option<&ChainFrame<T>> {
+        self.frames.last()
+    }
+
+    pub fn into_vec(self) -> Vec<ChainFrame<T>> {
+        self.frames
+    }
+}
+
+impl<T: RealField> Deref for ChainFrames<T> {
+    type Target = [ChainFrame<T>];
+    fn deref(&self) -> &Self::Target {
+        &self.frames
+    }
+}
+
+impl<T: RealField> Index<usize> for ChainFrames<T> {
+    type Output = ChainFrame<T>;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.frames[index]
+    }
+}
+
+impl<'a, T: RealField> IntoIterator for &'a ChainFrames<T> {
+    type Item = &'a ChainFrame<T>;
+    type IntoIter = std::slice::Iter<'a, ChainFrame<T>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.frames.iter()
+    }
+}
+
+impl<T> SerialChain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Get all the joint frames with the names and the world transforms in order
+    ///
+    /// It calls `update_transforms()` once, so the per-link processing doesn't need
+    /// to traverse the tree for each link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .name("j0")
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .name("j1")
+    ///     .translation(Translation3::new(1.0, 0.0, 0.0))
+    ///     .into_node();
+    /// connect![j0 => j1];
+    /// let arm = SerialChain::<f64>::from_end(&j1);
+    /// arm.set_joint_positions(&[std::f64::consts::FRAC_PI_2]).unwrap();
+    /// let frames = arm.frames();
+    /// assert_eq!(frames.len(), 2);
+    /// assert_eq!(frames[1].name, "j1");
+    /// assert!((frames[1].transform.translation.vector - Vector3::y()).norm() < 1e-10);
+    /// assert_eq!(frames.position("j1"), Some(1));
+    /// let relative = frames.relative_transform(0, 1);
+    /// assert!((relative.translation.vector - Vector3::x()).norm() < 1e-10);
+    /// ```
+    pub fn frames(&self) -> ChainFrames<T> {
+        let transforms = self.update_all_transforms();
+        let frames = self
+            .iter()
+            .zip(transforms)
+            .map(|(node, transform)| ChainFrame {
+                name: node.joint().name.clone(),
+                node: node.clone(),
+                transform,
+            })
+            .collect();
+        ChainFrames { frames }
+    }
+}
@@ -1,40 +1,207 @@
 use super::chain::*;
-use super::joint::*;
-use na::{DMatrix, RealField, Vector3};
+use super::errors::*;
+use super::node::*;
+use na::{DMatrix, DMatrixSlice, DMatrixSliceMut, Matrix6, RealField, Vector3};
 use nalgebra as na;
 use simba::scalar::SubsetOf;
+use std::any::Any;
+use std::cell::RefCell;
+
+thread_local! {
+    /// Buffers for `with_jacobian_scratch()`, a `Vec<T>` for each scalar type
+    static JACOBIAN_SCRATCH: RefCell<Vec<Box<dyn Any>>> = RefCell::new(Vec::new());
+}
 
 /// Calculate Jacobian of the serial chain (manipulator).
 pub fn jacobian<T>(arm: &SerialChain<T>) -> DMatrix<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let mut jacobi = DMatrix::zeros(6, arm.dof());
+    jacobian_into(arm, &mut jacobi);
+    jacobi
+}
+
+/// Calculate Jacobian of the serial chain into `jacobi`
+///
+/// `jacobi` is resized to 6 x `dof()` only if the shape is different, so the matrix
+/// can be reused in control loops without allocating it every cycle.
+///
+/// ```
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// let mut jacobi = k::nalgebra::DMatrix::zeros(6, arm.dof());
+/// k::jacobian_into(&arm, &mut jacobi);
+/// assert_eq!(jacobi, k::jacobian(&arm));
+/// ```
+pub fn jacobian_into<T>(arm: &SerialChain<T>, jacobi: &mut DMatrix<T>)
 where
     T: RealField + SubsetOf<f64>,
 {
     let dof = arm.dof();
-    let t_n = arm.end_transform();
-    arm.update_transforms();
-    let p_n = t_n.translation;
-    let jacobi_vec = arm
-        .iter_joints()
-        .map(|joint| {
-            let t_i = joint.world_transform().unwrap();
-            match joint.joint_type {
-                JointType::Linear { axis } => {
-                    let p_i = t_i.rotation * axis;
-                    [p_i[0], p_i[1], p_i[2], na::zero(), na::zero(), na::zero()]
-                }
-                JointType::Rotational { axis } => {
-                    let p_i = t_i.translation;
-                    let a_i = t_i.rotation * axis;
-                    let dp_i = a_i.cross(&(p_n.vector - p_i.vector));
-                    [dp_i[0], dp_i[1], dp_i[2], a_i[0], a_i[1], a_i[2]]
+    if jacobi.shape() != (6, dof) {
+        *jacobi = DMatrix::zeros(6, dof);
+    }
+    fill_jacobian(arm, jacobi.slice_mut((0, 0), (6, dof)));
+}
+
+/// Calculate Jacobian of the serial chain in the scratch buffer of the current thread,
+/// and call `f` with it
+///
+/// The buffer is reused by the following calls in the same thread, so it does not
+/// allocate the matrix after the first call (or `reserve_jacobian_scratch()`) unless the
+/// DoF is larger. It is reentrant, but the nested calls use temporary buffers.
+///
+/// ```
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// k::reserve_jacobian_scratch::<f64>(7);
+/// let norm = k::with_jacobian_scratch(&arm, |jacobi| jacobi.norm());
+/// assert_eq!(norm, k::jacobian(&arm).norm());
+/// ```
+pub fn with_jacobian_scratch<T, R>(arm: &SerialChain<T>, f: impl FnOnce(DMatrixSlice<T>) -> R) -> R
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let dof = arm.dof();
+    let mut buffer = take_jacobian_scratch::<T>();
+    if buffer.len() < 6 * dof {
+        buffer.resize(6 * dof, T::zero());
+    }
+    let data = &mut buffer[..6 * dof];
+    fill_jacobian(arm, DMatrixSliceMut::from_slice(data, 6, dof));
+    let result = f(DMatrixSlice::from_slice(data, 6, dof));
+    return_jacobian_scratch(buffer);
+    result
+}
+
+/// Allocate the scratch buffer of `with_jacobian_scratch()` for `max_dof` in the current thread
+///
+/// Call it at the start of each worker thread to avoid the allocations in the loops.
+pub fn reserve_jacobian_scratch<T: RealField>(max_dof: usize) {
+    let mut buffer = take_jacobian_scratch::<T>();
+    if buffer.len() < 6 * max_dof {
+        buffer.resize(6 * max_dof, T::zero());
+    }
+    return_jacobian_scratch(buffer);
+}
+
+fn take_jacobian_scratch<T: RealField>() -> Vec<T> {
+    JACOBIAN_SCRATCH.with(|scratch| {
+        let mut scratch = scratch.borrow_mut();
+        match scratch.iter().position(|b| b.is::<Vec<T>>()) {
+            Some(index) => *scratch.swap_remove(index).downcast::<Vec<T>>().unwrap(),
+            None => Vec::new(),
+        }
+    })
+}
+
+fn return_jacobian_scratch<T: RealField>(buffer: Vec<T>) {
+    JACOBIAN_SCRATCH.with(|scratch| {
+        let mut scratch = scratch.borrow_mut();
+        // keep the larger one if a nested call returned a buffer already
+        match scratch.iter().position(|b| b.is::<Vec<T>>()) {
+            Some(index) => {
+                if scratch[index].downcast_ref::<Vec<T>>().unwrap().len() < buffer.len() {
+                    scratch[index] = Box::new(buffer);
                 }
-                JointType::Fixed => panic!("impossible, bug of jacobian"),
             }
-        })
-        .collect::<Vec<_>>();
-    // Pi: a_i x (p_n - Pi)
-    // wi: a_i
-    DMatrix::from_fn(6, dof, |r, c| jacobi_vec[c][r])
+            None => scratch.push(Box::new(buffer)),
+        }
+    })
+}
+
+fn fill_jacobian<T>(arm: &SerialChain<T>, jacobi: DMatrixSliceMut<T>)
+where
+    T: RealField + SubsetOf<f64>,
+{
+    arm.update_transforms();
+    fill_jacobian_at_world_transforms(arm, jacobi);
+}
+
+/// Fill `jacobi` from the world transforms already updated, without allocating
+pub(crate) fn fill_jacobian_at_world_transforms<T>(
+    arm: &SerialChain<T>,
+    mut jacobi: DMatrixSliceMut<T>,
+) where
+    T: RealField + SubsetOf<f64>,
+{
+    let p_n = arm.end_transform().translation.vector;
+    let mut columns = jacobi.column_iter_mut();
+    for joint in arm.iter_joints() {
+        let t_i = joint.world_transform().unwrap();
+        // for the rotation around a_i at p_i,
+        // Pi: a_i x (p_n - p_i)
+        // wi: a_i
+        for (index, mut column) in (0..joint.num_positions()).zip(&mut columns) {
+            let motion = joint.world_motion(&t_i, index, &p_n);
+            column.fixed_rows_mut::<3>(0).copy_from(&motion.translation);
+            column.fixed_rows_mut::<3>(3).copy_from(&motion.rotation);
+        }
+    }
+}
+
+/// Calculate the derivative of the end pose of the serial chain w.r.t. the base placement
+///
+/// The base is the origin of the first node of `arm`, and the columns are its translation
+/// (x, y, z) and rotation (x, y, z) in the frame of its parent, which is the frame of
+/// `SerialChain::end_transform()`. The rotation is about the position of the base.
+/// The rows are the same as `jacobian()` if the first node is the root.
+///
+/// ```
+/// use k::*;
+///
+/// let j0 = NodeBuilder::new()
+///     .translation(Translation3::new(0.0, 0.0, 1.0))
+///     .into_node();
+/// let j1 = NodeBuilder::new()
+///     .translation(Translation3::new(1.0, 0.0, 0.0))
+///     .into_node();
+/// j1.set_parent(&j0);
+/// let arm = SerialChain::from_end(&j1);
+/// let base_jacobi = base_jacobian(&arm);
+/// // rotation of the base around z moves the end toward y
+/// assert_eq!(base_jacobi[(1, 5)], 1.0);
+/// ```
+pub fn base_jacobian<T>(arm: &SerialChain<T>) -> Matrix6<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let t_n = arm.end_transform();
+    // both are relative to the parent of the base
+    let base_position = arm.origin().translation.vector;
+    let mut jacobi = Matrix6::identity();
+    // d(p_n) = v + w x (p_n - p_base)
+    jacobi
+        .fixed_slice_mut::<3, 3>(0, 3)
+        .copy_from(&-(t_n.translation.vector - base_position).cross_matrix());
+    jacobi
+}
+
+/// Calculate `jacobian()` with the 6 columns of `base_jacobian()` appended
+///
+/// It is for the optimization of the placement of the robot, or the floating base.
+///
+/// ```
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// let jacobi = k::jacobian_with_base(&arm);
+/// assert_eq!(jacobi.shape(), (6, arm.dof() + 6));
+/// ```
+pub fn jacobian_with_base<T>(arm: &SerialChain<T>) -> DMatrix<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let dof = arm.dof();
+    let jacobi = jacobian(arm);
+    let base_jacobi = base_jacobian(arm);
+    DMatrix::from_fn(6, dof + 6, |r, c| {
+        if c < dof {
+            jacobi[(r, c)]
+        } else {
+            base_jacobi[(r, c - dof)]
+        }
+    })
 }
 
 /// Calculate the center of mass of the chain
@@ -75,6 +242,141 @@ where
     com / total_mass
 }
 
+/// A point on a link used by `task_points_jacobian()`
+#[derive(Debug, Clone)]
+pub struct TaskPoint<T: RealField> {
+    /// the node which the point is attached to
+    pub node: Node<T>,
+    /// position of the point in the frame of `node`
+    pub offset: Vector3<T>,
+}
+
+impl<T: RealField> TaskPoint<T> {
+    pub fn new(node: Node<T>, offset: Vector3<T>) -> Self {
+        Self { node, offset }
+    }
+}
+
+/// Calculate the stacked Jacobian of the task points w.r.t. all the movable joints of `chain`
+///
+/// The rows are the translation (x, y, z) and the rotation (x, y, z) of each point in
+/// the world frame, so the shape is `6 * points.len()` x `chain.dof()`. The columns are
+/// in the order of `iter_joints()`, and the joints which are not the ancestors of a point
+/// are zero. The transforms are updated only once for all the points, so it is much faster
+/// than calling `jacobian()` for each point.
+/// It returns `Error::JointNotFoundError` if a node of the points is not in `chain`.
+///
+/// ```
+/// use k::*;
+///
+/// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let l_wrist = chain.find("l_wrist_pitch").unwrap();
+/// let r_wrist = chain.find("r_wrist_pitch").unwrap();
+/// let points = vec![
+///     TaskPoint::new(l_wrist.clone(), Vector3::zeros()),
+///     TaskPoint::new(r_wrist.clone(), Vector3::new(0.0, 0.0, -0.1)),
+/// ];
+/// let jacobi = task_points_jacobian(&chain, &points).unwrap();
+/// assert_eq!(jacobi.shape(), (12, chain.dof()));
+///
+/// // the same as the jacobian of the arm for the columns of the arm
+/// let arm = SerialChain::from_end(l_wrist);
+/// let arm_jacobi = jacobian(&arm);
+/// let names = chain.iter_joints().map(|j| j.name.clone()).collect::<Vec<_>>();
+/// for (i, joint) in arm.iter_joints().enumerate() {
+///     let column = names.iter().position(|n| *n == joint.name).unwrap();
+///     for r in 0..6 {
+///         assert!((jacobi[(r, column)] - arm_jacobi[(r, i)]).abs() < 1e-10);
+///     }
+/// }
+/// ```
+pub fn task_points_jacobian<T>(
+    chain: &Chain<T>,
+    points: &[TaskPoint<T>],
+) -> Result<DMatrix<T>, Error>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let transforms = chain.update_all_transforms();
+    let nodes = chain.iter().collect::<Vec<_>>();
+    let parents = nodes
+        .iter()
+        .map(|node| {
+            node.parent()
+                .and_then(|parent| nodes.iter().position(|n| **n == parent))
+        })
+        .collect::<Vec<_>>();
+    // the first column of each node
+    let mut columns = Vec::with_capacity(nodes.len());
+    let mut dof = 0;
+    for node in &nodes {
+        columns.push(dof);
+        dof += node.joint().num_positions();
+    }
+
+    let mut jacobi = DMatrix::zeros(6 * points.len(), dof);
+    for (k, point) in points.iter().enumerate() {
+        let mut index = nodes
+            .iter()
+            .position(|n| **n == point.node)
+            .ok_or_else(|| Error::JointNotFoundError {
+                joint_name: point.node.joint().name.clone(),
+            })?;
+        let p_n = transforms[index] * na::Point3::from(point.offset);
+        loop {
+            let joint = nodes[index].joint();
+            for i in 0..joint.num_positions() {
+                let motion = joint.world_motion(&transforms[index], i, &p_n.coords);
+                let mut column = jacobi.fixed_slice_mut::<6, 1>(6 * k, columns[index] + i);
+                column.fixed_rows_mut::<3>(0).copy_from(&motion.translation);
+                column.fixed_rows_mut::<3>(3).copy_from(&motion.rotation);
+            }
+            match parents[index] {
+                Some(parent) => index = parent,
+                None => break,
+            }
+        }
+    }
+    Ok(jacobi)
+}
+
+/// Calculate the Jacobian of the center of mass of `chain` w.r.t. the movable joints
+///
+/// The shape is 3 x `chain.dof()`. It is the mass-weighted average of the translational
+/// parts of `task_points_jacobian()` at the centers of mass of the links.
+///
+/// ```
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let jacobi = k::center_of_mass_jacobian(&chain);
+/// assert_eq!(jacobi.shape(), (3, chain.dof()));
+/// ```
+pub fn center_of_mass_jacobian<T>(chain: &Chain<T>) -> DMatrix<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let mut points = Vec::new();
+    let mut masses = Vec::new();
+    for node in chain.iter() {
+        if let Some(ref link) = *node.link() {
+            points.push(TaskPoint::new(
+                node.clone(),
+                link.inertial.origin().translation.vector,
+            ));
+            masses.push(link.inertial.mass);
+        }
+    }
+    let total_mass = masses.iter().fold(T::zero(), |sum, m| sum + *m);
+    let mut jacobi = DMatrix::zeros(3, chain.dof());
+    if total_mass <= T::zero() {
+        return jacobi;
+    }
+    let stacked = task_points_jacobian(chain, &points).expect("nodes must be in the chain");
+    for (k, mass) in masses.iter().enumerate() {
+        jacobi += stacked.rows(6 * k, 3) * (*mass / total_mass);
+    }
+    jacobi
+}
+
 #[test]
 fn test_update_center_of_mass() {
     use super::joint::*;
@@ -114,3 +416,67 @@ fn test_update_center_of_mass() {
     assert_eq!(com2.y, 1.0);
     assert!((com2.z - 1.502066).abs() < 0.0001);
 }
+
+#[test]
+fn test_base_jacobian() {
+    use super::joint::*;
+    use super::node::*;
+    use na::*;
+    let j0 = NodeBuilder::new()
+        .translation(Translation3::new(0.1, 0.2, 0.3))
+        .joint_type(JointType::Rotational {
+            axis: Vector3::y_axis(),
+        })
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .translation(Translation3::new(0.5, 0.0, 0.2))
+        .joint_type(JointType::Rotational {
+            axis: Vector3::x_axis(),
+        })
+        .into_node();
+    j1.set_parent(&j0);
+    let arm = SerialChain::from_end(&j1);
+    arm.set_joint_positions(&[0.3, -0.4]).unwrap();
+    let base_jacobi = base_jacobian(&arm);
+    check_base_jacobian(&arm, &base_jacobi);
+    let jacobi = jacobian_with_base(&arm);
+    assert_eq!(jacobi.columns(2, 6), base_jacobi);
+    assert_eq!(jacobi.columns(0, 2), jacobian(&arm));
+
+    // the base which is not the root
+    let j2 = NodeBuilder::new()
+        .translation(Translation3::new(0.0, 0.3, 0.4))
+        .joint_type(JointType::Rotational {
+            axis: Vector3::z_axis(),
+        })
+        .into_node();
+    j2.set_parent(&j1);
+    j2.set_joint_position(0.7).unwrap();
+    let arm = SerialChain::from_end_to_root(&j2, &j1);
+    check_base_jacobian(&arm, &base_jacobian(&arm));
+}
+
+#[cfg(test)]
+fn check_base_jacobian(arm: &SerialChain<f64>, base_jacobi: &Matrix6<f64>) {
+    use na::*;
+    let end = arm.end_transform();
+    let base = arm.origin();
+    const EPS: f64 = 1e-6;
+    for i in 0..6 {
+        let mut delta = Vector6::zeros();
+        delta[i] = EPS;
+        let translation = Translation3::from(delta.fixed_rows::<3>(0).into_owned());
+        let rotation = UnitQuaternion::new(delta.fixed_rows::<3>(3).into_owned());
+        // rotate about the base position
+        let moved = Isometry3::from_parts(translation * base.translation, rotation * base.rotation);
+        arm.set_origin(moved);
+        let moved_end = arm.end_transform();
+        let dp = (moved_end.translation.vector - end.translation.vector) / EPS;
+        let dw = (moved_end.rotation * end.rotation.inverse()).scaled_axis() / EPS;
+        for r in 0..3 {
+            assert!((dp[r] - base_jacobi[(r, i)]).abs() < 1e-4);
+            assert!((dw[r] - base_jacobi[(r + 3, i)]).abs() < 1e-4);
+        }
+    }
+    arm.set_origin(base);
+}
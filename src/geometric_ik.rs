@@ -0,0 +1,260 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::funcs::*;
+use super::ik::*;
+use na::{DMatrix, DVector, Isometry3, RealField, Unit, Vector3};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Geometric target of the end of the arm, solved by `JacobianIkSolver::solve_geometric()`
+///
+/// Only the constrained directions are in the task space, so the arm moves freely
+/// in the others, e.g. along the line of `PointOnLine`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeometricTarget<T: RealField> {
+    /// The origin of the end is anywhere on the line through `point` along `direction`
+    PointOnLine {
+        point: Vector3<T>,
+        direction: Unit<Vector3<T>>,
+    },
+    /// The origin of the end is anywhere on the plane through `point` with `normal`
+    PointOnPlane {
+        point: Vector3<T>,
+        normal: Unit<Vector3<T>>,
+    },
+    /// The XY plane of the end coincides with the plane through `point` with `normal`,
+    /// i.e. the origin is on the plane and the Z axis is along `normal`
+    PlaneOnPlane {
+        point: Vector3<T>,
+        normal: Unit<Vector3<T>>,
+    },
+    /// The origin of the end is at `point` and the Z axis is along `direction`
+    AxisAlignment {
+        point: Vector3<T>,
+        direction: Unit<Vector3<T>>,
+    },
+}
+
+impl<T> GeometricTarget<T>
+where
+    T: RealField,
+{
+    /// Dimension of the task space, the arm needs this DoF at least
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let target = k::GeometricTarget::<f64>::PointOnPlane {
+    ///     point: k::Vector3::zeros(),
+    ///     normal: k::Vector3::z_axis(),
+    /// };
+    /// assert_eq!(target.task_dof(), 1);
+    /// ```
+    pub fn task_dof(&self) -> usize {
+        match self {
+            GeometricTarget::PointOnLine { .. } => 2,
+            GeometricTarget::PointOnPlane { .. } => 1,
+            GeometricTarget::PlaneOnPlane { .. } => 3,
+            GeometricTarget::AxisAlignment { .. } => 5,
+        }
+    }
+
+    fn diff(&self, end: &Isometry3<T>) -> TaskDiff<T> {
+        let p = end.translation.vector;
+        let mut err = Vec::new();
+        let mut rows = Vec::new();
+        let mut push_linear = |axis: Vector3<T>, diff: &Vector3<T>, err: &mut Vec<T>| {
+            err.push(axis.dot(diff));
+            rows.push((axis, Vector3::zeros()));
+        };
+        let (p_diff, w_diff) = match self {
+            GeometricTarget::PointOnLine { point, direction } => {
+                let (a, b) = perpendicular_axes(direction);
+                let diff = point - p;
+                push_linear(a, &diff, &mut err);
+                push_linear(b, &diff, &mut err);
+                (
+                    diff - direction.scale(direction.dot(&diff)),
+                    Vector3::zeros(),
+                )
+            }
+            GeometricTarget::PointOnPlane { point, normal } => {
+                let diff = point - p;
+                push_linear(normal.into_inner(), &diff, &mut err);
+                (normal.scale(normal.dot(&diff)), Vector3::zeros())
+            }
+            GeometricTarget::PlaneOnPlane { point, normal } => {
+                let diff = point - p;
+                push_linear(normal.into_inner(), &diff, &mut err);
+                (
+                    normal.scale(normal.dot(&diff)),
+                    z_alignment_diff(end, normal),
+                )
+            }
+            GeometricTarget::AxisAlignment { point, direction } => {
+                let diff = point - p;
+                push_linear(Vector3::x(), &diff, &mut err);
+                push_linear(Vector3::y(), &diff, &mut err);
+                push_linear(Vector3::z(), &diff, &mut err);
+                (diff, z_alignment_diff(end, direction))
+            }
+        };
+        if let GeometricTarget::PlaneOnPlane { .. } | GeometricTarget::AxisAlignment { .. } = self {
+            // the rotation around the X and Y axes of the end
+            for axis in &[Vector3::x(), Vector3::y()] {
+                let axis = end.rotation * axis;
+                err.push(axis.dot(&w_diff));
+                rows.push((Vector3::zeros(), axis));
+            }
+        }
+        let selector = DMatrix::from_fn(rows.len(), 6, |r, c| {
+            if c < 3 {
+                rows[r].0[c]
+            } else {
+                rows[r].1[c - 3]
+            }
+        });
+        TaskDiff {
+            err: DVector::from_vec(err),
+            selector,
+            len_diff: p_diff,
+            rot_diff: w_diff,
+        }
+    }
+}
+
+/// Error of the end for `GeometricTarget`
+struct TaskDiff<T: RealField> {
+    /// reduced error in the task space
+    err: DVector<T>,
+    /// rows of the task space in the spatial velocity (linear, angular) of the end
+    selector: DMatrix<T>,
+    /// position error in the world
+    len_diff: Vector3<T>,
+    /// rotation error in the world
+    rot_diff: Vector3<T>,
+}
+
+/// Rotation to align the Z axis of `end` to `direction`
+fn z_alignment_diff<T: RealField>(end: &Isometry3<T>, direction: &Unit<Vector3<T>>) -> Vector3<T> {
+    let z = end.rotation * Vector3::z_axis();
+    let angle = z.angle(direction);
+    match Unit::try_new(z.cross(direction), T::default_epsilon()) {
+        Some(axis) => axis.into_inner() * angle,
+        // opposite direction: any axis perpendicular to z is fine
+        None if angle > T::frac_pi_2() => end.rotation * Vector3::x() * angle,
+        None => Vector3::zeros(),
+    }
+}
+
+/// Two unit vectors perpendicular to `direction` and each other
+fn perpendicular_axes<T: RealField>(direction: &Unit<Vector3<T>>) -> (Vector3<T>, Vector3<T>) {
+    // the world axis least parallel to the direction
+    let reference = if direction.x.abs() < na::convert(0.9) {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    let a = direction.cross(&reference).normalize();
+    let b = direction.cross(&a);
+    (a, b)
+}
+
+impl<T> JacobianIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Move the end of the `arm` to satisfy the geometric `target`
+    ///
+    /// The task space is reduced to the constrained directions of the target, so
+    /// the remaining DoF are used freely, e.g. the tool origin can slide along the line.
+    /// It is common in machining and scanning applications.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("r_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let end = arm.end_transform();
+    ///
+    /// // the tool origin is anywhere on the vertical line
+    /// let point = end.translation.vector + k::Vector3::new(0.05, 0.02, 0.0);
+    /// let solver = k::JacobianIkSolver::default();
+    /// solver
+    ///     .solve_geometric(&arm, &k::GeometricTarget::PointOnLine { point, direction: k::Vector3::z_axis() })
+    ///     .unwrap();
+    /// let position = arm.end_transform().translation.vector;
+    /// assert!((position.x - point.x).abs() < 0.001);
+    /// assert!((position.y - point.y).abs() < 0.001);
+    ///
+    /// // the tool plane on the tilted plane
+    /// let normal = k::UnitQuaternion::from_euler_angles(0.2, 0.0, 0.0) * end.rotation * k::Vector3::z_axis();
+    /// solver
+    ///     .solve_geometric(&arm, &k::GeometricTarget::PlaneOnPlane { point, normal })
+    ///     .unwrap();
+    /// let end = arm.end_transform();
+    /// assert!(normal.dot(&(end.translation.vector - point)).abs() < 0.001);
+    /// assert!((end.rotation * k::Vector3::z()).angle(&normal) < 0.005);
+    /// ```
+    pub fn solve_geometric(
+        &self,
+        arm: &SerialChain<T>,
+        target: &GeometricTarget<T>,
+    ) -> Result<(), Error> {
+        let orig_positions = arm.joint_positions();
+        if orig_positions.len() < target.task_dof() {
+            return Err(Error::PreconditionError {
+                dof: orig_positions.len(),
+                necessary_dof: target.task_dof(),
+            });
+        }
+        let weights = self.resolve_joint_weights(&movable_nodes(arm))?;
+        let mut last_diff = None;
+        for i in 0..self.num_max_try {
+            let TaskDiff { err, selector, .. } = target.diff(&arm.end_transform());
+            let jacobi = self.measure("ik/jacobian", i, || selector * jacobian(arm));
+            let positions_vec = self.calc_next_positions(
+                jacobi,
+                err,
+                &arm.joint_positions(),
+                weights.as_deref(),
+                i,
+            )?;
+            let (len_diff, rot_diff) = self.measure("ik/update", i, || {
+                arm.set_joint_positions_clamped(&positions_vec);
+                let diff = target.diff(&arm.end_transform());
+                (diff.len_diff, diff.rot_diff)
+            });
+            if self.is_reached(&len_diff, &rot_diff) {
+                return Ok(());
+            }
+            last_diff = Some((len_diff, rot_diff));
+        }
+        arm.set_joint_positions(&orig_positions)?;
+        let (len_diff, rot_diff) =
+            last_diff.unwrap_or_else(|| (Vector3::zeros(), Vector3::zeros()));
+        Err(Error::NotConvergedError {
+            num_tried: self.num_max_try,
+            position_diff: na::try_convert(len_diff).unwrap_or_default(),
+            rotation_diff: na::try_convert(rot_diff).unwrap_or_default(),
+        })
+    }
+}
@@ -0,0 +1,242 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::link::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+use std::fmt::Write;
+
+/// Options of `Chain::to_gltf_with_options()`
+#[derive(Debug, Clone, Default)]
+pub struct GltfExportOptions {
+    /// Add a child node for each visual of the links.
+    ///
+    /// The geometry is stored in `extras` of the node (e.g. the file name of the mesh),
+    /// because glTF cannot embed the mesh files of URDF without converting them.
+    pub include_visuals: bool,
+}
+
+struct GltfNode {
+    name: String,
+    transform: Isometry3<f64>,
+    children: Vec<usize>,
+    extras: Option<String>,
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Export the node hierarchy posed at the current joint positions as glTF 2.0 JSON
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let gltf = chain.to_gltf();
+    /// assert!(gltf.contains(r#""version":"2.0""#));
+    /// assert!(gltf.contains(r#""name":"l_elbow_pitch""#));
+    /// ```
+    pub fn to_gltf(&self) -> String {
+        self.to_gltf_with_options(&GltfExportOptions::default())
+    }
+
+    /// Export as glTF 2.0 JSON with options
+    pub fn to_gltf_with_options(&self, options: &GltfExportOptions) -> String {
+        let nodes = self.iter().collect::<Vec<_>>();
+        let mut gltf_nodes = nodes
+            .iter()
+            .map(|node| {
+                let joint = node.joint();
+                GltfNode {
+                    name: joint.name.clone(),
+                    transform: na::convert(joint.local_transform()),
+                    children: Vec::new(),
+                    extras: None,
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut scene_nodes = Vec::new();
+        for (i, node) in nodes.iter().enumerate() {
+            match node
+                .parent()
+                .and_then(|p| nodes.iter().position(|n| **n == p))
+            {
+                Some(parent) => gltf_nodes[parent].children.push(i),
+                None => scene_nodes.push(i),
+            }
+        }
+        if options.include_visuals {
+            for (i, node) in nodes.iter().enumerate() {
+                let visuals = match *node.link() {
+                    Some(ref link) => link
+                        .visuals
+                        .iter()
+                        .map(|visual| GltfNode {
+                            name: visual.name.clone(),
+                            transform: na::convert(*visual.origin()),
+                            children: Vec::new(),
+                            extras: Some(geometry_extras(&visual.geometry)),
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                };
+                for visual in visuals {
+                    let index = gltf_nodes.len();
+                    gltf_nodes[i].children.push(index);
+                    gltf_nodes.push(visual);
+                }
+            }
+        }
+
+        let mut json = String::new();
+        json.push_str(
+            r#"{"asset":{"version":"2.0","generator":"k"},"scene":0,"scenes":[{"nodes":"#,
+        );
+        json.push_str(&index_array(&scene_nodes));
+        json.push_str(r#"}],"nodes":["#);
+        for (i, node) in gltf_nodes.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let t = &node.transform.translation.vector;
+            let q = &node.transform.rotation.coords;
+            write!(
+                json,
+                r#"{{"name":{},"translation":[{},{},{}],"rotation":[{},{},{},{}]"#,
+                json_string(&node.name),
+                t.x,
+                t.y,
+                t.z,
+                q.x,
+                q.y,
+                q.z,
+                q.w
+            )
+            .unwrap();
+            if !node.children.is_empty() {
+                write!(json, r#","children":{}"#, index_array(&node.children)).unwrap();
+            }
+            if let Some(ref extras) = node.extras {
+                write!(json, r#","extras":{}"#, extras).unwrap();
+            }
+            json.push('}');
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+fn index_array(indices: &[usize]) -> String {
+    let items = indices.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+    format!("[{}]", items.join(","))
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn geometry_extras<T>(geometry: &Geometry<T>) -> String
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let f = |v: T| na::convert::<T, f64>(v);
+    match geometry {
+        Geometry::Box {
+            depth,
+            width,
+            height,
+        } => format!(
+            r#"{{"geometry":"box","size":[{},{},{}]}}"#,
+            f(*depth),
+            f(*width),
+            f(*height)
+        ),
+        Geometry::Cylinder { radius, length } => format!(
+            r#"{{"geometry":"cylinder","radius":{},"length":{}}}"#,
+            f(*radius),
+            f(*length)
+        ),
+        Geometry::Capsule { radius, length } => format!(
+            r#"{{"geometry":"capsule","radius":{},"length":{}}}"#,
+            f(*radius),
+            f(*length)
+        ),
+        Geometry::Sphere { radius } => {
+            format!(r#"{{"geometry":"sphere","radius":{}}}"#, f(*radius))
+        }
+        Geometry::Mesh { filename, scale } => format!(
+            r#"{{"geometry":"mesh","uri":{},"scale":[{},{},{}]}}"#,
+            json_string(filename),
+            f(scale[0]),
+            f(scale[1]),
+            f(scale[2])
+        ),
+    }
+}
+
+#[test]
+fn test_gltf_hierarchy() {
+    use super::joint::*;
+    use super::node::*;
+    let j0 = NodeBuilder::new()
+        .name("j\"0")
+        .joint_type(JointType::Rotational {
+            axis: na::Vector3::z_axis(),
+        })
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .name("j1")
+        .translation(na::Translation3::new(1.0, 0.0, 0.0))
+        .into_node();
+    j1.set_parent(&j0);
+    j1.set_link(Some(Link {
+        visuals: vec![Visual::new(
+            "v".to_owned(),
+            Isometry3::identity(),
+            Geometry::Sphere { radius: 0.5 },
+            Material::default(),
+        )],
+        ..Default::default()
+    }));
+    let chain = Chain::<f64>::from_root(j0);
+    chain.set_joint_positions(&[std::f64::consts::PI]).unwrap();
+    let gltf = chain.to_gltf();
+    assert!(gltf.starts_with(
+        r#"{"asset":{"version":"2.0","generator":"k"},"scene":0,"scenes":[{"nodes":[0]}]"#
+    ));
+    assert!(gltf.contains(r#"{"name":"j\"0","translation":[0,0,0],"rotation":[0,0,1,"#));
+    assert!(gltf.contains(r#""children":[1]"#));
+    assert!(!gltf.contains("sphere"));
+    let gltf = chain.to_gltf_with_options(&GltfExportOptions {
+        include_visuals: true,
+    });
+    assert!(gltf.contains(r#""name":"v","translation":[0,0,0],"rotation":[0,0,0,1],"extras""#));
+    assert!(gltf.contains(r#""children":[2]}"#));
+    assert!(gltf.contains(r#""extras":{"geometry":"sphere","radius":0.5}"#));
+}
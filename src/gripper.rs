@@ -0,0 +1,299 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::joint::*;
+use super::node::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Number of the bisections to find the position of `Gripper::set_opening()`
+const NUM_BISECTIONS: usize = 64;
+
+/// Two-finger gripper driven by one actuated joint, whose fingers follow it by mimics
+///
+/// The opening is the distance between the frames of the two fingers, calculated
+/// through the kinematics, so it works for both prismatic and revolute fingers if the
+/// finger frames are placed at the tips. It must change monotonically in the limits of
+/// the actuated joint.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// let base = NodeBuilder::<f64>::new().name("base").into_node();
+/// let left = NodeBuilder::new()
+///     .name("finger_l")
+///     .joint_type(JointType::Linear{axis: Vector3::y_axis()})
+///     .limits(Some((0.0..=0.04).into()))
+///     .into_node();
+/// let right = NodeBuilder::new()
+///     .name("finger_r")
+///     .joint_type(JointType::Linear{axis: -Vector3::y_axis()})
+///     .limits(Some((0.0..=0.04).into()))
+///     .into_node();
+/// left.set_parent(&base);
+/// right.set_parent(&base);
+/// right.set_mimic_parent(&left, joint::Mimic::new(1.0, 0.0));
+/// let chain = Chain::from_root(base);
+///
+/// let grippers = Gripper::find(&chain);
+/// assert_eq!(grippers.len(), 1);
+/// let gripper = &grippers[0];
+/// assert_eq!(gripper.joint().joint().name, "finger_l");
+///
+/// gripper.set_opening(0.05).unwrap();
+/// assert!((left.joint_position().unwrap() - 0.025).abs() < 1e-9);
+/// assert!((gripper.open_fraction() - 0.625).abs() < 1e-9);
+/// // too wide
+/// assert!(gripper.set_opening(0.1).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Gripper<T: RealField> {
+    joint: Node<T>,
+    fingers: [Node<T>; 2],
+}
+
+impl<T> Gripper<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create a gripper of the actuated `joint`, whose opening is the distance of `fingers`
+    ///
+    /// The fingers can be any nodes moved by the joint, e.g. fixed nodes at the tips.
+    /// Returns `None` if `joint` is not movable, mimics another joint or has no limits.
+    pub fn new(joint: Node<T>, fingers: [Node<T>; 2]) -> Option<Self> {
+        if !joint.joint().is_movable()
+            || joint.mimic_parent().is_some()
+            || joint.joint().limits.is_none()
+        {
+            return None;
+        }
+        Some(Self { joint, fingers })
+    }
+
+    /// Find the common two-finger patterns in `chain`
+    ///
+    /// An actuated joint with limits, followed by one mimic joint, is a gripper whose
+    /// fingers are the two joints (e.g. parallel prismatic fingers). An actuated joint
+    /// followed by two mimic joints is a gripper whose fingers are the mimic joints.
+    /// Use `Gripper::new()` to place the finger frames at the tips.
+    pub fn find(chain: &Chain<T>) -> Vec<Self> {
+        chain
+            .iter()
+            .filter_map(|node| {
+                let followers = node.lock().mimic_children.clone();
+                let fingers = match followers.as_slice() {
+                    [follower] => [node.clone(), follower.clone()],
+                    [first, second] => [first.clone(), second.clone()],
+                    _ => return None,
+                };
+                Self::new(node.clone(), fingers)
+            })
+            .collect()
+    }
+
+    /// The actuated joint
+    pub fn joint(&self) -> &Node<T> {
+        &self.joint
+    }
+
+    /// The nodes whose distance is the opening
+    pub fn fingers(&self) -> &[Node<T>; 2] {
+        &self.fingers
+    }
+
+    /// The current distance between the fingers
+    pub fn opening(&self) -> T {
+        self.opening_with(None)
+    }
+
+    /// The openings at the lower and the upper limits of the joint
+    ///
+    /// The joint is not moved.
+    pub fn opening_range(&self) -> (T, T) {
+        let range = self.limits();
+        (self.opening_at(range.min), self.opening_at(range.max))
+    }
+
+    /// The current opening normalized to 0.0 (closed) to 1.0 (fully open)
+    pub fn open_fraction(&self) -> T {
+        let (a, b) = self.opening_range();
+        let (closed, open) = (a.min(b), a.max(b));
+        if open <= closed {
+            return T::zero();
+        }
+        (self.opening() - closed) / (open - closed)
+    }
+
+    /// Open by the fraction, 0.0 is closed and 1.0 is fully open
+    pub fn set_open_fraction(&self, fraction: T) -> Result<(), Error> {
+        let (a, b) = self.opening_range();
+        let (closed, open) = (a.min(b), a.max(b));
+        self.set_opening(closed + (open - closed) * fraction)
+    }
+
+    /// Move the joint to make the distance between the fingers `width`
+    ///
+    /// Returns `Error::OutOfLimitError` with the range of the opening if `width` is not
+    /// achievable in the limits of the joint, and the position is not changed.
+    pub fn set_opening(&self, width: T) -> Result<(), Error> {
+        let limits = self.limits();
+        let (mut low, mut high) = (limits.min, limits.max);
+        let (low_width, high_width) = (self.opening_at(low), self.opening_at(high));
+        let (min_width, max_width) = (low_width.min(high_width), low_width.max(high_width));
+        if width < min_width || width > max_width {
+            return Err(Error::OutOfLimitError {
+                joint_name: self.joint.joint().name.clone(),
+                position: na::convert(width),
+                max_limit: na::convert(max_width),
+                min_limit: na::convert(min_width),
+            });
+        }
+        let increasing = high_width >= low_width;
+        for _ in 0..NUM_BISECTIONS {
+            let middle = (low + high) * na::convert(0.5);
+            if (self.opening_at(middle) < width) == increasing {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        // the mimic joints can have narrower limits
+        self.joint
+            .set_joint_position_with_policy((low + high) * na::convert(0.5), LimitPolicy::Clamp)
+    }
+
+    fn limits(&self) -> Range<T> {
+        self.joint
+            .joint()
+            .limits
+            .expect("Must be a bug: gripper joint must have limits")
+    }
+
+    /// The opening if the joint is at `position`, without moving it
+    fn opening_at(&self, position: T) -> T {
+        self.opening_with(Some(position))
+    }
+
+    /// The opening with the joint at `position`, or the current one if `None`
+    fn opening_with(&self, position: Option<T>) -> T {
+        let local_transform = |node: &Node<T>| {
+            let mut joint = node.joint().clone();
+            if let Some(position) = position.and_then(|p| self.position_of(node, p)) {
+                // same as LimitPolicy::Clamp of the mimic joints
+                joint.set_joint_position_clamped(position);
+            }
+            joint.local_transform()
+        };
+        let diff = transform_from_root(&self.fingers[0], &local_transform)
+            .translation
+            .vector
+            - transform_from_root(&self.fingers[1], &local_transform)
+                .translation
+                .vector;
+        diff.norm()
+    }
+
+    /// The position of `node` when the joint is at `position`, `None` if it doesn't follow it
+    fn position_of(&self, node: &Node<T>, position: T) -> Option<T> {
+        if *node == self.joint {
+            return Some(position);
+        }
+        match (node.mimic_parent(), node.mimic()) {
+            (Some(parent), Some(mimic)) if parent == self.joint => {
+                Some(mimic.mimic_position(position))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The transform of `node` from its root by `local_transform` of each node, without using
+/// the cache of the world transforms
+fn transform_from_root<T, F>(node: &Node<T>, local_transform: &F) -> Isometry3<T>
+where
+    T: RealField + SubsetOf<f64>,
+    F: Fn(&Node<T>) -> Isometry3<T>,
+{
+    let mut transform = local_transform(node);
+    let mut parent = node.parent();
+    while let Some(p) = parent {
+        transform = local_transform(&p) * transform;
+        parent = p.parent();
+    }
+    transform
+}
+
+#[test]
+fn test_revolute_gripper() {
+    use na::{Translation3, Vector3};
+
+    // the actuated joint drives two revolute fingers of 0.1 length at ±0.02
+    let base = NodeBuilder::<f64>::new()
+        .name("gripper")
+        .joint_type(JointType::Rotational {
+            axis: Vector3::z_axis(),
+        })
+        .limits(Some((0.0..=0.5).into()))
+        .into_node();
+    let finger = |name: &str, y: f64, axis| {
+        let node = NodeBuilder::new()
+            .name(name)
+            .joint_type(JointType::Rotational { axis })
+            .translation(Translation3::new(0.0, y, 0.0))
+            .limits(Some((0.0..=0.5).into()))
+            .into_node();
+        let tip = NodeBuilder::new()
+            .name(&format!("{}_tip", name))
+            .translation(Translation3::new(0.1, 0.0, 0.0))
+            .into_node();
+        tip.set_parent(&node);
+        node.set_parent(&base);
+        node.set_mimic_parent(&base, Mimic::new(1.0, 0.0));
+        (node, tip)
+    };
+    let (left, left_tip) = finger("left", 0.02, Vector3::z_axis());
+    let (_right, right_tip) = finger("right", -0.02, -Vector3::z_axis());
+    let chain = Chain::from_root(base.clone());
+
+    let grippers = Gripper::find(&chain);
+    assert_eq!(grippers.len(), 1);
+    assert_eq!(grippers[0].fingers()[0], left);
+    let gripper = Gripper::new(base.clone(), [left_tip, right_tip]).unwrap();
+    base.set_joint_position(0.1).unwrap();
+
+    let (closed, open) = gripper.opening_range();
+    assert!((closed - 0.04).abs() < 1e-10);
+    assert!((open - (0.04 + 0.2 * 0.5f64.sin())).abs() < 1e-10);
+    // the joint is not moved by the getters
+    assert_eq!(base.joint_position().unwrap(), 0.1);
+    let fraction = gripper.open_fraction();
+    assert!((fraction - 0.1f64.sin() / 0.5f64.sin()).abs() < 1e-10);
+    assert_eq!(base.joint_position().unwrap(), 0.1);
+
+    gripper.set_opening(0.1).unwrap();
+    assert!((gripper.opening() - 0.1).abs() < 1e-9);
+    assert!((left.joint_position().unwrap() - 0.3f64.asin()).abs() < 1e-9);
+    let position = base.joint_position().unwrap();
+    assert!(gripper.set_opening(0.2).is_err());
+    assert_eq!(base.joint_position().unwrap(), position);
+
+    gripper.set_open_fraction(1.0).unwrap();
+    assert!((base.joint_position().unwrap() - 0.5).abs() < 1e-9);
+}
@@ -13,15 +13,28 @@
   See the License for the specific language governing permissions and
   limitations under the License.
 */
-use na::{DVector, Isometry3, RealField, Vector3, Vector6};
+use na::{
+    DMatrix, DMatrixSlice, DVector, Isometry3, Matrix6, RealField, Unit, UnitQuaternion, Vector2,
+    Vector3, Vector6,
+};
 use nalgebra as na;
 #[cfg(feature = "serde-serialize")]
 use serde::{Deserialize, Serialize};
 use simba::scalar::SubsetOf;
+use std::fmt;
+use std::sync::Mutex;
 
+use super::angles;
 use super::chain::*;
+use super::clearance::*;
 use super::errors::*;
 use super::funcs::*;
+use super::geometric_ik::*;
+use super::multi_ik::{BalanceTask, MultiChainTask};
+use super::node::*;
+use super::profile::*;
+use super::tolerances::*;
+use super::world::{current_arm_root_transform, current_world_transform};
 
 /// From 'Humanoid Robot (Kajita)' P.64
 fn calc_pose_diff<T>(a: &Isometry3<T>, b: &Isometry3<T>) -> Vector6<T>
@@ -29,21 +42,47 @@ where
     T: RealField,
 {
     let p_diff = a.translation.vector - b.translation.vector;
-    let w_diff = b.rotation.rotation_to(&a.rotation).scaled_axis();
+    let w_diff = angles::rotation_diff(&a.rotation, &b.rotation);
     Vector6::new(
         p_diff[0], p_diff[1], p_diff[2], w_diff[0], w_diff[1], w_diff[2],
     )
 }
 
-fn calc_pose_diff_with_constraints<T>(
+/// Difference of `a` from `b`, expressed in the frame of `b` if `frame` is
+/// `ConstraintFrame::End`
+fn calc_pose_diff_in_frame<T>(
     a: &Isometry3<T>,
     b: &Isometry3<T>,
-    operational_space: [bool; 6],
+    frame: ConstraintFrame,
+) -> Vector6<T>
+where
+    T: RealField,
+{
+    let mut full_diff = calc_pose_diff(a, b);
+    if frame == ConstraintFrame::End {
+        let inv = b.rotation.inverse();
+        let p_diff = inv * full_diff.fixed_rows::<3>(0).into_owned();
+        let w_diff = inv * full_diff.fixed_rows::<3>(3).into_owned();
+        full_diff.fixed_rows_mut::<3>(0).copy_from(&p_diff);
+        full_diff.fixed_rows_mut::<3>(3).copy_from(&w_diff);
+    }
+    full_diff
+}
+
+/// Difference of `a` from `b` in the coordinates selected by `constraints`
+///
+/// If `constraints.frame` is `ConstraintFrame::End`, the difference is expressed in
+/// the frame of `b`, the current end.
+pub(crate) fn calc_pose_diff_with_constraints<T>(
+    a: &Isometry3<T>,
+    b: &Isometry3<T>,
+    constraints: &Constraints,
 ) -> DVector<T>
 where
     T: RealField,
 {
-    let full_diff = calc_pose_diff(a, b);
+    let operational_space = define_operational_space(constraints);
+    let full_diff = calc_pose_diff_in_frame(a, b, constraints.frame);
     let use_dof = operational_space.iter().filter(|x| **x).count();
     let mut diff = DVector::from_element(use_dof, na::zero());
     let mut index = 0;
@@ -56,12 +95,57 @@ where
     diff
 }
 
+/// Coordinates of the flags of `Constraints`
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let end = arm.end_transform();
+/// // move, and rotate a lot about the tool axis
+/// let mut target = end;
+/// target.translation.vector.z += 0.02;
+/// target.rotation = end.rotation * k::UnitQuaternion::from_euler_angles(0.0, 0.0, 2.0);
+///
+/// // the rotation about the tool axis is free
+/// let constraints = k::Constraints {
+///     rotation_z: false,
+///     frame: k::ConstraintFrame::End,
+///     ..Default::default()
+/// };
+/// let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+/// solver.solve_with_constraints(&arm, &target, &constraints).unwrap();
+/// let solved = arm.end_transform();
+/// assert!((solved.translation.vector - target.translation.vector).norm() < 0.001);
+/// let tool_axis = solved.rotation * k::Vector3::z();
+/// assert!(tool_axis.angle(&(target.rotation * k::Vector3::z())) < 0.002);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum ConstraintFrame {
+    /// The axes of the world
+    World,
+    /// The axes of the end of the arm (tool), e.g. `rotation_z: false` leaves the
+    /// rotation about the tool axis free for drilling or pointing tasks
+    End,
+}
+
+impl Default for ConstraintFrame {
+    fn default() -> Self {
+        ConstraintFrame::World
+    }
+}
+
 /// A bundle of flags determining which coordinates are constrained for a target
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
 pub struct Constraints {
     /// true means the constraint is used.
-    ///  The coordinates is the world, not the end of the arm.
+    ///  The coordinates is the world by default, see `frame`.
     #[cfg_attr(feature = "serde-serialize", serde(default = "default_true"))]
     pub position_x: bool,
     #[cfg_attr(feature = "serde-serialize", serde(default = "default_true"))]
@@ -76,6 +160,9 @@ pub struct Constraints {
     pub rotation_z: bool,
     #[cfg_attr(feature = "serde-serialize", serde(default))]
     pub ignored_joint_names: Vec<String>,
+    /// The coordinates of the position and rotation flags
+    #[cfg_attr(feature = "serde-serialize", serde(default))]
+    pub frame: ConstraintFrame,
 }
 
 fn default_true() -> bool {
@@ -94,6 +181,7 @@ impl Default for Constraints {
     /// assert!(c.rotation_y);
     /// assert!(c.rotation_z);
     /// assert!(c.ignored_joint_names.is_empty());
+    /// assert_eq!(c.frame, k::ConstraintFrame::World);
     /// ```
     fn default() -> Self {
         Self {
@@ -104,11 +192,43 @@ impl Default for Constraints {
             rotation_y: default_true(),
             rotation_z: default_true(),
             ignored_joint_names: Default::default(),
+            frame: ConstraintFrame::default(),
+        }
+    }
+}
+
+/// Rows of the full jacobian selected by `constraints`, rotated into `end` if
+/// `constraints.frame` is `ConstraintFrame::End`
+pub(crate) fn constrained_jacobian<T>(
+    full: DMatrixSlice<'_, T>,
+    end: &Isometry3<T>,
+    constraints: &Constraints,
+) -> DMatrix<T>
+where
+    T: RealField,
+{
+    let operational_space = define_operational_space(constraints);
+    let rows = (0..6).filter(|i| operational_space[*i]).collect::<Vec<_>>();
+    match constraints.frame {
+        ConstraintFrame::World => {
+            DMatrix::from_fn(rows.len(), full.ncols(), |r, c| full[(rows[r], c)])
+        }
+        ConstraintFrame::End => {
+            let inv = end.rotation.to_rotation_matrix().inverse();
+            let linear = inv.matrix() * full.fixed_rows::<3>(0);
+            let angular = inv.matrix() * full.fixed_rows::<3>(3);
+            DMatrix::from_fn(rows.len(), full.ncols(), |r, c| {
+                if rows[r] < 3 {
+                    linear[(rows[r], c)]
+                } else {
+                    angular[(rows[r] - 3, c)]
+                }
+            })
         }
     }
 }
 
-fn define_operational_space(constraints: &Constraints) -> [bool; 6] {
+pub(crate) fn define_operational_space(constraints: &Constraints) -> [bool; 6] {
     let mut arr = [true; 6];
     arr[0] = constraints.position_x;
     arr[1] = constraints.position_y;
@@ -119,6 +239,146 @@ fn define_operational_space(constraints: &Constraints) -> [bool; 6] {
     arr
 }
 
+type NullspaceFunction<T> = Box<dyn Fn(&[T]) -> Vec<T> + Send + Sync>;
+
+/// Secondary task of redundant arms, minimized in the nullspace of the jacobian
+///
+/// The joints move along `-gain * gradient` as far as it doesn't disturb the end,
+/// so the secondary task never compromises the target pose.
+pub struct NullspaceObjective<T: RealField> {
+    gradient: NullspaceFunction<T>,
+    /// step size of the gradient descent in each iteration
+    pub gain: T,
+}
+
+impl<T> NullspaceObjective<T>
+where
+    T: RealField,
+{
+    /// Create the objective from the gradient of the cost by the joint positions
+    pub fn new<F>(gradient: F, gain: T) -> Self
+    where
+        F: Fn(&[T]) -> Vec<T> + Send + Sync + 'static,
+    {
+        Self {
+            gradient: Box::new(gradient),
+            gain,
+        }
+    }
+
+    /// Bias the joints toward the rest posture
+    ///
+    /// The cost is `1/2 (q - q_rest)ᵀ W (q - q_rest)`, where `W` is the diagonal matrix
+    /// of `weights`. Zero weight leaves the joint free.
+    pub fn rest_posture(positions: Vec<T>, weights: Vec<T>, gain: T) -> Self {
+        Self::new(
+            create_reference_positions_nullspace_function(positions, weights),
+            gain,
+        )
+    }
+
+    /// Push the joints toward the centers of their limits
+    ///
+    /// The cost is `1/2 Σ ((q - c) / h)²`, where `c` is the center and `h` is the half of
+    /// the range of the limits of each movable joint of `arm`, so the joints near their
+    /// limits are pushed more. The joints without limits are free. The limits are read
+    /// when it is created.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let wrist = chain.find("l_wrist_pitch").unwrap();
+    /// let arm = k::SerialChain::from_end(wrist);
+    /// let objective = k::NullspaceObjective::limit_avoidance(&arm, 0.5);
+    /// // the range of l_elbow_pitch is [-2, 3], so it is pushed to 0.5
+    /// let step = objective.step(&[0.0, 0.0, 0.5, 2.5, 0.5, 0.5]);
+    /// assert_eq!(step[0], 0.0);
+    /// assert_eq!(step[2], 0.0);
+    /// assert!(step[3] < 0.0);
+    /// ```
+    pub fn limit_avoidance(arm: &SerialChain<T>, gain: T) -> Self
+    where
+        T: SubsetOf<f64>,
+    {
+        let two = T::one() + T::one();
+        let ranges = arm
+            .iter_position_nodes()
+            .map(|node| {
+                node.joint().limits.as_ref().and_then(|range| {
+                    let half = (range.max - range.min) / two;
+                    if half > T::zero() {
+                        Some(((range.max + range.min) / two, half))
+                    } else {
+                        None
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        Self::new(
+            move |positions: &[T]| {
+                positions
+                    .iter()
+                    .zip(&ranges)
+                    .map(|(q, range)| match range {
+                        Some((center, half)) => (*q - *center) / (*half * *half),
+                        None => T::zero(),
+                    })
+                    .collect()
+            },
+            gain,
+        )
+    }
+
+    /// Minimize the sum of the costs of two objectives, weighted by their gains
+    ///
+    /// The gain of the combined objective is one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let a = k::NullspaceObjective::new(|q: &[f64]| q.to_vec(), 0.5);
+    /// let b = k::NullspaceObjective::new(|q: &[f64]| vec![1.0; q.len()], 0.1);
+    /// let combined = a.combine(b);
+    /// assert_eq!(combined.step(&[2.0]), vec![-1.1]);
+    /// ```
+    pub fn combine(self, other: Self) -> Self
+    where
+        T: 'static,
+    {
+        let (a, a_gain) = (self.gradient, self.gain);
+        let (b, b_gain) = (other.gradient, other.gain);
+        Self::new(
+            move |positions: &[T]| {
+                a(positions)
+                    .into_iter()
+                    .zip(b(positions))
+                    .map(|(a, b)| a * a_gain + b * b_gain)
+                    .collect()
+            },
+            T::one(),
+        )
+    }
+
+    /// The step of the joint positions, `-gain * gradient`
+    pub fn step(&self, positions: &[T]) -> Vec<T> {
+        (self.gradient)(positions)
+            .into_iter()
+            .map(|g| -self.gain * g)
+            .collect()
+    }
+}
+
+impl<T: RealField> fmt::Debug for NullspaceObjective<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NullspaceObjective")
+            .field("gain", &self.gain)
+            .finish()
+    }
+}
+
 /// IK solver
 pub trait InverseKinematicsSolver<T>
 where
@@ -135,20 +395,447 @@ where
         target_pose: &Isometry3<T>,
         constraints: &Constraints,
     ) -> Result<(), Error>;
+    /// Same as `solve_with_constraints()`, but returns the number of the iterations
+    ///
+    /// It returns `Ok(None)` if the solver does not count the iterations.
+    fn solve_with_iterations(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<Option<usize>, Error> {
+        self.solve_with_constraints(arm, target_pose, constraints)
+            .map(|_| None)
+    }
+    /// Allocate the buffers of the solver for `arm` in advance
+    ///
+    /// The solvers supporting it don't allocate memory in the following solves of
+    /// the arms of the same DoF, so that they can run in real-time control loops.
+    /// It does nothing by default.
+    fn preallocate(&self, _arm: &SerialChain<T>) {}
+    /// Move the end transform of the `arm` to `target_pose`, starting from `seed`
+    /// instead of the current joint positions
+    ///
+    /// The joint positions before the call are restored if it fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let mut target = arm.end_transform();
+    /// target.translation.vector.z += 0.02;
+    ///
+    /// // the solution of the previous waypoint
+    /// let seed = arm.joint_positions();
+    /// arm.set_joint_positions(&[0.0; 6]).unwrap();
+    /// let solver = k::JacobianIkSolver::default();
+    /// solver.solve_from(&arm, &seed, &target).unwrap();
+    /// let end = arm.end_transform();
+    /// assert!((end.translation.vector - target.translation.vector).norm() < 0.001);
+    /// ```
+    fn solve_from(
+        &self,
+        arm: &SerialChain<T>,
+        seed: &[T],
+        target_pose: &Isometry3<T>,
+    ) -> Result<(), Error>
+    where
+        T: SubsetOf<f64>,
+    {
+        self.solve_from_with_constraints(arm, seed, target_pose, &Constraints::default())
+    }
+    /// Same as `solve_from()` with constraints
+    ///
+    /// It returns `SizeMismatchError` or `OutOfLimitError` if `seed` is not valid for `arm`.
+    fn solve_from_with_constraints(
+        &self,
+        arm: &SerialChain<T>,
+        seed: &[T],
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error>
+    where
+        T: SubsetOf<f64>,
+    {
+        let orig_positions = arm.joint_positions();
+        arm.set_joint_positions(seed)?;
+        let result = self.solve_with_constraints(arm, target_pose, constraints);
+        if result.is_err() {
+            arm.set_joint_positions_unchecked(&orig_positions);
+        }
+        result
+    }
+    /// Move the end transform of the `arm` to `target_pose` in the frame of `reference`
+    ///
+    /// `reference` can be any node, e.g. the frame of a camera, in the same tree as `arm`
+    /// or in another chain placed in the same world. Its transform is calculated from the
+    /// current joint positions, and fixed during the solve even if it is moved by `arm`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// chain.update_transforms();
+    /// // e.g. a pose detected by the camera on the right arm
+    /// let reference = chain.find("r_wrist_pitch").unwrap();
+    /// let mut goal = arm.end_transform();
+    /// goal.translation.vector.z += 0.02;
+    /// let target = reference.world_transform().unwrap().inverse() * goal;
+    ///
+    /// let solver = k::JacobianIkSolver::default();
+    /// solver
+    ///     .solve_with_frame(&arm, &target, reference, &k::Constraints::default())
+    ///     .unwrap();
+    /// chain.update_transforms();
+    /// let relative = reference.world_transform().unwrap().inverse()
+    ///     * chain.find("l_wrist_pitch").unwrap().world_transform().unwrap();
+    /// assert!((relative.translation.vector - target.translation.vector).norm() < 0.001);
+    /// ```
+    fn solve_with_frame(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        reference: &Node<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error>
+    where
+        T: SubsetOf<f64>,
+    {
+        let target_pose = current_arm_root_transform(arm).inverse()
+            * current_world_transform(reference)
+            * target_pose;
+        self.solve_with_constraints(arm, &target_pose, constraints)
+    }
 }
 
 /// Inverse Kinematics Solver using Jacobian matrix
+///
+/// The target is reached when both the position error is smaller than
+/// `allowable_target_distance` and the rotation error is smaller than
+/// `allowable_target_angle`, which are checked independently.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let mut target = arm.end_transform();
+/// target.translation.vector.z += 0.05;
+///
+/// // 0.1 mm in position, but 1 degree in rotation
+/// let solver = k::JacobianIkSolver::new(0.0001, 1.0f64.to_radians(), 0.5, 100);
+/// let diagnostics = solver
+///     .solve_with_diagnostics(&arm, &target, &k::Constraints::default())
+///     .unwrap();
+/// assert!(diagnostics.is_converged());
+/// assert!(diagnostics.position_residual.norm() < 0.0001);
+/// assert!(diagnostics.rotation_residual.norm() < 1.0f64.to_radians());
+/// ```
 pub struct JacobianIkSolver<T: RealField> {
     /// If the distance is smaller than this value, it is reached.
+    ///
+    /// Values smaller than `Tolerances::convergence_floor()` are raised to it.
     pub allowable_target_distance: T,
     /// If the angle distance is smaller than this value, it is reached.
+    ///
+    /// Values smaller than `Tolerances::convergence_floor()` are raised to it.
     pub allowable_target_angle: T,
     /// multiplier for jacobian
     pub jacobian_multiplier: T,
     /// How many times the joints are tried to be moved
     pub num_max_try: usize,
     /// Nullspace function for a redundant system
-    nullspace_function: Option<Box<dyn Fn(&[T]) -> Vec<T> + Send + Sync>>,
+    nullspace_function: Option<NullspaceFunction<T>>,
+    /// Secondary task for a redundant system
+    nullspace_objective: Option<NullspaceObjective<T>>,
+    /// Singular values of the jacobian smaller than this are regarded as zero
+    singular_value_tolerance: T,
+    /// Recorder of the time of each phase of the iterations
+    profiler: Option<Profiler>,
+    /// How the motion is distributed to the joints of redundant arms
+    weighting: JointWeighting,
+    /// Weights of the joints given by the user, preferred to `weighting`
+    custom_weights: Option<Vec<T>>,
+    /// Damping of the inverse of the jacobian
+    damping: IkDamping<T>,
+    /// How the joint step is calculated from the jacobian
+    update_rule: IkUpdateRule<T>,
+    /// Called after each iteration, which can cancel the solve
+    iteration_callback: Option<IterationCallback<T>>,
+    /// Clearance from the obstacles kept in the iterations
+    clearance_constraint: Option<ClearanceConstraint<T>>,
+    /// Buffers allocated by `preallocate()`
+    workspace: Mutex<Option<IkWorkspace<T>>>,
+}
+
+/// Buffers of `JacobianIkSolver` for the solves without allocations
+struct IkWorkspace<T: RealField> {
+    /// full 6 x DoF jacobian
+    jacobian: DMatrix<T>,
+    /// joint positions at the start of the solve
+    orig_positions: Vec<T>,
+    /// joint positions of the current iteration
+    positions: Vec<T>,
+}
+
+impl<T> IkWorkspace<T>
+where
+    T: RealField,
+{
+    fn new(dof: usize) -> Self {
+        IkWorkspace {
+            jacobian: DMatrix::zeros(6, dof),
+            orig_positions: vec![T::zero(); dof],
+            positions: vec![T::zero(); dof],
+        }
+    }
+
+    fn dof(&self) -> usize {
+        self.positions.len()
+    }
+}
+
+type IterationCallback<T> = Mutex<Box<dyn FnMut(&IkIterationInfo<T>) -> IkIterationControl + Send>>;
+
+/// Returned by the iteration callback of `JacobianIkSolver` to continue or cancel the solve
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IkIterationControl {
+    Continue,
+    Break,
+}
+
+/// State of `JacobianIkSolver` after an iteration, given to the iteration callback
+#[derive(Debug, Clone, PartialEq)]
+pub struct IkIterationInfo<T: RealField> {
+    /// index of the iteration, starting from zero
+    pub iteration: usize,
+    /// joint positions after the iteration
+    pub joint_positions: Vec<T>,
+    /// translational error after the iteration, only the constrained axes
+    pub position_residual: Vector3<T>,
+    /// rotational error after the iteration, only the constrained axes
+    pub rotation_residual: Vector3<T>,
+}
+
+/// Why `JacobianIkSolver` stopped the iterations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum IkTermination {
+    /// The errors are within the allowable ones
+    Converged,
+    /// `num_max_try` iterations are done without reaching the target
+    MaxIterations,
+    /// The jacobian could not be inverted
+    Singular,
+    /// The iteration callback returned `IkIterationControl::Break`
+    Cancelled,
+}
+
+/// Result of a solve of `JacobianIkSolver::solve_with_diagnostics()`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct IkDiagnostics<T: RealField> {
+    /// number of the iterations used
+    pub iterations: usize,
+    /// final translational error, only the constrained axes
+    pub position_residual: Vector3<T>,
+    /// final rotational error, only the constrained axes
+    pub rotation_residual: Vector3<T>,
+    /// why the iterations stopped
+    pub termination: IkTermination,
+}
+
+impl<T> IkDiagnostics<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Returns true if the target is reached
+    pub fn is_converged(&self) -> bool {
+        self.termination == IkTermination::Converged
+    }
+
+    /// Convert to the result of `solve_with_iterations()`
+    pub(crate) fn into_result(self) -> Result<usize, Error> {
+        match self.termination {
+            IkTermination::Converged => Ok(self.iterations),
+            IkTermination::MaxIterations | IkTermination::Cancelled => {
+                Err(Error::NotConvergedError {
+                    num_tried: self.iterations,
+                    position_diff: na::try_convert(self.position_residual).unwrap_or_default(),
+                    rotation_diff: na::try_convert(self.rotation_residual).unwrap_or_default(),
+                })
+            }
+            IkTermination::Singular => Err(Error::InverseMatrixError),
+        }
+    }
+}
+
+/// Damping of the inverse of the jacobian used by `JacobianIkSolver`
+///
+/// The damped least squares (Levenberg-Marquardt) inverse `Jᵀ (J Jᵀ + λ² I)⁻¹`
+/// keeps the joint motion small near singularities, in exchange for the slower
+/// convergence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum IkDamping<T: RealField> {
+    /// No damping, the pseudo inverse of the jacobian
+    Disabled,
+    /// Constant damping factor λ
+    Constant(T),
+    /// The damping factor grows from zero to `max` as the smallest singular value of
+    /// the jacobian becomes smaller than `threshold`, so the solution far from
+    /// singularities is not changed.
+    Adaptive { max: T, threshold: T },
+}
+
+impl<T> Default for IkDamping<T>
+where
+    T: RealField,
+{
+    fn default() -> Self {
+        IkDamping::Disabled
+    }
+}
+
+impl<T> IkDamping<T>
+where
+    T: RealField,
+{
+    /// The squared damping factor λ² for the `jacobi`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::IkDamping;
+    /// use k::nalgebra::DMatrix;
+    ///
+    /// let damping = IkDamping::Adaptive { max: 0.1, threshold: 0.5 };
+    /// assert_eq!(damping.squared_factor(&DMatrix::<f64>::identity(3, 3)), 0.0);
+    /// // singular
+    /// let jacobi = DMatrix::<f64>::from_diagonal_element(3, 3, 0.0);
+    /// assert!((damping.squared_factor(&jacobi) - 0.01).abs() < 1e-10);
+    /// ```
+    pub fn squared_factor(&self, jacobi: &DMatrix<T>) -> T {
+        match *self {
+            IkDamping::Disabled => T::zero(),
+            IkDamping::Constant(lambda) => lambda * lambda,
+            IkDamping::Adaptive { max, threshold } => {
+                let min_singular_value = jacobi
+                    .singular_values()
+                    .iter()
+                    .fold(T::max_value(), |min, s| min.min(*s));
+                if min_singular_value < threshold {
+                    let ratio = min_singular_value / threshold;
+                    (T::one() - ratio * ratio) * max * max
+                } else {
+                    T::zero()
+                }
+            }
+        }
+    }
+}
+
+/// Rule to calculate the joint step from the jacobian in each iteration of `JacobianIkSolver`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum IkUpdateRule<T: RealField> {
+    /// The (damped) inverse of the jacobian, configured by `IkDamping`
+    Inverse,
+    /// Selectively Damped Least Squares (Buss and Kim, 2005)
+    ///
+    /// Each singular direction of the jacobian is damped independently, by clamping
+    /// the joint step of the direction in proportion to how much the end moves by it.
+    /// The maximum absolute value of the joint step of an iteration is `max_step` [rad].
+    /// It is stable near the boundaries of the workspace without tuning a damping
+    /// factor. `IkDamping` is ignored with this rule.
+    SelectivelyDamped { max_step: T },
+}
+
+impl<T> Default for IkUpdateRule<T>
+where
+    T: RealField,
+{
+    fn default() -> Self {
+        IkUpdateRule::Inverse
+    }
+}
+
+/// Strategy to distribute the motion to the joints of redundant arms, used by `JacobianIkSolver`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum JointWeighting {
+    /// All the joints are weighted equally (the minimum norm solution)
+    Uniform,
+    /// The joints moving heavier subtrees move less, weighted by `downstream_mass_weights()`
+    DownstreamMass,
+}
+
+impl Default for JointWeighting {
+    fn default() -> Self {
+        JointWeighting::Uniform
+    }
+}
+
+/// Weights of the joints by the total mass of the links moved by each joint
+///
+/// The mass includes all the descendants in the tree, not only the ones in the same
+/// chain. The weights are normalized by the largest one, and at least 0.01, so the
+/// joints moving only massless links still move. If all the masses are zero, the weights
+/// are one.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+/// use k::link::*;
+///
+/// let nodes = (0..3)
+///     .map(|i| {
+///         let node = NodeBuilder::<f64>::new()
+///             .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+///             .into_node();
+///         node.set_link(Some(LinkBuilder::new().inertial(Inertial::from_mass(i as f64)).finalize()));
+///         node
+///     })
+///     .collect::<Vec<_>>();
+/// nodes[1].set_parent(&nodes[0]);
+/// nodes[2].set_parent(&nodes[1]);
+/// let weights = downstream_mass_weights(&nodes);
+/// // the first joint moves all the links
+/// assert_eq!(weights, vec![1.0, 1.0, 2.0 / 3.0]);
+/// ```
+pub fn downstream_mass_weights<T>(nodes: &[Node<T>]) -> Vec<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let masses = nodes
+        .iter()
+        .map(|node| {
+            node.iter_descendants().fold(T::zero(), |sum, n| {
+                sum + n.link().as_ref().map_or(T::zero(), |l| l.inertial.mass)
+            })
+        })
+        .collect::<Vec<_>>();
+    let max_mass = masses.iter().fold(T::zero(), |max, m| max.max(*m));
+    if max_mass <= T::zero() {
+        return vec![T::one(); nodes.len()];
+    }
+    let min_weight = na::convert(0.01);
+    masses
+        .into_iter()
+        .map(|m| (m / max_mass).max(min_weight))
+        .collect()
 }
 
 impl<T> JacobianIkSolver<T>
@@ -176,253 +863,1903 @@ where
             jacobian_multiplier,
             num_max_try,
             nullspace_function: None,
+            nullspace_objective: None,
+            singular_value_tolerance: Tolerances::default().singular_value,
+            profiler: None,
+            weighting: JointWeighting::default(),
+            custom_weights: None,
+            damping: IkDamping::default(),
+            update_rule: IkUpdateRule::default(),
+            iteration_callback: None,
+            clearance_constraint: None,
+            workspace: Mutex::new(None),
         }
     }
-    /// Set a null space function for redundant manipulator.
+
+    /// Keep the clearance from the obstacles calculated by the user function, `None` to disable it
+    ///
+    /// It is applied to the iterations of `solve_with_constraints()` and
+    /// `solve_with_diagnostics()`. The steps which reduce the clearance below the
+    /// threshold are pushed away or shortened, so the solve may fail if the target
+    /// is only reachable through the obstacles.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut solver = k::JacobianIkSolver::new(0.01, 0.01, 0.5, 100);
-    /// solver.set_nullspace_function(Box::new(
-    /// k::create_reference_positions_nullspace_function(
-    ///    vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
-    ///    vec![0.1, 0.1, 0.1, 1.0, 0.1, 0.5, 0.0],
-    ///    ),
-    /// ));
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -1.0, 0.0, -0.3]).unwrap();
+    /// let target = arm.end_transform();
+    /// let start = [0.1, 0.2, 0.0, -0.5, 0.0, -0.3];
+    ///
+    /// // the elbow must not bend more than -0.7 (e.g. hits the body)
+    /// let mut solver = k::JacobianIkSolver::default();
+    /// solver.set_clearance_constraint(Some(k::ClearanceConstraint::new(
+    ///     |q: &[f64]| k::Clearance::with_gradient(q[3] + 0.7, vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0]),
+    ///     0.05,
+    /// )));
+    /// arm.set_joint_positions(&start).unwrap();
+    /// assert!(solver.solve(&arm, &target).is_err());
+    ///
+    /// solver.set_clearance_constraint(None);
+    /// assert!(solver.solve(&arm, &target).is_ok());
     /// ```
-    pub fn set_nullspace_function(&mut self, func: Box<dyn Fn(&[T]) -> Vec<T> + Send + Sync>) {
-        self.nullspace_function = Some(func);
+    pub fn set_clearance_constraint(&mut self, constraint: Option<ClearanceConstraint<T>>) {
+        self.clearance_constraint = constraint;
     }
 
-    /// Clear the null function which is set by `set_nullspace_funtion`.
-    pub fn clear_nullspace_function(&mut self) {
-        self.nullspace_function = None;
+    /// Get the constraint set by `set_clearance_constraint()`
+    pub fn clearance_constraint(&self) -> Option<&ClearanceConstraint<T>> {
+        self.clearance_constraint.as_ref()
     }
 
-    fn add_positions_with_multiplier(&self, input: &[T], add_values: &[T]) -> Vec<T> {
-        input
-            .iter()
-            .zip(add_values.iter())
-            .map(|(ang, add)| *ang + self.jacobian_multiplier * *add)
-            .collect()
+    /// Set the function called after each iteration, `None` to remove it
+    ///
+    /// It receives the joint positions and the errors after the iteration, for
+    /// visualization or logging. If it returns `IkIterationControl::Break`, the solve stops
+    /// as not converged, and `IkDiagnostics::termination` is `IkTermination::Cancelled`.
+    /// It can be used to cancel a long solve from another thread through a shared flag.
+    /// The callback must not use this solver.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let wrist = chain.find("l_wrist_pitch").unwrap();
+    /// let arm = k::SerialChain::from_end(wrist);
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let mut target = arm.update_transforms().last().unwrap().clone();
+    /// target.translation.vector.z += 0.1;
+    ///
+    /// let cancelled = Arc::new(AtomicBool::new(false));
+    /// let flag = cancelled.clone();
+    /// let mut solver = k::JacobianIkSolver::default();
+    /// solver.set_iteration_callback(Some(move |info: &k::IkIterationInfo<f64>| {
+    ///     println!("{}: {}", info.iteration, info.position_residual.norm());
+    ///     if flag.load(Ordering::Relaxed) {
+    ///         k::IkIterationControl::Break
+    ///     } else {
+    ///         k::IkIterationControl::Continue
+    ///     }
+    /// }));
+    /// // e.g. a new target arrives
+    /// cancelled.store(true, Ordering::Relaxed);
+    /// let diagnostics = solver
+    ///     .solve_with_diagnostics(&arm, &target, &k::Constraints::default())
+    ///     .unwrap();
+    /// assert_eq!(diagnostics.termination, k::IkTermination::Cancelled);
+    /// assert_eq!(diagnostics.iterations, 1);
+    /// ```
+    pub fn set_iteration_callback<F>(&mut self, callback: Option<F>)
+    where
+        F: FnMut(&IkIterationInfo<T>) -> IkIterationControl + Send + 'static,
+    {
+        self.iteration_callback = callback.map(|f| {
+            Mutex::new(
+                Box::new(f) as Box<dyn FnMut(&IkIterationInfo<T>) -> IkIterationControl + Send>
+            )
+        });
     }
 
-    fn solve_one_loop_with_constraints(
-        &self,
-        arm: &SerialChain<T>,
-        target_pose: &Isometry3<T>,
-        constraints: &Constraints,
-    ) -> Result<DVector<T>, Error> {
-        let operational_space = define_operational_space(&constraints);
-        let orig_positions = arm.joint_positions();
-        let dof = orig_positions.len();
-        let t_n = arm.end_transform();
-        let err = calc_pose_diff_with_constraints(target_pose, &t_n, operational_space);
-        let orig_positions = arm.joint_positions();
-        let mut jacobi = jacobian(arm);
-        let use_dof = operational_space.iter().filter(|x| **x).count();
-        let mut removed_count = 0;
-        for (i, use_i) in operational_space.iter().enumerate() {
-            if !use_i {
-                jacobi = jacobi.remove_row(i - removed_count);
-                removed_count += 1;
-            }
+    /// Returns true if the callback is set by `set_iteration_callback()`
+    pub fn has_iteration_callback(&self) -> bool {
+        self.iteration_callback.is_some()
+    }
+
+    /// Set the tolerances of the singularity and the convergence
+    ///
+    /// `allowable_target_distance` and `allowable_target_angle` are overwritten by
+    /// `convergence_distance` and `convergence_angle`. `joint_limit` and `coupled_limit`
+    /// are not used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut solver = k::JacobianIkSolver::<f32>::default();
+    /// solver.set_tolerances(&k::Tolerances {
+    ///     convergence_distance: 0.01,
+    ///     ..Default::default()
+    /// });
+    /// assert_eq!(solver.allowable_target_distance, 0.01);
+    /// assert_eq!(solver.tolerances().convergence_distance, 0.01);
+    /// ```
+    pub fn set_tolerances(&mut self, tolerances: &Tolerances<T>) {
+        self.allowable_target_distance = tolerances.convergence_distance;
+        self.allowable_target_angle = tolerances.convergence_angle;
+        self.singular_value_tolerance = tolerances.singular_value;
+    }
+
+    /// Get the tolerances used by the solver
+    pub fn tolerances(&self) -> Tolerances<T> {
+        Tolerances {
+            singular_value: self.singular_value_tolerance,
+            convergence_distance: self.allowable_target_distance,
+            convergence_angle: self.allowable_target_angle,
+            ..Default::default()
+        }
+    }
+    /// Set how the motion is distributed to the joints of redundant arms
+    ///
+    /// It doesn't change the solution of the arms without redundancy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let mut target = arm.end_transform();
+    /// target.translation.vector.z += 0.05;
+    ///
+    /// let mut solver = k::JacobianIkSolver::default();
+    /// solver.set_weighting(k::JointWeighting::DownstreamMass);
+    /// let mut constraints = k::Constraints::default();
+    /// constraints.rotation_x = false;
+    /// constraints.rotation_y = false;
+    /// constraints.rotation_z = false;
+    /// solver.solve_with_constraints(&arm, &target, &constraints).unwrap();
+    /// ```
+    pub fn set_weighting(&mut self, weighting: JointWeighting) {
+        self.weighting = weighting;
+    }
+
+    /// Get the strategy set by `set_weighting()`
+    pub fn weighting(&self) -> JointWeighting {
+        self.weighting
+    }
+
+    /// Set the damping of the inverse of the jacobian to move stably near singularities
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// // the elbow is almost stretched
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.01, 0.0, -0.3]).unwrap();
+    /// let mut target = arm.end_transform();
+    /// target.translation.vector.z += 0.05;
+    ///
+    /// let mut solver = k::JacobianIkSolver::default();
+    /// solver.set_damping(k::IkDamping::Adaptive { max: 0.05, threshold: 0.05 });
+    /// solver.solve(&arm, &target).unwrap();
+    /// ```
+    pub fn set_damping(&mut self, damping: IkDamping<T>) {
+        self.damping = damping;
+    }
+
+    /// Get the damping set by `set_damping()`
+    pub fn damping(&self) -> IkDamping<T> {
+        self.damping
+    }
+
+    /// Set the rule to calculate the joint step from the jacobian
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// // the elbow is almost stretched
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.01, 0.0, -0.3]).unwrap();
+    /// let mut target = arm.end_transform();
+    /// target.translation.vector.z += 0.05;
+    ///
+    /// let mut solver = k::JacobianIkSolver::default();
+    /// solver.num_max_try = 200;
+    /// solver.set_update_rule(k::IkUpdateRule::SelectivelyDamped { max_step: 0.5 });
+    /// solver.solve(&arm, &target).unwrap();
+    /// let end = arm.end_transform();
+    /// assert!((end.translation.vector - target.translation.vector).norm() < 0.001);
+    /// ```
+    pub fn set_update_rule(&mut self, update_rule: IkUpdateRule<T>) {
+        self.update_rule = update_rule;
+    }
+
+    /// Get the rule set by `set_update_rule()`
+    pub fn update_rule(&self) -> IkUpdateRule<T> {
+        self.update_rule
+    }
+
+    /// The damped least squares inverse of `jacobi`, `None` if it is not damped
+    pub(crate) fn damped_pseudo_inverse(
+        &self,
+        jacobi: &DMatrix<T>,
+    ) -> Result<Option<DMatrix<T>>, Error> {
+        let squared_factor = self.damping.squared_factor(jacobi);
+        if squared_factor <= T::zero() {
+            return Ok(None);
+        }
+        let rows = jacobi.nrows();
+        let inv = (jacobi * jacobi.transpose() + DMatrix::identity(rows, rows) * squared_factor)
+            .try_inverse()
+            .ok_or(Error::InverseMatrixError)?;
+        Ok(Some(jacobi.transpose() * inv))
+    }
+
+    /// Set the weights of the joints, `None` to use `weighting()`
+    ///
+    /// The solution minimizes `dqᵀ W dq`, where `W` is the diagonal matrix of `weights`,
+    /// so the joints with larger weights (e.g. torso, base) move less than the others.
+    /// The weights must be positive, and the size must be the DoF of the arm to solve,
+    /// otherwise `SizeMismatchError` is returned by the solve. Like `set_weighting()`,
+    /// it changes the solution of the redundant or damped arms only.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// let start = [0.1, 0.2, 0.0, -0.5, 0.0, -0.3];
+    /// arm.set_joint_positions(&start).unwrap();
+    /// let mut target = arm.end_transform();
+    /// target.translation.vector.z += 0.02;
+    /// let mut constraints = k::Constraints::default();
+    /// constraints.rotation_x = false;
+    /// constraints.rotation_y = false;
+    /// constraints.rotation_z = false;
+    ///
+    /// let mut solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+    /// // the shoulder is expensive to move
+    /// solver.set_joint_weights(Some(vec![100.0, 100.0, 100.0, 1.0, 1.0, 1.0]));
+    /// solver.solve_with_constraints(&arm, &target, &constraints).unwrap();
+    /// let positions = arm.joint_positions();
+    /// assert!((positions[0] - start[0]).abs() < (positions[3] - start[3]).abs());
+    ///
+    /// solver.set_joint_weights(Some(vec![1.0; 3]));
+    /// assert!(solver.solve_with_constraints(&arm, &target, &constraints).is_err());
+    /// ```
+    pub fn set_joint_weights(&mut self, weights: Option<Vec<T>>) {
+        self.custom_weights = weights;
+    }
+
+    /// Get the weights set by `set_joint_weights()`
+    pub fn joint_weights(&self) -> Option<&[T]> {
+        self.custom_weights.as_deref()
+    }
+
+    /// Weights of `nodes` by `set_joint_weights()` or `weighting`, `None` if uniform
+    pub(crate) fn resolve_joint_weights(&self, nodes: &[Node<T>]) -> Result<Option<Vec<T>>, Error> {
+        if let Some(ref weights) = self.custom_weights {
+            if weights.len() != nodes.len() {
+                return Err(Error::SizeMismatchError {
+                    input: weights.len(),
+                    required: nodes.len(),
+                });
+            }
+            return Ok(Some(weights.clone()));
+        }
+        Ok(match self.weighting {
+            JointWeighting::Uniform => None,
+            JointWeighting::DownstreamMass => Some(downstream_mass_weights(nodes)),
+        })
+    }
+
+    /// Set a null space function for redundant manipulator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut solver = k::JacobianIkSolver::new(0.01, 0.01, 0.5, 100);
+    /// solver.set_nullspace_function(Box::new(
+    /// k::create_reference_positions_nullspace_function(
+    ///    vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    ///    vec![0.1, 0.1, 0.1, 1.0, 0.1, 0.5, 0.0],
+    ///    ),
+    /// ));
+    /// ```
+    pub fn set_nullspace_function(&mut self, func: NullspaceFunction<T>) {
+        self.nullspace_function = Some(func);
+    }
+
+    /// Clear the null function which is set by `set_nullspace_funtion`.
+    pub fn clear_nullspace_function(&mut self) {
+        self.nullspace_function = None;
+    }
+
+    /// Set the secondary task of redundant arms, `None` to disable it
+    ///
+    /// It is applied in the nullspace of the jacobian in each iteration, together with
+    /// the function set by `set_nullspace_function()`. The arms without redundancy
+    /// in the constrained task space are not affected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// let start = [0.1, 0.2, 0.0, -0.5, 0.0, -0.3];
+    /// let rest = vec![0.0, 0.0, 0.0, -1.0, 0.0, 0.0];
+    /// arm.set_joint_positions(&start).unwrap();
+    /// let mut target = arm.end_transform();
+    /// target.translation.vector.z += 0.02;
+    /// // position only, three redundant joints
+    /// let mut constraints = k::Constraints::default();
+    /// constraints.rotation_x = false;
+    /// constraints.rotation_y = false;
+    /// constraints.rotation_z = false;
+    /// let distance_to_rest = |q: &[f64]| q.iter().zip(&rest).map(|(a, b)| (a - b).powi(2)).sum::<f64>();
+    ///
+    /// let mut solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+    /// solver.solve_with_constraints(&arm, &target, &constraints).unwrap();
+    /// let without = distance_to_rest(&arm.joint_positions());
+    ///
+    /// arm.set_joint_positions(&start).unwrap();
+    /// solver.set_nullspace_objective(Some(k::NullspaceObjective::rest_posture(
+    ///     rest.clone(),
+    ///     vec![1.0; 6],
+    ///     0.5,
+    /// )));
+    /// solver.solve_with_constraints(&arm, &target, &constraints).unwrap();
+    /// assert!(distance_to_rest(&arm.joint_positions()) < without);
+    /// ```
+    pub fn set_nullspace_objective(&mut self, objective: Option<NullspaceObjective<T>>) {
+        self.nullspace_objective = objective;
+    }
+
+    /// Get the secondary task set by `set_nullspace_objective()`
+    pub fn nullspace_objective(&self) -> Option<&NullspaceObjective<T>> {
+        self.nullspace_objective.as_ref()
+    }
+
+    pub(crate) fn take_nullspace_objective(&mut self) -> Option<NullspaceObjective<T>> {
+        self.nullspace_objective.take()
+    }
+
+    /// Sum of the steps in the nullspace, `None` if nothing is set
+    fn nullspace_step(&self, positions: &[T]) -> Option<DVector<T>> {
+        let function = self
+            .nullspace_function
+            .as_ref()
+            .map(|f| DVector::from_vec(f(positions)));
+        let objective = self
+            .nullspace_objective
+            .as_ref()
+            .map(|o| DVector::from_vec(o.step(positions)));
+        match (function, objective) {
+            (Some(f), Some(o)) => Some(f + o),
+            (f, o) => f.or(o),
+        }
+    }
+
+    /// Set the profiler to record the time of the phases of each iteration, `None` to disable it
+    ///
+    /// The phases are `"ik/jacobian"` (calculation of the jacobian),
+    /// `"ik/solve"` (linear solve) and `"ik/update"` (setting positions and forward kinematics).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("r_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let mut target = arm.end_transform();
+    /// target.translation.vector.x -= 0.05;
+    ///
+    /// let profiler = k::Profiler::new();
+    /// let mut solver = k::JacobianIkSolver::default();
+    /// solver.set_profiler(Some(profiler.clone()));
+    /// let iterations = solver
+    ///     .solve_with_iterations(&arm, &target, &k::Constraints::default())
+    ///     .unwrap()
+    ///     .unwrap();
+    /// assert_eq!(profiler.entry("ik/jacobian").unwrap().count, iterations);
+    /// ```
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    /// Move the end of the `arm` to `target_position`, with its Z axis aligned to `target_direction`
+    ///
+    /// The rotation about the Z axis of the end is free, so the arm needs five DoF at least.
+    /// It is the common task for drilling, spraying or welding tools, and it is solved
+    /// with the five dimensional task space directly, instead of removing one of
+    /// the rotation axes of the world like `Constraints`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("r_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let end = arm.end_transform();
+    /// let position = end.translation.vector + k::Vector3::new(-0.05, 0.0, 0.02);
+    /// // tilt the Z axis of the end
+    /// let direction = k::UnitQuaternion::from_euler_angles(0.2, 0.0, 0.0) * end.rotation * k::Vector3::z();
+    ///
+    /// let solver = k::JacobianIkSolver::default();
+    /// solver
+    ///     .solve_with_axis_alignment(&arm, &position, &k::nalgebra::Unit::new_normalize(direction))
+    ///     .unwrap();
+    /// let end = arm.end_transform();
+    /// assert!((end.translation.vector - position).norm() < 0.001);
+    /// assert!((end.rotation * k::Vector3::z()).angle(&direction) < 0.005);
+    /// ```
+    pub fn solve_with_axis_alignment(
+        &self,
+        arm: &SerialChain<T>,
+        target_position: &Vector3<T>,
+        target_direction: &Unit<Vector3<T>>,
+    ) -> Result<(), Error> {
+        self.solve_geometric(
+            arm,
+            &GeometricTarget::AxisAlignment {
+                point: *target_position,
+                direction: *target_direction,
+            },
+        )
+    }
+
+    pub(crate) fn measure<R>(&self, name: &str, iteration: usize, f: impl FnOnce() -> R) -> R {
+        match self.profiler {
+            Some(ref profiler) => profiler.measure(name, Some(iteration), f),
+            None => f(),
+        }
+    }
+
+    fn add_positions_with_multiplier(&self, input: &[T], add_values: &[T]) -> Vec<T> {
+        input
+            .iter()
+            .zip(add_values.iter())
+            .map(|(ang, add)| *ang + self.jacobian_multiplier * *add)
+            .collect()
+    }
+
+    pub(crate) fn calc_next_positions(
+        &self,
+        jacobi: DMatrix<T>,
+        err: DVector<T>,
+        orig_positions: &[T],
+        weights: Option<&[T]>,
+        iteration: usize,
+    ) -> Result<Vec<T>, Error> {
+        let dof = orig_positions.len();
+        let use_dof = jacobi.nrows();
+        self.measure("ik/solve", iteration, || {
+            Ok(
+                if let IkUpdateRule::SelectivelyDamped { max_step } = self.update_rule {
+                    let mut d_q = selectively_damped_step(
+                        &jacobi,
+                        &err,
+                        max_step,
+                        self.singular_value_tolerance,
+                    );
+                    if let Some(gradient) = self.nullspace_step(orig_positions) {
+                        let jacobi_inv = jacobi
+                            .clone()
+                            .pseudo_inverse(self.singular_value_tolerance)
+                            .map_err(|_| Error::InverseMatrixError)?;
+                        d_q += (na::DMatrix::identity(dof, dof) - jacobi_inv * jacobi) * gradient;
+                    }
+                    self.add_positions_with_multiplier(orig_positions, d_q.as_slice())
+                } else if dof > use_dof || self.damping != IkDamping::Disabled {
+                    let eps = self.singular_value_tolerance;
+                    // weighted: solve in the joint space scaled by 1 / sqrt(weight)
+                    let scales =
+                        weights.map(|w| w.iter().map(|w| T::one() / w.sqrt()).collect::<Vec<_>>());
+                    let mut jacobi = jacobi;
+                    if let Some(ref scales) = scales {
+                        for (mut column, scale) in jacobi.column_iter_mut().zip(scales) {
+                            column *= *scale;
+                        }
+                    }
+                    // redundant or damped: pseudo inverse
+                    let damped_inv = self.damped_pseudo_inverse(&jacobi)?;
+                    let mut d_q = match self.nullspace_step(orig_positions) {
+                        Some(mut gradient) => {
+                            if let Some(ref scales) = scales {
+                                gradient.component_mul_assign(&DVector::from_column_slice(scales));
+                            }
+                            let jacobi_inv = match damped_inv {
+                                Some(inv) => inv,
+                                None => jacobi.clone().pseudo_inverse(eps).unwrap(),
+                            };
+                            jacobi_inv.clone() * err
+                                + (na::DMatrix::identity(dof, dof) - jacobi_inv * jacobi) * gradient
+                        }
+                        None => match damped_inv {
+                            Some(inv) => inv * err,
+                            None => jacobi.svd(true, true).solve(&err, eps).unwrap(), // TODO
+                        },
+                    };
+                    if let Some(ref scales) = scales {
+                        d_q.component_mul_assign(&DVector::from_column_slice(scales));
+                    }
+                    self.add_positions_with_multiplier(orig_positions, d_q.as_slice())
+                } else {
+                    // normal inverse matrix
+                    self.add_positions_with_multiplier(
+                        orig_positions,
+                        jacobi
+                            .lu()
+                            .solve(&err)
+                            .ok_or(Error::InverseMatrixError)?
+                            .as_slice(),
+                    )
+                },
+            )
+        })
+    }
+
+    fn solve_one_loop_with_constraints(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+        weights: Option<&[T]>,
+        iteration: usize,
+    ) -> Result<DVector<T>, Error> {
+        let t_n = arm.end_transform();
+        let err = calc_pose_diff_with_constraints(target_pose, &t_n, constraints);
+        let orig_positions = arm.joint_positions();
+        let jacobi = self.measure("ik/jacobian", iteration, || {
+            with_jacobian_scratch(arm, |full| constrained_jacobian(full, &t_n, constraints))
+        });
+        let mut positions_vec =
+            self.calc_next_positions(jacobi, err, &orig_positions, weights, iteration)?;
+        if let Some(ref constraint) = self.clearance_constraint {
+            positions_vec = constraint.filter_step(&orig_positions, positions_vec);
+        }
+        Ok(self.measure("ik/update", iteration, || {
+            arm.set_joint_positions_clamped(&positions_vec);
+            calc_pose_diff_with_constraints(target_pose, &arm.end_transform(), constraints)
+        }))
+    }
+
+    /// Returns true if the errors are within the allowable ones, which are raised to
+    /// `Tolerances::convergence_floor()` if they are smaller
+    pub(crate) fn is_reached(&self, len_diff: &Vector3<T>, rot_diff: &Vector3<T>) -> bool {
+        let floor = Tolerances::<T>::convergence_floor();
+        len_diff.norm() < self.allowable_target_distance.max(floor)
+            && rot_diff.norm() < self.allowable_target_angle.max(floor)
+    }
+
+    /// Solve from the current positions, leaving the last positions on failure
+    pub(crate) fn solve_with_constraints_internal(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<usize, Error> {
+        self.solve_with_diagnostics_internal(arm, target_pose, constraints)?
+            .into_result()
+    }
+
+    /// Same as `solve_with_constraints_internal()`, but a failure of the iterations
+    /// is reported by the diagnostics
+    pub(crate) fn solve_with_diagnostics_internal(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<IkDiagnostics<T>, Error> {
+        let operational_space = define_operational_space(constraints);
+        let orig_positions = arm.joint_positions();
+        let use_dof = operational_space.iter().filter(|x| **x).count();
+        if orig_positions.len() < use_dof {
+            return Err(Error::PreconditionError {
+                dof: orig_positions.len(),
+                necessary_dof: use_dof,
+            });
+        }
+        let weights = self.resolve_joint_weights(&movable_nodes(arm))?;
+        let diagnostics = |iterations, target_diff: &DVector<T>, termination| {
+            let (position_residual, rotation_residual) =
+                target_diff_to_len_rot_diff(target_diff, operational_space);
+            IkDiagnostics {
+                iterations,
+                position_residual,
+                rotation_residual,
+                termination,
+            }
+        };
+        for i in 0..self.num_max_try {
+            let target_diff = match self.solve_one_loop_with_constraints(
+                arm,
+                target_pose,
+                constraints,
+                weights.as_deref(),
+                i,
+            ) {
+                Ok(target_diff) => target_diff,
+                Err(Error::InverseMatrixError) => {
+                    let target_diff = calc_pose_diff_with_constraints(
+                        target_pose,
+                        &arm.end_transform(),
+                        constraints,
+                    );
+                    return Ok(diagnostics(i, &target_diff, IkTermination::Singular));
+                }
+                Err(error) => return Err(error),
+            };
+            let (len_diff, rot_diff) = target_diff_to_len_rot_diff(&target_diff, operational_space);
+            let flow = match self.iteration_callback {
+                Some(ref callback) => (callback.lock().unwrap())(&IkIterationInfo {
+                    iteration: i,
+                    joint_positions: arm.joint_positions(),
+                    position_residual: len_diff,
+                    rotation_residual: rot_diff,
+                }),
+                None => IkIterationControl::Continue,
+            };
+            if self.is_reached(&len_diff, &rot_diff) {
+                let mut non_checked_positions = arm.joint_positions();
+                wrap_continuous_positions(arm, &mut non_checked_positions, &orig_positions);
+                arm.set_joint_positions_clamped(&non_checked_positions);
+                return Ok(diagnostics(i + 1, &target_diff, IkTermination::Converged));
+            }
+            if flow == IkIterationControl::Break {
+                return Ok(diagnostics(i + 1, &target_diff, IkTermination::Cancelled));
+            }
+        }
+        let target_diff =
+            calc_pose_diff_with_constraints(target_pose, &arm.end_transform(), constraints);
+        Ok(diagnostics(
+            self.num_max_try,
+            &target_diff,
+            IkTermination::MaxIterations,
+        ))
+    }
+
+    /// Solve like `solve_with_constraints()` and report how the iterations went
+    ///
+    /// Unlike `solve_with_constraints()`, a failure to reach the target is not an error
+    /// but reported by `IkDiagnostics::termination`. The joint positions are restored
+    /// in that case, same as `solve_with_constraints()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let wrist = chain.find("l_wrist_pitch").unwrap();
+    /// let arm = k::SerialChain::from_end(wrist);
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let mut target = arm.update_transforms().last().unwrap().clone();
+    /// target.translation.vector.z += 0.1;
+    ///
+    /// let solver = k::JacobianIkSolver::default();
+    /// let diagnostics = solver
+    ///     .solve_with_diagnostics(&arm, &target, &k::Constraints::default())
+    ///     .unwrap();
+    /// assert_eq!(diagnostics.termination, k::IkTermination::Converged);
+    /// assert!(diagnostics.iterations <= 10);
+    /// assert!(diagnostics.position_residual.norm() < 0.001);
+    ///
+    /// // out of reach
+    /// target.translation.vector.z += 10.0;
+    /// let positions = arm.joint_positions();
+    /// let diagnostics = solver
+    ///     .solve_with_diagnostics(&arm, &target, &k::Constraints::default())
+    ///     .unwrap();
+    /// assert_eq!(diagnostics.termination, k::IkTermination::MaxIterations);
+    /// assert!(diagnostics.position_residual.norm() > 9.0);
+    /// assert_eq!(arm.joint_positions(), positions);
+    /// ```
+    pub fn solve_with_diagnostics(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<IkDiagnostics<T>, Error> {
+        let orig_positions = arm.joint_positions();
+        let re = self.solve_with_diagnostics_internal(arm, target_pose, constraints);
+        if !matches!(re, Ok(ref diagnostics) if diagnostics.is_converged()) {
+            arm.set_joint_positions(&orig_positions)?;
+        }
+        re
+    }
+
+    /// Solve with the buffers of `preallocate()`, `None` if they are not allocated for
+    /// `arm` or the settings need allocations
+    fn solve_preallocated(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Option<Result<usize, Error>> {
+        if self.nullspace_function.is_some()
+            || self.nullspace_objective.is_some()
+            || self.clearance_constraint.is_some()
+            || self.iteration_callback.is_some()
+            || self.profiler.is_some()
+            || self.update_rule != IkUpdateRule::Inverse
+            || matches!(self.damping, IkDamping::Adaptive { .. })
+            || (self.custom_weights.is_none() && self.weighting != JointWeighting::Uniform)
+            || !arm.coupled_limits().is_empty()
+        {
+            return None;
+        }
+        let mut workspace = self.workspace.try_lock().ok()?;
+        let workspace = workspace.as_mut().filter(|w| w.dof() == arm.dof())?;
+        let operational_space = define_operational_space(constraints);
+        let use_dof = operational_space.iter().filter(|x| **x).count();
+        if workspace.dof() < use_dof {
+            return Some(Err(Error::PreconditionError {
+                dof: workspace.dof(),
+                necessary_dof: use_dof,
+            }));
+        }
+        if let Some(ref weights) = self.custom_weights {
+            if weights.len() != workspace.dof() {
+                return Some(Err(Error::SizeMismatchError {
+                    input: weights.len(),
+                    required: workspace.dof(),
+                }));
+            }
+        }
+        #[cfg(feature = "assert-no-alloc")]
+        let num_allocations = crate::allocation::num_allocations();
+        arm.copy_joint_positions(&mut workspace.orig_positions);
+        let re = self.iterate_preallocated(arm, target_pose, constraints, workspace);
+        if re.is_err() {
+            arm.set_joint_positions_unchecked(&workspace.orig_positions);
+        }
+        #[cfg(feature = "assert-no-alloc")]
+        crate::allocation::assert_no_allocations_since(num_allocations, "preallocated IK solve");
+        Some(re)
+    }
+
+    /// The iterations of `solve_preallocated()`, with the inverse of the 6 x 6 `J Jᵀ`
+    /// whose unused rows are zero
+    fn iterate_preallocated(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+        workspace: &mut IkWorkspace<T>,
+    ) -> Result<usize, Error> {
+        let operational_space = define_operational_space(constraints);
+        let squared_factor = match self.damping {
+            IkDamping::Constant(lambda) => lambda * lambda,
+            IkDamping::Disabled | IkDamping::Adaptive { .. } => T::zero(),
+        };
+        let masked_diff = |end: &Isometry3<T>| {
+            let mut diff = calc_pose_diff_in_frame(target_pose, end, constraints.frame);
+            for (i, use_i) in operational_space.iter().enumerate() {
+                if !*use_i {
+                    diff[i] = T::zero();
+                }
+            }
+            diff
+        };
+        let use_dof = operational_space.iter().filter(|x| **x).count();
+        let eps = self.singular_value_tolerance;
+        let mut target_diff = masked_diff(&arm.end_transform());
+        for i in 0..self.num_max_try {
+            let t_n = arm.end_transform();
+            let jacobi = &mut workspace.jacobian;
+            arm.update_world_transforms();
+            fill_jacobian_at_world_transforms(arm, jacobi.columns_mut(0, jacobi.ncols()));
+            if constraints.frame == ConstraintFrame::End {
+                let inv = t_n.rotation.inverse();
+                for mut column in jacobi.column_iter_mut() {
+                    let linear = inv * column.fixed_rows::<3>(0).into_owned();
+                    let angular = inv * column.fixed_rows::<3>(3).into_owned();
+                    column.fixed_rows_mut::<3>(0).copy_from(&linear);
+                    column.fixed_rows_mut::<3>(3).copy_from(&angular);
+                }
+            }
+            for (r, use_r) in operational_space.iter().enumerate() {
+                if !*use_r {
+                    jacobi.row_mut(r).fill(T::zero());
+                }
+            }
+            // weighted: solve in the joint space scaled by 1 / sqrt(weight)
+            if let Some(ref weights) = self.custom_weights {
+                for (mut column, weight) in jacobi.column_iter_mut().zip(weights) {
+                    column *= T::one() / weight.sqrt();
+                }
+            }
+            let jacobi = &workspace.jacobian;
+            let mut square = Matrix6::from_fn(|r, c| jacobi.row(r).dot(&jacobi.row(c)));
+            let step = if squared_factor <= T::zero()
+                && (jacobi.ncols() > use_dof || self.damping != IkDamping::Disabled)
+            {
+                // pseudo inverse like the SVD of the normal solve: the eigenvalues of J Jᵀ
+                // are the squares of the singular values, and the small ones are cut off
+                let eigen = square.symmetric_eigen();
+                let mut step = eigen.eigenvectors.tr_mul(&target_diff);
+                for (s, value) in step.iter_mut().zip(eigen.eigenvalues.iter()) {
+                    *s = if *value > eps * eps {
+                        *s / *value
+                    } else {
+                        T::zero()
+                    };
+                }
+                eigen.eigenvectors * step
+            } else {
+                for (r, use_r) in operational_space.iter().enumerate() {
+                    square[(r, r)] += if *use_r { squared_factor } else { T::one() };
+                }
+                square
+                    .lu()
+                    .solve(&target_diff)
+                    .ok_or(Error::InverseMatrixError)?
+            };
+            arm.copy_joint_positions(&mut workspace.positions);
+            for (c, position) in workspace.positions.iter_mut().enumerate() {
+                let mut d_q = jacobi.column(c).dot(&step);
+                if let Some(ref weights) = self.custom_weights {
+                    d_q *= T::one() / weights[c].sqrt();
+                }
+                *position += self.jacobian_multiplier * d_q;
+            }
+            arm.set_joint_positions_clamped(&workspace.positions);
+            target_diff = masked_diff(&arm.end_transform());
+            let len_diff = target_diff.fixed_rows::<3>(0).into_owned();
+            let rot_diff = target_diff.fixed_rows::<3>(3).into_owned();
+            if self.is_reached(&len_diff, &rot_diff) {
+                arm.copy_joint_positions(&mut workspace.positions);
+                wrap_continuous_positions(arm, &mut workspace.positions, &workspace.orig_positions);
+                arm.set_joint_positions_clamped(&workspace.positions);
+                return Ok(i + 1);
+            }
+        }
+        Err(Error::NotConvergedError {
+            num_tried: self.num_max_try,
+            position_diff: na::try_convert(target_diff.fixed_rows::<3>(0).into_owned())
+                .unwrap_or_default(),
+            rotation_diff: na::try_convert(target_diff.fixed_rows::<3>(3).into_owned())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// The node of each position of `arm`, see `Chain::iter_position_nodes()`
+pub(crate) fn movable_nodes<T>(arm: &SerialChain<T>) -> Vec<Node<T>>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    arm.iter_position_nodes().cloned().collect()
+}
+
+/// Add the positions of `chain` to `keys` without duplication, and returns their indices
+///
+/// A key is a movable node and the index in its `Joint::joint_positions()`. The returned
+/// indices are in the order of `Chain::joint_positions()`.
+pub(crate) fn add_position_keys<T>(keys: &mut Vec<(Node<T>, usize)>, chain: &Chain<T>) -> Vec<usize>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let mut indices = Vec::with_capacity(chain.dof());
+    for node in chain.iter().filter(|n| n.joint().is_movable()) {
+        let num_positions = node.joint().num_positions();
+        for i in 0..num_positions {
+            let index = match keys.iter().position(|(n, j)| n == node && *j == i) {
+                Some(index) => index,
+                None => {
+                    keys.push((node.clone(), i));
+                    keys.len() - 1
+                }
+            };
+            indices.push(index);
+        }
+    }
+    indices
+}
+
+/// The current positions of `keys` of `add_position_keys()`
+pub(crate) fn positions_of_keys<T>(keys: &[(Node<T>, usize)]) -> Vec<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    keys.iter()
+        .map(|(node, i)| node.joint().joint_positions()[*i])
+        .collect()
+}
+
+/// Set `positions` of the union of the movable nodes of `chains` by
+/// `Chain::set_joint_positions_clamped()` of each chain, so that the limits and the
+/// coupled limits of all of them are respected
+///
+/// `columns[i]` is the indices of the positions of `chains[i]` in `positions`.
+/// `positions` is updated to the clamped values, so the joints shared by the chains
+/// are passed to the next chain after they are clamped by the previous one.
+pub(crate) fn set_union_positions_clamped<T>(
+    chains: &[&Chain<T>],
+    columns: &[Vec<usize>],
+    positions: &mut [T],
+) where
+    T: RealField + SubsetOf<f64>,
+{
+    for (chain, chain_columns) in chains.iter().zip(columns) {
+        let chain_positions = chain_columns
+            .iter()
+            .map(|c| positions[*c])
+            .collect::<Vec<_>>();
+        chain.set_joint_positions_clamped(&chain_positions);
+        for (c, position) in chain_columns.iter().zip(chain.joint_positions()) {
+            positions[*c] = position;
+        }
+    }
+}
+
+pub(crate) fn target_diff_to_len_rot_diff<T>(
+    target_diff: &DVector<T>,
+    operational_space: [bool; 6],
+) -> (Vector3<T>, Vector3<T>)
+where
+    T: RealField,
+{
+    let mut len_diff = Vector3::zeros();
+    let mut index = 0;
+    for i in 0..3 {
+        if operational_space[i] {
+            len_diff[i] = target_diff[index];
+            index += 1;
+        }
+    }
+    let mut rot_diff = Vector3::zeros();
+    for i in 0..3 {
+        if operational_space[i + 3] {
+            rot_diff[i] = target_diff[index];
+            index += 1;
+        }
+    }
+    (len_diff, rot_diff)
+}
+
+impl<T> InverseKinematicsSolver<T> for JacobianIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Set joint positions of `arm` to reach the `target_pose`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f32>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// // Create sub-`Chain` to make it easy to use inverse kinematics
+    /// let target_joint_name = "r_wrist_pitch";
+    /// let r_wrist = chain.find(target_joint_name).unwrap();
+    /// let mut arm = k::SerialChain::from_end(r_wrist);
+    /// println!("arm: {}", arm);
+    ///
+    /// // Set joint positions of `arm`
+    /// let positions = vec![0.1, 0.2, 0.0, -0.5, 0.0, -0.3];
+    /// arm.set_joint_positions(&positions).unwrap();
+    /// println!("initial positions={:?}", arm.joint_positions());
+    ///
+    /// // Get the transform of the end of the manipulator (forward kinematics)
+    /// let mut target = arm.update_transforms().last().unwrap().clone();
+    ///
+    /// println!("initial target pos = {}", target.translation);
+    /// println!("move x: -0.1");
+    /// target.translation.vector.x -= 0.1;
+    ///
+    /// // Create IK solver with default settings
+    /// let solver = k::JacobianIkSolver::default();
+    ///
+    /// // solve and move the manipulator positions
+    /// solver.solve(&arm, &target).unwrap();
+    /// println!("solved positions={:?}", arm.joint_positions());
+    /// ```
+    fn solve(&self, arm: &SerialChain<T>, target_pose: &Isometry3<T>) -> Result<(), Error> {
+        self.solve_with_constraints(arm, target_pose, &Constraints::default())
+    }
+
+    /// Set joint positions of `arm` to reach the `target_pose` with constraints
+    ///
+    /// If you want to loose the constraints, use this method.
+    /// For example, ignoring rotation with an axis.
+    /// It enables to use the arms which has less than six DoF.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f32>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let target_joint_name = "r_wrist_pitch";
+    /// let r_wrist = chain.find(target_joint_name).unwrap();
+    /// let mut arm = k::SerialChain::from_end(r_wrist);
+    /// let positions = vec![0.1, 0.2, 0.0, -0.5, 0.0, -0.3];
+    /// arm.set_joint_positions(&positions).unwrap();
+    /// let mut target = arm.update_transforms().last().unwrap().clone();
+    /// target.translation.vector.x -= 0.1;
+    /// let solver = k::JacobianIkSolver::default();
+    ///
+    /// let mut constraints = k::Constraints::default();
+    /// constraints.rotation_x = false;
+    /// constraints.rotation_z = false;
+    /// solver
+    ///    .solve_with_constraints(&arm, &target, &constraints)
+    ///    .unwrap_or_else(|err| {
+    ///        println!("Err: {}", err);
+    ///    });
+    /// ```
+    fn solve_with_constraints(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error> {
+        self.solve_with_iterations(arm, target_pose, constraints)
+            .map(|_| ())
+    }
+
+    fn solve_with_iterations(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<Option<usize>, Error> {
+        if let Some(re) = self.solve_preallocated(arm, target_pose, constraints) {
+            return re.map(Some);
+        }
+        let orig_positions = arm.joint_positions();
+        let re = self.solve_with_constraints_internal(arm, target_pose, constraints);
+        if re.is_err() {
+            arm.set_joint_positions(&orig_positions)?;
+        };
+        re.map(Some)
+    }
+
+    /// Allocate the jacobian and the joint positions for the DoF of `arm`
+    ///
+    /// The following `solve()`, `solve_with_constraints()` and `solve_with_iterations()`
+    /// of the arms of the same DoF use them, and don't allocate memory if
+    ///
+    /// * no nullspace function, nullspace objective, clearance constraint, iteration
+    ///   callback and profiler are set,
+    /// * the update rule is `IkUpdateRule::Inverse`, the damping is not `IkDamping::Adaptive`
+    ///   and the weighting is `JointWeighting::Uniform` or the custom weights, and
+    /// * the arm has no coupled limits.
+    ///
+    /// The hooks, the profiler and the pose sink of the chain are not called in the
+    /// iterations. The step is the same as the normal solve, the damped least squares
+    /// `Jᵀ (J Jᵀ + λ² I)⁻¹ e` or the pseudo inverse cutting off the singular values smaller
+    /// than the tolerance, but it is calculated from `J Jᵀ` with the fixed size matrices.
+    /// If the settings need allocations, the normal solve is used.
+    ///
+    /// With the `assert-no-alloc` feature and `CountingAllocator` as the global allocator,
+    /// the solve panics if it allocates.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let mut target = arm.end_transform();
+    /// target.translation.vector.z += 0.02;
+    ///
+    /// let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+    /// solver.preallocate(&arm);
+    /// // e.g. in the control loop
+    /// solver.solve(&arm, &target).unwrap();
+    /// let end = arm.end_transform();
+    /// assert!((end.translation.vector - target.translation.vector).norm() < 0.001);
+    /// ```
+    fn preallocate(&self, arm: &SerialChain<T>) {
+        *self.workspace.lock().unwrap() = Some(IkWorkspace::new(arm.dof()));
+    }
+}
+
+impl<T> Default for JacobianIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn default() -> Self {
+        let tolerances = Tolerances::default();
+        Self::new(
+            tolerances.convergence_distance,
+            tolerances.convergence_angle,
+            na::convert(0.5),
+            10,
+        )
+    }
+}
+
+/// Inverse Kinematics Solver using FABRIK (Forward And Backward Reaching Inverse Kinematics)
+///
+/// The positions of the joints are moved by the forward and backward reaching passes,
+/// and the joint positions are fitted to them from the base one by one. It is much
+/// cheaper than `JacobianIkSolver` for each iteration and works well with long chains
+/// like the rigs of characters, but it solves only the position of the end.
+/// The rotation of the target and the rotation flags of `Constraints` are ignored.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let mut target = arm.end_transform();
+/// target.translation.vector.x += 0.05;
+/// target.translation.vector.z += 0.05;
+///
+/// let solver = k::FabrikIkSolver::default();
+/// solver.solve(&arm, &target).unwrap();
+/// let end = arm.end_transform();
+/// assert!((end.translation.vector - target.translation.vector).norm() < 0.001);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FabrikIkSolver<T: RealField> {
+    /// If the distance is smaller than this value, it is reached.
+    ///
+    /// Values smaller than `Tolerances::convergence_floor()` are raised to it.
+    pub allowable_target_distance: T,
+    /// How many times the forward and backward passes are tried
+    pub num_max_try: usize,
+}
+
+impl<T> FabrikIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    pub fn new(allowable_target_distance: T, num_max_try: usize) -> Self {
+        Self {
+            allowable_target_distance,
+            num_max_try,
+        }
+    }
+
+    fn solve_internal(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error> {
+        let nodes = arm
+            .iter()
+            .filter(|n| n.joint().is_movable())
+            .collect::<Vec<_>>();
+        if arm.iter().next().is_none() {
+            return Err(Error::PreconditionError {
+                dof: 0,
+                necessary_dof: 1,
+            });
+        }
+        let use_axes = [
+            constraints.position_x,
+            constraints.position_y,
+            constraints.position_z,
+        ];
+        let allowable_distance = self
+            .allowable_target_distance
+            .max(Tolerances::<T>::convergence_floor());
+        let epsilon = Tolerances::<T>::convergence_floor();
+        let mut diff = Vector3::zeros();
+        for _ in 0..self.num_max_try {
+            arm.update_transforms();
+            let mut points = nodes
+                .iter()
+                .map(|n| n.world_transform().unwrap().translation.vector)
+                .collect::<Vec<_>>();
+            // the tool center point, which includes the end effector
+            points.push(arm.end_transform().translation.vector);
+            let end = points[points.len() - 1];
+            let mut target = target_pose.translation.vector;
+            for i in 0..3 {
+                if !use_axes[i] {
+                    target[i] = end[i];
+                }
+            }
+            diff = target - end;
+            if diff.norm() < allowable_distance {
+                return Ok(());
+            }
+            let goals = fabrik_points(&points, &target);
+            // fit the joints to the goals from the base
+            let mut column = 0;
+            for i in 0..nodes.len() {
+                let num_positions = nodes[i].joint().num_positions();
+                for k in 0..num_positions {
+                    column += 1;
+                    let t_i = nodes[i].world_transform().unwrap();
+                    let p_i = t_i.translation.vector;
+                    let motion = nodes[i].joint().world_motion(&t_i, k, &p_i);
+                    // the rotation (or the translation) which fits the downstream points
+                    // to their goals best in the least squares sense
+                    let delta = if motion.rotation != Vector3::zeros() {
+                        let scale = motion.rotation.norm();
+                        let a = motion.rotation / scale;
+                        let (sin, cos) =
+                            (i + 1..points.len()).fold((T::zero(), T::zero()), |(sin, cos), j| {
+                                let u = points[j] - p_i;
+                                let v = goals[j] - p_i;
+                                let u = u - a * a.dot(&u);
+                                let v = v - a * a.dot(&v);
+                                (sin + a.dot(&u.cross(&v)), cos + u.dot(&v))
+                            });
+                        if sin.abs() < epsilon && cos.abs() < epsilon {
+                            continue;
+                        }
+                        sin.atan2(cos) / scale
+                    } else {
+                        let a = motion.translation;
+                        let scale = a.norm_squared();
+                        if scale < epsilon {
+                            continue;
+                        }
+                        let num: T = na::convert((points.len() - i - 1) as f64);
+                        (i + 1..points.len())
+                            .fold(T::zero(), |sum, j| sum + a.dot(&(goals[j] - points[j])))
+                            / num
+                            / scale
+                    };
+                    let mut positions = arm.joint_positions();
+                    positions[column - 1] += delta;
+                    arm.set_joint_positions_clamped(&positions);
+                    arm.update_transforms();
+                    for (point, node) in points.iter_mut().zip(&nodes) {
+                        *point = node.world_transform().unwrap().translation.vector;
+                    }
+                    let last = points.len() - 1;
+                    points[last] = arm.end_transform().translation.vector;
+                }
+            }
+        }
+        Err(Error::NotConvergedError {
+            num_tried: self.num_max_try,
+            position_diff: na::try_convert(diff).unwrap_or_default(),
+            rotation_diff: Vector3::zeros(),
+        })
+    }
+}
+
+/// Move `points` to reach `target` keeping the distances between them by FABRIK
+fn fabrik_points<T: RealField>(points: &[Vector3<T>], target: &Vector3<T>) -> Vec<Vector3<T>> {
+    let lengths = points
+        .windows(2)
+        .map(|w| (w[1] - w[0]).norm())
+        .collect::<Vec<_>>();
+    let base = points[0];
+    let reach = |from: &Vector3<T>, to: &Vector3<T>, length: T| match (to - from)
+        .try_normalize(T::default_epsilon())
+    {
+        Some(direction) => from + direction * length,
+        None => *from,
+    };
+    let mut goals = points.to_vec();
+    let last = goals.len() - 1;
+    // backward
+    goals[last] = *target;
+    for i in (0..last).rev() {
+        goals[i] = reach(&goals[i + 1], &goals[i], lengths[i]);
+    }
+    // forward
+    goals[0] = base;
+    for i in 0..last {
+        goals[i + 1] = reach(&goals[i], &goals[i + 1], lengths[i]);
+    }
+    goals
+}
+
+impl<T> Default for FabrikIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn default() -> Self {
+        Self::new(Tolerances::default().convergence_distance, 100)
+    }
+}
+
+impl<T> InverseKinematicsSolver<T> for FabrikIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Move the end of `arm` to the position of `target_pose`, ignoring the rotation
+    ///
+    /// The positions are restored if it fails.
+    fn solve_with_constraints(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error> {
+        let orig_positions = arm.joint_positions();
+        let result = self.solve_internal(arm, target_pose, constraints);
+        if result.is_err() {
+            arm.set_joint_positions_unchecked(&orig_positions);
+        }
+        result
+    }
+}
+
+/// Scale `vector` down so that the maximum absolute value of the elements is `max`
+fn clamp_max_abs<T: RealField>(vector: &mut DVector<T>, max: T) {
+    let max_abs = vector.amax();
+    if max_abs > max {
+        *vector *= max / max_abs;
+    }
+}
+
+/// Joint step of the Selectively Damped Least Squares for the error `err`
+///
+/// Each row of `jacobi` is regarded as a target of its own, when the ratio of
+/// the motion of the targets to that of the joints is estimated.
+fn selectively_damped_step<T>(
+    jacobi: &DMatrix<T>,
+    err: &DVector<T>,
+    max_step: T,
+    eps: T,
+) -> DVector<T>
+where
+    T: RealField,
+{
+    let column_norms = jacobi
+        .column_iter()
+        .map(|column| column.iter().fold(T::zero(), |sum, x| sum + x.abs()))
+        .collect::<Vec<_>>();
+    let svd = jacobi.clone().svd(true, true);
+    let u = svd.u.unwrap();
+    let v_t = svd.v_t.unwrap();
+    let mut d_q = DVector::zeros(jacobi.ncols());
+    for (i, sigma) in svd.singular_values.iter().enumerate() {
+        if *sigma <= eps {
+            continue;
+        }
+        let u_i = u.column(i);
+        let v_i = v_t.row(i).transpose();
+        // how much the targets move by the unit step in this direction
+        let n = u_i.iter().fold(T::zero(), |sum, x| sum + x.abs());
+        // how much the targets move by the joints of the step to move them by unit
+        let m = v_i
+            .iter()
+            .zip(column_norms.iter())
+            .fold(T::zero(), |sum, (v, rho)| sum + v.abs() * *rho)
+            / *sigma;
+        let gamma = (n / m).min(T::one()) * max_step;
+        let mut phi = v_i * (u_i.dot(err) / *sigma);
+        clamp_max_abs(&mut phi, gamma);
+        d_q += phi;
+    }
+    clamp_max_abs(&mut d_q, max_step);
+    d_q
+}
+
+/// Inverse Kinematics Solver using the transpose of the Jacobian matrix
+///
+/// The joints move along `gain * Jᵀ e` for the error `e` of the end in each iteration,
+/// which is the gradient descent of the squared error. It needs no matrix inversion,
+/// so it is cheap enough for embedded targets, but it converges slower than
+/// `JacobianIkSolver` and less precisely.
+/// `gain` has to be small enough for the size of the arm, or it oscillates.
+/// The default tolerates a few millimeters of error. After `preallocate()`, the solves
+/// don't allocate memory.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let mut target = arm.end_transform();
+/// target.translation.vector.x += 0.02;
+/// target.translation.vector.z += 0.02;
+///
+/// // only the position of the end
+/// let constraints = k::Constraints {
+///     rotation_x: false,
+///     rotation_y: false,
+///     rotation_z: false,
+///     ..Default::default()
+/// };
+/// let solver = k::JacobianTransposeSolver::new(0.003, 0.03, 1.0, 1000);
+/// solver
+///     .solve_with_constraints(&arm, &target, &constraints)
+///     .unwrap();
+/// let end = arm.end_transform();
+/// assert!((end.translation.vector - target.translation.vector).norm() < 0.003);
+/// ```
+#[derive(Debug)]
+pub struct JacobianTransposeSolver<T: RealField> {
+    /// If the distance is smaller than this value, it is reached.
+    ///
+    /// Values smaller than `Tolerances::convergence_floor()` are raised to it.
+    pub allowable_target_distance: T,
+    /// If the angle distance is smaller than this value, it is reached.
+    ///
+    /// Values smaller than `Tolerances::convergence_floor()` are raised to it.
+    pub allowable_target_angle: T,
+    /// step size of the gradient descent
+    pub gain: T,
+    /// How many times the joints are tried to be moved
+    pub num_max_try: usize,
+    /// The original joint positions and the ones of the current iteration, allocated
+    /// by `preallocate()`
+    buffers: Mutex<(Vec<T>, Vec<T>)>,
+}
+
+impl<T> Clone for JacobianTransposeSolver<T>
+where
+    T: RealField,
+{
+    fn clone(&self) -> Self {
+        Self {
+            allowable_target_distance: self.allowable_target_distance,
+            allowable_target_angle: self.allowable_target_angle,
+            gain: self.gain,
+            num_max_try: self.num_max_try,
+            buffers: Mutex::new(self.buffers.lock().map(|b| b.clone()).unwrap_or_default()),
+        }
+    }
+}
+
+impl<T> JacobianTransposeSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    pub fn new(
+        allowable_target_distance: T,
+        allowable_target_angle: T,
+        gain: T,
+        num_max_try: usize,
+    ) -> Self {
+        Self {
+            allowable_target_distance,
+            allowable_target_angle,
+            gain,
+            num_max_try,
+            buffers: Mutex::new((Vec::new(), Vec::new())),
+        }
+    }
+
+    /// Error of the end selected by `constraints`, in the frame of the root of `arm`
+    fn masked_error(
+        end: &Isometry3<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> (Vector3<T>, Vector3<T>) {
+        let diff = calc_pose_diff(target_pose, end);
+        let mut p_diff = diff.fixed_rows::<3>(0).into_owned();
+        let mut w_diff = diff.fixed_rows::<3>(3).into_owned();
+        let to_end = match constraints.frame {
+            ConstraintFrame::World => UnitQuaternion::identity(),
+            ConstraintFrame::End => end.rotation.inverse(),
+        };
+        p_diff = to_end * p_diff;
+        w_diff = to_end * w_diff;
+        let space = define_operational_space(constraints);
+        for i in 0..3 {
+            if !space[i] {
+                p_diff[i] = T::zero();
+            }
+            if !space[i + 3] {
+                w_diff[i] = T::zero();
+            }
         }
-        let positions_vec = if dof > use_dof {
-            const EPS: f64 = 0.0001;
-            // redundant: pseudo inverse
-            match self.nullspace_function {
-                Some(ref f) => {
-                    let jacobi_inv = jacobi.clone().pseudo_inverse(na::convert(EPS)).unwrap();
-                    let d_q = jacobi_inv.clone() * err
-                        + (na::DMatrix::identity(dof, dof) - jacobi_inv * jacobi)
-                            * na::DVector::from_vec(f(&orig_positions));
-                    self.add_positions_with_multiplier(&orig_positions, d_q.as_slice())
+        (to_end.inverse() * p_diff, to_end.inverse() * w_diff)
+    }
+
+    /// Call `f` with the original positions and the positions of the iterations, in the
+    /// buffers of `preallocate()` if they are allocated for `arm`
+    fn with_buffers<R>(&self, arm: &SerialChain<T>, f: impl FnOnce(&mut [T], &mut [T]) -> R) -> R {
+        let dof = arm.dof();
+        match self.buffers.try_lock() {
+            Ok(mut buffers) if buffers.0.len() == dof => {
+                #[cfg(feature = "assert-no-alloc")]
+                let num_allocations = crate::allocation::num_allocations();
+                let (orig_positions, positions) = &mut *buffers;
+                let re = f(orig_positions, positions);
+                #[cfg(feature = "assert-no-alloc")]
+                if arm.coupled_limits().is_empty() {
+                    crate::allocation::assert_no_allocations_since(
+                        num_allocations,
+                        "preallocated IK solve",
+                    );
                 }
-                None => self.add_positions_with_multiplier(
-                    &orig_positions,
-                    jacobi
-                        .svd(true, true)
-                        .solve(&err, na::convert(EPS))
-                        .unwrap() // TODO
-                        .as_slice(),
-                ),
+                re
             }
-        } else {
-            // normal inverse matrix
-            self.add_positions_with_multiplier(
-                &orig_positions,
-                jacobi
-                    .lu()
-                    .solve(&err)
-                    .ok_or(Error::InverseMatrixError)?
-                    .as_slice(),
-            )
-        };
-        arm.set_joint_positions_clamped(&positions_vec);
-        Ok(calc_pose_diff_with_constraints(
-            target_pose,
-            &arm.end_transform(),
-            operational_space,
-        ))
+            _ => f(&mut vec![T::zero(); dof], &mut vec![T::zero(); dof]),
+        }
     }
 
-    fn solve_with_constraints_internal(
+    fn solve_internal(
         &self,
         arm: &SerialChain<T>,
         target_pose: &Isometry3<T>,
         constraints: &Constraints,
-    ) -> Result<(), Error> {
-        let operational_space = define_operational_space(&constraints);
-        let orig_positions = arm.joint_positions();
-        let use_dof = operational_space.iter().filter(|x| **x).count();
-        if orig_positions.len() < use_dof {
-            return Err(Error::PreconditionError {
-                dof: orig_positions.len(),
-                necessary_dof: use_dof,
-            });
-        }
-        let mut last_target_distance = None;
-        for _ in 0..self.num_max_try {
-            let target_diff =
-                self.solve_one_loop_with_constraints(&arm, target_pose, constraints)?;
-            let (len_diff, rot_diff) = target_diff_to_len_rot_diff(&target_diff, operational_space);
-            if len_diff.norm() < self.allowable_target_distance
-                && rot_diff.norm() < self.allowable_target_angle
-            {
-                let non_checked_positions = arm.joint_positions();
-                arm.set_joint_positions_clamped(&non_checked_positions);
-                return Ok(());
+        positions: &mut [T],
+    ) -> Result<usize, Error> {
+        let floor = Tolerances::<T>::convergence_floor();
+        let allowable_distance = self.allowable_target_distance.max(floor);
+        let allowable_angle = self.allowable_target_angle.max(floor);
+        let mut diff = (Vector3::zeros(), Vector3::zeros());
+        for i in 0..self.num_max_try {
+            let end = arm.end_transform();
+            diff = Self::masked_error(&end, target_pose, constraints);
+            if diff.0.norm() < allowable_distance && diff.1.norm() < allowable_angle {
+                return Ok(i);
             }
-            last_target_distance = Some((len_diff, rot_diff));
+            let (p_diff, w_diff) = &diff;
+            arm.copy_joint_positions(positions);
+            let mut index = 0;
+            let mut trans = Isometry3::identity();
+            for node in arm.iter() {
+                let joint = node.joint();
+                trans *= joint.local_transform();
+                for k in 0..joint.num_positions() {
+                    // the column of the jacobian
+                    let motion = joint.world_motion(&trans, k, &end.translation.vector);
+                    let gradient = motion.translation.dot(p_diff) + motion.rotation.dot(w_diff);
+                    positions[index] += self.gain * gradient;
+                    index += 1;
+                }
+            }
+            // through the chain, so the coupled limits are respected
+            arm.set_joint_positions_clamped(positions);
         }
-        arm.set_joint_positions(&orig_positions)?;
         Err(Error::NotConvergedError {
             num_tried: self.num_max_try,
-            position_diff: na::try_convert(last_target_distance.unwrap().0).unwrap_or_default(),
-            rotation_diff: na::try_convert(last_target_distance.unwrap().1).unwrap_or_default(),
+            position_diff: na::try_convert(diff.0).unwrap_or_default(),
+            rotation_diff: na::try_convert(diff.1).unwrap_or_default(),
         })
     }
 }
 
-fn target_diff_to_len_rot_diff<T>(
-    target_diff: &DVector<T>,
-    operational_space: [bool; 6],
-) -> (Vector3<T>, Vector3<T>)
+impl<T> Default for JacobianTransposeSolver<T>
 where
-    T: RealField,
+    T: RealField + SubsetOf<f64>,
 {
-    let mut len_diff = Vector3::zeros();
-    let mut index = 0;
-    for i in 0..3 {
-        if operational_space[i] {
-            len_diff[i] = target_diff[index];
-            index += 1;
+    fn default() -> Self {
+        Self::new(
+            na::convert(0.005),
+            na::convert(0.05),
+            na::convert(0.4),
+            3000,
+        )
+    }
+}
+
+impl<T> InverseKinematicsSolver<T> for JacobianTransposeSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// The positions are restored if it fails.
+    fn solve_with_constraints(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error> {
+        self.solve_with_iterations(arm, target_pose, constraints)
+            .map(|_| ())
+    }
+
+    fn solve_with_iterations(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<Option<usize>, Error> {
+        self.with_buffers(arm, |orig_positions, positions| {
+            arm.copy_joint_positions(orig_positions);
+            let result = self.solve_internal(arm, target_pose, constraints, positions);
+            if result.is_err() {
+                arm.set_joint_positions_unchecked(orig_positions);
+            }
+            result.map(Some)
+        })
+    }
+
+    /// Allocate the buffers of the joint positions for the DoF of `arm`
+    ///
+    /// The following solves of the arms of the same DoF don't allocate memory if the arm
+    /// has no coupled limits. Without it, the buffers are allocated once per solve.
+    /// With the `assert-no-alloc` feature and `CountingAllocator` as the global allocator,
+    /// the solve panics if it allocates.
+    fn preallocate(&self, arm: &SerialChain<T>) {
+        let dof = arm.dof();
+        *self.buffers.lock().unwrap() = (vec![T::zero(); dof], vec![T::zero(); dof]);
+    }
+}
+
+/// A task of `JacobianIkSolver::solve_prioritized()`
+#[derive(Debug, Clone)]
+pub enum PriorityTask<'a, T: RealField> {
+    /// Pose of the end of an arm
+    Pose(MultiChainTask<'a, T>),
+    /// Center of mass in the support region, it has no rows while the center of mass is in it
+    Balance(BalanceTask<'a, T>),
+    /// Positions of all the movable joints of a chain, e.g. the nominal posture
+    ///
+    /// It is done as much as possible, and it is not required for the convergence.
+    Posture {
+        /// the chain whose movable joints are moved
+        chain: &'a Chain<T>,
+        /// the target positions, the length must be the DoF of `chain`
+        positions: Vec<T>,
+    },
+}
+
+impl<'a, T> PriorityTask<'a, T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// The chain whose movable joints are moved by the task
+    fn chain(&self) -> &'a Chain<T> {
+        match self {
+            PriorityTask::Pose(task) => task.arm,
+            PriorityTask::Balance(balance) => balance.chain,
+            PriorityTask::Posture { chain, .. } => chain,
         }
     }
-    let mut rot_diff = Vector3::zeros();
-    for i in 0..3 {
-        if operational_space[i + 3] {
-            rot_diff[i] = target_diff[index];
+
+    /// The jacobian and the error of the task, in the columns of `Chain::joint_positions()`
+    fn jacobian_and_error(&self) -> (DMatrix<T>, DVector<T>) {
+        match self {
+            PriorityTask::Pose(task) => {
+                let end = task.arm.end_transform();
+                let err = calc_pose_diff_with_constraints(&task.target, &end, &task.constraints);
+                let full = jacobian(task.arm);
+                let jacobi =
+                    constrained_jacobian(full.columns(0, full.ncols()), &end, &task.constraints);
+                (jacobi, err)
+            }
+            PriorityTask::Balance(balance) => match balance_error(balance) {
+                Some(err) => (
+                    center_of_mass_jacobian(balance.chain)
+                        .rows(0, 2)
+                        .into_owned(),
+                    DVector::from_column_slice(err.as_slice()),
+                ),
+                None => (DMatrix::zeros(0, balance.chain.dof()), DVector::zeros(0)),
+            },
+            PriorityTask::Posture { chain, positions } => {
+                let current = DVector::from_vec(chain.joint_positions());
+                (
+                    DMatrix::identity(chain.dof(), chain.dof()),
+                    DVector::from_column_slice(positions) - current,
+                )
+            }
+        }
+    }
+}
+
+/// Move the positions of the continuous joints to the equivalent angles nearest
+/// to the original ones, so that they take the shortest way around
+fn wrap_continuous_positions<T>(arm: &SerialChain<T>, positions: &mut [T], orig_positions: &[T])
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let mut index = 0;
+    for joint in arm.iter_joints() {
+        for i in 0..joint.num_positions() {
+            if joint.continuous && joint.joint_type.is_angle(i) {
+                positions[index] =
+                    angles::nearest_equivalent_angle(positions[index], orig_positions[index]);
+            }
             index += 1;
         }
     }
-    (len_diff, rot_diff)
 }
 
-impl<T> InverseKinematicsSolver<T> for JacobianIkSolver<T>
+/// The error of the projected center of mass from the region, if it is out of it
+pub(crate) fn balance_error<T>(balance: &BalanceTask<'_, T>) -> Option<Vector2<T>>
 where
     T: RealField + SubsetOf<f64>,
 {
-    /// Set joint positions of `arm` to reach the `target_pose`
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use k::prelude::*;
-    ///
-    /// let chain = k::Chain::<f32>::from_urdf_file("urdf/sample.urdf").unwrap();
-    /// // Create sub-`Chain` to make it easy to use inverse kinematics
-    /// let target_joint_name = "r_wrist_pitch";
-    /// let r_wrist = chain.find(target_joint_name).unwrap();
-    /// let mut arm = k::SerialChain::from_end(r_wrist);
-    /// println!("arm: {}", arm);
-    ///
-    /// // Set joint positions of `arm`
-    /// let positions = vec![0.1, 0.2, 0.0, -0.5, 0.0, -0.3];
-    /// arm.set_joint_positions(&positions).unwrap();
-    /// println!("initial positions={:?}", arm.joint_positions());
-    ///
-    /// // Get the transform of the end of the manipulator (forward kinematics)
-    /// let mut target = arm.update_transforms().last().unwrap().clone();
-    ///
-    /// println!("initial target pos = {}", target.translation);
-    /// println!("move x: -0.1");
-    /// target.translation.vector.x -= 0.1;
-    ///
-    /// // Create IK solver with default settings
-    /// let solver = k::JacobianIkSolver::default();
-    ///
-    /// // solve and move the manipulator positions
-    /// solver.solve(&arm, &target).unwrap();
-    /// println!("solved positions={:?}", arm.joint_positions());
-    /// ```
-    fn solve(&self, arm: &SerialChain<T>, target_pose: &Isometry3<T>) -> Result<(), Error> {
-        self.solve_with_constraints(arm, target_pose, &Constraints::default())
+    let com = center_of_mass(balance.chain);
+    let com = Vector2::new(com.x, com.y);
+    let closest = balance.region.closest_point(&com);
+    if closest == com {
+        None
+    } else {
+        Some(closest - com)
     }
+}
 
-    /// Set joint positions of `arm` to reach the `target_pose` with constraints
+impl<T> JacobianIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Solve the tasks with strict priorities, e.g. feet poses > center of mass > hands > posture
     ///
-    /// If you want to loose the constraints, use this method.
-    /// For example, ignoring rotation with an axis.
-    /// It enables to use the arms which has less than six DoF.
+    /// `levels[0]` has the highest priority, and the tasks in a level have the same priority.
+    /// Each level is solved in the nullspace of all the higher levels (successive nullspace
+    /// projections), so the lower levels never disturb the higher ones. The joints shared by
+    /// the tasks are solved as a single value, like `solve_multiple()`.
     ///
-    /// # Example
+    /// It converges when all the `Pose` and `Balance` tasks are reached, so they must be
+    /// feasible together. `Posture` tasks are done as much as possible in the remaining
+    /// nullspace. The projections are strict only without damping. If it fails, the positions
+    /// of all the joints are restored.
+    ///
+    /// # Examples
     ///
     /// ```
-    /// use k::prelude::*;
+    /// use k::*;
     ///
-    /// let chain = k::Chain::<f32>::from_urdf_file("urdf/sample.urdf").unwrap();
-    /// let target_joint_name = "r_wrist_pitch";
-    /// let r_wrist = chain.find(target_joint_name).unwrap();
-    /// let mut arm = k::SerialChain::from_end(r_wrist);
-    /// let positions = vec![0.1, 0.2, 0.0, -0.5, 0.0, -0.3];
-    /// arm.set_joint_positions(&positions).unwrap();
-    /// let mut target = arm.update_transforms().last().unwrap().clone();
-    /// target.translation.vector.x -= 0.1;
-    /// let solver = k::JacobianIkSolver::default();
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let left = SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// let right = SerialChain::from_end(chain.find("r_wrist_pitch").unwrap());
+    /// left.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// right.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let mut left_target = left.end_transform();
+    /// left_target.translation.vector.z += 0.02;
+    /// let mut right_target = right.end_transform();
+    /// right_target.translation.vector.x -= 0.02;
     ///
-    /// let mut constraints = k::Constraints::default();
-    /// constraints.rotation_x = false;
-    /// constraints.rotation_z = false;
-    /// solver
-    ///    .solve_with_constraints(&arm, &target, &constraints)
-    ///    .unwrap_or_else(|err| {
-    ///        println!("Err: {}", err);
-    ///    });
+    /// let mut position_only = Constraints::default();
+    /// position_only.rotation_x = false;
+    /// position_only.rotation_y = false;
+    /// position_only.rotation_z = false;
+    /// let levels = vec![
+    ///     vec![PriorityTask::Pose(MultiChainTask::new(&left, left_target))],
+    ///     vec![PriorityTask::Pose(MultiChainTask {
+    ///         arm: &right,
+    ///         target: right_target,
+    ///         constraints: position_only,
+    ///     })],
+    ///     vec![PriorityTask::Posture { chain: &chain, positions: vec![0.0; 12] }],
+    /// ];
+    /// let solver = JacobianIkSolver::new(0.001, 0.01, 0.5, 100);
+    /// solver.solve_prioritized(&levels).unwrap();
+    /// for (arm, target) in [(&left, left_target), (&right, right_target)].iter() {
+    ///     let diff = arm.end_transform().translation.vector - target.translation.vector;
+    ///     assert!(diff.norm() < 0.001);
+    /// }
     /// ```
-    fn solve_with_constraints(
-        &self,
-        arm: &SerialChain<T>,
-        target_pose: &Isometry3<T>,
-        constraints: &Constraints,
-    ) -> Result<(), Error> {
-        let orig_positions = arm.joint_positions();
-        let re = self.solve_with_constraints_internal(arm, target_pose, constraints);
-        if re.is_err() {
-            arm.set_joint_positions(&orig_positions)?;
-        };
-        re
-    }
-}
+    pub fn solve_prioritized(&self, levels: &[Vec<PriorityTask<'_, T>>]) -> Result<(), Error> {
+        // the positions of all the tasks without duplication
+        let mut keys = Vec::new();
+        let mut columns = Vec::with_capacity(levels.len());
+        for level in levels {
+            let mut level_columns = Vec::with_capacity(level.len());
+            for task in level {
+                if let PriorityTask::Posture { chain, positions } = task {
+                    if positions.len() != chain.dof() {
+                        return Err(Error::SizeMismatchError {
+                            input: positions.len(),
+                            required: chain.dof(),
+                        });
+                    }
+                }
+                level_columns.push(add_position_keys(&mut keys, task.chain()));
+            }
+            columns.push(level_columns);
+        }
+        let chains = levels
+            .iter()
+            .flatten()
+            .map(|task| task.chain())
+            .collect::<Vec<_>>();
+        let chain_columns = columns.iter().flatten().cloned().collect::<Vec<_>>();
+        let orig_positions = chains
+            .iter()
+            .map(|chain| chain.joint_positions())
+            .collect::<Vec<_>>();
+        let mut positions = positions_of_keys(&keys);
+        let eps = self.tolerances().singular_value;
+        let dof = keys.len();
 
-impl<T> Default for JacobianIkSolver<T>
-where
-    T: RealField + SubsetOf<f64>,
-{
-    fn default() -> Self {
-        Self::new(na::convert(0.001), na::convert(0.005), na::convert(0.5), 10)
+        let mut last_diff = (Vector3::zeros(), Vector3::zeros());
+        for _ in 0..self.num_max_try {
+            let mut d_q = DVector::zeros(dof);
+            let mut projector = DMatrix::<T>::identity(dof, dof);
+            for (level, level_columns) in levels.iter().zip(&columns) {
+                let rows = level
+                    .iter()
+                    .zip(level_columns)
+                    .map(|(task, task_columns)| (task.jacobian_and_error(), task_columns))
+                    .collect::<Vec<_>>();
+                let num_rows = rows.iter().map(|((j, _), _)| j.nrows()).sum::<usize>();
+                if num_rows == 0 {
+                    continue;
+                }
+                let mut jacobi = DMatrix::zeros(num_rows, dof);
+                let mut err = DVector::zeros(num_rows);
+                let mut row = 0;
+                for ((task_jacobi, task_err), task_columns) in &rows {
+                    for (r, task_row) in task_jacobi.row_iter().enumerate() {
+                        for (c, column) in task_columns.iter().enumerate() {
+                            jacobi[(row + r, *column)] = task_row[c];
+                        }
+                    }
+                    err.rows_mut(row, task_err.len()).copy_from(task_err);
+                    row += task_err.len();
+                }
+                let projected = &jacobi * &projector;
+                let inv = match self.damped_pseudo_inverse(&projected)? {
+                    Some(inv) => inv,
+                    None => projected
+                        .clone()
+                        .pseudo_inverse(eps)
+                        .map_err(|_| Error::InverseMatrixError)?,
+                };
+                d_q += &inv * (err - &jacobi * &d_q);
+                projector -= inv * projected;
+            }
+            for (position, d) in positions.iter_mut().zip(d_q.iter()) {
+                *position += self.jacobian_multiplier * *d;
+            }
+            set_union_positions_clamped(&chains, &chain_columns, &mut positions);
+
+            let mut converged = true;
+            for task in levels.iter().flatten() {
+                match task {
+                    PriorityTask::Pose(task) => {
+                        let diff = calc_pose_diff_with_constraints(
+                            &task.target,
+                            &task.arm.end_transform(),
+                            &task.constraints,
+                        );
+                        let space = define_operational_space(&task.constraints);
+                        let (len_diff, rot_diff) = target_diff_to_len_rot_diff(&diff, space);
+                        if !self.is_reached(&len_diff, &rot_diff) {
+                            converged = false;
+                            last_diff = (len_diff, rot_diff);
+                            break;
+                        }
+                    }
+                    PriorityTask::Balance(balance) => {
+                        if let Some(com_error) = balance_error(balance) {
+                            converged = false;
+                            last_diff = (
+                                Vector3::new(com_error.x, com_error.y, T::zero()),
+                                Vector3::zeros(),
+                            );
+                            break;
+                        }
+                    }
+                    PriorityTask::Posture { .. } => {}
+                }
+            }
+            if converged {
+                return Ok(());
+            }
+        }
+        for (chain, positions) in chains.iter().zip(&orig_positions).rev() {
+            chain.set_joint_positions_unchecked(positions);
+        }
+        Err(Error::NotConvergedError {
+            num_tried: self.num_max_try,
+            position_diff: na::try_convert(last_diff.0).unwrap_or_default(),
+            rotation_diff: na::try_convert(last_diff.1).unwrap_or_default(),
+        })
     }
 }
 
@@ -458,3 +2795,18 @@ fn test_nullspace_func() {
     assert_eq!(values[0], 0.25);
     assert_eq!(values[1], -0.05);
 }
+
+#[test]
+fn test_selectively_damped_step() {
+    // well-conditioned: same as the inverse for small errors
+    let jacobi = DMatrix::from_row_slice(2, 2, &[1.0, 0.0, 0.0, 2.0]);
+    let err = DVector::from_vec(vec![0.1, 0.1]);
+    let d_q = selectively_damped_step(&jacobi, &err, 1.0, 1e-10);
+    assert!((d_q - DVector::from_vec(vec![0.1, 0.05])).norm() < 1e-10);
+    // nearly singular: the step of the weak direction is clamped
+    let jacobi = DMatrix::from_row_slice(2, 3, &[1.0, 1.0, 0.0, 1e-4, 0.0, 0.0]);
+    let err = DVector::from_vec(vec![0.0, 0.1]);
+    let d_q = selectively_damped_step(&jacobi, &err, 0.5, 1e-10);
+    assert!(d_q.amax() <= 0.5 + 1e-10);
+    assert!((jacobi.pseudo_inverse(1e-10).unwrap() * err).amax() > 100.0);
+}
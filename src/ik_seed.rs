@@ -0,0 +1,239 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use na::{Isometry3, RealField, Vector3};
+use nalgebra as na;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+use simba::scalar::SubsetOf;
+
+/// A successful IK solution stored in `IkSeedDatabase`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct IkSeed<T: RealField> {
+    /// index of the workspace region, `floor(position / cell_size)`
+    pub region: [i64; 3],
+    /// the solved target pose
+    pub target: Isometry3<T>,
+    /// the joint positions of the solution
+    pub positions: Vec<T>,
+}
+
+/// Store of the IK solutions keyed by the workspace region of the targets
+///
+/// The workspace is divided into cubes of `cell_size`. The nearest stored solution
+/// in the region of a new target or its neighbor regions is proposed as the initial
+/// positions of the solver, which improves the solve rate for repetitive targets.
+/// It can be saved and loaded with serde if the `serde-serialize` feature is enabled.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let wrist = chain.find("l_wrist_pitch").unwrap();
+/// let arm = SerialChain::from_end(wrist);
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let target = arm.update_transforms().last().unwrap().clone();
+///
+/// let mut database = IkSeedDatabase::new(0.1).unwrap();
+/// let solver = JacobianIkSolver::default();
+/// arm.set_joint_positions(&[0.0, 0.1, 0.0, -0.3, 0.0, -0.1]).unwrap();
+/// database.solve(&solver, &arm, &target).unwrap();
+/// assert_eq!(database.len(), 1);
+///
+/// // the stored solution is used as the seed of a near target
+/// let seed = database.nearest_seed(&target).unwrap();
+/// assert_eq!(seed.positions, arm.joint_positions());
+/// assert!((seed.target.translation.vector - target.translation.vector).norm() < 1e-3);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct IkSeedDatabase<T: RealField> {
+    cell_size: T,
+    /// the maximum number of the seeds in a region, the oldest one is replaced
+    pub max_seeds_per_region: usize,
+    seeds: Vec<IkSeed<T>>,
+}
+
+impl<T> IkSeedDatabase<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create an empty database with the size of the regions
+    ///
+    /// It returns `Error::InvalidParameterError` if `cell_size` is not positive.
+    pub fn new(cell_size: T) -> Result<Self, Error> {
+        if cell_size <= T::zero() {
+            return Err(Error::InvalidParameterError {
+                reason: format!("cell_size must be positive, but {}", cell_size),
+            });
+        }
+        Ok(Self {
+            cell_size,
+            max_seeds_per_region: 8,
+            seeds: Vec::new(),
+        })
+    }
+
+    pub fn cell_size(&self) -> T {
+        self.cell_size
+    }
+
+    /// All the stored seeds, in the order of the insertion
+    pub fn seeds(&self) -> &[IkSeed<T>] {
+        &self.seeds
+    }
+
+    pub fn len(&self) -> usize {
+        self.seeds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seeds.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.seeds.clear();
+    }
+
+    /// Index of the region which contains `position`
+    pub fn region_of(&self, position: &Vector3<T>) -> [i64; 3] {
+        let index = |v: T| na::convert::<T, f64>((v / self.cell_size).floor()) as i64;
+        [index(position.x), index(position.y), index(position.z)]
+    }
+
+    /// Store a solution of `target`
+    ///
+    /// If the region already has `max_seeds_per_region` seeds, the oldest one is removed.
+    pub fn insert(&mut self, target: &Isometry3<T>, positions: Vec<T>) {
+        let region = self.region_of(&target.translation.vector);
+        let in_region = self.seeds.iter().filter(|s| s.region == region).count();
+        if in_region >= self.max_seeds_per_region {
+            if let Some(index) = self.seeds.iter().position(|s| s.region == region) {
+                self.seeds.remove(index);
+            }
+        }
+        self.seeds.push(IkSeed {
+            region,
+            target: *target,
+            positions,
+        });
+    }
+
+    /// The nearest seed in the region of `target` or its neighbor regions
+    ///
+    /// The distance is the translation distance plus the rotation angle multiplied
+    /// by `cell_size`, so that one cell is comparable to one radian.
+    /// Returns `None` if there are no seeds around the target.
+    pub fn nearest_seed(&self, target: &Isometry3<T>) -> Option<&IkSeed<T>> {
+        let region = self.region_of(&target.translation.vector);
+        self.seeds
+            .iter()
+            .filter(|s| {
+                s.region
+                    .iter()
+                    .zip(&region)
+                    .all(|(a, b)| (a - b).abs() <= 1)
+            })
+            .map(|s| (self.distance(&s.target, target), s))
+            .fold(
+                None,
+                |nearest: Option<(T, &IkSeed<T>)>, (d, s)| match nearest {
+                    Some((min, _)) if min <= d => nearest,
+                    _ => Some((d, s)),
+                },
+            )
+            .map(|(_, s)| s)
+    }
+
+    /// Solve IK starting from the nearest seed, and store the solution if it succeeds
+    ///
+    /// The current positions of `arm` are used if no seed is found. The positions
+    /// are restored when the solver fails.
+    pub fn solve<S>(
+        &mut self,
+        solver: &S,
+        arm: &SerialChain<T>,
+        target: &Isometry3<T>,
+    ) -> Result<(), Error>
+    where
+        S: InverseKinematicsSolver<T>,
+    {
+        self.solve_with_constraints(solver, arm, target, &Constraints::default())
+    }
+
+    /// Solve IK with constraints starting from the nearest seed, see `solve()`
+    pub fn solve_with_constraints<S>(
+        &mut self,
+        solver: &S,
+        arm: &SerialChain<T>,
+        target: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error>
+    where
+        S: InverseKinematicsSolver<T>,
+    {
+        let orig_positions = arm.joint_positions();
+        if let Some(seed) = self.nearest_seed(target) {
+            if seed.positions.len() == arm.dof() {
+                arm.set_joint_positions(&seed.positions)?;
+            }
+        }
+        match solver.solve_with_constraints(arm, target, constraints) {
+            Ok(()) => {
+                self.insert(target, arm.joint_positions());
+                Ok(())
+            }
+            Err(err) => {
+                arm.set_joint_positions_unchecked(&orig_positions);
+                Err(err)
+            }
+        }
+    }
+
+    fn distance(&self, a: &Isometry3<T>, b: &Isometry3<T>) -> T {
+        (a.translation.vector - b.translation.vector).norm()
+            + a.rotation.angle_to(&b.rotation) * self.cell_size
+    }
+}
+
+#[test]
+fn test_seed_regions() {
+    let mut database = IkSeedDatabase::<f64>::new(1.0).unwrap();
+    assert!(IkSeedDatabase::<f64>::new(0.0).is_err());
+    database.max_seeds_per_region = 2;
+    let pose = |x: f64| Isometry3::translation(x, 0.0, 0.0);
+    database.insert(&pose(0.1), vec![0.1]);
+    database.insert(&pose(0.2), vec![0.2]);
+    database.insert(&pose(0.3), vec![0.3]);
+    assert_eq!(database.len(), 2);
+    assert_eq!(database.seeds()[0].positions, vec![0.2]);
+    // neighbor region
+    assert_eq!(
+        database.nearest_seed(&pose(1.5)).unwrap().positions,
+        vec![0.3]
+    );
+    assert_eq!(
+        database.nearest_seed(&pose(-0.9)).unwrap().positions,
+        vec![0.2]
+    );
+    // too far
+    assert!(database.nearest_seed(&pose(2.5)).is_none());
+}
@@ -14,14 +14,19 @@
   limitations under the License.
 */
 //! Joint related structs
+#[allow(clippy::module_inception)]
 mod joint;
 mod joint_type;
+mod limit_policy;
 mod mimic;
 mod range;
+mod units;
 mod velocity;
 
 pub use joint::*;
 pub use joint_type::*;
+pub use limit_policy::*;
 pub use mimic::*;
 pub use range::*;
+pub use units::*;
 pub use velocity::*;
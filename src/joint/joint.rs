@@ -14,10 +14,12 @@
   limitations under the License.
 */
 use super::joint_type::*;
+use super::limit_policy::*;
 use super::range::*;
+use super::units::*;
 use super::velocity::*;
 use crate::errors::*;
-use na::{Isometry3, RealField, Translation3, UnitQuaternion};
+use na::{Isometry3, RealField, Vector3};
 use nalgebra as na;
 use simba::scalar::SubsetOf;
 use std::cell::RefCell;
@@ -29,13 +31,38 @@ pub struct Joint<T: RealField> {
     /// Name of this joint
     pub name: String,
     /// Type of this joint
+    ///
+    /// Use `convert_to()` to change it to a type with a different `num_positions()`.
     pub joint_type: JointType<T>,
-    /// position (angle) of this joint
-    position: T,
-    /// velocity of this joint
-    velocity: T,
-    /// Limits of this joint
+    /// positions (angles) of this joint, the length is `num_positions()`
+    positions: Vec<T>,
+    /// velocities of this joint, the same length as `positions`
+    velocities: Vec<T>,
+    /// Limits of this joint, applied to each of the positions
     pub limits: Option<Range<T>>,
+    /// Rotational joint which rotates endlessly, like the continuous joint of URDF.
+    /// The limits are not applied (see `effective_limits()`), and IK takes the shortest
+    /// way around.
+    pub continuous: bool,
+    /// How `set_joint_position()` and `set_joint_positions()` handle the positions out of
+    /// the limits, set to all the joints of a chain by `Chain::set_limit_policy()`
+    pub limit_policy: LimitPolicy,
+    /// Limit of the absolute value of the velocity of this joint
+    pub velocity_limit: Option<T>,
+    /// Limit of the absolute value of the acceleration of this joint
+    pub acceleration_limit: Option<T>,
+    /// Total play of this joint, in radians or meters
+    pub backlash: Option<T>,
+    /// Torsional (or linear) stiffness of this joint, in Nm/rad or N/m.
+    /// `None` means it is rigid.
+    pub stiffness: Option<T>,
+    /// Viscous damping coefficient of this joint, in Nms/rad or Ns/m
+    pub damping: Option<T>,
+    /// Coulomb friction of this joint, in Nm or N
+    pub friction: Option<T>,
+    /// Conversion to the units of the hardware, e.g. encoder counts.
+    /// `None` means the hardware uses radians or meters.
+    pub units: Option<JointUnits<T>>,
     /// local origin transform of joint
     origin: Isometry3<T>,
     /// cache of world transform
@@ -67,10 +94,19 @@ where
     pub fn new(name: &str, joint_type: JointType<T>) -> Joint<T> {
         Joint {
             name: name.to_string(),
+            positions: joint_type.identity_positions(),
+            velocities: vec![T::zero(); joint_type.num_positions()],
             joint_type,
-            position: T::zero(),
-            velocity: T::zero(),
             limits: None,
+            continuous: false,
+            limit_policy: LimitPolicy::default(),
+            velocity_limit: None,
+            acceleration_limit: None,
+            backlash: None,
+            stiffness: None,
+            damping: None,
+            friction: None,
+            units: None,
             origin: Isometry3::identity(),
             world_transform_cache: RefCell::new(None),
             world_velocity_cache: RefCell::new(None),
@@ -78,7 +114,9 @@ where
     }
     /// Set the position of the joint
     ///
-    /// It returns Err if it is out of the limits, or this is fixed joint.
+    /// It returns Err if this is fixed joint, or it is out of the limits and `limit_policy`
+    /// doesn't accept it (`LimitPolicy::Error` by default).
+    /// Use `set_joint_positions()` for the joints which have several positions.
     ///
     /// # Examples
     ///
@@ -102,30 +140,130 @@ where
     /// ```
     ///
     pub fn set_joint_position(&mut self, position: T) -> Result<(), Error> {
+        self.set_joint_position_with_policy(position, self.limit_policy)
+    }
+    /// Set the position of the joint, handling the limits by `policy`
+    ///
+    /// It returns Err if this is fixed joint, or the position is not acceptable with the `policy`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nalgebra as na;
+    /// use k::joint::LimitPolicy;
+    ///
+    /// let mut rot = k::Joint::<f64>::new("r0", k::JointType::Rotational { axis: na::Vector3::y_axis() });
+    /// rot.limits = Some(k::joint::Range::new(-1.0, 1.0));
+    ///
+    /// assert!(rot.set_joint_position_with_policy(2.0, LimitPolicy::Error).is_err());
+    /// rot.set_joint_position_with_policy(2.0, LimitPolicy::Clamp).unwrap();
+    /// assert_eq!(rot.joint_position().unwrap(), 1.0);
+    /// rot.set_joint_position_with_policy(2.0, LimitPolicy::Ignore).unwrap();
+    /// assert_eq!(rot.joint_position().unwrap(), 2.0);
+    /// rot.set_joint_position_with_policy(6.5, LimitPolicy::Wrap).unwrap();
+    /// assert!((rot.joint_position().unwrap() - (6.5 - 2.0 * std::f64::consts::PI)).abs() < 1e-10);
+    /// assert!(rot.set_joint_position_with_policy(3.0, LimitPolicy::Wrap).is_err());
+    /// ```
+    pub fn set_joint_position_with_policy(
+        &mut self,
+        position: T,
+        policy: LimitPolicy,
+    ) -> Result<(), Error> {
         if !self.is_movable() {
             return Err(Error::SetToFixedError {
                 joint_name: self.name.to_string(),
             });
         }
-        if let Some(ref range) = self.limits {
-            if !range.is_valid(position) {
-                return Err(Error::OutOfLimitError {
-                    joint_name: self.name.to_string(),
-                    position: na::try_convert(position).unwrap_or_default(),
-                    max_limit: na::try_convert(range.max).unwrap_or_default(),
-                    min_limit: na::try_convert(range.min).unwrap_or_default(),
-                });
-            }
+        self.set_joint_positions_with_policy(std::slice::from_ref(&position), policy)
+    }
+    /// Set the positions from a slice of the length `num_positions()`, handling the limits by `policy`
+    ///
+    /// The policy is applied to each of the positions. It returns `Error::SizeMismatchError`
+    /// if the length is different, and the positions are not changed if it returns Err.
+    pub fn set_joint_positions_with_policy(
+        &mut self,
+        positions: &[T],
+        policy: LimitPolicy,
+    ) -> Result<(), Error> {
+        if positions.len() != self.num_positions() {
+            return Err(Error::SizeMismatchError {
+                input: positions.len(),
+                required: self.num_positions(),
+            });
         }
-        self.position = position;
-        // TODO: have to reset descendent `world_transform_cache`
-        self.world_transform_cache.replace(None);
-        self.world_velocity_cache.replace(None);
+        for (index, position) in positions.iter().enumerate() {
+            self.position_with_policy(index, *position, policy)?;
+        }
+        for (index, position) in positions.iter().enumerate() {
+            self.positions[index] = self.position_with_policy(index, *position, policy)?;
+        }
+        self.clear_caches();
         Ok(())
     }
+    /// The value to be stored as the `index`-th position for `position` with `policy`
+    fn position_with_policy(
+        &self,
+        index: usize,
+        position: T,
+        policy: LimitPolicy,
+    ) -> Result<T, Error> {
+        let range = match self.effective_limits() {
+            Some(range) if !range.is_valid(position) => range,
+            _ => return Ok(position),
+        };
+        match policy {
+            LimitPolicy::Error => Err(self.out_of_limit_error(position, &range)),
+            LimitPolicy::Clamp => Ok(range.clamp(position)),
+            LimitPolicy::Ignore => Ok(position),
+            LimitPolicy::Wrap => {
+                if self.joint_type.is_angle(index) {
+                    let two_pi = T::two_pi();
+                    let wrapped = position - two_pi * ((position - range.min) / two_pi).floor();
+                    if range.is_valid(wrapped) {
+                        return Ok(wrapped);
+                    }
+                }
+                Err(self.out_of_limit_error(position, &range))
+            }
+        }
+    }
+    /// The limits applied to the positions, `None` if the joint has no limits or it is
+    /// continuous
+    ///
+    /// `limits` of the continuous joints are kept, e.g. to export them to URDF, but
+    /// all the functions checking or using the limits refer to this instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let mut joint = Joint::<f64>::new("a", JointType::Rotational { axis: Vector3::z_axis() });
+    /// joint.limits = Some((-1.0..=1.0).into());
+    /// assert!(joint.effective_limits().is_some());
+    /// joint.continuous = true;
+    /// assert!(joint.effective_limits().is_none());
+    /// assert!(joint.set_joint_position(3.0).is_ok());
+    /// ```
+    pub fn effective_limits(&self) -> Option<Range<T>> {
+        if self.continuous {
+            None
+        } else {
+            self.limits
+        }
+    }
+    fn out_of_limit_error(&self, position: T, range: &Range<T>) -> Error {
+        Error::OutOfLimitError {
+            joint_name: self.name.to_string(),
+            position: na::try_convert(position).unwrap_or_default(),
+            max_limit: na::try_convert(range.max).unwrap_or_default(),
+            min_limit: na::try_convert(range.min).unwrap_or_default(),
+        }
+    }
     /// Set the clamped position of the joint
     ///
-    /// It refers to the joint limit and clamps the argument. This function does nothing if this is fixed joint.
+    /// It refers to the joint limit and clamps the argument. This function does nothing if this
+    /// is fixed joint, or a joint which has several positions.
     ///
     /// # Examples
     ///
@@ -147,31 +285,173 @@ where
     /// ```
     ///
     pub fn set_joint_position_clamped(&mut self, position: T) {
-        if !self.is_movable() {
-            return;
+        if self.num_positions() == 1 {
+            self.set_joint_positions_clamped(std::slice::from_ref(&position));
         }
-        let position_clamped = if let Some(ref range) = self.limits {
-            range.clamp(position)
-        } else {
-            position
-        };
-        self.set_joint_position_unchecked(position_clamped);
     }
+    /// Set the clamped positions, each of them is clamped by the limits
+    ///
+    /// The values more than `num_positions()` are ignored.
+    pub fn set_joint_positions_clamped(&mut self, positions: &[T]) {
+        let range = self.effective_limits();
+        for (stored, position) in self.positions.iter_mut().zip(positions) {
+            *stored = match range {
+                Some(ref range) => range.clamp(*position),
+                None => *position,
+            };
+        }
+        self.clear_caches();
+    }
+    /// Set the position without checking the limits, for the joints which have one position
     pub fn set_joint_position_unchecked(&mut self, position: T) {
-        self.position = position;
+        if self.num_positions() == 1 {
+            self.set_joint_positions_unchecked(std::slice::from_ref(&position));
+        }
+    }
+    /// Set the positions without checking the limits
+    ///
+    /// The values more than `num_positions()` are ignored.
+    pub fn set_joint_positions_unchecked(&mut self, positions: &[T]) {
+        for (stored, position) in self.positions.iter_mut().zip(positions) {
+            *stored = *position;
+        }
+        self.clear_caches();
+    }
+    fn clear_caches(&self) {
         // TODO: have to reset descendent `world_transform_cache`
         self.world_transform_cache.replace(None);
         self.world_velocity_cache.replace(None);
     }
     /// Returns the position (angle)
+    ///
+    /// It is `None` for the fixed joint, and the joints which have several positions.
+    /// Use `joint_positions()` for them.
     #[inline]
     pub fn joint_position(&self) -> Option<T> {
+        match self.joint_positions() {
+            [position] => Some(*position),
+            _ => None,
+        }
+    }
+
+    /// Returns the position converted by `units`, the same as `joint_position()` without them
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nalgebra as na;
+    ///
+    /// let mut rot = k::Joint::<f64>::new("r0", k::JointType::Rotational { axis: na::Vector3::y_axis() });
+    /// rot.units = Some(k::joint::JointUnits::new("counts", 1000.0, 0.0).unwrap());
+    /// rot.set_joint_position_in_units(500.0).unwrap();
+    /// assert_eq!(rot.joint_position(), Some(0.5));
+    /// assert_eq!(rot.joint_position_in_units(), Some(500.0));
+    /// ```
+    pub fn joint_position_in_units(&self) -> Option<T> {
+        let position = self.joint_position()?;
+        Some(match self.units {
+            Some(ref units) => units.to_hardware(position),
+            None => position,
+        })
+    }
+
+    /// Set the position from the value in `units`, like `set_joint_position()`
+    pub fn set_joint_position_in_units(&mut self, value: T) -> Result<(), Error> {
+        let position = self.position_from_units(value);
+        self.set_joint_position(position)
+    }
+
+    /// Convert the value in `units` to the position, the same value without them
+    pub fn position_from_units(&self, value: T) -> T {
+        match self.units {
+            Some(ref units) => units.from_hardware(value),
+            None => value,
+        }
+    }
+
+    /// The number of the positions of this joint, zero for the fixed joint
+    ///
+    /// It is the length of `joint_positions()`, and the number of the elements of this
+    /// joint in the positions of `Chain`.
+    #[inline]
+    pub fn num_positions(&self) -> usize {
+        self.joint_type.num_positions()
+    }
+
+    /// Returns the positions as a slice of the length `num_positions()`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nalgebra as na;
+    ///
+    /// let mut rot = k::Joint::<f64>::new("r0", k::JointType::Rotational { axis: na::Vector3::y_axis() });
+    /// rot.set_joint_positions(&[0.2]).unwrap();
+    /// assert_eq!(rot.joint_positions(), &[0.2]);
+    /// assert!(rot.set_joint_positions(&[0.2, 0.3]).is_err());
+    ///
+    /// let mut fixed = k::Joint::<f64>::new("f0", k::JointType::Fixed);
+    /// assert!(fixed.joint_positions().is_empty());
+    /// fixed.set_joint_positions(&[]).unwrap();
+    /// ```
+    #[inline]
+    pub fn joint_positions(&self) -> &[T] {
+        &self.positions
+    }
+
+    /// Set the positions from a slice of the length `num_positions()`
+    ///
+    /// It returns `Error::SizeMismatchError` if the length is different, and
+    /// `Error::OutOfLimitError` if any of them is out of the limits and `limit_policy`
+    /// doesn't accept it. An empty slice for the fixed joint is accepted and does nothing.
+    pub fn set_joint_positions(&mut self, positions: &[T]) -> Result<(), Error> {
+        self.set_joint_positions_with_policy(positions, self.limit_policy)
+    }
+
+    /// Returns the pose from the origin of `JointType::Floating`, `None` for the other types
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// use k::angles::RotationParameterization;
+    ///
+    /// let mut base = Joint::<f64>::new(
+    ///     "base",
+    ///     JointType::Floating { rotation: RotationParameterization::Quaternion },
+    /// );
+    /// assert_eq!(base.joint_positions(), &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+    /// assert_eq!(base.floating_pose().unwrap(), Isometry3::identity());
+    /// let pose = Isometry3::new(Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.1, 0.2, 0.3));
+    /// base.set_floating_pose(&pose).unwrap();
+    /// assert!((base.floating_pose().unwrap().to_homogeneous() - pose.to_homogeneous()).norm() < 1e-10);
+    /// ```
+    pub fn floating_pose(&self) -> Option<Isometry3<T>> {
         match self.joint_type {
-            JointType::Fixed => None,
-            _ => Some(self.position),
+            JointType::Floating { .. } => Some(self.joint_type.transform(&self.positions)),
+            _ => None,
         }
     }
 
+    /// Set the pose from the origin of `JointType::Floating`
+    ///
+    /// It returns `Error::InvalidParameterError` for the other types, and
+    /// `Error::OutOfLimitError` if the positions are out of the limits.
+    pub fn set_floating_pose(&mut self, pose: &Isometry3<T>) -> Result<(), Error> {
+        let rotation = match self.joint_type {
+            JointType::Floating { rotation } => rotation,
+            _ => {
+                return Err(Error::InvalidParameterError {
+                    reason: format!("{} is not a floating joint", self.name),
+                })
+            }
+        };
+        let mut positions = pose.translation.vector.as_slice().to_vec();
+        positions.extend(rotation.parameters_of(&pose.rotation));
+        self.set_joint_positions(&positions)
+    }
+
     #[inline]
     pub fn origin(&self) -> &Isometry3<T> {
         &self.origin
@@ -183,26 +463,44 @@ where
         self.world_transform_cache.replace(None);
     }
 
+    /// Set the velocity of the joint which has one position
     pub fn set_joint_velocity(&mut self, velocity: T) -> Result<(), Error> {
-        if let JointType::Fixed = self.joint_type {
+        if !self.is_movable() {
             return Err(Error::SetToFixedError {
                 joint_name: self.name.to_string(),
             });
         }
-        self.velocity = velocity;
+        self.set_joint_velocities(std::slice::from_ref(&velocity))
+    }
+
+    /// Set the velocities from a slice of the length `num_positions()`
+    pub fn set_joint_velocities(&mut self, velocities: &[T]) -> Result<(), Error> {
+        if velocities.len() != self.num_positions() {
+            return Err(Error::SizeMismatchError {
+                input: velocities.len(),
+                required: self.num_positions(),
+            });
+        }
+        self.velocities.copy_from_slice(velocities);
         self.world_velocity_cache.replace(None);
         Ok(())
     }
 
-    /// Returns the velocity
+    /// Returns the velocity, `None` for the joints which do not have one position
     #[inline]
     pub fn joint_velocity(&self) -> Option<T> {
-        match self.joint_type {
-            JointType::Fixed => None,
-            _ => Some(self.velocity),
+        match self.joint_velocities() {
+            [velocity] => Some(*velocity),
+            _ => None,
         }
     }
 
+    /// Returns the velocities as a slice of the length `num_positions()`
+    #[inline]
+    pub fn joint_velocities(&self) -> &[T] {
+        &self.velocities
+    }
+
     /// Calculate and returns the transform of the end of this joint
     ///
     /// # Examples
@@ -218,18 +516,27 @@ where
     /// ```
     ///
     pub fn local_transform(&self) -> Isometry3<T> {
-        let joint_transform = match self.joint_type {
-            JointType::Fixed => Isometry3::identity(),
-            JointType::Rotational { axis } => Isometry3::from_parts(
-                Translation3::new(T::zero(), T::zero(), T::zero()),
-                UnitQuaternion::from_axis_angle(&axis, self.position),
-            ),
-            JointType::Linear { axis } => Isometry3::from_parts(
-                Translation3::from(axis.into_inner() * self.position),
-                UnitQuaternion::identity(),
-            ),
-        };
-        self.origin * joint_transform
+        self.origin * self.joint_type.transform(&self.positions)
+    }
+
+    /// Velocity of the frame of this joint by the unit velocity of the `index`-th position
+    ///
+    /// It is in the world frame, for the point at `world_point`. `world_transform` is
+    /// the world transform of this joint, like `world_transform()`.
+    pub(crate) fn world_motion(
+        &self,
+        world_transform: &Isometry3<T>,
+        index: usize,
+        world_point: &Vector3<T>,
+    ) -> Velocity<T> {
+        let motion = self.joint_type.motion(&self.positions, index);
+        // rotation of the frame of the origin
+        let moved = self.joint_type.transform(&self.positions);
+        let rotation = world_transform.rotation * moved.rotation.inverse();
+        let angular = rotation * motion.rotation;
+        let linear = rotation * motion.translation
+            + angular.cross(&(world_point - world_transform.translation.vector));
+        Velocity::from_parts(linear, angular)
     }
 
     #[inline]
@@ -254,12 +561,38 @@ where
         *self.world_velocity_cache.borrow()
     }
 
+    /// Change the type of the joint, keeping the current local transform
+    ///
+    /// The current local transform is baked into the origin, so the position of the
+    /// new joint is zero at the current pose. The velocity is reset, and the limits are
+    /// removed because they are relative to the old zero position.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nalgebra as na;
+    ///
+    /// let mut joint = k::Joint::<f64>::new("j0", k::JointType::Rotational { axis: na::Vector3::z_axis() });
+    /// joint.set_joint_position(0.5).unwrap();
+    /// let transform = joint.local_transform();
+    /// joint.convert_to(k::JointType::Fixed);
+    /// assert!(!joint.is_movable());
+    /// assert_eq!(joint.local_transform(), transform);
+    /// ```
+    pub fn convert_to(&mut self, joint_type: JointType<T>) {
+        self.origin = self.local_transform();
+        self.joint_type = joint_type;
+        self.positions = joint_type.identity_positions();
+        self.velocities = vec![T::zero(); joint_type.num_positions()];
+        self.limits = None;
+        self.continuous = false;
+        self.world_transform_cache.replace(None);
+        self.world_velocity_cache.replace(None);
+    }
+
     #[inline]
     pub fn is_movable(&self) -> bool {
-        match self.joint_type {
-            JointType::Fixed => false,
-            _ => true,
-        }
+        !matches!(self.joint_type, JointType::Fixed)
     }
 }
 
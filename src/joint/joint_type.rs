@@ -13,10 +13,13 @@
   See the License for the specific language governing permissions and
   limitations under the License.
 */
-use nalgebra::{RealField, Unit, Vector3};
+use super::velocity::Velocity;
+use crate::angles::RotationParameterization;
+use nalgebra::{Isometry3, RealField, Translation3, Unit, UnitQuaternion, Vector3};
 use std::fmt::{self, Display};
 
-/// Type of Joint, `Fixed`, `Rotational`, `Linear` is supported now
+/// Type of Joint, `Fixed`, `Rotational`, `Linear`, `Universal`, `Planar`, `Floating`
+/// is supported now
 #[derive(Copy, Debug, Clone)]
 pub enum JointType<T: RealField> {
     /// Fixed joint. It has no `joint_position` and axis.
@@ -31,6 +34,185 @@ pub enum JointType<T: RealField> {
         /// axis of the joint
         axis: Unit<Vector3<T>>,
     },
+    /// Universal joint with two intersecting axes, like a gimbal. It has two positions [rad].
+    ///
+    /// The first position rotates around `first_axis`, and the second one rotates around
+    /// `second_axis` in the frame rotated by the first one.
+    Universal {
+        /// axis of the first rotation
+        first_axis: Unit<Vector3<T>>,
+        /// axis of the second rotation, which is moved by the first rotation
+        second_axis: Unit<Vector3<T>>,
+    },
+    /// Planar joint moving on the plane of `normal`, e.g. a wheeled base.
+    /// It has three positions, (x, y, yaw).
+    ///
+    /// x and y are the translations along two orthogonal axes in the plane, and yaw is
+    /// the rotation around `normal` [rad]. If `normal` is the Z axis, x and y are along
+    /// the X and Y axes.
+    Planar {
+        /// normal of the plane
+        normal: Unit<Vector3<T>>,
+    },
+    /// Floating joint which moves freely, e.g. the base of a humanoid or a drone.
+    /// It has the positions of the translation (x, y, z) followed by the parameters of
+    /// the rotation.
+    ///
+    /// The positions of the identity pose are `rotation.parameters_of()` of the identity,
+    /// e.g. `[0, 0, 0, 0, 0, 0, 1]` for `Quaternion`. Use `Joint::floating_pose()` and
+    /// `Joint::set_floating_pose()` to handle them as `Isometry3`.
+    Floating {
+        /// parameterization of the rotation, `ExponentialCoordinates` and `Quaternion`
+        /// have no gimbal lock, `Rpy` has it at pitch = ±π/2
+        rotation: RotationParameterization,
+    },
+}
+
+impl<T: RealField> JointType<T> {
+    /// The number of the positions of the joint of this type, zero for `Fixed`
+    #[inline]
+    pub fn num_positions(&self) -> usize {
+        match *self {
+            JointType::Fixed => 0,
+            JointType::Rotational { .. } | JointType::Linear { .. } => 1,
+            JointType::Universal { .. } => 2,
+            JointType::Planar { .. } => 3,
+            JointType::Floating { rotation } => 3 + rotation.num_parameters(),
+        }
+    }
+
+    /// Returns true if the `index`-th position is an angle [rad]
+    pub(crate) fn is_angle(&self, index: usize) -> bool {
+        match *self {
+            JointType::Rotational { .. } => index == 0,
+            JointType::Universal { .. } => index < 2,
+            JointType::Planar { .. } => index == 2,
+            JointType::Floating { rotation } => {
+                rotation == RotationParameterization::Rpy && (3..6).contains(&index)
+            }
+            JointType::Fixed | JointType::Linear { .. } => false,
+        }
+    }
+
+    /// The positions at which `transform()` is the identity
+    pub(crate) fn identity_positions(&self) -> Vec<T> {
+        let mut positions = vec![T::zero(); self.num_positions()];
+        if let JointType::Floating { rotation } = *self {
+            positions[3..].copy_from_slice(&rotation.parameters_of(&UnitQuaternion::identity()));
+        }
+        positions
+    }
+
+    /// Transform of the joint motion with `positions`, without the origin transform
+    ///
+    /// The missing positions are regarded as zero.
+    pub(crate) fn transform(&self, positions: &[T]) -> Isometry3<T> {
+        let position = |i: usize| positions.get(i).copied().unwrap_or_else(T::zero);
+        match *self {
+            JointType::Fixed => Isometry3::identity(),
+            JointType::Rotational { axis } => Isometry3::from_parts(
+                Translation3::new(T::zero(), T::zero(), T::zero()),
+                UnitQuaternion::from_axis_angle(&axis, position(0)),
+            ),
+            JointType::Linear { axis } => Isometry3::from_parts(
+                Translation3::from(axis.into_inner() * position(0)),
+                UnitQuaternion::identity(),
+            ),
+            JointType::Universal {
+                first_axis,
+                second_axis,
+            } => Isometry3::from_parts(
+                Translation3::new(T::zero(), T::zero(), T::zero()),
+                UnitQuaternion::from_axis_angle(&first_axis, position(0))
+                    * UnitQuaternion::from_axis_angle(&second_axis, position(1)),
+            ),
+            JointType::Planar { normal } => {
+                let (x_axis, y_axis) = plane_axes(&normal);
+                Isometry3::from_parts(
+                    Translation3::from(x_axis * position(0) + y_axis * position(1)),
+                    UnitQuaternion::from_axis_angle(&normal, position(2)),
+                )
+            }
+            JointType::Floating { rotation } => Isometry3::from_parts(
+                Translation3::new(position(0), position(1), position(2)),
+                rotation
+                    .rotation_of(&rotation_parameters(positions)[..rotation.num_parameters()])
+                    .expect("Must be a bug: the number of the rotation parameters is checked"),
+            ),
+        }
+    }
+
+    /// Velocity of the moved frame by the unit velocity of the `index`-th position
+    ///
+    /// It is in the frame of the joint origin, and the linear part is the velocity of
+    /// the origin of the moved frame.
+    pub(crate) fn motion(&self, positions: &[T], index: usize) -> Velocity<T> {
+        let position = |i: usize| positions.get(i).copied().unwrap_or_else(T::zero);
+        match *self {
+            JointType::Rotational { axis } if index == 0 => {
+                Velocity::from_parts(Vector3::zeros(), axis.into_inner())
+            }
+            JointType::Linear { axis } if index == 0 => {
+                Velocity::from_parts(axis.into_inner(), Vector3::zeros())
+            }
+            JointType::Universal { first_axis, .. } if index == 0 => {
+                Velocity::from_parts(Vector3::zeros(), first_axis.into_inner())
+            }
+            JointType::Universal {
+                first_axis,
+                second_axis,
+            } if index == 1 => Velocity::from_parts(
+                Vector3::zeros(),
+                UnitQuaternion::from_axis_angle(&first_axis, position(0))
+                    * second_axis.into_inner(),
+            ),
+            JointType::Planar { normal } if index < 2 => {
+                let (x_axis, y_axis) = plane_axes(&normal);
+                let axis = if index == 0 { x_axis } else { y_axis };
+                Velocity::from_parts(axis, Vector3::zeros())
+            }
+            JointType::Planar { normal } if index == 2 => {
+                Velocity::from_parts(Vector3::zeros(), normal.into_inner())
+            }
+            JointType::Floating { .. } if index < 3 => {
+                let mut axis = Vector3::zeros();
+                axis[index] = T::one();
+                Velocity::from_parts(axis, Vector3::zeros())
+            }
+            JointType::Floating { rotation } if index < 3 + rotation.num_parameters() => {
+                let parameters = rotation_parameters(positions);
+                Velocity::from_parts(
+                    Vector3::zeros(),
+                    rotation.angular_velocity_column(
+                        &parameters[..rotation.num_parameters()],
+                        index - 3,
+                    ),
+                )
+            }
+            _ => Velocity::zero(),
+        }
+    }
+}
+
+/// The rotation parameters of `Floating` in `positions`, the missing ones are zero
+fn rotation_parameters<T: RealField>(positions: &[T]) -> [T; 4] {
+    let mut parameters = [T::zero(); 4];
+    for (parameter, position) in parameters.iter_mut().zip(positions.iter().skip(3)) {
+        *parameter = *position;
+    }
+    parameters
+}
+
+/// Two orthogonal axes in the plane of `normal`, X and Y if `normal` is Z
+fn plane_axes<T: RealField>(normal: &Unit<Vector3<T>>) -> (Vector3<T>, Vector3<T>) {
+    let reference = if normal.x.abs() < nalgebra::convert(0.9) {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    let x_axis = (reference - normal.into_inner() * normal.dot(&reference)).normalize();
+    let y_axis = normal.cross(&x_axis);
+    (x_axis, y_axis)
 }
 
 fn axis_to_string<T: RealField>(axis: &Unit<Vector3<T>>) -> &str {
@@ -57,6 +239,17 @@ impl<T: RealField> Display for JointType<T> {
             JointType::Fixed => write!(f, "[⚓]"),
             JointType::Rotational { axis } => write!(f, "[⚙{}]", axis_to_string(axis)),
             JointType::Linear { axis } => write!(f, "[↕{}]", axis_to_string(axis)),
+            JointType::Universal {
+                first_axis,
+                second_axis,
+            } => write!(
+                f,
+                "[⚙{}{}]",
+                axis_to_string(first_axis),
+                axis_to_string(second_axis)
+            ),
+            JointType::Planar { normal } => write!(f, "[⇄{}]", axis_to_string(normal)),
+            JointType::Floating { .. } => write!(f, "[✈]"),
         }
     }
 }
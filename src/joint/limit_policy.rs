@@ -0,0 +1,40 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+
+/// How to handle the position which is out of the joint limits
+///
+/// It is set to `Joint::limit_policy` by `Chain::set_limit_policy()`.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum LimitPolicy {
+    /// Returns `Error::OutOfLimitError` (default)
+    Error,
+    /// Clamps the position with the limits
+    Clamp,
+    /// Sets the position without checking the limits
+    Ignore,
+    /// Shifts the position of rotational joints by multiples of 2π into the limits.
+    /// If it is impossible, or the joint is not rotational, it returns `Error::OutOfLimitError`.
+    Wrap,
+}
+
+impl Default for LimitPolicy {
+    fn default() -> Self {
+        LimitPolicy::Error
+    }
+}
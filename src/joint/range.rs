@@ -14,9 +14,12 @@
   limitations under the License.
 */
 use nalgebra::RealField;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
 
 /// min/max range to check the joint position
 #[derive(Copy, Debug, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
 pub struct Range<T: RealField> {
     pub min: T,
     pub max: T,
@@ -0,0 +1,140 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use crate::errors::*;
+use nalgebra::RealField;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde-serialize")]
+use std::convert::TryFrom;
+
+/// Conversion between the position of a joint (rad or m) and the units of the hardware
+///
+/// For example, a belt-driven joint whose controller counts encoder pulses.
+/// `hardware = scale * position + offset`.
+///
+/// # Examples
+///
+/// ```
+/// // 4096 counts per revolution of the motor, and the belt reduces it by 3
+/// let units = k::joint::JointUnits::new("counts", 4096.0 * 3.0 / (2.0 * std::f64::consts::PI), 100.0)
+///     .unwrap();
+/// assert_eq!(units.to_hardware(0.0), 100.0);
+/// let position = units.from_hardware(100.0 + 4096.0 * 3.0);
+/// assert!((position - 2.0 * std::f64::consts::PI).abs() < 1e-10);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(
+        try_from = "UncheckedJointUnits<T>",
+        bound(deserialize = "T: RealField + Deserialize<'de>")
+    )
+)]
+pub struct JointUnits<T: RealField> {
+    unit: String,
+    scale: T,
+    offset: T,
+}
+
+/// `JointUnits` deserialized by serde, before the check of the scale
+#[cfg(feature = "serde-serialize")]
+#[derive(Deserialize)]
+struct UncheckedJointUnits<T: RealField> {
+    unit: String,
+    scale: T,
+    offset: T,
+}
+
+#[cfg(feature = "serde-serialize")]
+impl<T> TryFrom<UncheckedJointUnits<T>> for JointUnits<T>
+where
+    T: RealField,
+{
+    type Error = Error;
+
+    fn try_from(units: UncheckedJointUnits<T>) -> Result<Self, Error> {
+        Self::new(&units.unit, units.scale, units.offset)
+    }
+}
+
+impl<T> JointUnits<T>
+where
+    T: RealField,
+{
+    /// Create new JointUnits instance
+    ///
+    /// It returns `Error::InvalidParameterError` if `scale` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// assert!(k::joint::JointUnits::new("counts", 0.0, 100.0).is_err());
+    /// ```
+    pub fn new(unit: &str, scale: T, offset: T) -> Result<Self, Error> {
+        if scale == T::zero() {
+            return Err(Error::InvalidParameterError {
+                reason: format!("scale of the units {} must not be zero", unit),
+            });
+        }
+        Ok(Self {
+            unit: unit.to_owned(),
+            scale,
+            offset,
+        })
+    }
+
+    /// Name of the unit of the hardware, e.g. `"counts"`
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    /// Hardware value per unit of the joint position, including the gear or belt ratio
+    ///
+    /// It is never zero.
+    pub fn scale(&self) -> T {
+        self.scale
+    }
+
+    /// Hardware value at the zero position of the joint
+    pub fn offset(&self) -> T {
+        self.offset
+    }
+
+    /// Convert the position of the joint to the hardware value
+    pub fn to_hardware(&self, position: T) -> T {
+        self.scale * position + self.offset
+    }
+
+    /// Convert the hardware value to the position of the joint
+    pub fn from_hardware(&self, value: T) -> T {
+        (value - self.offset) / self.scale
+    }
+}
+
+#[cfg(feature = "serde-serialize")]
+#[test]
+fn test_unchecked_joint_units() {
+    let unchecked = |scale| UncheckedJointUnits {
+        unit: "counts".to_owned(),
+        scale,
+        offset: 100.0,
+    };
+    let units = JointUnits::try_from(unchecked(2.0)).unwrap();
+    assert_eq!(units, JointUnits::new("counts", 2.0, 100.0).unwrap());
+    assert_eq!(units.from_hardware(102.0), 1.0);
+    assert!(JointUnits::try_from(unchecked(0.0)).is_err());
+}
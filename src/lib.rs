@@ -27,25 +27,115 @@
 #[cfg(doctest)]
 doc_comment::doctest!("../README.md");
 
+#[cfg(feature = "assert-no-alloc")]
+mod allocation;
+mod analytical_ik;
+mod batch;
+mod bounds;
 mod chain;
+mod clearance;
+mod compliance;
+mod condition;
+mod continuity;
+mod coordinated;
+mod coupled_limits;
+mod dh;
+mod effort;
 mod errors;
+mod filter;
+mod fk_cache;
+mod fk_derivatives;
+mod frames;
 mod funcs;
+mod geometric_ik;
+mod gltf;
+mod gripper;
 mod ik;
+mod ik_seed;
+mod lod;
+mod metadata;
+mod multi_ik;
+mod offsets;
+mod orientation_reach;
+mod placement;
+mod pose_buffer;
+mod profile;
+mod reroot;
+mod restart_ik;
+mod retarget;
+mod servo;
+mod shortcut;
+mod spatial;
+mod srdf;
+mod summary;
+mod swept;
+mod symmetry;
+mod synergy;
+mod tolerances;
+mod trajectory;
+mod update_hooks;
+mod workspace_map;
+mod world;
+mod wrist;
 use nalgebra as na;
+pub mod angles;
+pub mod bench;
 pub mod iterator;
 pub mod joint;
 pub mod link;
+pub mod math;
 pub mod node;
 pub mod prelude;
 pub mod urdf;
 
+#[cfg(feature = "assert-no-alloc")]
+pub use self::allocation::*;
+pub use self::analytical_ik::*;
+pub use self::batch::*;
 pub use self::chain::*;
+pub use self::clearance::*;
+pub use self::condition::*;
+pub use self::continuity::*;
+pub use self::coordinated::*;
+pub use self::coupled_limits::*;
+pub use self::dh::*;
 pub use self::errors::*;
+pub use self::filter::*;
+pub use self::fk_cache::*;
+pub use self::fk_derivatives::*;
+pub use self::frames::*;
 pub use self::funcs::*;
+pub use self::geometric_ik::*;
+pub use self::gltf::*;
+pub use self::gripper::*;
 pub use self::ik::*;
+pub use self::ik_seed::*;
 pub use self::joint::{Joint, JointType};
 pub use self::link::Link;
+pub use self::lod::*;
+pub use self::metadata::*;
+pub use self::multi_ik::*;
 pub use self::node::{Node, NodeBuilder};
+pub use self::orientation_reach::*;
+pub use self::placement::*;
+pub use self::pose_buffer::*;
+pub use self::profile::*;
+pub use self::restart_ik::*;
+pub use self::retarget::*;
+pub use self::servo::*;
+pub use self::shortcut::*;
+pub use self::spatial::*;
+pub use self::srdf::*;
+pub use self::summary::*;
+pub use self::swept::*;
+pub use self::symmetry::*;
+pub use self::synergy::*;
+pub use self::tolerances::*;
+pub use self::trajectory::*;
+pub use self::update_hooks::*;
+pub use self::workspace_map::*;
+pub use self::world::*;
+pub use self::wrist::*;
 
 // re-export from nalgebra
 // include Real for backwards compatibility purposes
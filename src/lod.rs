@@ -0,0 +1,278 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::node::*;
+use na::RealField;
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// How to reduce the DoF of a chain, used by `Chain::reduce_dof()`
+#[derive(Debug, Clone)]
+pub struct LodOptions<T: RealField> {
+    /// Joints whose range of the limits is smaller than this value are fixed
+    pub min_motion_range: Option<T>,
+    /// Names of the joints which are fixed at the current positions
+    pub frozen_joints: Vec<String>,
+    /// Names of the joints which are removed with their descendants (e.g. fingers).
+    /// The root joint cannot be removed.
+    pub dropped_joints: Vec<String>,
+}
+
+impl<T: RealField> Default for LodOptions<T> {
+    fn default() -> Self {
+        Self {
+            min_motion_range: None,
+            frozen_joints: Vec::new(),
+            dropped_joints: Vec::new(),
+        }
+    }
+}
+
+/// Reduced-DoF approximation of a chain with the mapping to the full chain
+#[derive(Debug)]
+pub struct ChainLod<T: RealField> {
+    /// the reduced chain, which does not share the nodes with the full chain
+    pub chain: Chain<T>,
+    /// index in the full joint positions of each position of the reduced chain
+    full_indices: Vec<usize>,
+    /// full joint positions when it is reduced, used for the removed joints
+    full_positions: Vec<T>,
+}
+
+impl<T> ChainLod<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Index in the full joint positions of each position of the reduced chain
+    pub fn full_indices(&self) -> &[usize] {
+        &self.full_indices
+    }
+
+    /// Extract the positions of the reduced chain from the positions of the full chain
+    pub fn reduced_positions(&self, full_positions: &[T]) -> Result<Vec<T>, Error> {
+        if full_positions.len() != self.full_positions.len() {
+            return Err(Error::SizeMismatchError {
+                input: full_positions.len(),
+                required: self.full_positions.len(),
+            });
+        }
+        Ok(self
+            .full_indices
+            .iter()
+            .map(|i| full_positions[*i])
+            .collect())
+    }
+
+    /// Expand the positions of the reduced chain to the positions of the full chain
+    ///
+    /// The fixed and removed joints get the positions when the chain was reduced.
+    pub fn full_positions(&self, reduced_positions: &[T]) -> Result<Vec<T>, Error> {
+        if reduced_positions.len() != self.full_indices.len() {
+            return Err(Error::SizeMismatchError {
+                input: reduced_positions.len(),
+                required: self.full_indices.len(),
+            });
+        }
+        let mut full = self.full_positions.clone();
+        for (i, position) in self.full_indices.iter().zip(reduced_positions) {
+            full[*i] = *position;
+        }
+        Ok(full)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LodState {
+    Movable,
+    Frozen,
+    Dropped,
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create a reduced-DoF approximation of this chain
+    ///
+    /// The fixed joints are merged into the origins with the current positions.
+    /// Mimic joints are fixed if the joints they mimic are fixed or removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let mut options = LodOptions::default();
+    /// options.dropped_joints.push("l_shoulder_yaw".to_owned());
+    /// options.frozen_joints.push("r_wrist_yaw".to_owned());
+    /// let lod = chain.reduce_dof(&options);
+    /// assert_eq!(lod.chain.dof(), chain.dof() - 6 - 1);
+    /// assert!(lod.chain.find("l_wrist_pitch").is_none());
+    ///
+    /// let reduced = vec![0.1; lod.chain.dof()];
+    /// let full = lod.full_positions(&reduced).unwrap();
+    /// chain.set_joint_positions(&full).unwrap();
+    /// assert_eq!(lod.reduced_positions(&chain.joint_positions()).unwrap(), reduced);
+    /// ```
+    pub fn reduce_dof(&self, options: &LodOptions<T>) -> ChainLod<T> {
+        let nodes = self.iter().collect::<Vec<_>>();
+        let index_of = |node: &Node<T>| nodes.iter().position(|n| *n == node);
+        let mut states = Vec::with_capacity(nodes.len());
+        for (i, node) in nodes.iter().enumerate() {
+            let parent_state = node
+                .parent()
+                .and_then(|p| index_of(&p))
+                .map(|p| states[p])
+                .unwrap_or(LodState::Movable);
+            let joint = node.joint();
+            let state = if parent_state == LodState::Dropped
+                || (i != 0 && options.dropped_joints.contains(&joint.name))
+            {
+                LodState::Dropped
+            } else if !joint.is_movable()
+                || options.frozen_joints.contains(&joint.name)
+                || match (&joint.effective_limits(), options.min_motion_range) {
+                    (Some(range), Some(min)) => range.max - range.min < min,
+                    _ => false,
+                }
+            {
+                LodState::Frozen
+            } else {
+                LodState::Movable
+            };
+            states.push(state);
+        }
+        for (i, node) in nodes.iter().enumerate() {
+            if states[i] != LodState::Movable {
+                continue;
+            }
+            if let Some(m) = node.mimic_parent() {
+                if index_of(&m).map(|m| states[m]) != Some(LodState::Movable) {
+                    states[i] = LodState::Frozen;
+                }
+            }
+        }
+
+        let new_nodes = nodes
+            .iter()
+            .zip(&states)
+            .map(|(node, state)| match state {
+                LodState::Dropped => None,
+                LodState::Movable => Some(Node::new(node.joint().clone())),
+                LodState::Frozen => {
+                    let joint = node.joint();
+                    Some(
+                        NodeBuilder::new()
+                            .name(&joint.name)
+                            .origin(joint.local_transform())
+                            .into_node(),
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+        for (i, node) in nodes.iter().enumerate() {
+            let new_node = match &new_nodes[i] {
+                Some(n) => n,
+                None => continue,
+            };
+            new_node.set_link(node.link().clone());
+            new_node.set_output_suppressed(node.is_output_suppressed());
+            if let Some(parent) = node.parent().and_then(|p| index_of(&p)) {
+                new_node.set_parent(new_nodes[parent].as_ref().expect("parent is not dropped"));
+            }
+            if states[i] == LodState::Movable {
+                if let (Some(m), Some(mimic)) = (node.mimic_parent(), node.mimic()) {
+                    let m = index_of(&m).expect("mimic parent is in the chain");
+                    new_node.set_mimic_parent(
+                        new_nodes[m].as_ref().expect("mimic parent is movable"),
+                        mimic,
+                    );
+                }
+            }
+        }
+        let mut chain = Chain::from_root(new_nodes[0].clone().expect("root is not dropped"));
+        let full_indices = chain
+            .iter()
+            .filter(|node| node.joint().is_movable())
+            .flat_map(|new_node| {
+                let i = new_nodes
+                    .iter()
+                    .position(|n| n.as_ref() == Some(new_node))
+                    .expect("node is created above");
+                let offset = self
+                    .position_offset(nodes[i])
+                    .expect("movable node is in the full chain");
+                offset..offset + new_node.joint().num_positions()
+            })
+            .collect();
+        chain.set_limit_policy(self.limit_policy());
+        chain.set_metadata(self.metadata().clone());
+        ChainLod {
+            chain,
+            full_indices,
+            full_positions: self.joint_positions(),
+        }
+    }
+}
+
+#[test]
+fn test_reduce_by_motion_range() {
+    use super::joint::*;
+    let j0 = NodeBuilder::new()
+        .name("j0")
+        .joint_type(JointType::Rotational {
+            axis: na::Vector3::z_axis(),
+        })
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .name("j1")
+        .translation(na::Translation3::new(1.0, 0.0, 0.0))
+        .joint_type(JointType::Rotational {
+            axis: na::Vector3::z_axis(),
+        })
+        .limits(Some((0.0..=0.01).into()))
+        .into_node();
+    let j2 = NodeBuilder::new()
+        .name("j2")
+        .translation(na::Translation3::new(1.0, 0.0, 0.0))
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::x_axis(),
+        })
+        .into_node();
+    j1.set_parent(&j0);
+    j2.set_parent(&j1);
+    let chain = Chain::<f64>::from_root(j0);
+    chain.set_joint_positions(&[0.0, 0.01, 0.0]).unwrap();
+    let lod = chain.reduce_dof(&LodOptions {
+        min_motion_range: Some(0.1),
+        ..Default::default()
+    });
+    assert_eq!(lod.chain.dof(), 2);
+    assert_eq!(lod.full_indices(), &[0, 2]);
+    assert_eq!(
+        lod.full_positions(&[0.3, 0.2]).unwrap(),
+        vec![0.3, 0.01, 0.2]
+    );
+    // the fixed joint keeps the position
+    lod.chain.set_joint_positions(&[0.0, 0.0]).unwrap();
+    let end = lod.chain.update_transforms()[2];
+    chain.update_transforms();
+    let full_end = chain.find("j2").unwrap().world_transform().unwrap();
+    assert!((end.translation.vector - full_end.translation.vector).norm() < 1e-10);
+    assert!(lod.reduced_positions(&[0.0]).is_err());
+}
@@ -0,0 +1,37 @@
+/*
+  Copyright 2017 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+//! Re-export of the math types of nalgebra used in the API of `k`
+//!
+//! The users don't need to depend on the matching version of nalgebra.
+//!
+//! # Examples
+//!
+//! ```
+//! use k::math::*;
+//!
+//! let pose = Isometry3::from_parts(
+//!     Translation3::new(0.1, 0.0, 0.0),
+//!     UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 0.5),
+//! );
+//! let jacobi: DMatrix<f64> = DMatrix::identity(6, 6);
+//! assert_eq!(jacobi.nrows(), 6);
+//! assert_eq!((pose * Point3::origin()).x, 0.1);
+//! ```
+pub use nalgebra::{
+    DMatrix, DVector, Isometry3, Matrix3, Matrix6, Point3, Quaternion, RealField, Rotation3,
+    Translation3, Unit, UnitQuaternion, Vector3, Vector6,
+};
+pub use simba::scalar::{SubsetOf, SupersetOf};
@@ -0,0 +1,183 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::errors::*;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Semantic version `major.minor.patch` of a robot model
+///
+/// # Examples
+///
+/// ```
+/// let version: k::ModelVersion = "1.2.3".parse().unwrap();
+/// assert_eq!(version, k::ModelVersion::new(1, 2, 3));
+/// assert_eq!(version.to_string(), "1.2.3");
+/// assert!(version < k::ModelVersion::new(1, 10, 0));
+/// assert!("1.2".parse::<k::ModelVersion>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct ModelVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl ModelVersion {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Returns true if `other` is compatible with this version by the semantic versioning,
+    /// the same major version and not older
+    pub fn is_compatible_with(&self, other: &ModelVersion) -> bool {
+        self.major == other.major && other >= self
+    }
+}
+
+impl fmt::Display for ModelVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for ModelVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::MetadataError {
+            reason: format!("invalid version {}", s),
+        };
+        let numbers = s
+            .trim()
+            .split('.')
+            .map(|n| n.parse::<u64>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match numbers[..] {
+            [major, minor, patch] => Ok(Self::new(major, minor, patch)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Name, version and other information of the model of a `Chain`
+///
+/// It is loaded from URDF by `Chain::from_urdf_file()`. The name is the name of `<robot>`,
+/// and the entries are the `<metadata key="..." value="..."/>` elements in `<robot>`.
+/// The entry of the key `version` is also parsed as `ModelVersion`.
+/// It can be saved and loaded with serde if the `serde-serialize` feature is enabled.
+///
+/// # Examples
+///
+/// ```
+/// let urdf = r#"
+/// <robot name="arm">
+///   <metadata key="version" value="2.1.0"/>
+///   <metadata key="vendor" value="example"/>
+///   <link name="base"/>
+/// </robot>
+/// "#;
+/// let metadata = k::ChainMetadata::from_urdf_str(urdf).unwrap();
+/// assert_eq!(metadata.name, "arm");
+/// assert_eq!(metadata.version, Some(k::ModelVersion::new(2, 1, 0)));
+/// assert_eq!(metadata.entries["vendor"], "example");
+///
+/// // embed into URDF again
+/// let elements = metadata.to_urdf_elements();
+/// let urdf = format!("<robot name=\"arm\">{}</robot>", elements);
+/// assert_eq!(k::ChainMetadata::from_urdf_str(&urdf).unwrap(), metadata);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct ChainMetadata {
+    /// the name of the robot
+    pub name: String,
+    /// the version of the model
+    pub version: Option<ModelVersion>,
+    /// arbitrary key-value pairs
+    pub entries: BTreeMap<String, String>,
+}
+
+impl ChainMetadata {
+    /// Create the metadata with the name only
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Load the metadata from the URDF string
+    ///
+    /// It returns `Error::MetadataError` if it is not a valid XML, the root is not
+    /// `<robot>`, or the version is invalid.
+    pub fn from_urdf_str(string: &str) -> Result<Self, Error> {
+        let root: xml::Element = string.parse().map_err(|e| Error::MetadataError {
+            reason: format!("{:?}", e),
+        })?;
+        if root.name != "robot" {
+            return Err(Error::MetadataError {
+                reason: format!("root element must be <robot>, but <{}>", root.name),
+            });
+        }
+        let mut metadata = Self::new(root.get_attribute("name", None).unwrap_or_default());
+        for element in root.get_children("metadata", None) {
+            let attribute = |name| {
+                element
+                    .get_attribute(name, None)
+                    .map(str::to_owned)
+                    .ok_or_else(|| Error::MetadataError {
+                        reason: format!("<metadata> has no attribute {}", name),
+                    })
+            };
+            metadata
+                .entries
+                .insert(attribute("key")?, attribute("value")?);
+        }
+        metadata.version = metadata
+            .entries
+            .get("version")
+            .map(|v| v.parse())
+            .transpose()?;
+        Ok(metadata)
+    }
+
+    /// The `<metadata>` elements of the entries and the version, to embed into `<robot>`
+    ///
+    /// The version overwrites the entry of the key `version`.
+    pub fn to_urdf_elements(&self) -> String {
+        let mut entries = self.entries.clone();
+        if let Some(version) = self.version {
+            entries.insert("version".to_owned(), version.to_string());
+        }
+        entries
+            .iter()
+            .map(|(key, value)| {
+                let mut element = xml::Element::new("metadata".to_owned(), None, vec![]);
+                element.set_attribute("key".to_owned(), None, key.to_owned());
+                element.set_attribute("value".to_owned(), None, value.to_owned());
+                element.to_string()
+            })
+            .collect()
+    }
+}
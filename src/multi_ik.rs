@@ -0,0 +1,473 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::funcs::*;
+use super::ik::*;
+use super::node::*;
+use na::{DMatrix, DVector, Isometry3, RealField, Vector2, Vector3};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// A target of an arm for `JacobianIkSolver::solve_multiple()`
+#[derive(Debug, Clone)]
+pub struct MultiChainTask<'a, T: RealField> {
+    pub arm: &'a SerialChain<T>,
+    pub target: Isometry3<T>,
+    pub constraints: Constraints,
+}
+
+impl<'a, T: RealField> MultiChainTask<'a, T> {
+    /// Create a task with the default constraints
+    pub fn new(arm: &'a SerialChain<T>, target: Isometry3<T>) -> Self {
+        Self {
+            arm,
+            target,
+            constraints: Constraints::default(),
+        }
+    }
+}
+
+/// Region on the XY plane of the world where the center of mass should be projected,
+/// e.g. the support polygon of the feet
+#[derive(Debug, Clone, PartialEq)]
+pub enum SupportRegion<T: RealField> {
+    /// axis aligned box from `min` to `max`
+    Box { min: Vector2<T>, max: Vector2<T> },
+    /// convex polygon, whose vertices are in the counter-clockwise order
+    Polygon(Vec<Vector2<T>>),
+}
+
+impl<T: RealField> SupportRegion<T> {
+    /// The closest point in the region to `point`, `point` itself if it is inside
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::nalgebra::Vector2;
+    /// use k::SupportRegion;
+    ///
+    /// let triangle = SupportRegion::Polygon(vec![
+    ///     Vector2::new(0.0, 0.0),
+    ///     Vector2::new(1.0, 0.0),
+    ///     Vector2::new(0.0, 1.0),
+    /// ]);
+    /// let inside = Vector2::new(0.2, 0.2);
+    /// assert_eq!(triangle.closest_point(&inside), inside);
+    /// let closest = triangle.closest_point(&Vector2::new(1.0, 1.0));
+    /// assert!((closest - Vector2::new(0.5, 0.5)).norm() < 1e-10);
+    /// ```
+    pub fn closest_point(&self, point: &Vector2<T>) -> Vector2<T> {
+        match self {
+            SupportRegion::Box { min, max } => {
+                Vector2::new(point.x.max(min.x).min(max.x), point.y.max(min.y).min(max.y))
+            }
+            SupportRegion::Polygon(vertices) => {
+                let num = vertices.len();
+                if num == 0 {
+                    return *point;
+                }
+                let cross = |a: &Vector2<T>, b: &Vector2<T>| a.x * b.y - a.y * b.x;
+                let is_inside = (0..num).all(|i| {
+                    let (a, b) = (vertices[i], vertices[(i + 1) % num]);
+                    cross(&(b - a), &(point - a)) >= T::zero()
+                });
+                if is_inside {
+                    return *point;
+                }
+                let mut closest = vertices[0];
+                for i in 0..num {
+                    let (a, b) = (vertices[i], vertices[(i + 1) % num]);
+                    let edge = b - a;
+                    let length2 = edge.norm_squared();
+                    let t = if length2 > T::zero() {
+                        ((point - a).dot(&edge) / length2)
+                            .max(T::zero())
+                            .min(T::one())
+                    } else {
+                        T::zero()
+                    };
+                    let candidate = a + edge * t;
+                    if (candidate - point).norm_squared() < (closest - point).norm_squared() {
+                        closest = candidate;
+                    }
+                }
+                closest
+            }
+        }
+    }
+
+    /// Returns true if `point` is in the region
+    pub fn contains(&self, point: &Vector2<T>) -> bool {
+        self.closest_point(point) == *point
+    }
+}
+
+/// Balance task of `JacobianIkSolver::solve_multiple_with_balance()`
+///
+/// The center of mass of `chain`, calculated by the inertial of the links, is kept
+/// in `region` when it is projected on the XY plane of the world.
+#[derive(Debug, Clone)]
+pub struct BalanceTask<'a, T: RealField> {
+    pub chain: &'a Chain<T>,
+    pub region: SupportRegion<T>,
+}
+
+impl<'a, T: RealField> BalanceTask<'a, T> {
+    pub fn new(chain: &'a Chain<T>, region: SupportRegion<T>) -> Self {
+        Self { chain, region }
+    }
+}
+
+impl<T> JacobianIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Solve the targets of multiple arms at once
+    ///
+    /// The joints shared by the arms (e.g. torso joints of both arms of a humanoid)
+    /// are solved as a single value, by stacking the jacobians of all the tasks.
+    /// If it fails, the positions of all the joints are restored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// // torso with two arms
+    /// let torso = NodeBuilder::new()
+    ///     .name("torso")
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// let mut ends = Vec::new();
+    /// for (i, y) in [0.3, -0.3].iter().enumerate() {
+    ///     let shoulder = NodeBuilder::new()
+    ///         .name(&format!("shoulder{}", i))
+    ///         .translation(Translation3::new(0.0, *y, 0.5))
+    ///         .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///         .into_node();
+    ///     let elbow = NodeBuilder::new()
+    ///         .name(&format!("elbow{}", i))
+    ///         .translation(Translation3::new(0.0, 0.0, -0.3))
+    ///         .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///         .into_node();
+    ///     let hand = NodeBuilder::new()
+    ///         .name(&format!("hand{}", i))
+    ///         .translation(Translation3::new(0.0, 0.0, -0.3))
+    ///         .into_node();
+    ///     shoulder.set_parent(&torso);
+    ///     elbow.set_parent(&shoulder);
+    ///     hand.set_parent(&elbow);
+    ///     ends.push(hand);
+    /// }
+    /// let left = SerialChain::<f64>::from_end(&ends[0]);
+    /// let right = SerialChain::from_end(&ends[1]);
+    /// left.set_joint_positions(&[0.0, 0.3, -0.6]).unwrap();
+    /// right.set_joint_positions(&[0.0, 0.3, -0.6]).unwrap();
+    /// let mut left_target = left.end_transform();
+    /// left_target.translation.vector.x += 0.05;
+    /// let mut right_target = right.end_transform();
+    /// right_target.translation.vector.x -= 0.05;
+    ///
+    /// let mut constraints = Constraints::default();
+    /// constraints.position_y = false;
+    /// constraints.rotation_x = false;
+    /// constraints.rotation_y = false;
+    /// constraints.rotation_z = false;
+    /// let tasks = [
+    ///     MultiChainTask { arm: &left, target: left_target, constraints: constraints.clone() },
+    ///     MultiChainTask { arm: &right, target: right_target, constraints },
+    /// ];
+    /// let solver = JacobianIkSolver::new(0.001, 0.01, 0.5, 100);
+    /// solver.solve_multiple(&tasks).unwrap();
+    /// for (arm, target) in [(&left, left_target), (&right, right_target)].iter() {
+    ///     let diff = arm.end_transform().translation.vector - target.translation.vector;
+    ///     assert!(diff.x.abs() < 0.001 && diff.z.abs() < 0.001);
+    /// }
+    /// // the torso joint is shared
+    /// assert_eq!(left.joint_positions()[0], right.joint_positions()[0]);
+    /// ```
+    pub fn solve_multiple(&self, tasks: &[MultiChainTask<'_, T>]) -> Result<(), Error> {
+        self.solve_multiple_internal(tasks, None)
+    }
+
+    /// Solve the targets of multiple arms like `solve_multiple()`, keeping the balance
+    ///
+    /// While the center of mass is out of the region of `balance`, it is moved toward the
+    /// closest point in the region as an additional task. All the movable joints of
+    /// `balance.chain` are used for it, e.g. the legs of a humanoid. The tasks are
+    /// solved only if the center of mass is in the region at the end.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use k::link::*;
+    /// use k::nalgebra::{Matrix3, Vector2};
+    ///
+    /// let link = |mass: f64, z: f64| {
+    ///     let inertial = Inertial::new(Isometry3::translation(0.0, 0.0, z), mass, Matrix3::zeros());
+    ///     Some(LinkBuilder::new().inertial(inertial).finalize())
+    /// };
+    /// let ankle = NodeBuilder::new()
+    ///     .name("ankle")
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// let hip = NodeBuilder::new()
+    ///     .name("hip")
+    ///     .translation(Translation3::new(0.0, 0.0, 0.8))
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// let shoulder = NodeBuilder::new()
+    ///     .name("shoulder")
+    ///     .translation(Translation3::new(0.0, 0.0, 0.6))
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// let elbow = NodeBuilder::new()
+    ///     .name("elbow")
+    ///     .translation(Translation3::new(0.0, 0.0, -0.3))
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// let hand = NodeBuilder::new()
+    ///     .name("hand")
+    ///     .translation(Translation3::new(0.0, 0.0, -0.3))
+    ///     .into_node();
+    /// ankle.set_link(link(10.0, 0.4));
+    /// hip.set_link(link(20.0, 0.3));
+    /// hand.set_link(link(5.0, 0.0));
+    /// hip.set_parent(&ankle);
+    /// shoulder.set_parent(&hip);
+    /// elbow.set_parent(&shoulder);
+    /// hand.set_parent(&elbow);
+    /// let body = Chain::<f64>::from_root(ankle);
+    /// let arm = SerialChain::from_end(&hand);
+    /// arm.set_joint_positions(&[0.0, 0.0, -0.5, -0.5]).unwrap();
+    ///
+    /// // reach forward
+    /// let mut target = arm.end_transform();
+    /// target.translation.vector.x += 0.3;
+    /// let mut constraints = Constraints::default();
+    /// constraints.position_y = false;
+    /// constraints.rotation_x = false;
+    /// constraints.rotation_y = false;
+    /// constraints.rotation_z = false;
+    /// let tasks = [MultiChainTask { arm: &arm, target, constraints }];
+    /// let region = SupportRegion::Box {
+    ///     min: Vector2::new(-0.05, -0.1),
+    ///     max: Vector2::new(0.05, 0.1),
+    /// };
+    /// let solver = JacobianIkSolver::new(0.001, 0.01, 0.5, 100);
+    /// solver
+    ///     .solve_multiple_with_balance(&tasks, &BalanceTask::new(&body, region.clone()))
+    ///     .unwrap();
+    /// let diff = arm.end_transform().translation.vector - target.translation.vector;
+    /// assert!(diff.x.abs() < 0.001 && diff.z.abs() < 0.001);
+    /// let com = center_of_mass(&body);
+    /// assert!(region.contains(&Vector2::new(com.x, com.y)));
+    /// ```
+    pub fn solve_multiple_with_balance(
+        &self,
+        tasks: &[MultiChainTask<'_, T>],
+        balance: &BalanceTask<'_, T>,
+    ) -> Result<(), Error> {
+        self.solve_multiple_internal(tasks, Some(balance))
+    }
+
+    fn solve_multiple_internal(
+        &self,
+        tasks: &[MultiChainTask<'_, T>],
+        balance: Option<&BalanceTask<'_, T>>,
+    ) -> Result<(), Error> {
+        // the positions of all the arms without duplication
+        let mut keys = Vec::new();
+        let columns = tasks
+            .iter()
+            .map(|task| add_position_keys(&mut keys, task.arm))
+            .collect::<Vec<_>>();
+        // the columns of the center of mass jacobian, in the order of `joint_positions()`
+        let balance_columns = balance.map(|balance| add_position_keys(&mut keys, balance.chain));
+        let spaces = tasks
+            .iter()
+            .map(|task| define_operational_space(&task.constraints))
+            .collect::<Vec<_>>();
+        let num_task_rows = spaces
+            .iter()
+            .map(|s| s.iter().filter(|x| **x).count())
+            .sum::<usize>();
+        let num_balance_rows = if balance.is_some() { 2 } else { 0 };
+        if keys.len() < num_task_rows + num_balance_rows {
+            return Err(Error::PreconditionError {
+                dof: keys.len(),
+                necessary_dof: num_task_rows + num_balance_rows,
+            });
+        }
+        let balance_error = || balance.and_then(balance_error);
+        let mut chains = tasks
+            .iter()
+            .map(|task| &**task.arm)
+            .collect::<Vec<&Chain<T>>>();
+        let mut chain_columns = columns.clone();
+        if let (Some(balance), Some(balance_columns)) = (balance, &balance_columns) {
+            chains.push(balance.chain);
+            chain_columns.push(balance_columns.clone());
+        }
+        let orig_positions = chains
+            .iter()
+            .map(|chain| chain.joint_positions())
+            .collect::<Vec<_>>();
+        let mut positions = positions_of_keys(&keys);
+        let nodes = keys
+            .iter()
+            .map(|(node, _)| node.clone())
+            .collect::<Vec<_>>();
+        let eps = self.tolerances().singular_value;
+        let scales = self
+            .resolve_joint_weights(&nodes)?
+            .map(|w| w.iter().map(|w| T::one() / w.sqrt()).collect::<Vec<_>>());
+
+        let mut last_diff = (Vector3::zeros(), Vector3::zeros());
+        for _ in 0..self.num_max_try {
+            let com_error = balance_error();
+            let num_rows = num_task_rows + if com_error.is_some() { 2 } else { 0 };
+            let mut jacobi = DMatrix::zeros(num_rows, nodes.len());
+            let mut err = DVector::zeros(num_rows);
+            let mut row = 0;
+            for (task, task_columns) in tasks.iter().zip(&columns) {
+                let end = task.arm.end_transform();
+                let task_err =
+                    calc_pose_diff_with_constraints(&task.target, &end, &task.constraints);
+                let full = jacobian(task.arm);
+                let task_jacobi =
+                    constrained_jacobian(full.columns(0, full.ncols()), &end, &task.constraints);
+                for task_row in task_jacobi.row_iter() {
+                    for (c, column) in task_columns.iter().enumerate() {
+                        jacobi[(row, *column)] = task_row[c];
+                    }
+                    row += 1;
+                }
+                err.rows_mut(row - task_err.len(), task_err.len())
+                    .copy_from(&task_err);
+            }
+            if let (Some(com_error), Some(balance), Some(balance_columns)) =
+                (com_error, balance, &balance_columns)
+            {
+                let com_jacobi = center_of_mass_jacobian(balance.chain);
+                for r in 0..2 {
+                    for (c, column) in balance_columns.iter().enumerate() {
+                        jacobi[(row + r, *column)] = com_jacobi[(r, c)];
+                    }
+                    err[row + r] = com_error[r];
+                }
+            }
+            // weighted: solve in the joint space scaled by 1 / sqrt(weight)
+            if let Some(ref scales) = scales {
+                for (mut column, scale) in jacobi.column_iter_mut().zip(scales) {
+                    column *= *scale;
+                }
+            }
+            let mut d_q = match self.damped_pseudo_inverse(&jacobi)? {
+                Some(inv) => inv * err,
+                None => jacobi
+                    .svd(true, true)
+                    .solve(&err, eps)
+                    .map_err(|_| Error::InverseMatrixError)?,
+            };
+            if let Some(ref scales) = scales {
+                d_q.component_mul_assign(&DVector::from_column_slice(scales));
+            }
+            for (position, d) in positions.iter_mut().zip(d_q.iter()) {
+                *position += self.jacobian_multiplier * *d;
+            }
+            set_union_positions_clamped(&chains, &chain_columns, &mut positions);
+
+            let mut converged = true;
+            for (task, space) in tasks.iter().zip(&spaces) {
+                let diff = calc_pose_diff_with_constraints(
+                    &task.target,
+                    &task.arm.end_transform(),
+                    &task.constraints,
+                );
+                let (len_diff, rot_diff) = target_diff_to_len_rot_diff(&diff, *space);
+                if !self.is_reached(&len_diff, &rot_diff) {
+                    converged = false;
+                    last_diff = (len_diff, rot_diff);
+                    break;
+                }
+            }
+            if converged {
+                if let Some(com_error) = balance_error() {
+                    converged = false;
+                    last_diff = (
+                        Vector3::new(com_error.x, com_error.y, T::zero()),
+                        Vector3::zeros(),
+                    );
+                }
+            }
+            if converged {
+                return Ok(());
+            }
+        }
+        for (chain, positions) in chains.iter().zip(&orig_positions).rev() {
+            chain.set_joint_positions_unchecked(positions);
+        }
+        Err(Error::NotConvergedError {
+            num_tried: self.num_max_try,
+            position_diff: na::try_convert(last_diff.0).unwrap_or_default(),
+            rotation_diff: na::try_convert(last_diff.1).unwrap_or_default(),
+        })
+    }
+    /// Solve the target poses of multiple end effectors in a tree at once
+    ///
+    /// Each end is moved by the joints from the root of the tree to it, and all the
+    /// rotation and translation are constrained. It is the shortcut of `solve_multiple()`
+    /// for whole-body tasks, e.g. both hands and the head of a humanoid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// chain.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3, 0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let left = chain.find("l_wrist_pitch").unwrap();
+    /// let right = chain.find("r_wrist_pitch").unwrap();
+    /// chain.update_transforms();
+    /// let mut left_target = left.world_transform().unwrap();
+    /// left_target.translation.vector.z += 0.02;
+    /// let mut right_target = right.world_transform().unwrap();
+    /// right_target.translation.vector.x -= 0.02;
+    ///
+    /// let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+    /// solver.solve_multi(&[(left, left_target), (right, right_target)]).unwrap();
+    /// chain.update_transforms();
+    /// for (end, target) in &[(left, left_target), (right, right_target)] {
+    ///     let diff = end.world_transform().unwrap().translation.vector - target.translation.vector;
+    ///     assert!(diff.norm() < 0.001);
+    /// }
+    /// ```
+    pub fn solve_multi(&self, targets: &[(&Node<T>, Isometry3<T>)]) -> Result<(), Error> {
+        let arms = targets
+            .iter()
+            .map(|(end, _)| SerialChain::from_end(end))
+            .collect::<Vec<_>>();
+        let tasks = arms
+            .iter()
+            .zip(targets)
+            .map(|(arm, (_, target))| MultiChainTask::new(arm, *target))
+            .collect::<Vec<_>>();
+        self.solve_multiple(&tasks)
+    }
+}
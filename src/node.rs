@@ -41,6 +41,10 @@ where
     pub mimic_children: Vec<Node<T>>,
     pub mimic: Option<Mimic<T>>,
     pub link: Option<Link<T>>,
+    /// skipped in the outputs of `Chain::update_transforms()` and the pose buffers
+    pub output_suppressed: bool,
+    /// the last position accepted by the `set_joint_position*()` methods of this node
+    pub commanded_position: Option<T>,
 }
 
 /// Parts of `Chain`
@@ -66,29 +70,144 @@ where
             mimic_children: Vec::new(),
             mimic: None,
             link: None,
+            output_suppressed: false,
+            commanded_position: None,
         })))
     }
 
-    pub(crate) fn lock(&self) -> MutexGuard<NodeImpl<T>> {
+    pub(crate) fn lock(&self) -> MutexGuard<'_, NodeImpl<T>> {
         self.0.lock().unwrap()
     }
 
-    pub fn joint(&self) -> JointRefGuard<T> {
+    pub fn joint(&self) -> JointRefGuard<'_, T> {
         JointRefGuard { guard: self.lock() }
     }
 
+    /// Returns the position used by the forward kinematics, same as `effective_position()`
     pub fn joint_position(&self) -> Option<T> {
         self.lock().joint.joint_position()
     }
 
+    /// Returns the positions of the joint, see `Joint::joint_positions()`
+    pub fn joint_positions(&self) -> Vec<T> {
+        self.lock().joint.joint_positions().to_vec()
+    }
+
+    /// Returns the pose of the floating joint, see `Joint::floating_pose()`
+    pub fn floating_pose(&self) -> Option<Isometry3<T>> {
+        self.lock().joint.floating_pose()
+    }
+
+    /// Set the pose of the floating joint, see `Joint::set_floating_pose()`
+    pub fn set_floating_pose(&self, pose: &Isometry3<T>) -> Result<(), Error> {
+        self.lock().joint.set_floating_pose(pose)
+    }
+
+    /// Set the positions of the joint from a slice of the length `Joint::num_positions()`
+    ///
+    /// The limits are handled by `Joint::limit_policy`, and the joints which mimic this
+    /// joint are also moved, like `set_joint_position()`.
+    pub fn set_joint_positions(&self, positions: &[T]) -> Result<(), Error> {
+        let policy = self.joint().limit_policy;
+        self.set_joint_positions_with_policy(positions, policy)
+    }
+
+    /// Set the positions of the joint, handling the limits by `policy`
+    ///
+    /// It is `set_joint_position_with_policy()` for the joints which have one position.
+    pub fn set_joint_positions_with_policy(
+        &self,
+        positions: &[T],
+        policy: LimitPolicy,
+    ) -> Result<(), Error> {
+        match positions {
+            [position] if self.joint().num_positions() == 1 => {
+                self.set_joint_position_with_policy(*position, policy)
+            }
+            _ => self
+                .lock()
+                .joint
+                .set_joint_positions_with_policy(positions, policy),
+        }
+    }
+
+    /// Set the clamped positions of the joint, see `Joint::set_joint_positions_clamped()`
+    pub fn set_joint_positions_clamped(&self, positions: &[T]) {
+        match positions {
+            [position] if self.joint().num_positions() == 1 => {
+                self.set_joint_position_clamped(*position)
+            }
+            _ => self.lock().joint.set_joint_positions_clamped(positions),
+        }
+    }
+
+    /// Set the positions of the joint without checking the limits
+    pub fn set_joint_positions_unchecked(&self, positions: &[T]) {
+        match positions {
+            [position] if self.joint().num_positions() == 1 => {
+                self.set_joint_position_unchecked(*position)
+            }
+            _ => self.lock().joint.set_joint_positions_unchecked(positions),
+        }
+    }
+
+    /// Returns the position after the limits and the mimic relation are applied
+    ///
+    /// It is the value used by the forward kinematics. For the joints which mimic another
+    /// joint, it is calculated from the position of the mimic parent, whatever is
+    /// commanded to this joint. It is `None` for the fixed joints.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use k::joint::{LimitPolicy, Mimic};
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Linear{axis: Vector3::z_axis()})
+    ///     .limits(Some((-1.0..=1.0).into()))
+    ///     .into_node();
+    /// let j1 = NodeBuilder::<f64>::new()
+    ///     .joint_type(JointType::Linear{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// j1.set_mimic_parent(&j0, Mimic::new(2.0, 0.0));
+    ///
+    /// j0.set_joint_position_with_policy(1.5, LimitPolicy::Clamp).unwrap();
+    /// assert_eq!(j0.commanded_position(), Some(1.5));
+    /// assert_eq!(j0.effective_position(), Some(1.0));
+    ///
+    /// // the command to the mimic joint is ignored
+    /// j1.set_joint_position(0.3).unwrap();
+    /// assert_eq!(j1.commanded_position(), Some(0.3));
+    /// assert_eq!(j1.effective_position(), Some(2.0));
+    /// ```
+    pub fn effective_position(&self) -> Option<T> {
+        self.joint_position()
+    }
+
+    /// Returns the last position accepted by the `set_joint_position*()` methods of this node
+    ///
+    /// It is the value before the limits are applied, and it is also recorded for the
+    /// joints which mimic another joint, though the command is ignored for them.
+    /// It is `None` if no position is set to this node, or this is a fixed joint.
+    /// Use `effective_position()` for the position of the forward kinematics.
+    pub fn commanded_position(&self) -> Option<T> {
+        let node = self.lock();
+        if node.joint.is_movable() {
+            node.commanded_position
+        } else {
+            None
+        }
+    }
+
     pub fn parent(&self) -> Option<Node<T>> {
         match self.lock().parent {
-            Some(ref weak) => weak.upgrade().and_then(|arc| Some(Node::from_arc(arc))),
+            Some(ref weak) => weak.upgrade().map(Node::from_arc),
             None => None,
         }
     }
 
-    pub fn children(&self) -> ChildrenRefGuard<T> {
+    pub fn children(&self) -> ChildrenRefGuard<'_, T> {
         ChildrenRefGuard { guard: self.lock() }
     }
 
@@ -157,7 +276,8 @@ where
 
     /// Set the position (angle) of the joint
     ///
-    /// If position is out of limit, it returns Err.
+    /// If position is out of limit, it is handled by `Joint::limit_policy` (returns Err by
+    /// default).
     ///
     /// # Examples
     ///
@@ -201,18 +321,51 @@ where
     /// assert_eq!(j1.joint_position().unwrap(), 1.6);
     /// ```
     pub fn set_joint_position(&self, position: T) -> Result<(), Error> {
+        let policy = self.joint().limit_policy;
+        self.set_joint_position_with_policy(position, policy)
+    }
+
+    /// Set the position (angle) of the joint, handling the limits by `policy`
+    ///
+    /// The policy is also applied to the joints which mimic this joint.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use k::joint::LimitPolicy;
+    /// let l0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Linear{axis: Vector3::z_axis()})
+    ///     .limits(Some((0.0..=2.0).into()))
+    ///     .into_node();
+    /// assert!(l0.set_joint_position_with_policy(3.0, LimitPolicy::Error).is_err());
+    /// assert!(l0.set_joint_position_with_policy(3.0, LimitPolicy::Clamp).is_ok());
+    /// assert_eq!(l0.joint_position().unwrap(), 2.0);
+    /// ```
+    pub fn set_joint_position_with_policy(
+        &self,
+        position: T,
+        policy: LimitPolicy,
+    ) -> Result<(), Error> {
         let mut node = self.lock();
         if node.mimic_parent.is_some() {
+            node.commanded_position = Some(position);
             return Ok(());
         }
-        node.joint.set_joint_position(position)?;
+        node.joint
+            .set_joint_position_with_policy(position, policy)?;
+        node.commanded_position = Some(position);
+        let position = node
+            .joint
+            .joint_position()
+            .expect("Must be a bug: movable joint must have position");
         for child in &node.mimic_children {
             let mut child_node = child.lock();
             let mimic = child_node.mimic.clone();
             match mimic {
                 Some(m) => child_node
                     .joint
-                    .set_joint_position(m.mimic_position(position))?,
+                    .set_joint_position_with_policy(m.mimic_position(position), policy)?,
                 None => {
                     let from = self.joint().name.to_owned();
                     let to = child.joint().name.to_owned();
@@ -241,20 +394,16 @@ where
     /// assert_eq!(l0.joint().joint_position(), Some(-1.0));
     /// ```
     pub fn set_joint_position_clamped(&self, position: T) {
-        self.0
-            .lock()
-            .unwrap()
-            .joint
-            .set_joint_position_clamped(position);
+        let mut node = self.lock();
+        node.commanded_position = Some(position);
+        node.joint.set_joint_position_clamped(position);
     }
 
     #[inline]
     pub fn set_joint_position_unchecked(&self, position: T) {
-        self.0
-            .lock()
-            .unwrap()
-            .joint
-            .set_joint_position_unchecked(position);
+        let mut node = self.lock();
+        node.commanded_position = Some(position);
+        node.joint.set_joint_position_unchecked(position);
     }
 
     pub(crate) fn parent_world_transform(&self) -> Option<Isometry3<T>> {
@@ -311,24 +460,102 @@ where
 
     pub fn mimic_parent(&self) -> Option<Node<T>> {
         match self.lock().mimic_parent {
-            Some(ref weak) => weak.upgrade().and_then(|arc| Some(Node::from_arc(arc))),
+            Some(ref weak) => weak.upgrade().map(Node::from_arc),
             None => None,
         }
     }
 
+    /// Get the mimic information (multiplier and offset) of this joint
+    ///
+    /// It is `None` if this joint does not mimic any joint.
+    /// The joint to be copied can be got by `mimic_parent()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// let j0 = NodeBuilder::<f64>::new()
+    ///     .name("j0")
+    ///     .joint_type(JointType::Linear{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .name("j1")
+    ///     .joint_type(JointType::Linear{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// assert!(j1.mimic().is_none());
+    /// j1.set_mimic_parent(&j0, k::joint::Mimic::new(-1.0, 0.5));
+    /// let mimic = j1.mimic().unwrap();
+    /// assert_eq!(mimic.multiplier, -1.0);
+    /// assert_eq!(mimic.origin, 0.5);
+    /// assert_eq!(j1.mimic_parent().unwrap().joint().name, "j0");
+    /// ```
+    pub fn mimic(&self) -> Option<Mimic<T>> {
+        self.lock().mimic.clone()
+    }
+
     pub fn set_mimic_parent(&self, parent: &Node<T>, mimic: Mimic<T>) {
         self.lock().mimic_parent = Some(Arc::downgrade(&parent.0));
         parent.lock().mimic_children.push(self.clone());
         self.lock().mimic = Some(mimic);
     }
 
+    /// Change the type of the joint at the current pose, see `Joint::convert_to()`
+    ///
+    /// The mimic relations of this joint are removed, because the position is reset.
+    /// The joints which mimic this joint keep their current positions and become
+    /// independent. Use `Chain::convert_joint()` to update the DoF of the chain, or call
+    /// `Chain::update_movable_nodes()` for the chains which contain this node.
+    pub fn convert_to(&self, joint_type: JointType<T>) {
+        let mimic_parent = self.mimic_parent();
+        if let Some(parent) = mimic_parent {
+            parent.lock().mimic_children.retain(|child| child != self);
+        }
+        let mimic_children = {
+            let mut node = self.lock();
+            node.mimic_parent = None;
+            node.mimic = None;
+            node.joint.convert_to(joint_type);
+            std::mem::take(&mut node.mimic_children)
+        };
+        for child in mimic_children {
+            let mut child = child.lock();
+            child.mimic_parent = None;
+            child.mimic = None;
+        }
+    }
+
     pub fn set_link(&self, link: Option<Link<T>>) {
         self.lock().link = link;
     }
 
-    pub fn link(&self) -> OptionLinkRefGuard<T> {
+    pub fn link(&self) -> OptionLinkRefGuard<'_, T> {
         OptionLinkRefGuard { guard: self.lock() }
     }
+
+    /// Skip this node in the outputs of `Chain::update_transforms()` and the pose buffers
+    ///
+    /// The world transform of the node is still updated and used by its descendants.
+    /// It is useful to reduce the data sent to renderers for many cosmetic frames.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let num_nodes = chain.iter().count();
+    /// let elbow = chain.find("l_elbow_pitch").unwrap();
+    /// elbow.set_output_suppressed(true);
+    /// assert!(elbow.is_output_suppressed());
+    /// assert_eq!(chain.update_transforms().len(), num_nodes - 1);
+    /// assert!(elbow.world_transform().is_some());
+    /// ```
+    pub fn set_output_suppressed(&self, suppressed: bool) {
+        self.lock().output_suppressed = suppressed;
+    }
+
+    /// Returns true if the node is skipped in the outputs, see `set_output_suppressed()`
+    pub fn is_output_suppressed(&self) -> bool {
+        self.lock().output_suppressed
+    }
 }
 
 impl<T> ::std::clone::Clone for Node<T>
@@ -345,7 +572,7 @@ where
     T: RealField,
 {
     fn eq(&self, other: &Node<T>) -> bool {
-        &*self.0 as *const Mutex<NodeImpl<T>> == &*other.0 as *const Mutex<NodeImpl<T>>
+        Arc::ptr_eq(&self.0, &other.0)
     }
 }
 
@@ -443,7 +670,7 @@ where
     type Target = Link<T>;
     fn deref(&self) -> &Self::Target {
         // danger
-        &self.guard.link.as_ref().unwrap()
+        self.guard.link.as_ref().unwrap()
     }
 }
 
@@ -500,6 +727,14 @@ pub struct NodeBuilder<T: RealField> {
     name: String,
     joint_type: JointType<T>,
     limits: Option<Range<T>>,
+    continuous: bool,
+    velocity_limit: Option<T>,
+    acceleration_limit: Option<T>,
+    backlash: Option<T>,
+    stiffness: Option<T>,
+    damping: Option<T>,
+    friction: Option<T>,
+    units: Option<JointUnits<T>>,
     origin: Isometry3<T>,
 }
 
@@ -521,6 +756,14 @@ where
             name: "".to_string(),
             joint_type: JointType::Fixed,
             limits: None,
+            continuous: false,
+            velocity_limit: None,
+            acceleration_limit: None,
+            backlash: None,
+            stiffness: None,
+            damping: None,
+            friction: None,
+            units: None,
             origin: Isometry3::identity(),
         }
     }
@@ -539,6 +782,58 @@ where
         self.limits = limits;
         self
     }
+    /// Make the joint rotate endlessly, see `Joint::continuous`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let wheel = k::NodeBuilder::<f64>::new()
+    ///     .joint_type(k::JointType::Rotational { axis: nalgebra::Vector3::y_axis() })
+    ///     .limits(Some(k::joint::Range::new(-1.0, 1.0)))
+    ///     .continuous(true)
+    ///     .into_node();
+    /// // the limits are not checked
+    /// wheel.set_joint_position(4.0).unwrap();
+    /// ```
+    pub fn continuous(mut self, continuous: bool) -> NodeBuilder<T> {
+        self.continuous = continuous;
+        self
+    }
+    /// Set the limit of the absolute value of the joint velocity
+    pub fn velocity_limit(mut self, velocity_limit: Option<T>) -> NodeBuilder<T> {
+        self.velocity_limit = velocity_limit;
+        self
+    }
+    /// Set the limit of the absolute value of the joint acceleration
+    pub fn acceleration_limit(mut self, acceleration_limit: Option<T>) -> NodeBuilder<T> {
+        self.acceleration_limit = acceleration_limit;
+        self
+    }
+    /// Set the total play of the joint
+    pub fn backlash(mut self, backlash: Option<T>) -> NodeBuilder<T> {
+        self.backlash = backlash;
+        self
+    }
+    /// Set the stiffness of the joint
+    pub fn stiffness(mut self, stiffness: Option<T>) -> NodeBuilder<T> {
+        self.stiffness = stiffness;
+        self
+    }
+    /// Set the viscous damping coefficient of the joint
+    pub fn damping(mut self, damping: Option<T>) -> NodeBuilder<T> {
+        self.damping = damping;
+        self
+    }
+    /// Set the Coulomb friction of the joint
+    pub fn friction(mut self, friction: Option<T>) -> NodeBuilder<T> {
+        self.friction = friction;
+        self
+    }
+    /// Set the conversion to the units of the hardware
+    pub fn units(mut self, units: Option<JointUnits<T>>) -> NodeBuilder<T> {
+        self.units = units;
+        self
+    }
     /// Set the origin transform of this joint
     pub fn origin(mut self, origin: Isometry3<T>) -> NodeBuilder<T> {
         self.origin = origin;
@@ -559,6 +854,14 @@ where
         let mut joint = Joint::new(&self.name, self.joint_type);
         joint.set_origin(self.origin);
         joint.limits = self.limits;
+        joint.velocity_limit = self.velocity_limit;
+        joint.acceleration_limit = self.acceleration_limit;
+        joint.backlash = self.backlash;
+        joint.stiffness = self.stiffness;
+        joint.damping = self.damping;
+        joint.friction = self.friction;
+        joint.units = self.units;
+        joint.continuous = self.continuous;
         joint
     }
     /// Create `Node` instead of `Joint` as output
@@ -597,3 +900,33 @@ macro_rules! connect {
         $crate::connect!($y => $($rest)*);
     };
 }
+
+#[test]
+fn test_convert_to_mimic() {
+    let j0 = NodeBuilder::<f64>::new()
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::z_axis(),
+        })
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::z_axis(),
+        })
+        .into_node();
+    let j2 = NodeBuilder::new()
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::z_axis(),
+        })
+        .into_node();
+    j1.set_mimic_parent(&j0, Mimic::new(2.0, 0.0));
+    j2.set_mimic_parent(&j1, Mimic::new(1.0, 0.0));
+    j0.set_joint_position(0.5).unwrap();
+    j1.convert_to(JointType::Fixed);
+    assert!(j1.mimic_parent().is_none());
+    assert!(j0.lock().mimic_children.is_empty());
+    assert!(j2.mimic().is_none());
+    assert_eq!(j1.origin().translation.vector.z, 1.0);
+    // independent now
+    j2.set_joint_position(0.3).unwrap();
+    assert_eq!(j2.joint_position(), Some(0.3));
+}
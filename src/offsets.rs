@@ -0,0 +1,119 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::node::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Transform the whole chain by pre-multiplying `iso` to the origins of the top nodes
+    ///
+    /// The top nodes are the nodes whose parents are not in the chain, so `iso` is in the
+    /// frame of their parents (the world for the root). It is for the correction of the
+    /// systematic mounting errors like a tilted base, without loading URDF again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let end = chain.find("l_wrist_pitch").unwrap();
+    /// chain.update_transforms();
+    /// let before = end.world_transform().unwrap();
+    ///
+    /// // the base is tilted by 2 degrees
+    /// let tilt = Isometry3::rotation(Vector3::new(2.0f64.to_radians(), 0.0, 0.0));
+    /// chain.transform_all_offsets(&tilt);
+    /// chain.update_transforms();
+    /// let after = end.world_transform().unwrap();
+    /// assert!(((tilt * before).to_homogeneous() - after.to_homogeneous()).norm() < 1e-10);
+    /// ```
+    pub fn transform_all_offsets(&self, iso: &Isometry3<T>) {
+        for node in self.iter() {
+            let is_top = match node.parent() {
+                Some(parent) => !self.iter().any(|n| *n == parent),
+                None => true,
+            };
+            if is_top {
+                node.set_origin(iso * node.origin());
+            }
+        }
+    }
+
+    /// Transform the branch from `node` by pre-multiplying `iso` to its origin
+    ///
+    /// `iso` is in the frame of the parent of `node`.
+    pub fn transform_branch_offset(&self, node: &Node<T>, iso: &Isometry3<T>) -> Result<(), Error> {
+        self.check_contains(node)?;
+        node.set_origin(iso * node.origin());
+        Ok(())
+    }
+
+    /// Transform the branch from `node` by `iso` in the world frame
+    ///
+    /// The world transforms of `node` and its descendants are pre-multiplied by `iso`,
+    /// at the current joint positions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// chain.set_joint_positions(&[0.3; 12]).unwrap();
+    /// let shoulder = chain.find("l_shoulder_pitch").unwrap();
+    /// let end = chain.find("l_wrist_pitch").unwrap();
+    /// chain.update_transforms();
+    /// let before = end.world_transform().unwrap();
+    ///
+    /// // the left arm is mounted 1 mm higher
+    /// let offset = Isometry3::translation(0.0, 0.0, 0.001);
+    /// chain.transform_branch_in_world(shoulder, &offset).unwrap();
+    /// chain.update_transforms();
+    /// let after = end.world_transform().unwrap();
+    /// assert!((after.translation.vector.z - before.translation.vector.z - 0.001).abs() < 1e-10);
+    /// ```
+    pub fn transform_branch_in_world(
+        &self,
+        node: &Node<T>,
+        iso: &Isometry3<T>,
+    ) -> Result<(), Error> {
+        self.check_contains(node)?;
+        self.update_transforms();
+        let parent_transform = node
+            .parent()
+            .and_then(|parent| parent.world_transform())
+            .unwrap_or_else(Isometry3::identity);
+        node.set_origin(parent_transform.inverse() * iso * parent_transform * node.origin());
+        Ok(())
+    }
+
+    fn check_contains(&self, node: &Node<T>) -> Result<(), Error> {
+        if self.iter().any(|n| n == node) {
+            Ok(())
+        } else {
+            Err(Error::JointNotFoundError {
+                joint_name: node.joint().name.clone(),
+            })
+        }
+    }
+}
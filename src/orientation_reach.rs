@@ -0,0 +1,162 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::geometric_ik::*;
+use super::ik::*;
+use na::{RealField, Unit, Vector3};
+use nalgebra as na;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+use simba::scalar::SubsetOf;
+
+/// Tool directions sampled at a point and whether they are reachable
+///
+/// The direction is the Z axis of the end, created by `reachable_orientations()`.
+/// Since sampling takes long, it can be saved and loaded with serde if
+/// the `serde-serialize` feature is enabled.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct ReachableOrientations<T: RealField> {
+    /// the sampled directions, evenly distributed on the unit sphere
+    pub directions: Vec<Unit<Vector3<T>>>,
+    /// true if the direction of the same index is reachable
+    pub reachable: Vec<bool>,
+}
+
+impl<T> ReachableOrientations<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Iterate the reachable directions
+    pub fn reachable_directions(&self) -> impl Iterator<Item = &Unit<Vector3<T>>> {
+        self.directions
+            .iter()
+            .zip(&self.reachable)
+            .filter(|(_, reachable)| **reachable)
+            .map(|(direction, _)| direction)
+    }
+
+    /// Ratio of the reachable directions to the sampled ones
+    pub fn fraction(&self) -> T {
+        if self.directions.is_empty() {
+            return T::zero();
+        }
+        let num_reachable = self.reachable.iter().filter(|r| **r).count();
+        na::convert(num_reachable as f64 / self.directions.len() as f64)
+    }
+
+    /// Mean of the reachable directions, `None` if nothing is reachable
+    pub fn cone_axis(&self) -> Option<Unit<Vector3<T>>> {
+        let sum = self
+            .reachable_directions()
+            .fold(Vector3::zeros(), |sum, direction| {
+                sum + direction.into_inner()
+            });
+        Unit::try_new(sum, T::default_epsilon())
+    }
+
+    /// The largest angle between `cone_axis()` and the reachable directions
+    ///
+    /// The cone of this half angle around `cone_axis()` contains all the reachable
+    /// directions. It is `None` if nothing is reachable.
+    pub fn cone_half_angle(&self) -> Option<T> {
+        let axis = self.cone_axis()?;
+        Some(
+            self.reachable_directions()
+                .map(|direction| axis.angle(direction))
+                .fold(T::zero(), |max, angle| max.max(angle)),
+        )
+    }
+}
+
+/// Estimate the reachable tool directions (Z axis of the end) at `point`
+///
+/// `num_samples` directions evenly distributed on the unit sphere (Fibonacci lattice)
+/// are tried by `JacobianIkSolver::solve_geometric()` with
+/// `GeometricTarget::AxisAlignment`, starting from the current joint positions.
+/// It is an estimate, because the solver may fail for a reachable direction far from
+/// the current one; more iterations of `solver` find more of them. The joint
+/// positions of `arm` are restored.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let point = arm.end_transform().translation.vector;
+///
+/// let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 50);
+/// let orientations = k::reachable_orientations(&solver, &arm, &point, 50);
+/// assert_eq!(orientations.directions.len(), 50);
+/// assert!(orientations.fraction() > 0.0);
+/// assert!(orientations.cone_half_angle().unwrap() > 0.0);
+///
+/// // out of reach
+/// let far = point + k::Vector3::new(10.0, 0.0, 0.0);
+/// let orientations = k::reachable_orientations(&solver, &arm, &far, 50);
+/// assert_eq!(orientations.fraction(), 0.0);
+/// assert!(orientations.cone_axis().is_none());
+/// ```
+pub fn reachable_orientations<T>(
+    solver: &JacobianIkSolver<T>,
+    arm: &SerialChain<T>,
+    point: &Vector3<T>,
+    num_samples: usize,
+) -> ReachableOrientations<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let orig_positions = arm.joint_positions();
+    let directions = fibonacci_sphere::<T>(num_samples);
+    let reachable = directions
+        .iter()
+        .map(|direction| {
+            let target = GeometricTarget::AxisAlignment {
+                point: *point,
+                direction: *direction,
+            };
+            let reached = solver.solve_geometric(arm, &target).is_ok();
+            arm.set_joint_positions_unchecked(&orig_positions);
+            reached
+        })
+        .collect();
+    ReachableOrientations {
+        directions,
+        reachable,
+    }
+}
+
+fn fibonacci_sphere<T>(num: usize) -> Vec<Unit<Vector3<T>>>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let golden_angle = std::f64::consts::PI * (3.0 - 5.0f64.sqrt());
+    (0..num)
+        .map(|i| {
+            let z = 1.0 - (2.0 * i as f64 + 1.0) / num as f64;
+            let r = (1.0 - z * z).sqrt();
+            let theta = golden_angle * i as f64;
+            Unit::new_normalize(Vector3::new(
+                na::convert(r * theta.cos()),
+                na::convert(r * theta.sin()),
+                na::convert(z),
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,176 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use na::{Isometry3, RealField, Vector2, Vector3};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+use std::cmp::Ordering;
+
+/// Evaluation of a candidate pose of the base, by `rank_base_placements()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasePlacement<T: RealField> {
+    /// the candidate pose of the origin of the chain
+    pub base_pose: Isometry3<T>,
+    /// IK solution of each target, `None` if it is not reached
+    pub solutions: Vec<Option<Vec<T>>>,
+    /// the number of the reached targets
+    pub num_reached: usize,
+    /// the smallest manipulability of the solutions, zero if no target is reached
+    pub min_manipulability: T,
+}
+
+impl<T: RealField> BasePlacement<T> {
+    /// Returns true if all the targets are reached
+    pub fn is_fully_reachable(&self) -> bool {
+        self.num_reached == self.solutions.len()
+    }
+}
+
+/// Candidate poses of the base on a grid of the floor (z = 0)
+///
+/// The positions are from `min` to `max` at the interval of `step`, and each of them
+/// has `num_yaws` orientations around the Z axis evenly spaced in [0, 2π).
+/// It returns `Error::InvalidParameterError` if `step` is not positive.
+///
+/// # Examples
+///
+/// ```
+/// use k::nalgebra::Vector2;
+///
+/// let grid = k::base_placement_grid(Vector2::new(-1.0, 0.0), Vector2::new(1.0, 0.5), 0.5, 4)
+///     .unwrap();
+/// assert_eq!(grid.len(), 5 * 2 * 4);
+/// assert!(k::base_placement_grid(Vector2::new(0.0, 0.0), Vector2::new(1.0, 1.0), 0.0, 1).is_err());
+/// ```
+pub fn base_placement_grid<T>(
+    min: Vector2<T>,
+    max: Vector2<T>,
+    step: T,
+    num_yaws: usize,
+) -> Result<Vec<Isometry3<T>>, Error>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    if step <= T::zero() {
+        return Err(Error::InvalidParameterError {
+            reason: format!("step of the grid must be positive, but {}", step),
+        });
+    }
+    // avoid losing the last one by the rounding errors
+    let count = |min: T, max: T| {
+        let n: f64 = na::convert((max - min) / step);
+        (n + 1e-9).floor().max(0.0) as usize + 1
+    };
+    let mut poses = Vec::new();
+    for i in 0..count(min.x, max.x) {
+        for j in 0..count(min.y, max.y) {
+            let x = min.x + step * na::convert(i as f64);
+            let y = min.y + step * na::convert(j as f64);
+            for k in 0..num_yaws {
+                let yaw = T::two_pi() * na::convert(k as f64 / num_yaws as f64);
+                poses.push(Isometry3::new(
+                    Vector3::new(x, y, T::zero()),
+                    Vector3::z() * yaw,
+                ));
+            }
+        }
+    }
+    Ok(poses)
+}
+
+/// Evaluate the candidate poses of the base of `arm` and rank them
+///
+/// For each candidate, the origin of the chain of `arm` is set to it, and `solver` solves
+/// IK of all the `targets` (in the world frame) from the current joint positions. The
+/// placements are sorted by the number of the reached targets, and then by the smallest
+/// manipulability of the solutions, the best one first. The origin and the joint positions
+/// of `arm` are restored.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+/// use k::nalgebra::Vector2;
+///
+/// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let reachable = arm.end_transform();
+/// // the targets are near the current end, and too far from the base 2.0 away
+/// let targets = [reachable, Isometry3::translation(0.0, 0.0, 0.02) * reachable];
+/// let candidates = base_placement_grid(Vector2::new(0.0, 0.0), Vector2::new(2.0, 0.0), 2.0, 1)
+///     .unwrap();
+/// let solver = JacobianIkSolver::new(0.001, 0.01, 0.5, 100);
+/// let ranked = rank_base_placements(&arm, &solver, &targets, &candidates, &Constraints::default());
+/// assert_eq!(ranked.len(), 2);
+/// assert!(ranked[0].is_fully_reachable());
+/// assert_eq!(ranked[0].base_pose, Isometry3::identity());
+/// assert_eq!(ranked[1].num_reached, 0);
+/// assert_eq!(arm.origin(), Isometry3::identity());
+/// ```
+pub fn rank_base_placements<T, S>(
+    arm: &SerialChain<T>,
+    solver: &S,
+    targets: &[Isometry3<T>],
+    candidates: &[Isometry3<T>],
+    constraints: &Constraints,
+) -> Vec<BasePlacement<T>>
+where
+    T: RealField + SubsetOf<f64>,
+    S: InverseKinematicsSolver<T>,
+{
+    let orig_origin = arm.origin();
+    let orig_positions = arm.joint_positions();
+    let mut placements = candidates
+        .iter()
+        .map(|base_pose| {
+            arm.set_origin(*base_pose);
+            let mut min_manipulability: Option<T> = None;
+            let solutions = targets
+                .iter()
+                .map(|target| {
+                    arm.set_joint_positions_unchecked(&orig_positions);
+                    solver
+                        .solve_with_constraints(arm, target, constraints)
+                        .ok()?;
+                    let manipulability = arm.kinematic_condition().manipulability;
+                    min_manipulability =
+                        Some(min_manipulability.map_or(manipulability, |m| m.min(manipulability)));
+                    Some(arm.joint_positions())
+                })
+                .collect::<Vec<_>>();
+            BasePlacement {
+                base_pose: *base_pose,
+                num_reached: solutions.iter().filter(|s| s.is_some()).count(),
+                solutions,
+                min_manipulability: min_manipulability.unwrap_or_else(T::zero),
+            }
+        })
+        .collect::<Vec<_>>();
+    arm.set_origin(orig_origin);
+    arm.set_joint_positions_unchecked(&orig_positions);
+    // stable, so the candidates of the same score keep the order
+    placements.sort_by(|a, b| {
+        b.num_reached.cmp(&a.num_reached).then_with(|| {
+            b.min_manipulability
+                .partial_cmp(&a.min_manipulability)
+                .unwrap_or(Ordering::Equal)
+        })
+    });
+    placements
+}
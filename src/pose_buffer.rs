@@ -0,0 +1,92 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Number of the elements of a pose in the pose buffers:
+/// translation (x, y, z) and quaternion (x, y, z, w)
+pub const POSE_BUFFER_STRIDE: usize = 7;
+
+fn push_pose<T: RealField>(buffer: &mut Vec<T>, pose: &Isometry3<T>) {
+    let t = &pose.translation.vector;
+    let q = &pose.rotation.coords;
+    buffer.extend_from_slice(&[t.x, t.y, t.z, q.x, q.y, q.z, q.w]);
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Packed local transforms of all the nodes at the current positions
+    ///
+    /// Each node has `POSE_BUFFER_STRIDE` elements, translation (x, y, z) and
+    /// quaternion (x, y, z, w), in the order of `iter()`. It is the layout
+    /// which skinning shaders and animation systems of game engines use.
+    /// The nodes marked by `Node::set_output_suppressed()` are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f32>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let buffer = chain.local_pose_buffer();
+    /// assert_eq!(buffer.len(), chain.iter().count() * POSE_BUFFER_STRIDE);
+    /// // root has identity rotation
+    /// assert_eq!(buffer[6], 1.0);
+    /// ```
+    pub fn local_pose_buffer(&self) -> Vec<T> {
+        let mut buffer = Vec::with_capacity(self.iter().count() * POSE_BUFFER_STRIDE);
+        for node in self.iter().filter(|node| !node.is_output_suppressed()) {
+            push_pose(&mut buffer, &node.joint().local_transform());
+        }
+        buffer
+    }
+
+    /// Packed world transforms of all the nodes, with the same layout as `local_pose_buffer()`
+    ///
+    /// It calls `update_transforms()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let buffer = chain.world_pose_buffer();
+    /// let index = chain.iter().position(|n| n.joint().name == "l_elbow_pitch").unwrap();
+    /// let pose = chain.find("l_elbow_pitch").unwrap().world_transform().unwrap();
+    /// assert_eq!(buffer[index * POSE_BUFFER_STRIDE], pose.translation.vector.x);
+    /// ```
+    pub fn world_pose_buffer(&self) -> Vec<T> {
+        let transforms = self.update_transforms();
+        let mut buffer = Vec::with_capacity(transforms.len() * POSE_BUFFER_STRIDE);
+        for pose in &transforms {
+            push_pose(&mut buffer, pose);
+        }
+        buffer
+    }
+
+    /// Names of the nodes in the order of the pose buffers
+    pub fn pose_buffer_names(&self) -> Vec<String> {
+        self.iter()
+            .filter(|node| !node.is_output_suppressed())
+            .map(|node| node.joint().name.clone())
+            .collect()
+    }
+}
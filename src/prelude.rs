@@ -14,4 +14,30 @@
   limitations under the License.
 */
 //! Load basic traits of `k`
+//!
+//! `use k::prelude::*;` imports the traits to call the methods of the solvers and
+//! the backends, and the scalar traits to write the code generic over `f32` and `f64`.
+//!
+//! # Examples
+//!
+//! ```
+//! use k::prelude::*;
+//!
+//! fn end_position<T: RealField + SubsetOf<f64>>(arm: &k::SerialChain<T>) -> k::Vector3<T> {
+//!     arm.end_transform().translation.vector
+//! }
+//!
+//! let chain = k::Chain::<f32>::from_urdf_file("urdf/sample.urdf").unwrap();
+//! let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+//! arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+//! let mut target = arm.end_transform();
+//! target.translation.vector.z += 0.01;
+//! k::JacobianIkSolver::default().solve(&arm, &target).unwrap();
+//! assert!((end_position(&arm) - target.translation.vector).norm() < 0.001);
+//! ```
+pub use super::analytical_ik::AnalyticalIkSolver;
+pub use super::batch::BatchFkBackend;
 pub use super::ik::InverseKinematicsSolver;
+pub use super::shortcut::StateValidator;
+pub use nalgebra::RealField;
+pub use simba::scalar::{SubsetOf, SupersetOf};
@@ -0,0 +1,122 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A timing sample recorded by `Profiler`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileRecord {
+    /// joint name for `Chain::update_transforms()`, or the phase of the solver
+    /// like `"ik/jacobian"`
+    pub name: String,
+    /// iteration of the solver, `None` for `Chain::update_transforms()`
+    pub iteration: Option<usize>,
+    pub duration: Duration,
+}
+
+/// Statistics of the records with the same name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileEntry {
+    pub name: String,
+    pub count: usize,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+/// Opt-in recorder of the timing of `Chain::update_transforms()` and `JacobianIkSolver`
+///
+/// It is a shared handle, so the clones record into the same storage.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// let mut chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let profiler = Profiler::new();
+/// chain.set_profiler(Some(profiler.clone()));
+/// chain.update_transforms();
+/// chain.update_transforms();
+/// let entry = profiler.entry("l_elbow_pitch").unwrap();
+/// assert_eq!(entry.count, 2);
+/// // the most expensive one comes first
+/// let report = profiler.report();
+/// assert!(report[0].total >= report[report.len() - 1].total);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    records: Arc<Mutex<Vec<ProfileRecord>>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a sample
+    pub fn record(&self, name: &str, iteration: Option<usize>, duration: Duration) {
+        self.records.lock().unwrap().push(ProfileRecord {
+            name: name.to_owned(),
+            iteration,
+            duration,
+        });
+    }
+
+    /// Run `f` and record the time
+    pub fn measure<R>(&self, name: &str, iteration: Option<usize>, f: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let result = f();
+        self.record(name, iteration, start.elapsed());
+        result
+    }
+
+    /// Get all the recorded samples in order
+    pub fn records(&self) -> Vec<ProfileRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// Aggregate the samples by the name, sorted by the total time in descending order
+    pub fn report(&self) -> Vec<ProfileEntry> {
+        let mut entries: Vec<ProfileEntry> = Vec::new();
+        for record in self.records.lock().unwrap().iter() {
+            match entries.iter_mut().find(|e| e.name == record.name) {
+                Some(entry) => {
+                    entry.count += 1;
+                    entry.total += record.duration;
+                    entry.max = entry.max.max(record.duration);
+                }
+                None => entries.push(ProfileEntry {
+                    name: record.name.clone(),
+                    count: 1,
+                    total: record.duration,
+                    max: record.duration,
+                }),
+            }
+        }
+        entries.sort_by_key(|e| std::cmp::Reverse(e.total));
+        entries
+    }
+
+    /// Get the statistics of the samples with `name`
+    pub fn entry(&self, name: &str) -> Option<ProfileEntry> {
+        self.report().into_iter().find(|e| e.name == name)
+    }
+
+    /// Remove all the samples
+    pub fn clear(&self) {
+        self.records.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,235 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::joint::*;
+use super::node::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create an equivalent chain whose root is the link of `new_root`
+    ///
+    /// The new root is a fixed node which has the link of `new_root`, and it is placed
+    /// at the current world transform of `new_root`. The joints on the path from
+    /// `new_root` to the old root are reversed: the axes are negated, so the positions,
+    /// the limits and the mimics are the same as the original joints, and each of them
+    /// is followed by a fixed node which has the link of the parent of the original joint.
+    /// The two axes of a reversed `JointType::Universal` are also swapped, so its two
+    /// positions are in the reverse order. A `JointType::Planar` or `JointType::Floating`
+    /// on the path cannot be reversed, and it returns `Err`.
+    /// The fixed node is named by the link, or `"<joint name>_link"` with the original
+    /// joint of the link if there is no link.
+    ///
+    /// The joint of the old root itself is the placement in the world, so it is not
+    /// included in the new chain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// chain.set_joint_positions(&[0.1, 0.2, 0.3, -0.4, 0.5, 0.6, 0.0, -0.2, 0.1, 0.3, 0.2, 0.1]).unwrap();
+    /// chain.update_transforms();
+    /// let l_wrist = chain.find("l_wrist_pitch").unwrap().world_transform().unwrap();
+    ///
+    /// // make the right hand the root
+    /// let rerooted = chain.rerooted(chain.find("r_wrist_pitch").unwrap()).unwrap();
+    /// assert_eq!(rerooted.dof(), chain.dof());
+    /// rerooted.update_transforms();
+    /// let l_wrist2 = rerooted.find("l_wrist_pitch").unwrap().world_transform().unwrap();
+    /// assert!((l_wrist.translation.vector - l_wrist2.translation.vector).norm() < 1e-6);
+    /// ```
+    pub fn rerooted(&self, new_root: &Node<T>) -> Result<Chain<T>, Error> {
+        if !self.iter().any(|node| node == new_root) {
+            return Err(Error::JointNotFoundError {
+                joint_name: new_root.joint().name.clone(),
+            });
+        }
+        self.update_transforms();
+        // pairs of the original node and the new node which has the same joint position
+        let mut mapping = Vec::new();
+
+        let root = Node::new(Joint::new(&link_node_name(new_root), JointType::Fixed));
+        root.set_origin(new_root.world_transform().unwrap());
+        root.set_link(new_root.link().clone());
+        self.clone_children(new_root, &root, None, &mut mapping);
+
+        let mut child = new_root.clone();
+        let mut parent_link_node = root.clone();
+        while let Some(parent) = child.parent().filter(|p| self.iter().any(|n| n == p)) {
+            let mut joint = child.joint().clone();
+            let origin = *joint.origin();
+            joint.joint_type = match joint.joint_type {
+                JointType::Fixed => JointType::Fixed,
+                JointType::Rotational { axis } => JointType::Rotational { axis: -axis },
+                JointType::Linear { axis } => JointType::Linear { axis: -axis },
+                JointType::Universal {
+                    first_axis,
+                    second_axis,
+                } => {
+                    let positions = [joint.joint_positions()[1], joint.joint_positions()[0]];
+                    joint.set_joint_positions_unchecked(&positions);
+                    JointType::Universal {
+                        first_axis: -second_axis,
+                        second_axis: -first_axis,
+                    }
+                }
+                JointType::Planar { .. } | JointType::Floating { .. } => {
+                    return Err(Error::InvalidStructureError {
+                        reason: format!("{} {} cannot be reversed", joint.joint_type, joint.name),
+                    })
+                }
+            };
+            joint.set_origin(Isometry3::identity());
+            let reversed = Node::new(joint);
+            reversed.set_parent(&parent_link_node);
+            mapping.push((child.clone(), reversed.clone()));
+
+            let link_node = Node::new(Joint::new(&link_node_name(&parent), JointType::Fixed));
+            link_node.set_origin(origin.inverse());
+            link_node.set_link(parent.link().clone());
+            link_node.set_parent(&reversed);
+            self.clone_children(&parent, &link_node, Some(&child), &mut mapping);
+
+            child = parent;
+            parent_link_node = link_node;
+        }
+
+        for (original, new) in &mapping {
+            if let (Some(mimic_parent), Some(mimic)) = (original.mimic_parent(), original.mimic()) {
+                if let Some((_, new_parent)) = mapping.iter().find(|(o, _)| *o == mimic_parent) {
+                    new.set_mimic_parent(new_parent, mimic);
+                }
+            }
+        }
+        let mut chain = Chain::from_root(root);
+        chain.set_limit_policy(self.limit_policy());
+        chain.set_tolerances(*self.tolerances());
+        chain.set_metadata(self.metadata().clone());
+        for limit in self.coupled_limits() {
+            // the limits over the joint of the old root are dropped with the joint
+            let _ = chain.add_coupled_limit(limit.clone());
+        }
+        chain.set_profiler(self.profiler().cloned());
+        *chain.update_hooks_mut() = self.update_hooks().clone();
+        Ok(chain)
+    }
+
+    /// Clone the descendants of `original` in this chain as the children of `new_parent`
+    fn clone_children(
+        &self,
+        original: &Node<T>,
+        new_parent: &Node<T>,
+        skip: Option<&Node<T>>,
+        mapping: &mut Vec<(Node<T>, Node<T>)>,
+    ) {
+        let children = original.children().clone();
+        for child in children
+            .iter()
+            .filter(|c| Some(*c) != skip && self.iter().any(|n| n == *c))
+        {
+            let new_child = Node::new(child.joint().clone());
+            new_child.set_link(child.link().clone());
+            new_child.set_output_suppressed(child.is_output_suppressed());
+            new_child.set_parent(new_parent);
+            mapping.push((child.clone(), new_child.clone()));
+            self.clone_children(child, &new_child, None, mapping);
+        }
+    }
+}
+
+/// Name of the fixed node which has the link of `node`
+fn link_node_name<T>(node: &Node<T>) -> String
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let link_name = node.link().as_ref().map(|link| link.name.clone());
+    match link_name {
+        Some(name) => name,
+        None => format!("{}_link", node.joint().name),
+    }
+}
+
+#[test]
+fn test_rerooted_path() {
+    use na::{Translation3, Vector3};
+    let j0 = NodeBuilder::new()
+        .name("j0")
+        .translation(Translation3::new(0.0, 0.0, 1.0))
+        .joint_type(JointType::Rotational {
+            axis: Vector3::y_axis(),
+        })
+        .limits(Some((-1.0..=1.0).into()))
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .name("j1")
+        .translation(Translation3::new(0.5, 0.0, 0.0))
+        .joint_type(JointType::Linear {
+            axis: Vector3::x_axis(),
+        })
+        .into_node();
+    let j2 = NodeBuilder::new()
+        .name("j2")
+        .translation(Translation3::new(0.0, 0.3, 0.0))
+        .joint_type(JointType::Rotational {
+            axis: Vector3::z_axis(),
+        })
+        .into_node();
+    j1.set_parent(&j0);
+    j2.set_parent(&j1);
+    let chain = Chain::<f64>::from_root(j0);
+    chain.set_joint_positions(&[0.3, 0.2, -0.5]).unwrap();
+    let transforms = chain.update_transforms();
+
+    let rerooted = chain.rerooted(&j2).unwrap();
+    // j0 is the old root, so it is removed
+    assert_eq!(rerooted.dof(), 2);
+    let names = rerooted
+        .iter()
+        .map(|n| n.joint().name.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["j2_link", "j2", "j1_link", "j1", "j0_link"]);
+    rerooted.update_transforms();
+    let old_root = rerooted.find("j0_link").unwrap().world_transform().unwrap();
+    // the link of the old root at the world transform of j0
+    assert!((old_root.to_homogeneous() - transforms[0].to_homogeneous()).norm() < 1e-10);
+    assert_eq!(
+        rerooted.find("j2").unwrap().joint().limits.is_none(),
+        chain.find("j2").unwrap().joint().limits.is_none()
+    );
+
+    // move the joints of the rerooted chain, and the relative transform is the same
+    chain.set_joint_positions(&[0.3, -0.1, 0.4]).unwrap();
+    rerooted.set_joint_positions(&[0.4, -0.1]).unwrap();
+    let transforms = chain.update_transforms();
+    rerooted.update_transforms();
+    let relative = transforms[2].inverse() * transforms[0];
+    let new_relative = rerooted
+        .iter()
+        .next()
+        .unwrap()
+        .world_transform()
+        .unwrap()
+        .inverse()
+        * rerooted.find("j0_link").unwrap().world_transform().unwrap();
+    assert!((relative.to_homogeneous() - new_relative.to_homogeneous()).norm() < 1e-10);
+}
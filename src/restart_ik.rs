@@ -0,0 +1,173 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::bench::Rng;
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+use std::time::{Duration, Instant};
+
+/// IK solver which restarts `JacobianIkSolver` from random positions
+///
+/// The first attempt starts from the current joint positions, and the following ones
+/// start from random positions within the joint limits ([-π, π] for the rotational
+/// joints without limits, the current positions for the linear joints without limits)
+/// until one of them reaches the target or the time budget expires. It avoids the
+/// local minima where a single gradient descent gets stuck, like TRAC-IK does.
+///
+/// If no attempt reaches the target, the joint positions are set to the best
+/// solution found, which has the smallest sum of the position and rotation errors,
+/// and `Error::NotConvergedError` with its errors is returned. The attempts stop
+/// when the iteration callback of the solver cancels the solve.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+/// use std::time::Duration;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let wrist = chain.find("l_wrist_pitch").unwrap();
+/// let arm = k::SerialChain::from_end(wrist);
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let mut target = arm.update_transforms().last().unwrap().clone();
+/// target.translation.vector.z += 0.1;
+///
+/// let solver = k::RandomRestartIkSolver::new(
+///     k::JacobianIkSolver::default(),
+///     Duration::from_millis(100),
+/// );
+/// solver.solve(&arm, &target).unwrap();
+/// let end = arm.end_transform();
+/// assert!((end.translation.vector - target.translation.vector).norm() < 0.001);
+/// ```
+pub struct RandomRestartIkSolver<T: RealField> {
+    /// solver used for each attempt
+    pub solver: JacobianIkSolver<T>,
+    /// time budget of a solve
+    pub timeout: Duration,
+    /// maximum number of the attempts including the first one, unlimited if `None`
+    pub max_attempts: Option<usize>,
+    /// seed of the random positions, same seed gives same sequence of attempts
+    pub seed: u64,
+}
+
+impl<T> RandomRestartIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create a solver with the time budget of each solve
+    pub fn new(solver: JacobianIkSolver<T>, timeout: Duration) -> Self {
+        Self {
+            solver,
+            timeout,
+            max_attempts: None,
+            seed: 0,
+        }
+    }
+
+    fn random_positions(&self, arm: &SerialChain<T>, rng: &mut Rng) -> Vec<T> {
+        let mut positions = Vec::with_capacity(arm.dof());
+        for joint in arm.iter_joints() {
+            for (i, position) in joint.joint_positions().iter().enumerate() {
+                let (min, max) = match joint.effective_limits() {
+                    Some(ref range) => (
+                        na::convert::<T, f64>(range.min),
+                        na::convert::<T, f64>(range.max),
+                    ),
+                    None if joint.joint_type.is_angle(i) => {
+                        (-std::f64::consts::PI, std::f64::consts::PI)
+                    }
+                    None => {
+                        positions.push(*position);
+                        continue;
+                    }
+                };
+                positions.push(na::convert(min + (max - min) * rng.next_f64()));
+            }
+        }
+        positions
+    }
+}
+
+impl<T> InverseKinematicsSolver<T> for RandomRestartIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn solve_with_constraints(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error> {
+        self.solve_with_iterations(arm, target_pose, constraints)
+            .map(|_| ())
+    }
+
+    /// Returns the total number of the iterations of all attempts
+    fn solve_with_iterations(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<Option<usize>, Error> {
+        let start = Instant::now();
+        let orig_positions = arm.joint_positions();
+        let mut rng = Rng::new(self.seed);
+        let mut best: Option<(T, Vec<T>, IkDiagnostics<T>)> = None;
+        let mut iterations = 0;
+        let mut attempt = 0;
+        loop {
+            if attempt > 0 {
+                arm.set_joint_positions_clamped(&self.random_positions(arm, &mut rng));
+            }
+            attempt += 1;
+            let diagnostics =
+                match self
+                    .solver
+                    .solve_with_diagnostics_internal(arm, target_pose, constraints)
+                {
+                    Ok(diagnostics) => diagnostics,
+                    Err(error) => {
+                        arm.set_joint_positions(&orig_positions)?;
+                        return Err(error);
+                    }
+                };
+            iterations += diagnostics.iterations;
+            if diagnostics.is_converged() {
+                return Ok(Some(iterations));
+            }
+            let cost = diagnostics.position_residual.norm() + diagnostics.rotation_residual.norm();
+            let is_better = match best {
+                Some((ref best_cost, _, _)) => cost < *best_cost,
+                None => true,
+            };
+            let cancelled = diagnostics.termination == IkTermination::Cancelled;
+            if is_better {
+                best = Some((cost, arm.joint_positions(), diagnostics));
+            }
+            let exhausted = matches!(self.max_attempts, Some(max) if attempt >= max);
+            if cancelled || exhausted || start.elapsed() >= self.timeout {
+                break;
+            }
+        }
+        let (_, positions, diagnostics) = best.unwrap();
+        arm.set_joint_positions_clamped(&positions);
+        diagnostics.into_result().map(Some)
+    }
+}
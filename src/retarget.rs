@@ -0,0 +1,268 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use super::trajectory::*;
+use log::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Correspondence between a frame of the source chain and a frame of the target chain
+#[derive(Debug, Clone)]
+pub struct RetargetMapping<T: RealField> {
+    /// joint name of the source chain
+    pub source_frame: String,
+    /// joint name of the target chain, which is moved by IK
+    pub target_frame: String,
+    /// transform from the (scaled) source frame to the target frame
+    pub offset: Isometry3<T>,
+    /// constraints of the IK for this mapping
+    pub constraints: Constraints,
+}
+
+impl<T: RealField> RetargetMapping<T> {
+    /// Create mapping without offset and with all constraints
+    pub fn new(source_frame: &str, target_frame: &str) -> Self {
+        Self {
+            source_frame: source_frame.to_owned(),
+            target_frame: target_frame.to_owned(),
+            offset: Isometry3::identity(),
+            constraints: Constraints::default(),
+        }
+    }
+}
+
+/// Result of `Retargeter::retarget()`
+#[derive(Debug, Clone)]
+pub struct RetargetOutput<T: RealField> {
+    /// trajectory of the target chain, which has the same times as the source
+    pub trajectory: JointTrajectory<T>,
+    /// indices of the points whose IK did not converge.
+    /// The positions of the previous point are used for them.
+    pub failed_point_indices: Vec<usize>,
+}
+
+/// Retarget the motion of a chain (e.g. a human skeleton) to another chain (e.g. a robot)
+///
+/// For each point of the source trajectory, the mapped frames of the source are
+/// calculated by forward kinematics and the target chain is moved to them by IK.
+/// The mappings are solved in order, so a later mapping can override the joints
+/// shared with an earlier one.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// // source: two links of length 1.0
+/// let s0 = NodeBuilder::new()
+///     .name("s0")
+///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+///     .into_node();
+/// let s1 = NodeBuilder::new()
+///     .name("s1")
+///     .translation(Translation3::new(1.0, 0.0, 0.0))
+///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+///     .into_node();
+/// let s_end = NodeBuilder::new()
+///     .name("s_end")
+///     .translation(Translation3::new(1.0, 0.0, 0.0))
+///     .into_node();
+/// connect![s0 => s1 => s_end];
+/// let source = Chain::<f64>::from_root(s0);
+///
+/// // target: two links of length 0.5
+/// let t0 = NodeBuilder::new()
+///     .name("t0")
+///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+///     .into_node();
+/// let t1 = NodeBuilder::new()
+///     .name("t1")
+///     .translation(Translation3::new(0.5, 0.0, 0.0))
+///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+///     .into_node();
+/// let t_end = NodeBuilder::new()
+///     .name("t_end")
+///     .translation(Translation3::new(0.5, 0.0, 0.0))
+///     .into_node();
+/// connect![t0 => t1 => t_end];
+/// let target = Chain::<f64>::from_root(t0);
+/// target.set_joint_positions(&[0.1, 0.1]).unwrap();
+///
+/// let mut mapping = RetargetMapping::new("s_end", "t_end");
+/// mapping.constraints.rotation_x = false;
+/// mapping.constraints.rotation_y = false;
+/// mapping.constraints.position_z = false;
+/// mapping.constraints.rotation_z = false;
+/// let mut retargeter = Retargeter::new(vec![mapping], JacobianIkSolver::new(0.001, 0.01, 0.5, 100));
+/// retargeter.position_scale = 0.5;
+///
+/// let motion = JointTrajectory::new(vec![
+///     TrajectoryPoint::new(vec![0.2, 0.3], 0.0),
+///     TrajectoryPoint::new(vec![0.3, 0.4], 0.1),
+/// ]);
+/// let output = retargeter.retarget(&source, &motion, &target).unwrap();
+/// assert!(output.failed_point_indices.is_empty());
+/// assert_eq!(output.trajectory.points.len(), 2);
+/// assert_eq!(output.trajectory.points[1].time_from_start, 0.1);
+/// ```
+pub struct Retargeter<T, S>
+where
+    T: RealField,
+    S: InverseKinematicsSolver<T>,
+{
+    /// mappings between the source and the target frames
+    pub mappings: Vec<RetargetMapping<T>>,
+    /// multiplier of the translation of the source frames, to adapt the size of the chains
+    pub position_scale: T,
+    /// IK solver for the target chain
+    pub solver: S,
+}
+
+impl<T, S> Retargeter<T, S>
+where
+    T: RealField + SubsetOf<f64>,
+    S: InverseKinematicsSolver<T>,
+{
+    pub fn new(mappings: Vec<RetargetMapping<T>>, solver: S) -> Self {
+        Self {
+            mappings,
+            position_scale: T::one(),
+            solver,
+        }
+    }
+
+    /// Retarget `source_trajectory` of `source` to `target`
+    ///
+    /// The joint positions of both chains are changed. The IK of the first point starts
+    /// from the current positions of `target`, and the others start from the previous result.
+    pub fn retarget(
+        &self,
+        source: &Chain<T>,
+        source_trajectory: &JointTrajectory<T>,
+        target: &Chain<T>,
+    ) -> Result<RetargetOutput<T>, Error> {
+        let arms = self
+            .mappings
+            .iter()
+            .map(|mapping| {
+                let source_node = source.find(&mapping.source_frame).ok_or_else(|| {
+                    Error::JointNotFoundError {
+                        joint_name: mapping.source_frame.clone(),
+                    }
+                })?;
+                let target_node = target.find(&mapping.target_frame).ok_or_else(|| {
+                    Error::JointNotFoundError {
+                        joint_name: mapping.target_frame.clone(),
+                    }
+                })?;
+                Ok((source_node, SerialChain::from_end(target_node)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut points: Vec<TrajectoryPoint<T>> =
+            Vec::with_capacity(source_trajectory.points.len());
+        let mut failed_point_indices = Vec::new();
+        for (i, point) in source_trajectory.points.iter().enumerate() {
+            source.set_joint_positions(&point.positions)?;
+            source.update_transforms();
+            let mut failed = false;
+            for ((source_node, arm), mapping) in arms.iter().zip(&self.mappings) {
+                let mut pose = source_node
+                    .world_transform()
+                    .expect("transforms are updated");
+                pose.translation.vector *= self.position_scale;
+                let target_pose = pose * mapping.offset;
+                if let Err(err) =
+                    self.solver
+                        .solve_with_constraints(arm, &target_pose, &mapping.constraints)
+                {
+                    warn!("retarget failed at point {}: {}", i, err);
+                    failed = true;
+                }
+            }
+            if failed {
+                failed_point_indices.push(i);
+                if let Some(prev) = points.last() {
+                    target.set_joint_positions(&prev.positions)?;
+                }
+            }
+            points.push(TrajectoryPoint::new(
+                target.joint_positions(),
+                point.time_from_start,
+            ));
+        }
+        Ok(RetargetOutput {
+            trajectory: JointTrajectory::new(points),
+            failed_point_indices,
+        })
+    }
+}
+
+#[test]
+fn test_retarget_scaled_end() {
+    use super::joint::*;
+    use super::node::*;
+    let create_chain = |prefix: &str, length: f64| {
+        let j0 = NodeBuilder::new()
+            .name(&format!("{}0", prefix))
+            .joint_type(JointType::Rotational {
+                axis: na::Vector3::z_axis(),
+            })
+            .into_node();
+        let j1 = NodeBuilder::new()
+            .name(&format!("{}1", prefix))
+            .translation(na::Translation3::new(length, 0.0, 0.0))
+            .joint_type(JointType::Rotational {
+                axis: na::Vector3::z_axis(),
+            })
+            .into_node();
+        let end = NodeBuilder::new()
+            .name(&format!("{}_end", prefix))
+            .translation(na::Translation3::new(length, 0.0, 0.0))
+            .into_node();
+        j1.set_parent(&j0);
+        end.set_parent(&j1);
+        Chain::<f64>::from_root(j0)
+    };
+    let source = create_chain("s", 1.0);
+    let target = create_chain("t", 0.5);
+    target.set_joint_positions(&[0.2, 0.2]).unwrap();
+    let mut mapping = RetargetMapping::new("s_end", "t_end");
+    mapping.constraints.position_z = false;
+    mapping.constraints.rotation_x = false;
+    mapping.constraints.rotation_y = false;
+    mapping.constraints.rotation_z = false;
+    let mut retargeter = Retargeter::new(
+        vec![mapping],
+        JacobianIkSolver::new(0.0001, 0.001, 0.5, 100),
+    );
+    retargeter.position_scale = 0.5;
+    let motion = JointTrajectory::new(vec![TrajectoryPoint::new(vec![0.3, 0.5], 0.0)]);
+    let output = retargeter.retarget(&source, &motion, &target).unwrap();
+    assert!(output.failed_point_indices.is_empty());
+    // same shape with half size
+    let positions = &output.trajectory.points[0].positions;
+    assert!((positions[0] - 0.3).abs() < 0.01);
+    assert!((positions[1] - 0.5).abs() < 0.01);
+
+    let missing = Retargeter::new(
+        vec![RetargetMapping::new("none", "t_end")],
+        JacobianIkSolver::default(),
+    );
+    assert!(missing.retarget(&source, &motion, &target).is_err());
+}
@@ -0,0 +1,403 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::funcs::*;
+use super::node::*;
+use super::tolerances::*;
+use na::{DMatrix, DVector, Isometry3, RealField, Vector6};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Differential IK helper to make the end of an arm track a stream of target poses
+///
+/// Each `update()` calculates the joint velocities by the damped least squares of the
+/// jacobian, slows down the joints approaching the limits, scales the velocities not to
+/// cross the limits within `limit_horizon`, saturates the velocities
+/// and the accelerations by `Joint::velocity_limit` and `Joint::acceleration_limit`,
+/// and integrates them over `dt`.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let mut target = arm.end_transform();
+/// target.translation.vector.z += 0.05;
+///
+/// let mut servo = k::CartesianServo::default();
+/// for _ in 0..500 {
+///     servo.update(&arm, &target, 0.01).unwrap();
+/// }
+/// let end = arm.end_transform();
+/// assert!((end.translation.vector - target.translation.vector).norm() < 0.001);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CartesianServo<T: RealField> {
+    /// proportional gain from the pose error to the end velocity [1/sec]
+    pub gain: T,
+    /// the maximum translational velocity of the end [m/sec], `None` for no limit
+    pub max_linear_velocity: Option<T>,
+    /// the maximum rotational velocity of the end [rad/sec], `None` for no limit
+    pub max_angular_velocity: Option<T>,
+    /// damping factor of the damped least squares, larger is more stable near singularities
+    pub damping: T,
+    /// the joints closer to the limits than this are slowed down when approaching them
+    pub limit_margin: T,
+    /// if set, all the velocities are scaled down together so that no joint crosses
+    /// its limit within this time [sec] at the current velocity, `None` to disable it
+    ///
+    /// The joints decelerate smoothly toward the limits, instead of stopping at them.
+    pub limit_horizon: Option<T>,
+    velocities: Vec<T>,
+}
+
+impl<T> CartesianServo<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    pub fn new(gain: T, damping: T, limit_margin: T) -> Self {
+        Self {
+            gain,
+            max_linear_velocity: None,
+            max_angular_velocity: None,
+            damping,
+            limit_margin,
+            limit_horizon: None,
+            velocities: Vec::new(),
+        }
+    }
+
+    /// The joint velocities of the last `update()`
+    pub fn velocities(&self) -> &[T] {
+        &self.velocities
+    }
+
+    /// Forget the last velocities, call it when the servo is restarted after stopping
+    pub fn reset(&mut self) {
+        self.velocities.clear();
+    }
+
+    /// Move the joints of `arm` toward `target` for `dt` [sec], and get the joint velocities
+    ///
+    /// It returns `Err(Error::InvalidParameterError)` if `dt` is not positive.
+    pub fn update(
+        &mut self,
+        arm: &SerialChain<T>,
+        target: &Isometry3<T>,
+        dt: T,
+    ) -> Result<Vec<T>, Error> {
+        if dt <= T::zero() {
+            return Err(Error::InvalidParameterError {
+                reason: format!("dt must be positive, but {}", dt),
+            });
+        }
+        let dof = arm.dof();
+        if self.velocities.len() != dof {
+            self.velocities = vec![T::zero(); dof];
+        }
+        let twist = self.target_twist(&arm.end_transform(), target);
+        let jacobi = jacobian(arm);
+        let mut velocities = self.damped_least_squares(&jacobi, &twist)?;
+
+        let positions = arm.joint_positions();
+        let mut scale = T::one();
+        for (i, node) in arm.iter_position_nodes().enumerate() {
+            let joint = node.joint();
+            if let Some(range) = joint.effective_limits() {
+                let distance = if velocities[i] > T::zero() {
+                    range.max - positions[i]
+                } else {
+                    positions[i] - range.min
+                };
+                if distance < self.limit_margin {
+                    velocities[i] *= (distance / self.limit_margin).max(T::zero());
+                }
+                if let Some(horizon) = self.limit_horizon {
+                    let reach = velocities[i].abs() * horizon;
+                    if reach > distance {
+                        scale = scale.min((distance / reach).max(T::zero()));
+                    }
+                }
+            }
+            if let Some(limit) = joint.velocity_limit {
+                if velocities[i].abs() > limit {
+                    scale = scale.min(limit / velocities[i].abs());
+                }
+            }
+        }
+        // keep the direction of the end velocity
+        velocities *= scale;
+        for (i, node) in arm.iter_position_nodes().enumerate() {
+            let joint = node.joint();
+            if let Some(limit) = joint.acceleration_limit {
+                let prev = self.velocities[i];
+                let max_change = limit * dt;
+                velocities[i] = velocities[i].max(prev - max_change).min(prev + max_change);
+            }
+        }
+
+        let next_positions = positions
+            .iter()
+            .zip(velocities.iter())
+            .map(|(q, v)| *q + *v * dt)
+            .collect::<Vec<_>>();
+        arm.set_joint_positions_clamped(&next_positions);
+        self.velocities = velocities.iter().cloned().collect();
+        Ok(self.velocities.clone())
+    }
+
+    fn target_twist(&self, current: &Isometry3<T>, target: &Isometry3<T>) -> Vector6<T> {
+        let mut linear = (target.translation.vector - current.translation.vector) * self.gain;
+        let mut angular = (target.rotation * current.rotation.inverse()).scaled_axis() * self.gain;
+        if let Some(max) = self.max_linear_velocity {
+            if linear.norm() > max {
+                linear *= max / linear.norm();
+            }
+        }
+        if let Some(max) = self.max_angular_velocity {
+            if angular.norm() > max {
+                angular *= max / angular.norm();
+            }
+        }
+        Vector6::new(
+            linear[0], linear[1], linear[2], angular[0], angular[1], angular[2],
+        )
+    }
+
+    fn damped_least_squares(
+        &self,
+        jacobi: &DMatrix<T>,
+        twist: &Vector6<T>,
+    ) -> Result<DVector<T>, Error> {
+        damped_least_squares(jacobi, twist, self.damping)
+    }
+}
+
+fn damped_least_squares<T>(
+    jacobi: &DMatrix<T>,
+    twist: &Vector6<T>,
+    damping: T,
+) -> Result<DVector<T>, Error>
+where
+    T: RealField,
+{
+    let damping = DMatrix::identity(6, 6) * (damping * damping);
+    let inv = (jacobi * jacobi.transpose() + damping)
+        .try_inverse()
+        .ok_or(Error::InverseMatrixError)?;
+    Ok(jacobi.transpose() * inv * DVector::from_column_slice(twist.as_slice()))
+}
+
+/// Joint velocities for a twist of the end, and how much the twist was scaled down
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScaledJointVelocities<T: RealField> {
+    /// the joint velocities of the arm
+    pub velocities: Vec<T>,
+    /// the ratio of the achieved twist to the commanded one, in `[0, 1]`
+    ///
+    /// It is `1` if all the joint velocity limits are satisfied without scaling.
+    pub scale: T,
+}
+
+impl<T: RealField> ScaledJointVelocities<T> {
+    /// `true` if the commanded twist was scaled down to satisfy the limits
+    pub fn is_scaled(&self) -> bool {
+        self.scale < T::one()
+    }
+}
+
+/// Calculate the joint velocities to move the end of `arm` by `twist`,
+/// respecting `Joint::velocity_limit`
+///
+/// `twist` is `[linear; angular]` of the end in the frame of the root of `arm`.
+/// The joint velocities are calculated by the damped least squares of the jacobian.
+/// If any of them exceeds its limit, all of them are scaled down by the same factor,
+/// so that the end moves in the commanded direction only slower.
+/// Saturating the joints independently would change the direction of the motion instead.
+///
+/// # Examples
+///
+/// ```
+/// use k::nalgebra::{DVector, Vector6};
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// for joint in arm.iter_joints() {
+///     assert!(joint.velocity_limit.is_some());
+/// }
+/// // too fast for the joints
+/// let twist = Vector6::new(0.0, 0.0, 100.0, 0.0, 0.0, 0.0);
+/// let result = k::inverse_velocity_kinematics(&arm, &twist, 1e-6).unwrap();
+/// assert!(result.is_scaled());
+/// for (v, joint) in result.velocities.iter().zip(arm.iter_joints()) {
+///     assert!(v.abs() <= joint.velocity_limit.unwrap() + 1e-10);
+/// }
+/// // the direction is kept
+/// let end_velocity = k::jacobian(&arm) * DVector::from_vec(result.velocities);
+/// let expected = twist * result.scale;
+/// assert!((end_velocity - DVector::from_column_slice(expected.as_slice())).norm() < 1e-3);
+/// ```
+pub fn inverse_velocity_kinematics<T>(
+    arm: &SerialChain<T>,
+    twist: &Vector6<T>,
+    damping: T,
+) -> Result<ScaledJointVelocities<T>, Error>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let mut velocities = damped_least_squares(&jacobian(arm), twist, damping)?;
+    let mut scale = T::one();
+    for (velocity, node) in velocities.iter().zip(arm.iter_position_nodes()) {
+        if let Some(limit) = node.joint().velocity_limit {
+            if velocity.abs() > limit {
+                scale = scale.min(limit / velocity.abs());
+            }
+        }
+    }
+    velocities *= scale;
+    Ok(ScaledJointVelocities {
+        velocities: velocities.iter().cloned().collect(),
+        scale,
+    })
+}
+
+impl<T> Default for CartesianServo<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn default() -> Self {
+        Self::new(na::convert(5.0), na::convert(0.01), na::convert(0.1))
+    }
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Calculate the joint velocities to move `end_node` by `twist`
+    ///
+    /// `twist` is `[linear; angular]` of `end_node` in the frame of the root of the chain.
+    /// The velocities are of the joints from the root to `end_node`, in the order of
+    /// `SerialChain::from_end(end_node)`, by the damped least squares of the jacobian
+    /// if `damping` is positive, otherwise by the pseudo inverse. The joint limits
+    /// are not considered, use `inverse_velocity_kinematics()` to respect the velocity
+    /// limits.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::nalgebra::{DVector, Vector6};
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let wrist = chain.find("l_wrist_pitch").unwrap();
+    /// let arm = k::SerialChain::from_end(wrist);
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    ///
+    /// let twist = Vector6::new(0.0, 0.0, 0.1, 0.0, 0.0, 0.0);
+    /// let velocities = chain.solve_velocity(wrist, &twist, 0.0).unwrap();
+    /// let end_velocity = k::jacobian(&arm) * DVector::from_vec(velocities);
+    /// assert!((end_velocity - DVector::from_column_slice(twist.as_slice())).norm() < 1e-10);
+    /// ```
+    pub fn solve_velocity(
+        &self,
+        end_node: &Node<T>,
+        twist: &Vector6<T>,
+        damping: T,
+    ) -> Result<Vec<T>, Error> {
+        if !self.iter().any(|node| node == end_node) {
+            return Err(Error::JointNotFoundError {
+                joint_name: end_node.joint().name.clone(),
+            });
+        }
+        let jacobi = jacobian(&SerialChain::from_end(end_node));
+        let velocities = if damping > T::zero() {
+            damped_least_squares(&jacobi, twist, damping)?
+        } else {
+            jacobi
+                .pseudo_inverse(Tolerances::<T>::default().singular_value)
+                .map_err(|_| Error::InverseMatrixError)?
+                * DVector::from_column_slice(twist.as_slice())
+        };
+        Ok(velocities.iter().cloned().collect())
+    }
+}
+
+#[test]
+fn test_servo_velocity_limit() {
+    use super::joint::*;
+    use super::node::*;
+    let j0 = NodeBuilder::new()
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::x_axis(),
+        })
+        .limits(Some((-1.0..=1.0).into()))
+        .velocity_limit(Some(0.2))
+        .acceleration_limit(Some(1.0))
+        .into_node();
+    let arm = SerialChain::<f64>::from_end(&j0);
+    let mut servo = CartesianServo::default();
+    let target = Isometry3::translation(0.5, 0.0, 0.0);
+    assert!(servo.update(&arm, &target, 0.0).is_err());
+    // limited by the acceleration at first
+    let v = servo.update(&arm, &target, 0.1).unwrap();
+    assert!((v[0] - 0.1).abs() < 1e-10);
+    let v = servo.update(&arm, &target, 0.1).unwrap();
+    assert!((v[0] - 0.2).abs() < 1e-10);
+    for _ in 0..100 {
+        servo.update(&arm, &target, 0.1).unwrap();
+    }
+    assert!((arm.joint_positions()[0] - 0.5).abs() < 1e-3);
+}
+
+#[test]
+fn test_servo_limit_horizon() {
+    use super::joint::*;
+    use super::node::*;
+    let j0 = NodeBuilder::new()
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::x_axis(),
+        })
+        .limits(Some((-1.0..=0.3).into()))
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::y_axis(),
+        })
+        .into_node();
+    j1.set_parent(&j0);
+    let arm = SerialChain::<f64>::from_end(&j1);
+    let mut servo = CartesianServo::new(5.0, 0.01, 0.0);
+    servo.limit_horizon = Some(0.5);
+    let target = Isometry3::translation(0.5, 0.5, 0.0);
+    let v = servo.update(&arm, &target, 0.01).unwrap();
+    // the free joint is scaled together
+    assert!((v[0] - v[1]).abs() < 1e-6);
+    assert!(v[0] * 0.5 <= 0.3 + 1e-10);
+    let mut last = v[0];
+    for _ in 0..400 {
+        let v = servo.update(&arm, &target, 0.01).unwrap();
+        assert!(v[0] <= last + 1e-10);
+        last = v[0];
+        assert!(arm.joint_positions()[0] <= 0.3);
+    }
+    assert!(last < 0.01);
+}
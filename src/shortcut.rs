@@ -0,0 +1,166 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::bench::Rng;
+use super::chain::*;
+use super::errors::*;
+use na::RealField;
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Checker of the joint positions, e.g. collision checking, used by `shortcut_path()`
+pub trait StateValidator<T: RealField> {
+    /// Returns true if the positions are acceptable
+    fn is_valid(&self, positions: &[T]) -> bool;
+}
+
+impl<T, F> StateValidator<T> for F
+where
+    T: RealField,
+    F: Fn(&[T]) -> bool,
+{
+    fn is_valid(&self, positions: &[T]) -> bool {
+        self(positions)
+    }
+}
+
+/// Parameters of `shortcut_path()`
+#[derive(Debug, Clone)]
+pub struct ShortcutConfig<T: RealField> {
+    /// number of the random shortcut attempts
+    pub num_attempts: usize,
+    /// the maximum step of the joint positions to check the states on a shortcut
+    pub resolution: T,
+    /// seed of the random numbers, the result is deterministic with the same seed
+    pub seed: u64,
+}
+
+impl<T: RealField> Default for ShortcutConfig<T> {
+    fn default() -> Self {
+        Self {
+            num_attempts: 100,
+            resolution: na::convert(0.05),
+            seed: 0,
+        }
+    }
+}
+
+/// Shorten a path of joint positions by removing the waypoints which can be skipped
+///
+/// Two random waypoints are connected by the linear interpolation, and the waypoints
+/// between them are removed if all the states on it are in the limits of `chain` and
+/// accepted by `validator`. The first and the last waypoints are kept.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// let j0 = NodeBuilder::new()
+///     .joint_type(JointType::Linear{axis: Vector3::x_axis()})
+///     .limits(Some((-1.0..=1.0).into()))
+///     .into_node();
+/// let j1 = NodeBuilder::new()
+///     .joint_type(JointType::Linear{axis: Vector3::y_axis()})
+///     .limits(Some((-1.0..=1.0).into()))
+///     .into_node();
+/// j1.set_parent(&j0);
+/// let chain = Chain::<f64>::from_root(j0);
+///
+/// // a detour around the obstacle at the center
+/// let path = vec![
+///     vec![-0.8, 0.0],
+///     vec![-0.8, 0.6],
+///     vec![-0.4, 0.6],
+///     vec![0.0, 0.6],
+///     vec![0.4, 0.6],
+///     vec![0.8, 0.6],
+///     vec![0.8, 0.0],
+/// ];
+/// let validator = |q: &[f64]| q[0].abs() > 0.3 || q[1].abs() > 0.3;
+/// let shortened = shortcut_path(&chain, &path, &validator, &ShortcutConfig::default()).unwrap();
+/// assert!(shortened.len() < path.len());
+/// assert_eq!(shortened.first(), path.first());
+/// assert_eq!(shortened.last(), path.last());
+/// ```
+pub fn shortcut_path<T, V>(
+    chain: &Chain<T>,
+    path: &[Vec<T>],
+    validator: &V,
+    config: &ShortcutConfig<T>,
+) -> Result<Vec<Vec<T>>, Error>
+where
+    T: RealField + SubsetOf<f64>,
+    V: StateValidator<T>,
+{
+    for positions in path {
+        if positions.len() != chain.dof() {
+            return Err(Error::SizeMismatchError {
+                input: positions.len(),
+                required: chain.dof(),
+            });
+        }
+    }
+    let limits = chain
+        .iter_position_nodes()
+        .map(|node| node.joint().effective_limits())
+        .collect::<Vec<_>>();
+    let is_valid = |positions: &[T]| {
+        positions.iter().zip(&limits).all(|(q, range)| match range {
+            Some(range) => range.is_valid(*q),
+            None => true,
+        }) && validator.is_valid(positions)
+    };
+    let mut path = path.to_vec();
+    let mut rng = Rng::new(config.seed);
+    for _ in 0..config.num_attempts {
+        if path.len() < 3 {
+            break;
+        }
+        let mut i = (rng.next_f64() * path.len() as f64) as usize;
+        let mut j = (rng.next_f64() * path.len() as f64) as usize;
+        if i > j {
+            std::mem::swap(&mut i, &mut j);
+        }
+        if j < i + 2 {
+            continue;
+        }
+        if is_valid_segment(&path[i], &path[j], config.resolution, &is_valid) {
+            path.drain(i + 1..j);
+        }
+    }
+    Ok(path)
+}
+
+fn is_valid_segment<T, F>(from: &[T], to: &[T], resolution: T, is_valid: &F) -> bool
+where
+    T: RealField + SubsetOf<f64>,
+    F: Fn(&[T]) -> bool,
+{
+    let max_diff = from
+        .iter()
+        .zip(to)
+        .fold(T::zero(), |max, (a, b)| max.max((*b - *a).abs()));
+    let num_steps = na::convert::<T, f64>(max_diff / resolution).ceil().max(1.0) as usize;
+    (0..=num_steps).all(|step| {
+        let ratio: T = na::convert(step as f64 / num_steps as f64);
+        let positions = from
+            .iter()
+            .zip(to)
+            .map(|(a, b)| *a + (*b - *a) * ratio)
+            .collect::<Vec<_>>();
+        is_valid(&positions)
+    })
+}
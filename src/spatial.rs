@@ -0,0 +1,193 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::joint::*;
+use na::{Isometry3, RealField, Vector3};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Force and torque
+///
+/// The torque is about the origin of the frame which the wrench is expressed in.
+#[derive(Clone, Debug, Copy, Default, PartialEq)]
+pub struct Wrench<T: RealField> {
+    pub force: Vector3<T>,
+    pub torque: Vector3<T>,
+}
+
+impl<T> Wrench<T>
+where
+    T: RealField,
+{
+    pub fn from_parts(force: Vector3<T>, torque: Vector3<T>) -> Self {
+        Self { force, torque }
+    }
+    pub fn zero() -> Self {
+        Self {
+            force: Vector3::zeros(),
+            torque: Vector3::zeros(),
+        }
+    }
+    /// Move the reference point of the torque by `offset`, without rotation
+    ///
+    /// `offset` is the new reference point seen from the current reference point.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::{Vector3, Wrench};
+    ///
+    /// let w = Wrench::from_parts(Vector3::new(0.0, 0.0, -10.0), Vector3::zeros());
+    /// let shifted = w.shift_reference_point(&Vector3::new(-1.0, 0.0, 0.0));
+    /// assert_eq!(shifted.torque, Vector3::new(0.0, 10.0, 0.0));
+    /// ```
+    pub fn shift_reference_point(&self, offset: &Vector3<T>) -> Self {
+        Self {
+            force: self.force,
+            torque: self.torque - offset.cross(&self.force),
+        }
+    }
+    /// Express the wrench in another frame
+    ///
+    /// `transform` is the pose of the current frame seen from the new frame.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// // F/T sensor 0.1 below the tool frame, with the same orientation
+    /// let sensor_in_tool = Isometry3::translation(0.0, 0.0, -0.1);
+    /// let measured = Wrench::from_parts(Vector3::new(1.0, 0.0, 0.0), Vector3::zeros());
+    /// let in_tool = measured.transform(&sensor_in_tool);
+    /// assert_eq!(in_tool.force, Vector3::new(1.0, 0.0, 0.0));
+    /// assert!((in_tool.torque - Vector3::new(0.0, -0.1, 0.0)).norm() < 1e-10);
+    /// ```
+    pub fn transform(&self, transform: &Isometry3<T>) -> Self {
+        let force = transform.rotation * self.force;
+        Self {
+            force,
+            torque: transform.rotation * self.torque + transform.translation.vector.cross(&force),
+        }
+    }
+}
+
+impl<T> Velocity<T>
+where
+    T: RealField,
+{
+    /// Move the reference point of the translational velocity by `offset`, without rotation
+    ///
+    /// `offset` is the new reference point seen from the current reference point.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::Vector3;
+    /// use k::joint::Velocity;
+    ///
+    /// let v = Velocity::from_parts(Vector3::zeros(), Vector3::new(0.0, 0.0, 1.0));
+    /// let shifted = v.shift_reference_point(&Vector3::new(1.0, 0.0, 0.0));
+    /// assert_eq!(shifted.translation, Vector3::new(0.0, 1.0, 0.0));
+    /// ```
+    pub fn shift_reference_point(&self, offset: &Vector3<T>) -> Self {
+        Self::from_parts(
+            self.translation + self.rotation.cross(offset),
+            self.rotation,
+        )
+    }
+    /// Express the velocity (twist) in another frame
+    ///
+    /// `transform` is the pose of the current frame seen from the new frame.
+    /// The reference point is also moved to the origin of the new frame.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use k::joint::Velocity;
+    ///
+    /// let v = Velocity::from_parts(Vector3::zeros(), Vector3::new(0.0, 0.0, 1.0));
+    /// let in_other = v.transform(&Isometry3::translation(1.0, 0.0, 0.0));
+    /// assert_eq!(in_other.rotation, Vector3::new(0.0, 0.0, 1.0));
+    /// assert_eq!(in_other.translation, Vector3::new(0.0, -1.0, 0.0));
+    /// ```
+    pub fn transform(&self, transform: &Isometry3<T>) -> Self {
+        let rotation = transform.rotation * self.rotation;
+        Self::from_parts(
+            transform.rotation * self.translation + transform.translation.vector.cross(&rotation),
+            rotation,
+        )
+    }
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Calculate the pose of the frame of `from_joint` seen from the frame of `to_joint`
+    ///
+    /// It uses the current joint positions. The result can be used for
+    /// `Wrench::transform()` and `Velocity::transform()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let wrist_in_shoulder = chain.relative_transform("r_wrist_pitch", "r_shoulder_yaw").unwrap();
+    /// chain.update_transforms();
+    /// let wrist = chain.find("r_wrist_pitch").unwrap().world_transform().unwrap();
+    /// let shoulder = chain.find("r_shoulder_yaw").unwrap().world_transform().unwrap();
+    /// assert!(((shoulder * wrist_in_shoulder).translation.vector - wrist.translation.vector).norm() < 1e-10);
+    /// assert!(chain.relative_transform("r_wrist_pitch", "no_joint").is_err());
+    /// ```
+    pub fn relative_transform(
+        &self,
+        from_joint: &str,
+        to_joint: &str,
+    ) -> Result<Isometry3<T>, Error> {
+        let find = |name: &str| {
+            self.find(name).ok_or_else(|| Error::JointNotFoundError {
+                joint_name: name.to_owned(),
+            })
+        };
+        let from = find(from_joint)?;
+        let to = find(to_joint)?;
+        self.update_transforms();
+        let from_transform = from.world_transform().expect("transforms are updated");
+        let to_transform = to.world_transform().expect("transforms are updated");
+        Ok(to_transform.inverse() * from_transform)
+    }
+}
+
+#[test]
+fn test_wrench_and_velocity_transform_are_consistent() {
+    // power (wrench . twist) does not depend on the frame
+    let transform = Isometry3::from_parts(
+        na::Translation3::new(0.3, -0.2, 0.5),
+        na::UnitQuaternion::from_euler_angles(0.1, 0.2, 0.3),
+    );
+    let wrench = Wrench::from_parts(Vector3::new(1.0f64, 2.0, 3.0), Vector3::new(-0.5, 0.2, 0.1));
+    let velocity = Velocity::from_parts(Vector3::new(0.1, 0.0, 0.2), Vector3::new(0.3, -0.1, 0.2));
+    let power = wrench.force.dot(&velocity.translation) + wrench.torque.dot(&velocity.rotation);
+    let w = wrench.transform(&transform);
+    let v = velocity.transform(&transform);
+    let transformed_power = w.force.dot(&v.translation) + w.torque.dot(&v.rotation);
+    assert!((power - transformed_power).abs() < 1e-10);
+}
@@ -0,0 +1,395 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::node::*;
+use na::RealField;
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+use std::path::Path;
+
+/// `<chain>` element of a group, the joints from `base_link` to `tip_link`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrdfChain {
+    pub base_link: String,
+    pub tip_link: String,
+}
+
+/// Planning group of the joints
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SrdfGroup {
+    pub name: String,
+    pub joints: Vec<String>,
+    /// the joints of the links (the joint above the link) are in the group
+    pub links: Vec<String>,
+    pub chains: Vec<SrdfChain>,
+    /// names of the groups included in this group
+    pub subgroups: Vec<String>,
+}
+
+/// Named end effector attached to `parent_link`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrdfEndEffector {
+    pub name: String,
+    pub parent_link: String,
+    /// group of the joints of the end effector itself, like the fingers
+    pub group: String,
+    pub parent_group: Option<String>,
+}
+
+/// Joint between the robot and the world, like the mobile base
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrdfVirtualJoint {
+    pub name: String,
+    /// `"fixed"`, `"floating"` or `"planar"`
+    pub joint_type: String,
+    pub parent_frame: String,
+    pub child_link: String,
+}
+
+/// Pair of the links which are never checked for collisions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrdfDisabledCollision {
+    pub link1: String,
+    pub link2: String,
+    pub reason: Option<String>,
+}
+
+/// Semantic information of the robot loaded from SRDF (Semantic Robot Description Format)
+///
+/// The groups, the end effectors, the virtual joints and the disabled collision pairs
+/// configured for MoveIt are loaded. The joints and the links are resolved by the names
+/// in `Chain` loaded from the URDF of the same robot.
+///
+/// # Examples
+///
+/// ```
+/// let srdf = k::Srdf::from_file("urdf/sample.srdf").unwrap();
+/// assert_eq!(srdf.name, "robo");
+/// assert_eq!(srdf.groups.len(), 5);
+/// assert_eq!(srdf.virtual_joints[0].child_link, "root_body");
+/// assert!(srdf.is_collision_disabled("l_shoulder1", "root_body"));
+/// assert!(!srdf.is_collision_disabled("l_wrist2", "r_wrist2"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Srdf {
+    pub name: String,
+    pub groups: Vec<SrdfGroup>,
+    pub end_effectors: Vec<SrdfEndEffector>,
+    pub virtual_joints: Vec<SrdfVirtualJoint>,
+    pub disabled_collisions: Vec<SrdfDisabledCollision>,
+}
+
+impl Srdf {
+    /// Load SRDF from the file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let string = std::fs::read_to_string(path.as_ref()).map_err(|e| Error::SrdfError {
+            reason: format!("failed to read {}: {}", path.as_ref().display(), e),
+        })?;
+        Self::from_xml_str(&string)
+    }
+
+    /// Load SRDF from the XML string
+    ///
+    /// Unknown elements like `<group_state>` and `<passive_joint>` are ignored.
+    pub fn from_xml_str(string: &str) -> Result<Self, Error> {
+        let root: xml::Element = string.parse().map_err(|e| Error::SrdfError {
+            reason: format!("{:?}", e),
+        })?;
+        if root.name != "robot" {
+            return Err(Error::SrdfError {
+                reason: format!("root element must be <robot>, but <{}>", root.name),
+            });
+        }
+        let mut srdf = Srdf {
+            name: attribute(&root, "name")?,
+            ..Default::default()
+        };
+        for group in root.get_children("group", None) {
+            srdf.groups.push(SrdfGroup {
+                name: attribute(group, "name")?,
+                joints: names_of_children(group, "joint")?,
+                links: names_of_children(group, "link")?,
+                chains: group
+                    .get_children("chain", None)
+                    .map(|c| {
+                        Ok(SrdfChain {
+                            base_link: attribute(c, "base_link")?,
+                            tip_link: attribute(c, "tip_link")?,
+                        })
+                    })
+                    .collect::<Result<_, Error>>()?,
+                subgroups: names_of_children(group, "group")?,
+            });
+        }
+        for e in root.get_children("end_effector", None) {
+            srdf.end_effectors.push(SrdfEndEffector {
+                name: attribute(e, "name")?,
+                parent_link: attribute(e, "parent_link")?,
+                group: attribute(e, "group")?,
+                parent_group: e.get_attribute("parent_group", None).map(str::to_owned),
+            });
+        }
+        for j in root.get_children("virtual_joint", None) {
+            srdf.virtual_joints.push(SrdfVirtualJoint {
+                name: attribute(j, "name")?,
+                joint_type: attribute(j, "type")?,
+                parent_frame: attribute(j, "parent_frame")?,
+                child_link: attribute(j, "child_link")?,
+            });
+        }
+        for d in root.get_children("disable_collisions", None) {
+            srdf.disabled_collisions.push(SrdfDisabledCollision {
+                link1: attribute(d, "link1")?,
+                link2: attribute(d, "link2")?,
+                reason: d.get_attribute("reason", None).map(str::to_owned),
+            });
+        }
+        Ok(srdf)
+    }
+
+    /// Find the group by the name
+    pub fn group(&self, name: &str) -> Option<&SrdfGroup> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    /// Find the end effector by the name
+    pub fn end_effector(&self, name: &str) -> Option<&SrdfEndEffector> {
+        self.end_effectors.iter().find(|e| e.name == name)
+    }
+
+    /// Returns true if the collision between the links is disabled, in any order
+    pub fn is_collision_disabled(&self, link1: &str, link2: &str) -> bool {
+        self.disabled_collisions.iter().any(|d| {
+            (d.link1 == link1 && d.link2 == link2) || (d.link1 == link2 && d.link2 == link1)
+        })
+    }
+
+    /// Names of the joints of the group in `chain`
+    ///
+    /// The joints, the links, the chains and the subgroups of the group are resolved
+    /// in this order, and the duplicated joints are removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let srdf = k::Srdf::from_file("urdf/sample.srdf").unwrap();
+    /// let names = srdf.group_joint_names(&chain, "l_arm").unwrap();
+    /// assert_eq!(names.len(), 6);
+    /// assert_eq!(names[0], "l_shoulder_yaw");
+    /// assert_eq!(srdf.group_joint_names(&chain, "arms").unwrap().len(), 12);
+    /// assert_eq!(srdf.group_joint_names(&chain, "l_hand").unwrap(), vec!["l_wrist_pitch"]);
+    /// ```
+    pub fn group_joint_names<T>(&self, chain: &Chain<T>, group: &str) -> Result<Vec<String>, Error>
+    where
+        T: RealField + SubsetOf<f64>,
+    {
+        let mut names = Vec::new();
+        self.collect_group_joint_names(chain, group, &mut vec![], &mut names)?;
+        Ok(names)
+    }
+
+    fn collect_group_joint_names<T>(
+        &self,
+        chain: &Chain<T>,
+        group: &str,
+        visiting: &mut Vec<String>,
+        names: &mut Vec<String>,
+    ) -> Result<(), Error>
+    where
+        T: RealField + SubsetOf<f64>,
+    {
+        if visiting.iter().any(|g| g == group) {
+            return Err(Error::SrdfError {
+                reason: format!("group {} includes itself", group),
+            });
+        }
+        let group = self.group(group).ok_or_else(|| Error::SrdfError {
+            reason: format!("group {} is not found", group),
+        })?;
+        let mut push = |name: String| {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        };
+        for joint_name in &group.joints {
+            if chain.find(joint_name).is_none() {
+                return Err(Error::JointNotFoundError {
+                    joint_name: joint_name.clone(),
+                });
+            }
+            push(joint_name.clone());
+        }
+        for link_name in &group.links {
+            push(find_link_node(chain, link_name)?.joint().name.clone());
+        }
+        for c in &group.chains {
+            for node in chain_nodes(chain, c)? {
+                push(node.joint().name.clone());
+            }
+        }
+        visiting.push(group.name.clone());
+        for subgroup in &group.subgroups {
+            self.collect_group_joint_names(chain, subgroup, visiting, names)?;
+        }
+        visiting.pop();
+        Ok(())
+    }
+
+    /// `SerialChain` of the group defined by a `<chain>`
+    ///
+    /// The joint of the base link is not included, as MoveIt does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let srdf = k::Srdf::from_file("urdf/sample.srdf").unwrap();
+    /// let arm = srdf.group_serial_chain(&chain, "r_arm").unwrap();
+    /// assert_eq!(arm.dof(), 6);
+    /// assert_eq!(arm.iter().next().unwrap().joint().name, "r_shoulder_yaw");
+    /// // not a chain
+    /// assert!(srdf.group_serial_chain(&chain, "arms").is_err());
+    /// ```
+    pub fn group_serial_chain<T>(
+        &self,
+        chain: &Chain<T>,
+        group: &str,
+    ) -> Result<SerialChain<T>, Error>
+    where
+        T: RealField + SubsetOf<f64>,
+    {
+        let srdf_group = self.group(group).ok_or_else(|| Error::SrdfError {
+            reason: format!("group {} is not found", group),
+        })?;
+        match srdf_group.chains.as_slice() {
+            [c] => {
+                let nodes = chain_nodes(chain, c)?;
+                Ok(SerialChain::from_end_to_root(
+                    &nodes[nodes.len() - 1],
+                    &nodes[0],
+                ))
+            }
+            _ => Err(Error::SrdfError {
+                reason: format!("group {} is not defined by a chain", group),
+            }),
+        }
+    }
+
+    /// Node of the parent link of the end effector, the tip to solve IK for
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let srdf = k::Srdf::from_file("urdf/sample.srdf").unwrap();
+    /// let tip = srdf.end_effector_node(&chain, "l_end").unwrap();
+    /// assert_eq!(tip.joint().name, "l_wrist_pitch");
+    /// ```
+    pub fn end_effector_node<T>(&self, chain: &Chain<T>, name: &str) -> Result<Node<T>, Error>
+    where
+        T: RealField + SubsetOf<f64>,
+    {
+        let end_effector = self.end_effector(name).ok_or_else(|| Error::SrdfError {
+            reason: format!("end effector {} is not found", name),
+        })?;
+        find_link_node(chain, &end_effector.parent_link)
+    }
+}
+
+fn attribute(element: &xml::Element, name: &str) -> Result<String, Error> {
+    element
+        .get_attribute(name, None)
+        .map(str::to_owned)
+        .ok_or_else(|| Error::SrdfError {
+            reason: format!("<{}> has no attribute {}", element.name, name),
+        })
+}
+
+fn names_of_children(element: &xml::Element, child: &str) -> Result<Vec<String>, Error> {
+    element
+        .get_children(child, None)
+        .map(|c| attribute(c, "name"))
+        .collect()
+}
+
+fn find_link_node<T>(chain: &Chain<T>, link_name: &str) -> Result<Node<T>, Error>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    chain
+        .iter()
+        .find(|node| match *node.link() {
+            Some(ref link) => link.name == link_name,
+            None => false,
+        })
+        .cloned()
+        .ok_or_else(|| Error::SrdfError {
+            reason: format!("link {} is not found", link_name),
+        })
+}
+
+/// Nodes from the child of the base link to the tip link
+fn chain_nodes<T>(chain: &Chain<T>, srdf_chain: &SrdfChain) -> Result<Vec<Node<T>>, Error>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let base = find_link_node(chain, &srdf_chain.base_link)?;
+    let tip = find_link_node(chain, &srdf_chain.tip_link)?;
+    let mut nodes = Vec::new();
+    for node in tip.iter_ancestors() {
+        if node == base && !nodes.is_empty() {
+            nodes.reverse();
+            return Ok(nodes);
+        }
+        nodes.push(node);
+    }
+    Err(Error::SrdfError {
+        reason: format!(
+            "link {} is not a descendant of {}",
+            srdf_chain.tip_link, srdf_chain.base_link
+        ),
+    })
+}
+
+#[test]
+fn test_srdf_errors() {
+    assert!(matches!(
+        Srdf::from_xml_str("<robot><group/></robot>"),
+        Err(Error::SrdfError { .. })
+    ));
+    assert!(Srdf::from_xml_str("<model name=\"a\"/>").is_err());
+    assert!(Srdf::from_xml_str("<robot name=\"a\"").is_err());
+    let srdf = Srdf::from_xml_str(
+        r#"<robot name="a">
+             <group name="g0"><group name="g1"/></group>
+             <group name="g1"><group name="g0"/></group>
+             <group name="g2"><joint name="not_exist"/></group>
+             <group name="g3"><chain base_link="l_wrist2" tip_link="root_body"/></group>
+           </robot>"#,
+    )
+    .unwrap();
+    let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    assert!(srdf.group_joint_names(&chain, "g0").is_err());
+    assert!(matches!(
+        srdf.group_joint_names(&chain, "g2"),
+        Err(Error::JointNotFoundError { .. })
+    ));
+    assert!(srdf.group_joint_names(&chain, "g3").is_err());
+    assert!(srdf.group_joint_names(&chain, "g4").is_err());
+    assert!(srdf.end_effector_node(&chain, "e").is_err());
+}
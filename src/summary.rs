@@ -0,0 +1,154 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::joint::*;
+use na::RealField;
+use nalgebra as na;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+use simba::scalar::SubsetOf;
+
+/// Limits of a movable joint, used in `ChainSummary`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct JointLimitSummary<T: RealField> {
+    /// name of the joint
+    pub joint_name: String,
+    /// `None` means the joint has no limits
+    pub limits: Option<Range<T>>,
+}
+
+/// Machine-readable statistics of a `Chain`
+///
+/// Created by `Chain::summary()`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct ChainSummary<T: RealField> {
+    /// number of all nodes, including fixed joints
+    pub num_nodes: usize,
+    /// degree of freedom, the same as `Chain::dof()`
+    pub dof: usize,
+    /// number of `JointType::Fixed`
+    pub num_fixed: usize,
+    /// number of `JointType::Rotational`
+    pub num_rotational: usize,
+    /// number of `JointType::Linear`
+    pub num_linear: usize,
+    /// number of `JointType::Universal`
+    pub num_universal: usize,
+    /// number of `JointType::Planar`
+    pub num_planar: usize,
+    /// number of `JointType::Floating`
+    pub num_floating: usize,
+    /// number of joints which mimic other joints
+    pub num_mimic: usize,
+    /// limits of the movable joints, in the same order as `Chain::iter_joints()`
+    pub limits: Vec<JointLimitSummary<T>>,
+    /// number of nodes in the longest path from the root to an end
+    pub depth: usize,
+    /// joint names of the longest path from the root to an end
+    pub longest_branch: Vec<String>,
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create the summary of the structure of this chain
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let summary = chain.summary();
+    /// assert_eq!(summary.num_nodes, 13);
+    /// assert_eq!(summary.dof, 12);
+    /// assert_eq!(summary.num_fixed, 1);
+    /// assert_eq!(summary.num_rotational, 12);
+    /// assert_eq!(summary.num_linear, 0);
+    /// assert_eq!(summary.num_universal, 0);
+    /// assert_eq!(summary.num_planar, 0);
+    /// assert_eq!(summary.num_floating, 0);
+    /// assert_eq!(summary.limits.len(), 12);
+    /// assert_eq!(summary.depth, 7);
+    /// assert_eq!(summary.longest_branch[0], "root");
+    /// assert_eq!(summary.longest_branch.len(), summary.depth);
+    /// ```
+    pub fn summary(&self) -> ChainSummary<T> {
+        let nodes = self.iter().collect::<Vec<_>>();
+        let mut num_fixed = 0;
+        let mut num_rotational = 0;
+        let mut num_linear = 0;
+        let mut num_universal = 0;
+        let mut num_planar = 0;
+        let mut num_floating = 0;
+        let mut num_mimic = 0;
+        // (depth, index of parent in `nodes`)
+        let mut depths: Vec<(usize, Option<usize>)> = Vec::with_capacity(nodes.len());
+        for node in &nodes {
+            match node.joint().joint_type {
+                JointType::Fixed => num_fixed += 1,
+                JointType::Rotational { .. } => num_rotational += 1,
+                JointType::Linear { .. } => num_linear += 1,
+                JointType::Universal { .. } => num_universal += 1,
+                JointType::Planar { .. } => num_planar += 1,
+                JointType::Floating { .. } => num_floating += 1,
+            }
+            if node.mimic_parent().is_some() {
+                num_mimic += 1;
+            }
+            let parent_index = node
+                .parent()
+                .and_then(|parent| nodes.iter().position(|n| **n == parent));
+            let depth = match parent_index {
+                Some(index) => depths[index].0 + 1,
+                None => 1,
+            };
+            depths.push((depth, parent_index));
+        }
+        let mut longest_branch = Vec::new();
+        let mut depth = 0;
+        if let Some(end_index) = (0..depths.len()).max_by_key(|i| (depths[*i].0, usize::MAX - i)) {
+            depth = depths[end_index].0;
+            let mut index = Some(end_index);
+            while let Some(i) = index {
+                longest_branch.push(nodes[i].joint().name.clone());
+                index = depths[i].1;
+            }
+            longest_branch.reverse();
+        }
+        ChainSummary {
+            num_nodes: nodes.len(),
+            dof: self.dof(),
+            num_fixed,
+            num_rotational,
+            num_linear,
+            num_universal,
+            num_planar,
+            num_floating,
+            num_mimic,
+            limits: self
+                .iter_joints()
+                .map(|joint| JointLimitSummary {
+                    joint_name: joint.name.clone(),
+                    limits: joint.limits,
+                })
+                .collect(),
+            depth,
+            longest_branch,
+        }
+    }
+}
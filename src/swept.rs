@@ -0,0 +1,236 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::batch::*;
+use super::chain::*;
+use super::errors::*;
+use super::funcs::*;
+use super::link::*;
+use super::trajectory::*;
+use na::{DMatrix, Point3, RealField, Vector3};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Point cloud swept by the task points along a trajectory, created by `Chain::swept_points()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweptPoints<T: RealField> {
+    /// time of each sample
+    pub times: Vec<T>,
+    /// `points[i][j]` is the position of the j-th task point at the i-th sample
+    pub points: Vec<Vec<Vector3<T>>>,
+}
+
+impl<T: RealField> SweptPoints<T> {
+    /// All the points of all the samples
+    pub fn cloud(&self) -> Vec<Vector3<T>> {
+        self.points.iter().flatten().cloned().collect()
+    }
+
+    /// Axis aligned bounding box of `cloud()` as (min, max), `None` if there are no points
+    pub fn bounding_box(&self) -> Option<(Vector3<T>, Vector3<T>)> {
+        let mut points = self.points.iter().flatten();
+        let first = *points.next()?;
+        Some(points.fold((first, first), |(min, max), p| (min.inf(p), max.sup(p))))
+    }
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Sample the positions of `task_points` along `trajectory` using `backend`
+    ///
+    /// The points of the trajectory are used as the samples if `period` is `None`,
+    /// otherwise it is resampled by `JointTrajectory::resample()` at first.
+    /// The positions are in the frame of the root of the chain, like
+    /// `BatchFkBackend::batch_transforms()`. It is a cheap precursor to collision checking,
+    /// e.g. to visualize the clearance of the workspace.
+    ///
+    /// Returns `Error::JointNotFoundError` if the node of a task point is not in the chain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use k::nalgebra::Point3;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let hand = chain.find("l_wrist_pitch").unwrap();
+    /// let points = vec![TaskPoint::new(hand.clone(), Vector3::new(0.0, 0.0, -0.1))];
+    /// let trajectory = JointTrajectory::new(vec![
+    ///     TrajectoryPoint::new(vec![0.0; 12], 0.0),
+    ///     TrajectoryPoint::new(vec![0.5; 12], 1.0),
+    /// ]);
+    /// let swept = chain
+    ///     .swept_points(&CpuBatchFkBackend, &trajectory, &points, Some(0.1))
+    ///     .unwrap();
+    /// assert_eq!(swept.times.len(), 11);
+    ///
+    /// // the same as the forward kinematics of the last sample
+    /// chain.set_joint_positions(&[0.5; 12]).unwrap();
+    /// chain.update_transforms();
+    /// let end = hand.world_transform().unwrap() * Point3::new(0.0, 0.0, -0.1);
+    /// assert!((swept.points[10][0] - end.coords).norm() < 1e-6);
+    /// let (min, max) = swept.bounding_box().unwrap();
+    /// assert!(min.x <= end.x && end.x <= max.x);
+    /// ```
+    pub fn swept_points<B>(
+        &self,
+        backend: &B,
+        trajectory: &JointTrajectory<T>,
+        task_points: &[TaskPoint<T>],
+        period: Option<T>,
+    ) -> Result<SweptPoints<T>, Error>
+    where
+        B: BatchFkBackend<T>,
+    {
+        let indices = task_points
+            .iter()
+            .map(|p| {
+                self.iter().position(|node| *node == p.node).ok_or_else(|| {
+                    Error::JointNotFoundError {
+                        joint_name: p.node.joint().name.clone(),
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let resampled;
+        let trajectory = match period {
+            Some(period) => {
+                resampled = trajectory.resample(period, None)?;
+                &resampled
+            }
+            None => trajectory,
+        };
+        let mut configurations = DMatrix::zeros(trajectory.points.len(), self.dof());
+        for (mut row, point) in configurations.row_iter_mut().zip(&trajectory.points) {
+            if point.positions.len() != self.dof() {
+                return Err(Error::SizeMismatchError {
+                    input: point.positions.len(),
+                    required: self.dof(),
+                });
+            }
+            row.copy_from_slice(&point.positions);
+        }
+        let transforms = backend.batch_transforms(&self.to_flat(), &configurations)?;
+        Ok(SweptPoints {
+            times: trajectory
+                .points
+                .iter()
+                .map(|p| p.time_from_start)
+                .collect(),
+            points: transforms
+                .iter()
+                .map(|sample| {
+                    task_points
+                        .iter()
+                        .zip(&indices)
+                        .map(|(p, index)| (sample[*index] * Point3::from(p.offset)).coords)
+                        .collect()
+                })
+                .collect(),
+        })
+    }
+
+    /// Representative points of the collision shapes of the links, for `swept_points()`
+    ///
+    /// The corners of the boxes, the poles of the spheres, eight points on each rim of the
+    /// cylinders and the capsules (with the tips of the capsules), and the origins of the
+    /// meshes are used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use k::link::*;
+    ///
+    /// let node = NodeBuilder::<f64>::new().into_node();
+    /// let link = LinkBuilder::new()
+    ///     .add_collision(Collision::new(
+    ///         "box".to_owned(),
+    ///         Isometry3::identity(),
+    ///         Geometry::Box { depth: 0.2, width: 0.2, height: 0.2 },
+    ///     ))
+    ///     .finalize();
+    /// node.set_link(Some(link));
+    /// let chain = Chain::from_root(node);
+    /// assert_eq!(chain.collision_task_points().len(), 8);
+    /// ```
+    pub fn collision_task_points(&self) -> Vec<TaskPoint<T>> {
+        let mut task_points = Vec::new();
+        for node in self.iter() {
+            if let Some(ref link) = *node.link() {
+                for collision in &link.collisions {
+                    for point in geometry_points(&collision.geometry) {
+                        let offset = (collision.origin() * Point3::from(point)).coords;
+                        task_points.push(TaskPoint::new(node.clone(), offset));
+                    }
+                }
+            }
+        }
+        task_points
+    }
+}
+
+/// Representative points of the geometry in its own frame
+fn geometry_points<T: RealField>(geometry: &Geometry<T>) -> Vec<Vector3<T>> {
+    let half = na::convert::<f64, T>(0.5);
+    let rim = |radius: T, z: T| {
+        (0..8)
+            .map(|i| {
+                let angle = T::two_pi() * na::convert(i as f64 / 8.0);
+                Vector3::new(radius * angle.cos(), radius * angle.sin(), z)
+            })
+            .collect::<Vec<_>>()
+    };
+    match *geometry {
+        Geometry::Box {
+            depth,
+            width,
+            height,
+        } => {
+            let mut points = Vec::with_capacity(8);
+            for &x in &[-half * depth, half * depth] {
+                for &y in &[-half * width, half * width] {
+                    for &z in &[-half * height, half * height] {
+                        points.push(Vector3::new(x, y, z));
+                    }
+                }
+            }
+            points
+        }
+        Geometry::Cylinder { radius, length } => {
+            let mut points = rim(radius, -half * length);
+            points.extend(rim(radius, half * length));
+            points
+        }
+        Geometry::Capsule { radius, length } => {
+            let mut points = rim(radius, -half * length);
+            points.extend(rim(radius, half * length));
+            points.push(Vector3::new(T::zero(), T::zero(), -half * length - radius));
+            points.push(Vector3::new(T::zero(), T::zero(), half * length + radius));
+            points
+        }
+        Geometry::Sphere { radius } => vec![
+            Vector3::x() * radius,
+            -Vector3::x() * radius,
+            Vector3::y() * radius,
+            -Vector3::y() * radius,
+            Vector3::z() * radius,
+            -Vector3::z() * radius,
+        ],
+        Geometry::Mesh { .. } => vec![Vector3::zeros()],
+    }
+}
@@ -0,0 +1,185 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use na::{Isometry3, RealField, Unit, UnitQuaternion, Vector3};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Symmetry of a grasp target about an axis in the frame of the target
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// // square peg
+/// let symmetry = GraspSymmetry::Discrete { axis: Vector3::z_axis(), order: 4 };
+/// let targets = symmetry.equivalent_targets(&Isometry3::translation(0.1, 0.0, 0.3));
+/// assert_eq!(targets.len(), 4);
+/// assert!((targets[1].rotation.angle() - std::f64::consts::FRAC_PI_2).abs() < 1e-10);
+/// ```
+#[derive(Debug, Clone)]
+pub enum GraspSymmetry<T: RealField> {
+    /// The target is the same if it is rotated by `2π / order` about `axis`,
+    /// e.g. 4 for a square peg
+    Discrete {
+        axis: Unit<Vector3<T>>,
+        order: usize,
+    },
+    /// The target is the same with any rotation about `axis`, e.g. a cylinder.
+    /// It is sampled by `num_samples` angles.
+    Continuous {
+        axis: Unit<Vector3<T>>,
+        num_samples: usize,
+    },
+}
+
+impl<T: RealField> GraspSymmetry<T> {
+    /// Enumerate the equivalent targets, starting from `target` itself
+    pub fn equivalent_targets(&self, target: &Isometry3<T>) -> Vec<Isometry3<T>> {
+        let (axis, num) = match self {
+            GraspSymmetry::Discrete { axis, order } => (axis, *order),
+            GraspSymmetry::Continuous { axis, num_samples } => (axis, *num_samples),
+        };
+        let num = num.max(1);
+        (0..num)
+            .map(|i| {
+                let angle = T::two_pi() * na::convert(i as f64) / na::convert(num as f64);
+                target * UnitQuaternion::from_axis_angle(axis, angle)
+            })
+            .collect()
+    }
+
+    /// The equivalent targets sorted by the rotation angle from `reference`
+    ///
+    /// The first one is the easiest to reach from `reference` in most cases.
+    pub fn sorted_targets(
+        &self,
+        target: &Isometry3<T>,
+        reference: &Isometry3<T>,
+    ) -> Vec<Isometry3<T>> {
+        let mut targets = self
+            .equivalent_targets(target)
+            .into_iter()
+            .map(|t| (reference.rotation.angle_to(&t.rotation), t))
+            .collect::<Vec<_>>();
+        targets.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        targets.into_iter().map(|(_, t)| t).collect()
+    }
+}
+
+/// IK solver which tries the equivalent targets of `GraspSymmetry`
+///
+/// The targets are tried in the order of the rotation from the current end transform,
+/// and the first solution is used.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("r_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let end = arm.end_transform();
+/// // the same grasp as the current one for a cylinder along the y axis of the end
+/// let target = end * k::UnitQuaternion::from_axis_angle(&k::Vector3::y_axis(), 2.0);
+///
+/// let solver = k::SymmetricIkSolver::new(
+///     k::JacobianIkSolver::default(),
+///     k::GraspSymmetry::Continuous { axis: k::Vector3::y_axis(), num_samples: 36 },
+/// );
+/// let chosen = solver
+///     .solve_with_target_choice(&arm, &target, &k::Constraints::default())
+///     .unwrap();
+/// assert!(chosen.rotation.angle_to(&end.rotation) < 0.1);
+/// ```
+pub struct SymmetricIkSolver<T, S>
+where
+    T: RealField,
+    S: InverseKinematicsSolver<T>,
+{
+    solver: S,
+    symmetry: GraspSymmetry<T>,
+}
+
+impl<T, S> SymmetricIkSolver<T, S>
+where
+    T: RealField + SubsetOf<f64>,
+    S: InverseKinematicsSolver<T>,
+{
+    pub fn new(solver: S, symmetry: GraspSymmetry<T>) -> Self {
+        Self { solver, symmetry }
+    }
+
+    /// Get the inner solver
+    pub fn solver(&self) -> &S {
+        &self.solver
+    }
+
+    /// Get the symmetry of the targets
+    pub fn symmetry(&self) -> &GraspSymmetry<T> {
+        &self.symmetry
+    }
+
+    /// Solve, and return the equivalent target which is used
+    ///
+    /// If all the targets fail, the positions are restored and the error of the
+    /// first target is returned.
+    pub fn solve_with_target_choice(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<Isometry3<T>, Error> {
+        let orig_positions = arm.joint_positions();
+        let mut first_error = None;
+        for target in self
+            .symmetry
+            .sorted_targets(target_pose, &arm.end_transform())
+        {
+            match self
+                .solver
+                .solve_with_constraints(arm, &target, constraints)
+            {
+                Ok(()) => return Ok(target),
+                Err(err) => {
+                    arm.set_joint_positions_unchecked(&orig_positions);
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        Err(first_error.expect("at least one target"))
+    }
+}
+
+impl<T, S> InverseKinematicsSolver<T> for SymmetricIkSolver<T, S>
+where
+    T: RealField + SubsetOf<f64>,
+    S: InverseKinematicsSolver<T>,
+{
+    fn solve_with_constraints(
+        &self,
+        arm: &SerialChain<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error> {
+        self.solve_with_target_choice(arm, target_pose, constraints)
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,251 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::funcs::*;
+use super::ik::*;
+use na::{DMatrix, DVector, Isometry3, RealField, Vector3};
+use nalgebra as na;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+use simba::scalar::SubsetOf;
+
+/// Linear map from the reduced coordinates to the joint positions, `q = matrix * z + offset`
+///
+/// It is the joint synergy of hands, e.g. one coordinate closes all the fingers.
+/// Each column of `matrix` is a synergy. Mimic joints are the special case with
+/// one coordinate and one joint.
+///
+/// # Examples
+///
+/// ```
+/// use k::nalgebra::{DMatrix, DVector};
+///
+/// // two fingers move together by the first coordinate, the third joint by the second
+/// let synergy = k::Synergy::new(
+///     DMatrix::<f64>::from_row_slice(3, 2, &[1.0, 0.0, 0.5, 0.0, 0.0, 1.0]),
+///     DVector::zeros(3),
+/// )
+/// .unwrap();
+/// assert_eq!(synergy.dim(), 2);
+/// let positions = synergy.positions(&[0.4, -0.1]).unwrap();
+/// assert_eq!(positions, vec![0.4, 0.2, -0.1]);
+/// let coordinates = synergy.coordinates(&positions).unwrap();
+/// assert!((coordinates[0] - 0.4).abs() < 1e-10);
+/// assert!((coordinates[1] + 0.1).abs() < 1e-10);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct Synergy<T: RealField> {
+    matrix: DMatrix<T>,
+    offset: DVector<T>,
+}
+
+impl<T> Synergy<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create the synergy, `offset` must have the same size as the rows of `matrix`
+    pub fn new(matrix: DMatrix<T>, offset: DVector<T>) -> Result<Self, Error> {
+        if offset.len() != matrix.nrows() {
+            return Err(Error::SizeMismatchError {
+                input: offset.len(),
+                required: matrix.nrows(),
+            });
+        }
+        Ok(Self { matrix, offset })
+    }
+
+    /// The matrix from the coordinates to the joint positions
+    pub fn matrix(&self) -> &DMatrix<T> {
+        &self.matrix
+    }
+
+    /// The joint positions at the zero coordinates
+    pub fn offset(&self) -> &DVector<T> {
+        &self.offset
+    }
+
+    /// Number of the reduced coordinates
+    pub fn dim(&self) -> usize {
+        self.matrix.ncols()
+    }
+
+    /// Number of the joints
+    pub fn dof(&self) -> usize {
+        self.matrix.nrows()
+    }
+
+    /// The joint positions of the coordinates
+    pub fn positions(&self, coordinates: &[T]) -> Result<Vec<T>, Error> {
+        if coordinates.len() != self.dim() {
+            return Err(Error::SizeMismatchError {
+                input: coordinates.len(),
+                required: self.dim(),
+            });
+        }
+        let positions = &self.matrix * DVector::from_column_slice(coordinates) + &self.offset;
+        Ok(positions.as_slice().to_vec())
+    }
+
+    /// The coordinates closest to the joint positions in the least squares sense
+    pub fn coordinates(&self, positions: &[T]) -> Result<Vec<T>, Error> {
+        if positions.len() != self.dof() {
+            return Err(Error::SizeMismatchError {
+                input: positions.len(),
+                required: self.dof(),
+            });
+        }
+        let diff = DVector::from_column_slice(positions) - &self.offset;
+        let coordinates = self
+            .matrix
+            .clone()
+            .svd(true, true)
+            .solve(&diff, T::default_epsilon())
+            .map_err(|_| Error::InverseMatrixError)?;
+        Ok(coordinates.as_slice().to_vec())
+    }
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Set the joint positions by the coordinates of the synergy
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::nalgebra::{DMatrix, DVector};
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let synergy = k::Synergy::new(DMatrix::from_element(chain.dof(), 1, 0.1), DVector::zeros(chain.dof())).unwrap();
+    /// chain.set_synergy_coordinates(&synergy, &[2.0]).unwrap();
+    /// assert!(chain.joint_positions().iter().all(|q| (q - 0.2).abs() < 1e-10));
+    /// ```
+    pub fn set_synergy_coordinates(
+        &self,
+        synergy: &Synergy<T>,
+        coordinates: &[T],
+    ) -> Result<(), Error> {
+        if synergy.dof() != self.dof() {
+            return Err(Error::SizeMismatchError {
+                input: synergy.dof(),
+                required: self.dof(),
+            });
+        }
+        self.set_joint_positions(&synergy.positions(coordinates)?)
+    }
+}
+
+impl<T> JacobianIkSolver<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Solve IK in the reduced coordinates of the synergy, and returns the coordinates
+    ///
+    /// The jacobian of the coordinates is `J * synergy.matrix()`, so the joints always
+    /// move together. The start coordinates are the projection of the current joint
+    /// positions. If the synergy cannot reach the target, `NotConvergedError` is returned
+    /// and the joint positions are restored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::nalgebra::{DMatrix, DVector};
+    /// use k::prelude::*;
+    ///
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// // the second coordinate moves the pitch joints together, the others move one joint
+    /// let mut matrix = DMatrix::zeros(6, 3);
+    /// matrix[(0, 0)] = 1.0;
+    /// matrix[(1, 1)] = 1.0;
+    /// matrix[(3, 1)] = -2.0;
+    /// matrix[(5, 1)] = 1.0;
+    /// matrix[(2, 2)] = 1.0;
+    /// let synergy = k::Synergy::new(matrix, DVector::zeros(6)).unwrap();
+    /// arm.set_joint_positions(&synergy.positions(&[0.1, 0.2, 0.0]).unwrap()).unwrap();
+    /// let mut target = arm.end_transform();
+    /// target.translation.vector.z += 0.02;
+    /// let mut constraints = k::Constraints::default();
+    /// constraints.rotation_x = false;
+    /// constraints.rotation_y = false;
+    /// constraints.rotation_z = false;
+    ///
+    /// let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+    /// let coordinates = solver.solve_with_synergy(&arm, &synergy, &target, &constraints).unwrap();
+    /// let positions = arm.joint_positions();
+    /// assert!((positions[3] + 2.0 * coordinates[1]).abs() < 1e-10);
+    /// assert!((arm.end_transform().translation.vector - target.translation.vector).norm() < 0.001);
+    /// ```
+    pub fn solve_with_synergy(
+        &self,
+        arm: &SerialChain<T>,
+        synergy: &Synergy<T>,
+        target_pose: &Isometry3<T>,
+        constraints: &Constraints,
+    ) -> Result<Vec<T>, Error> {
+        let orig_positions = arm.joint_positions();
+        if synergy.dof() != orig_positions.len() {
+            return Err(Error::SizeMismatchError {
+                input: synergy.dof(),
+                required: orig_positions.len(),
+            });
+        }
+        let operational_space = define_operational_space(constraints);
+        let eps = self.tolerances().singular_value;
+        let mut coordinates = DVector::from_vec(synergy.coordinates(&orig_positions)?);
+        let mut last_diff = None;
+        for i in 0..self.num_max_try {
+            let end = arm.end_transform();
+            let err = calc_pose_diff_with_constraints(target_pose, &end, constraints);
+            let jacobi = self.measure("ik/jacobian", i, || {
+                with_jacobian_scratch(arm, |full| constrained_jacobian(full, &end, constraints))
+                    * synergy.matrix()
+            });
+            let d_z = self.measure("ik/solve", i, || {
+                Ok::<_, Error>(match self.damped_pseudo_inverse(&jacobi)? {
+                    Some(inv) => inv * err,
+                    None => jacobi
+                        .svd(true, true)
+                        .solve(&err, eps)
+                        .map_err(|_| Error::InverseMatrixError)?,
+                })
+            })?;
+            coordinates += d_z * self.jacobian_multiplier;
+            let (len_diff, rot_diff) = self.measure("ik/update", i, || {
+                let positions = synergy.matrix() * &coordinates + synergy.offset();
+                arm.set_joint_positions_clamped(positions.as_slice());
+                let diff =
+                    calc_pose_diff_with_constraints(target_pose, &arm.end_transform(), constraints);
+                target_diff_to_len_rot_diff(&diff, operational_space)
+            });
+            if self.is_reached(&len_diff, &rot_diff) {
+                return Ok(coordinates.as_slice().to_vec());
+            }
+            last_diff = Some((len_diff, rot_diff));
+        }
+        arm.set_joint_positions(&orig_positions)?;
+        let (len_diff, rot_diff) =
+            last_diff.unwrap_or_else(|| (Vector3::zeros(), Vector3::zeros()));
+        Err(Error::NotConvergedError {
+            num_tried: self.num_max_try,
+            position_diff: na::try_convert(len_diff).unwrap_or_default(),
+            rotation_diff: na::try_convert(rot_diff).unwrap_or_default(),
+        })
+    }
+}
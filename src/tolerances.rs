@@ -0,0 +1,94 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use na::RealField;
+use nalgebra as na;
+
+/// Numeric tolerances used by `Chain` and `JacobianIkSolver`
+///
+/// The default values depend on the precision of `T`, because the reasonable values
+/// for `f32` and `f64` are different.
+///
+/// # Examples
+///
+/// ```
+/// let tolerances_f32 = k::Tolerances::<f32>::default();
+/// let tolerances_f64 = k::Tolerances::<f64>::default();
+/// assert!(tolerances_f32.joint_limit > tolerances_f64.joint_limit as f32);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerances<T: RealField> {
+    /// Singular values of the jacobian smaller than this are regarded as zero
+    pub singular_value: T,
+    /// Positions out of the joint limits by less than this are regarded as
+    /// in the limits, and they are clamped
+    pub joint_limit: T,
+    /// Positions violating a `CoupledLimit` by less than this are regarded as satisfying it.
+    /// The unit is the one of `CoupledLimit::violation()`.
+    pub coupled_limit: T,
+    /// The target of IK is reached if the distance is smaller than this
+    pub convergence_distance: T,
+    /// The target of IK is reached if the angle is smaller than this
+    pub convergence_angle: T,
+}
+
+impl<T: RealField> Default for Tolerances<T> {
+    fn default() -> Self {
+        let eps = T::default_epsilon();
+        Self {
+            singular_value: eps.sqrt().max(na::convert(0.0001)),
+            joint_limit: eps * na::convert(100.0),
+            coupled_limit: eps.sqrt(),
+            convergence_distance: na::convert(0.001),
+            convergence_angle: na::convert(0.005),
+        }
+    }
+}
+
+impl<T: RealField> Tolerances<T> {
+    /// The smallest convergence tolerance which is meaningful with the precision of `T`
+    ///
+    /// The IK solvers raise the smaller tolerances to this value, because the rounding
+    /// errors of the forward kinematics in `f32` can be larger than them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// assert!(k::Tolerances::<f32>::convergence_floor() < 0.0001);
+    /// assert!(k::Tolerances::<f64>::convergence_floor() < 1e-12);
+    /// ```
+    pub fn convergence_floor() -> T {
+        T::default_epsilon() * na::convert(100.0)
+    }
+
+    /// Returns the position clamped into `[min, max]` if it is out of it within `joint_limit`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let tolerances = k::Tolerances::<f64>::default();
+    /// assert_eq!(tolerances.snap_to_limits(1.0 + 1e-15, -1.0, 1.0), 1.0);
+    /// assert_eq!(tolerances.snap_to_limits(1.1, -1.0, 1.0), 1.1);
+    /// ```
+    pub fn snap_to_limits(&self, position: T, min: T, max: T) -> T {
+        if position > max && position - max <= self.joint_limit {
+            max
+        } else if position < min && min - position <= self.joint_limit {
+            min
+        } else {
+            position
+        }
+    }
+}
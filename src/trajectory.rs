@@ -0,0 +1,795 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use na::{Isometry3, Quaternion, RealField, Translation3, Unit, UnitQuaternion, Vector3};
+use nalgebra as na;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+use simba::scalar::SubsetOf;
+
+/// A sample of `JointTrajectory`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct TrajectoryPoint<T: RealField> {
+    /// positions of the movable joints, in the same order as `Chain::iter_joints()`
+    pub positions: Vec<T>,
+    /// time of this sample [sec]
+    pub time_from_start: T,
+}
+
+impl<T: RealField> TrajectoryPoint<T> {
+    pub fn new(positions: Vec<T>, time_from_start: T) -> Self {
+        Self {
+            positions,
+            time_from_start,
+        }
+    }
+}
+
+/// Sequence of joint positions with time
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct JointTrajectory<T: RealField> {
+    pub points: Vec<TrajectoryPoint<T>>,
+}
+
+/// Which limit is violated, used in `TrajectoryViolation`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub enum TrajectoryViolationKind {
+    /// `Joint::limits`
+    Position,
+    /// `Joint::velocity_limit`
+    Velocity,
+    /// `Joint::acceleration_limit`
+    Acceleration,
+}
+
+/// The first violation of the limits found by `JointTrajectory::validate()`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct TrajectoryViolation<T: RealField> {
+    /// index of the violating sample in `JointTrajectory::points`
+    pub point_index: usize,
+    /// name of the violating joint
+    pub joint_name: String,
+    /// which limit is violated
+    pub kind: TrajectoryViolationKind,
+    /// the position, or the finite-difference velocity or acceleration
+    pub value: T,
+}
+
+impl<T> JointTrajectory<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    pub fn new(points: Vec<TrajectoryPoint<T>>) -> Self {
+        Self { points }
+    }
+
+    /// Check that the trajectory respects the limits of the joints of `chain`
+    ///
+    /// The velocity of the i-th sample is the finite difference between the (i-1)-th and i-th
+    /// samples, and the acceleration of the i-th sample is the central difference around it.
+    /// It returns `Ok(Some(violation))` for the first violating sample and joint, and
+    /// `Err` if the trajectory is malformed (wrong number of positions or non-increasing time).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .name("j0")
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .limits(Some((-1.0..=1.0).into()))
+    ///     .velocity_limit(Some(1.0))
+    ///     .into_node();
+    /// let chain = Chain::<f64>::from_root(j0);
+    ///
+    /// let trajectory = JointTrajectory::new(vec![
+    ///     TrajectoryPoint::new(vec![0.0], 0.0),
+    ///     TrajectoryPoint::new(vec![0.5], 1.0),
+    ///     TrajectoryPoint::new(vec![0.8], 1.1),
+    /// ]);
+    /// let violation = trajectory.validate(&chain).unwrap().unwrap();
+    /// assert_eq!(violation.point_index, 2);
+    /// assert_eq!(violation.joint_name, "j0");
+    /// assert_eq!(violation.kind, TrajectoryViolationKind::Velocity);
+    ///
+    /// let trajectory = JointTrajectory::new(vec![
+    ///     TrajectoryPoint::new(vec![0.0], 0.0),
+    ///     TrajectoryPoint::new(vec![0.5], 1.0),
+    /// ]);
+    /// assert!(trajectory.validate(&chain).unwrap().is_none());
+    /// ```
+    pub fn validate(&self, chain: &Chain<T>) -> Result<Option<TrajectoryViolation<T>>, Error> {
+        let dof = chain.dof();
+        for (i, point) in self.points.iter().enumerate() {
+            if point.positions.len() != dof {
+                return Err(Error::SizeMismatchError {
+                    input: point.positions.len(),
+                    required: dof,
+                });
+            }
+            if i > 0 && point.time_from_start <= self.points[i - 1].time_from_start {
+                return Err(Error::InvalidTrajectoryError { point_index: i });
+            }
+        }
+        let nodes = chain.iter_position_nodes().collect::<Vec<_>>();
+        let velocity = |i: usize, j: usize| {
+            let prev = &self.points[i - 1];
+            let point = &self.points[i];
+            (point.positions[j] - prev.positions[j])
+                / (point.time_from_start - prev.time_from_start)
+        };
+        for (i, point) in self.points.iter().enumerate() {
+            for (j, node) in nodes.iter().enumerate() {
+                let joint = node.joint();
+                let violation = |kind, value| {
+                    Some(TrajectoryViolation {
+                        point_index: i,
+                        joint_name: joint.name.clone(),
+                        kind,
+                        value,
+                    })
+                };
+                let position = point.positions[j];
+                if let Some(ref range) = joint.effective_limits() {
+                    if !range.is_valid(position) {
+                        return Ok(violation(TrajectoryViolationKind::Position, position));
+                    }
+                }
+                if i == 0 {
+                    continue;
+                }
+                let v = velocity(i, j);
+                if let Some(limit) = joint.velocity_limit {
+                    if v.abs() > limit {
+                        return Ok(violation(TrajectoryViolationKind::Velocity, v));
+                    }
+                }
+                if i + 1 == self.points.len() {
+                    continue;
+                }
+                if let Some(limit) = joint.acceleration_limit {
+                    let dt = (self.points[i + 1].time_from_start
+                        - self.points[i - 1].time_from_start)
+                        / na::convert(2.0);
+                    let a = (velocity(i + 1, j) - v) / dt;
+                    if a.abs() > limit {
+                        return Ok(violation(TrajectoryViolationKind::Acceleration, a));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<T> JointTrajectory<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// The positions at `time` by the linear interpolation of the samples
+    ///
+    /// It returns `None` if `time` is out of the trajectory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let trajectory = JointTrajectory::new(vec![
+    ///     TrajectoryPoint::new(vec![0.0, 1.0], 0.0),
+    ///     TrajectoryPoint::new(vec![1.0, 0.0], 2.0),
+    /// ]);
+    /// assert_eq!(trajectory.position_at(0.5).unwrap(), vec![0.25, 0.75]);
+    /// assert!(trajectory.position_at(2.5).is_none());
+    /// ```
+    pub fn position_at(&self, time: T) -> Option<Vec<T>> {
+        let k = self.segment_at(time)?;
+        if k + 1 == self.points.len() {
+            return Some(self.points[k].positions.clone());
+        }
+        let (a, b) = (&self.points[k], &self.points[k + 1]);
+        let ratio = (time - a.time_from_start) / (b.time_from_start - a.time_from_start);
+        Some(
+            a.positions
+                .iter()
+                .zip(&b.positions)
+                .map(|(pa, pb)| *pa + (*pb - *pa) * ratio)
+                .collect(),
+        )
+    }
+
+    /// The positions at `time` with parabolic blends around the intermediate samples
+    ///
+    /// The velocity changes linearly in `blend_time` around each intermediate sample,
+    /// instead of the step of the linear interpolation. The blend is shortened to
+    /// the half of the neighboring segments. The blended path does not pass through
+    /// the intermediate samples exactly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let trajectory = JointTrajectory::new(vec![
+    ///     TrajectoryPoint::new(vec![0.0], 0.0),
+    ///     TrajectoryPoint::new(vec![1.0], 1.0),
+    ///     TrajectoryPoint::new(vec![1.0], 2.0),
+    /// ]);
+    /// // the corner at 1.0 is rounded
+    /// assert!(trajectory.blended_position_at(1.0, 0.4).unwrap()[0] < 1.0);
+    /// // out of the blend, it is the same as the linear interpolation
+    /// assert_eq!(trajectory.blended_position_at(0.5, 0.4).unwrap()[0], 0.5);
+    /// ```
+    pub fn blended_position_at(&self, time: T, blend_time: T) -> Option<Vec<T>> {
+        let k = self.segment_at(time)?;
+        for i in [k, k + 1].iter().cloned() {
+            if i == 0 || i + 1 >= self.points.len() {
+                continue;
+            }
+            let h = self.blend_half_width(i, blend_time);
+            let t_i = self.points[i].time_from_start;
+            if h > T::zero() && time >= t_i - h && time <= t_i + h {
+                let before = self.segment_velocities(i - 1);
+                let after = self.segment_velocities(i);
+                let dt = time - t_i;
+                let four: T = na::convert(4.0);
+                return Some(
+                    self.points[i]
+                        .positions
+                        .iter()
+                        .zip(before.iter().zip(&after))
+                        .map(|(q, (va, vb))| {
+                            *q + *va * dt + (*vb - *va) / (four * h) * (dt + h) * (dt + h)
+                        })
+                        .collect(),
+                );
+            }
+        }
+        self.position_at(time)
+    }
+
+    /// Sample the trajectory at the fixed `period`, with parabolic blends if `blend_time` is given
+    ///
+    /// The last sample is always included. `period` must be positive, otherwise it
+    /// returns `Err(Error::InvalidParameterError)`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let trajectory = JointTrajectory::new(vec![
+    ///     TrajectoryPoint::new(vec![0.0], 0.0),
+    ///     TrajectoryPoint::new(vec![1.0], 1.0),
+    ///     TrajectoryPoint::new(vec![0.0], 2.5),
+    /// ]);
+    /// let resampled = trajectory.resample(0.1, Some(0.2)).unwrap();
+    /// assert_eq!(resampled.points.len(), 26);
+    /// assert_eq!(resampled.points[25].time_from_start, 2.5);
+    /// assert!(trajectory.resample(0.0, None).is_err());
+    /// ```
+    pub fn resample(&self, period: T, blend_time: Option<T>) -> Result<Self, Error> {
+        check_period(period)?;
+        self.check_samples()?;
+        let (start, end) = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => (first.time_from_start, last.time_from_start),
+            _ => return Ok(Self::new(Vec::new())),
+        };
+        let mut points = Vec::new();
+        let mut i = 0;
+        loop {
+            let time = start + period * na::convert(i as f64);
+            // avoid a tiny segment at the end by the rounding errors
+            if time >= end - period * na::convert(1e-6) {
+                break;
+            }
+            let positions = match blend_time {
+                Some(blend_time) => self.blended_position_at(time, blend_time),
+                None => self.position_at(time),
+            };
+            points.push(TrajectoryPoint::new(positions.unwrap(), time));
+            i += 1;
+        }
+        points.push(self.points.last().unwrap().clone());
+        Ok(Self::new(points))
+    }
+
+    /// Scale the time of the trajectory by `factor`
+    ///
+    /// `factor` larger than 1.0 makes the motion slower. The velocities are divided by
+    /// `factor`, and the accelerations by its square.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let trajectory = JointTrajectory::new(vec![
+    ///     TrajectoryPoint::new(vec![0.0], 0.0),
+    ///     TrajectoryPoint::new(vec![1.0], 1.0),
+    /// ]);
+    /// let slow = trajectory.time_scaled(2.0);
+    /// assert_eq!(slow.points[1].time_from_start, 2.0);
+    /// ```
+    pub fn time_scaled(&self, factor: T) -> Self {
+        Self::new(
+            self.points
+                .iter()
+                .map(|p| TrajectoryPoint::new(p.positions.clone(), p.time_from_start * factor))
+                .collect(),
+        )
+    }
+
+    /// Index of the segment which contains `time`, the last index for the end time
+    fn segment_at(&self, time: T) -> Option<usize> {
+        let first = self.points.first()?;
+        let last = self.points.last()?;
+        if time < first.time_from_start || time > last.time_from_start {
+            return None;
+        }
+        Some(
+            self.points
+                .windows(2)
+                .position(|w| time < w[1].time_from_start)
+                .unwrap_or(self.points.len() - 1),
+        )
+    }
+
+    fn segment_velocities(&self, k: usize) -> Vec<T> {
+        let (a, b) = (&self.points[k], &self.points[k + 1]);
+        let dt = b.time_from_start - a.time_from_start;
+        a.positions
+            .iter()
+            .zip(&b.positions)
+            .map(|(pa, pb)| (*pb - *pa) / dt)
+            .collect()
+    }
+
+    fn blend_half_width(&self, i: usize, blend_time: T) -> T {
+        let half: T = na::convert(0.5);
+        let before = self.points[i].time_from_start - self.points[i - 1].time_from_start;
+        let after = self.points[i + 1].time_from_start - self.points[i].time_from_start;
+        (blend_time * half).min(before * half).min(after * half)
+    }
+
+    /// Check that all the samples have the same size and the time is increasing
+    fn check_samples(&self) -> Result<(), Error> {
+        for (i, point) in self.points.iter().enumerate().skip(1) {
+            if point.positions.len() != self.points[0].positions.len() {
+                return Err(Error::SizeMismatchError {
+                    input: point.positions.len(),
+                    required: self.points[0].positions.len(),
+                });
+            }
+            if point.time_from_start <= self.points[i - 1].time_from_start {
+                return Err(Error::InvalidTrajectoryError { point_index: i });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A pose of `CartesianSpline` with time
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct PoseWaypoint<T: RealField> {
+    /// pose of the end
+    pub pose: Isometry3<T>,
+    /// time of this waypoint [sec]
+    pub time_from_start: T,
+}
+
+impl<T: RealField> PoseWaypoint<T> {
+    pub fn new(pose: Isometry3<T>, time_from_start: T) -> Self {
+        Self {
+            pose,
+            time_from_start,
+        }
+    }
+}
+
+/// Smooth path of the end pose through the waypoints
+///
+/// The position is interpolated by the cubic Hermite spline with Catmull-Rom tangents,
+/// and the rotation by squad (spherical quadrangle interpolation), so both pass
+/// through the waypoints and their velocities are continuous.
+///
+/// Note that the position is not a B-spline: a cubic B-spline has continuous
+/// accelerations too, but it only approximates the waypoints, and the end pose must
+/// reach the waypoints exactly to be used as the IK targets.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+pub struct CartesianSpline<T: RealField> {
+    waypoints: Vec<PoseWaypoint<T>>,
+    /// velocities of the position at the waypoints
+    tangents: Vec<Vector3<T>>,
+    /// rotations of the waypoints in the same hemisphere as the previous one
+    rotations: Vec<UnitQuaternion<T>>,
+    /// intermediate control rotations of squad
+    controls: Vec<UnitQuaternion<T>>,
+}
+
+impl<T> CartesianSpline<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create a spline through the waypoints
+    ///
+    /// It returns `Err` if no waypoint is given or the time is not increasing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let spline = CartesianSpline::new(vec![
+    ///     PoseWaypoint::new(Isometry3::identity(), 0.0),
+    ///     PoseWaypoint::new(Isometry3::translation(1.0, 0.0, 0.0), 1.0),
+    ///     PoseWaypoint::new(Isometry3::rotation(Vector3::new(0.0, 0.0, 1.0)), 2.0),
+    /// ])
+    /// .unwrap();
+    /// assert_eq!(spline.duration(), 2.0);
+    /// let pose = spline.pose_at(1.0).unwrap();
+    /// assert!((pose.translation.vector - Vector3::x()).norm() < 1e-10);
+    /// assert!(spline.pose_at(2.5).is_none());
+    /// ```
+    pub fn new(waypoints: Vec<PoseWaypoint<T>>) -> Result<Self, Error> {
+        if waypoints.is_empty() {
+            return Err(Error::InvalidTrajectoryError { point_index: 0 });
+        }
+        for i in 1..waypoints.len() {
+            if waypoints[i].time_from_start <= waypoints[i - 1].time_from_start {
+                return Err(Error::InvalidTrajectoryError { point_index: i });
+            }
+        }
+        let n = waypoints.len();
+        let position = |i: usize| waypoints[i].pose.translation.vector;
+        let time = |i: usize| waypoints[i].time_from_start;
+        let tangents = (0..n)
+            .map(|i| {
+                if n == 1 {
+                    return Vector3::zeros();
+                }
+                let (a, b) = (i.saturating_sub(1), (i + 1).min(n - 1));
+                (position(b) - position(a)) / (time(b) - time(a))
+            })
+            .collect();
+        let mut rotations: Vec<UnitQuaternion<T>> = Vec::with_capacity(n);
+        for waypoint in &waypoints {
+            let mut q = waypoint.pose.rotation;
+            if let Some(prev) = rotations.last() {
+                if prev.coords.dot(&q.coords) < T::zero() {
+                    q = UnitQuaternion::new_unchecked(-q.into_inner());
+                }
+            }
+            rotations.push(q);
+        }
+        let controls = (0..n)
+            .map(|i| {
+                if i == 0 || i == n - 1 {
+                    return rotations[i];
+                }
+                // the angular velocities of both sides are same in time, not in the
+                // parameter of each segment like the original squad
+                let (dt_prev, dt_next) = (time(i) - time(i - 1), time(i + 1) - time(i));
+                let inv = rotations[i].inverse();
+                let sum = (inv * rotations[i + 1]).scaled_axis() * dt_prev
+                    + (inv * rotations[i - 1]).scaled_axis() * dt_next;
+                let scale = -T::one() / ((dt_prev + dt_next) * na::convert(2.0));
+                rotations[i] * UnitQuaternion::from_scaled_axis(sum * scale)
+            })
+            .collect();
+        Ok(Self {
+            waypoints,
+            tangents,
+            rotations,
+            controls,
+        })
+    }
+
+    /// The waypoints given to `new()`
+    pub fn waypoints(&self) -> &[PoseWaypoint<T>] {
+        &self.waypoints
+    }
+
+    /// Time of the last waypoint
+    pub fn duration(&self) -> T {
+        self.waypoints.last().unwrap().time_from_start
+    }
+
+    /// The pose at `time`, `None` if `time` is out of the spline
+    pub fn pose_at(&self, time: T) -> Option<Isometry3<T>> {
+        let first = &self.waypoints[0];
+        let last = self.waypoints.last().unwrap();
+        if time < first.time_from_start || time > last.time_from_start {
+            return None;
+        }
+        let k = match self
+            .waypoints
+            .windows(2)
+            .position(|w| time < w[1].time_from_start)
+        {
+            Some(k) => k,
+            None => return Some(last.pose),
+        };
+        let (a, b) = (&self.waypoints[k], &self.waypoints[k + 1]);
+        let dt = b.time_from_start - a.time_from_start;
+        let t = (time - a.time_from_start) / dt;
+        // cubic Hermite basis
+        let two: T = na::convert(2.0);
+        let three: T = na::convert(3.0);
+        let (t2, t3) = (t * t, t * t * t);
+        let h00 = two * t3 - three * t2 + T::one();
+        let h10 = t3 - two * t2 + t;
+        let h01 = -two * t3 + three * t2;
+        let h11 = t3 - t2;
+        let position = a.pose.translation.vector * h00
+            + self.tangents[k] * (h10 * dt)
+            + b.pose.translation.vector * h01
+            + self.tangents[k + 1] * (h11 * dt);
+        let rotation = squad(
+            &self.rotations[k],
+            &self.controls[k],
+            &self.controls[k + 1],
+            &self.rotations[k + 1],
+            t,
+        );
+        Some(Isometry3::from_parts(
+            Translation3::from(position),
+            rotation,
+        ))
+    }
+
+    /// Convert to the joint trajectory of `arm` by solving IK at every `period`
+    ///
+    /// Each IK is started from the solution of the previous sample (from the current
+    /// positions for the first one), so the solutions follow the same branch. It returns
+    /// `Err(Error::InvalidTrajectoryError)` if a joint moves more than `max_joint_step`
+    /// between the samples, which means the solution jumped to another branch, and
+    /// the error of the solver if IK fails. `period` must be positive, otherwise it
+    /// returns `Err(Error::InvalidParameterError)`. The joint positions of `arm` are
+    /// restored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let arm = SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    /// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+    /// let start = arm.end_transform();
+    /// let mut middle = start;
+    /// middle.translation.vector.z += 0.03;
+    /// middle.rotation = UnitQuaternion::from_euler_angles(0.1, 0.0, 0.0) * start.rotation;
+    /// let mut end = start;
+    /// end.translation.vector.z += 0.06;
+    ///
+    /// let spline = CartesianSpline::new(vec![
+    ///     PoseWaypoint::new(start, 0.0),
+    ///     PoseWaypoint::new(middle, 1.0),
+    ///     PoseWaypoint::new(end, 2.0),
+    /// ])
+    /// .unwrap();
+    /// let solver = JacobianIkSolver::default();
+    /// let trajectory = spline
+    ///     .to_joint_trajectory(&solver, &arm, &Constraints::default(), 0.1, Some(0.2))
+    ///     .unwrap();
+    /// assert_eq!(trajectory.points.len(), 21);
+    /// assert!(spline
+    ///     .to_joint_trajectory(&solver, &arm, &Constraints::default(), 0.0, None)
+    ///     .is_err());
+    ///
+    /// arm.set_joint_positions(&trajectory.points[20].positions).unwrap();
+    /// let diff = arm.end_transform().translation.vector - end.translation.vector;
+    /// assert!(diff.norm() < 0.001);
+    /// ```
+    pub fn to_joint_trajectory<S>(
+        &self,
+        solver: &S,
+        arm: &SerialChain<T>,
+        constraints: &Constraints,
+        period: T,
+        max_joint_step: Option<T>,
+    ) -> Result<JointTrajectory<T>, Error>
+    where
+        S: InverseKinematicsSolver<T>,
+    {
+        check_period(period)?;
+        let orig_positions = arm.joint_positions();
+        let result = self.track(solver, arm, constraints, period, max_joint_step);
+        arm.set_joint_positions_unchecked(&orig_positions);
+        result
+    }
+
+    fn track<S>(
+        &self,
+        solver: &S,
+        arm: &SerialChain<T>,
+        constraints: &Constraints,
+        period: T,
+        max_joint_step: Option<T>,
+    ) -> Result<JointTrajectory<T>, Error>
+    where
+        S: InverseKinematicsSolver<T>,
+    {
+        let start = self.waypoints[0].time_from_start;
+        let num_samples: f64 = na::convert((self.duration() - start) / period);
+        let num_samples = num_samples.ceil() as usize;
+        let mut points: Vec<TrajectoryPoint<T>> = Vec::with_capacity(num_samples + 1);
+        for i in 0..=num_samples {
+            let time = (start + period * na::convert(i as f64)).min(self.duration());
+            let pose = self.pose_at(time).expect("time must be in the spline");
+            solver.solve_with_constraints(arm, &pose, constraints)?;
+            let positions = arm.joint_positions();
+            if let (Some(max), Some(prev)) = (max_joint_step, points.last()) {
+                let jumped = positions
+                    .iter()
+                    .zip(&prev.positions)
+                    .any(|(a, b)| (*a - *b).abs() > max);
+                if jumped {
+                    return Err(Error::InvalidTrajectoryError { point_index: i });
+                }
+            }
+            points.push(TrajectoryPoint::new(positions, time - start));
+        }
+        Ok(JointTrajectory::new(points))
+    }
+}
+
+fn check_period<T: RealField>(period: T) -> Result<(), Error> {
+    if period > T::zero() {
+        Ok(())
+    } else {
+        Err(Error::InvalidParameterError {
+            reason: format!("period must be positive, but {}", period),
+        })
+    }
+}
+
+fn squad<T: RealField>(
+    q0: &UnitQuaternion<T>,
+    s0: &UnitQuaternion<T>,
+    s1: &UnitQuaternion<T>,
+    q1: &UnitQuaternion<T>,
+    t: T,
+) -> UnitQuaternion<T> {
+    // without the flip to the shortest path, which breaks the continuity of squad
+    let slerp = |a: &UnitQuaternion<T>, b: &UnitQuaternion<T>, t: T| {
+        let coords = Unit::new_unchecked(a.coords)
+            .try_slerp(&Unit::new_unchecked(b.coords), t, T::default_epsilon())
+            .map(|c| c.into_inner())
+            // almost same or opposite, nlerp
+            .unwrap_or_else(|| a.coords * (T::one() - t) + b.coords * t);
+        UnitQuaternion::new_normalize(Quaternion::from(coords))
+    };
+    let two: T = na::convert(2.0);
+    slerp(
+        &slerp(q0, q1, t),
+        &slerp(s0, s1, t),
+        two * t * (T::one() - t),
+    )
+}
+
+#[test]
+fn test_validate_acceleration() {
+    use super::joint::*;
+    use super::node::*;
+    let j0 = NodeBuilder::new()
+        .name("j0")
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::x_axis(),
+        })
+        .acceleration_limit(Some(1.0))
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .name("j1")
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::x_axis(),
+        })
+        .acceleration_limit(Some(1.0))
+        .into_node();
+    j1.set_parent(&j0);
+    let chain = Chain::<f64>::from_root(j0);
+    let trajectory = JointTrajectory::new(vec![
+        TrajectoryPoint::new(vec![0.0, 0.0], 0.0),
+        TrajectoryPoint::new(vec![0.5, 0.5], 1.0),
+        TrajectoryPoint::new(vec![1.0, 1.0], 2.0),
+    ]);
+    assert!(trajectory.validate(&chain).unwrap().is_none());
+    let trajectory = JointTrajectory::new(vec![
+        TrajectoryPoint::new(vec![0.0, 0.0], 0.0),
+        TrajectoryPoint::new(vec![0.0, 0.0], 1.0),
+        TrajectoryPoint::new(vec![0.5, 2.0], 2.0),
+        TrajectoryPoint::new(vec![1.0, 4.0], 3.0),
+    ]);
+    let violation = trajectory.validate(&chain).unwrap().unwrap();
+    assert_eq!(violation.point_index, 1);
+    assert_eq!(violation.joint_name, "j1");
+    assert_eq!(violation.kind, TrajectoryViolationKind::Acceleration);
+    assert_eq!(violation.value, 2.0);
+    let trajectory = JointTrajectory::new(vec![
+        TrajectoryPoint::new(vec![0.0, 0.0], 0.0),
+        TrajectoryPoint::new(vec![0.0, 0.0], 0.0),
+    ]);
+    assert!(trajectory.validate(&chain).is_err());
+}
+
+#[test]
+fn test_blend_continuity() {
+    let trajectory = JointTrajectory::<f64>::new(vec![
+        TrajectoryPoint::new(vec![0.0], 0.0),
+        TrajectoryPoint::new(vec![1.0], 1.0),
+        TrajectoryPoint::new(vec![-1.0], 2.0),
+    ]);
+    let blend = 0.5;
+    // continuous at the boundaries of the blend
+    for t in [0.75, 1.25].iter() {
+        let inside = trajectory.blended_position_at(*t, blend).unwrap()[0];
+        let outside = trajectory.position_at(*t).unwrap()[0];
+        assert!((inside - outside).abs() < 1e-10);
+    }
+    // velocity changes linearly from 1.0 to -2.0 in the blend
+    let eps = 1e-6;
+    let v = (trajectory.blended_position_at(1.0 + eps, blend).unwrap()[0]
+        - trajectory.blended_position_at(1.0 - eps, blend).unwrap()[0])
+        / (2.0 * eps);
+    assert!((v - (-0.5)).abs() < 1e-4);
+}
+
+#[test]
+fn test_cartesian_spline_continuity() {
+    let spline = CartesianSpline::<f64>::new(vec![
+        PoseWaypoint::new(Isometry3::identity(), 0.0),
+        PoseWaypoint::new(
+            Isometry3::new(Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+            1.0,
+        ),
+        PoseWaypoint::new(
+            Isometry3::new(Vector3::new(1.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 2.0)),
+            3.0,
+        ),
+    ])
+    .unwrap();
+    // passes through the waypoints
+    for waypoint in spline.waypoints() {
+        let pose = spline.pose_at(waypoint.time_from_start).unwrap();
+        assert!((pose.to_homogeneous() - waypoint.pose.to_homogeneous()).norm() < 1e-10);
+    }
+    // the velocities are continuous at the intermediate waypoint
+    let eps = 1e-6;
+    let velocity = |t: f64| {
+        let (a, b) = (
+            spline.pose_at(t - eps).unwrap(),
+            spline.pose_at(t + eps).unwrap(),
+        );
+        let linear = (b.translation.vector - a.translation.vector) / (2.0 * eps);
+        let angular = (b.rotation * a.rotation.inverse()).scaled_axis() / (2.0 * eps);
+        (linear, angular)
+    };
+    let (before, before_w) = velocity(1.0 - 1e-3);
+    let (after, after_w) = velocity(1.0 + 1e-3);
+    assert!((before - after).norm() < 1e-2);
+    assert!((before_w - after_w).norm() < 1e-2);
+    assert!(CartesianSpline::<f64>::new(vec![]).is_err());
+}
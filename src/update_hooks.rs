@@ -0,0 +1,267 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::node::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+use std::fmt;
+use std::sync::Arc;
+
+/// Function called for a node by `Chain::update_transforms()`
+pub type UpdateHook<T> = Arc<dyn Fn(&Node<T>) + Send + Sync>;
+
+/// Receiver of the world poses calculated by `Chain::update_transforms()`
+///
+/// It is the extension point to send the poses to visualizers, without the
+/// dependencies on them in this crate. The closures of the same signature
+/// implement it.
+pub trait PoseSink<T: RealField>: Send + Sync {
+    /// Receive the joint names and the world transforms of the same index
+    ///
+    /// They are the nodes returned by `update_transforms()`, in the same order.
+    fn publish(&self, names: &[String], poses: &[Isometry3<T>]);
+}
+
+impl<T, F> PoseSink<T> for F
+where
+    T: RealField,
+    F: Fn(&[String], &[Isometry3<T>]) + Send + Sync,
+{
+    fn publish(&self, names: &[String], poses: &[Isometry3<T>]) {
+        self(names, poses)
+    }
+}
+
+/// Hooks of `Chain::update_transforms()`, keyed by the joint name
+///
+/// They are kept by the clones of the chain, because the names don't change.
+#[derive(Clone)]
+pub(crate) struct UpdateHooks<T: RealField> {
+    pre: Vec<(String, UpdateHook<T>)>,
+    post: Vec<(String, UpdateHook<T>)>,
+    sink: Option<Arc<dyn PoseSink<T>>>,
+}
+
+impl<T: RealField> Default for UpdateHooks<T> {
+    fn default() -> Self {
+        Self {
+            pre: Vec::new(),
+            post: Vec::new(),
+            sink: None,
+        }
+    }
+}
+
+impl<T: RealField> fmt::Debug for UpdateHooks<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = |hooks: &[(String, UpdateHook<T>)]| {
+            hooks
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+        f.debug_struct("UpdateHooks")
+            .field("pre", &names(&self.pre))
+            .field("post", &names(&self.post))
+            .field("sink", &self.sink.is_some())
+            .finish()
+    }
+}
+
+impl<T> UpdateHooks<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    pub(crate) fn call_pre(&self, node: &Node<T>) {
+        call(&self.pre, node);
+    }
+
+    pub(crate) fn call_post(&self, node: &Node<T>) {
+        call(&self.post, node);
+    }
+
+    pub(crate) fn has_sink(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    pub(crate) fn publish(&self, names: &[String], poses: &[Isometry3<T>]) {
+        if let Some(ref sink) = self.sink {
+            sink.publish(names, poses);
+        }
+    }
+}
+
+fn call<T>(hooks: &[(String, UpdateHook<T>)], node: &Node<T>)
+where
+    T: RealField + SubsetOf<f64>,
+{
+    if hooks.is_empty() {
+        return;
+    }
+    // the hooks may lock the node, so the name is copied
+    let name = node.joint().name.clone();
+    for (_, hook) in hooks.iter().filter(|(n, _)| *n == name) {
+        hook(node);
+    }
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Names of the joints in the order of the update by `update_transforms()`
+    ///
+    /// It is the order of `iter()`, depth first from the root, so the parent of a node is
+    /// always updated before the node. The transforms returned by `update_transforms()`
+    /// are in this order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let order = chain.update_order();
+    /// let shoulder = order.iter().position(|name| name == "l_shoulder_yaw").unwrap();
+    /// let elbow = order.iter().position(|name| name == "l_elbow_pitch").unwrap();
+    /// assert!(shoulder < elbow);
+    /// assert_eq!(order.len(), chain.update_transforms().len());
+    /// ```
+    pub fn update_order(&self) -> Vec<String> {
+        self.iter().map(|node| node.joint().name.clone()).collect()
+    }
+
+    /// Add a function called by `update_transforms()` before the world transform of
+    /// the joint is calculated
+    ///
+    /// The parent is already updated, and the hook can change the node, e.g. set the
+    /// origin of the base measured by odometry. The hooks are called in the order of
+    /// the addition.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mut chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// // the latest base pose from odometry
+    /// let odometry = Arc::new(Mutex::new(Isometry3::translation(1.0, 0.0, 0.0)));
+    /// let measured = odometry.clone();
+    /// chain
+    ///     .add_pre_update_hook("root", move |node: &Node<f64>| {
+    ///         node.set_origin(*measured.lock().unwrap());
+    ///     })
+    ///     .unwrap();
+    /// chain.update_transforms();
+    /// assert_eq!(chain.find("root").unwrap().world_transform().unwrap().translation.vector.x, 1.0);
+    ///
+    /// *odometry.lock().unwrap() = Isometry3::translation(2.0, 0.0, 0.0);
+    /// chain.update_transforms();
+    /// assert_eq!(chain.find("root").unwrap().world_transform().unwrap().translation.vector.x, 2.0);
+    /// ```
+    pub fn add_pre_update_hook<F>(&mut self, joint_name: &str, hook: F) -> Result<(), Error>
+    where
+        F: Fn(&Node<T>) + Send + Sync + 'static,
+    {
+        self.check_hook_joint(joint_name)?;
+        self.update_hooks_mut()
+            .pre
+            .push((joint_name.to_owned(), Arc::new(hook)));
+        Ok(())
+    }
+
+    /// Add a function called by `update_transforms()` after the world transform of
+    /// the joint is calculated
+    ///
+    /// The children are not updated yet, so changing the node affects them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mut chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let elbow = Arc::new(Mutex::new(None));
+    /// let recorded = elbow.clone();
+    /// chain
+    ///     .add_post_update_hook("l_elbow_pitch", move |node: &Node<f64>| {
+    ///         *recorded.lock().unwrap() = node.world_transform();
+    ///     })
+    ///     .unwrap();
+    /// chain.update_transforms();
+    /// assert!(elbow.lock().unwrap().is_some());
+    /// assert!(chain.add_post_update_hook("not_exist", |_: &Node<f64>| {}).is_err());
+    /// ```
+    pub fn add_post_update_hook<F>(&mut self, joint_name: &str, hook: F) -> Result<(), Error>
+    where
+        F: Fn(&Node<T>) + Send + Sync + 'static,
+    {
+        self.check_hook_joint(joint_name)?;
+        self.update_hooks_mut()
+            .post
+            .push((joint_name.to_owned(), Arc::new(hook)));
+        Ok(())
+    }
+
+    /// Remove all the hooks added by `add_pre_update_hook()` and `add_post_update_hook()`
+    ///
+    /// The sink set by `set_pose_sink()` is kept.
+    pub fn clear_update_hooks(&mut self) {
+        let hooks = self.update_hooks_mut();
+        hooks.pre.clear();
+        hooks.post.clear();
+    }
+
+    /// Publish the poses calculated by `update_transforms()` to `sink`, `None` to stop it
+    ///
+    /// The sink receives the names and the world transforms of the nodes returned
+    /// by `update_transforms()`, after all of them are updated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mut chain = Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let published = Arc::new(Mutex::new(Vec::new()));
+    /// let received = published.clone();
+    /// chain.set_pose_sink(Some(Arc::new(
+    ///     move |names: &[String], poses: &[Isometry3<f64>]| {
+    ///         *received.lock().unwrap() = names.iter().cloned().zip(poses.iter().cloned()).collect();
+    ///     },
+    /// )));
+    /// let poses = chain.update_transforms();
+    /// let published = published.lock().unwrap();
+    /// assert_eq!(published.len(), poses.len());
+    /// assert_eq!(published[0].0, "root");
+    /// assert_eq!(published[0].1, poses[0]);
+    /// ```
+    pub fn set_pose_sink(&mut self, sink: Option<Arc<dyn PoseSink<T>>>) {
+        self.update_hooks_mut().sink = sink;
+    }
+
+    fn check_hook_joint(&self, joint_name: &str) -> Result<(), Error> {
+        match self.find(joint_name) {
+            Some(_) => Ok(()),
+            None => Err(Error::JointNotFoundError {
+                joint_name: joint_name.to_owned(),
+            }),
+        }
+    }
+}
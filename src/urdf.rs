@@ -16,20 +16,22 @@
 //! Load [URDF](http://wiki.ros.org/urdf) format and create `k::Chain`
 //!
 
+use super::angles;
 use super::chain::*;
 use super::joint::*;
 use super::link::*;
+use super::metadata::*;
 use super::node::*;
 use log::*;
 use na::{Isometry3, Matrix3, RealField};
 use nalgebra as na;
 use simba::scalar::SubsetOf;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
 pub const ROOT_JOINT_NAME: &str = "root";
 
-impl<'a, T> From<&'a urdf_rs::Color> for Color<T>
+impl<T> From<&urdf_rs::Color> for Color<T>
 where
     T: RealField,
 {
@@ -182,7 +184,7 @@ where
     }
 }
 
-impl<'a, T> From<&'a urdf_rs::Mimic> for Mimic<T>
+impl<T> From<&urdf_rs::Mimic> for Mimic<T>
 where
     T: RealField,
 {
@@ -212,9 +214,7 @@ pub fn quaternion_from<T>(array3: &[f64; 3]) -> na::UnitQuaternion<T>
 where
     T: RealField,
 {
-    na::convert(na::UnitQuaternion::from_euler_angles(
-        array3[0], array3[1], array3[2],
-    ))
+    na::convert(angles::quaternion_from_rpy_array(array3))
 }
 
 /// Returns nalgebra::Translation3 from f64 array
@@ -225,12 +225,22 @@ where
     na::convert(na::Translation3::new(array3[0], array3[1], array3[2]))
 }
 
-impl<'a, T> From<&'a urdf_rs::Joint> for Joint<T>
+impl<T> From<&urdf_rs::Joint> for Joint<T>
 where
     T: RealField + SubsetOf<f64>,
 {
+    /// Convert the joint of URDF
+    ///
+    /// The rotation of a floating joint is `RotationParameterization::ExponentialCoordinates`,
+    /// and the planar and floating joints have no limits.
     fn from(joint: &urdf_rs::Joint) -> Joint<T> {
-        let limit = if (joint.limit.upper - joint.limit.lower) == 0.0 {
+        let continuous = matches!(joint.joint_type, urdf_rs::JointType::Continuous);
+        // the limits of URDF are only for revolute and prismatic joints
+        let multi = matches!(
+            joint.joint_type,
+            urdf_rs::JointType::Planar | urdf_rs::JointType::Floating
+        );
+        let limit = if continuous || multi || (joint.limit.upper - joint.limit.lower) == 0.0 {
             None
         } else {
             Some(Range::new(
@@ -249,16 +259,30 @@ where
                 urdf_rs::JointType::Prismatic => JointType::Linear {
                     axis: axis_from(joint.axis.xyz),
                 },
+                urdf_rs::JointType::Planar => JointType::Planar {
+                    normal: axis_from(joint.axis.xyz),
+                },
+                urdf_rs::JointType::Floating => JointType::Floating {
+                    rotation: angles::RotationParameterization::ExponentialCoordinates,
+                },
                 _ => JointType::Fixed,
             })
             .limits(limit)
+            .continuous(continuous)
+            .velocity_limit(if joint.limit.velocity > 0.0 {
+                Some(na::convert(joint.limit.velocity))
+            } else {
+                None
+            })
+            .damping(joint.dynamics.as_ref().map(|d| na::convert(d.damping)))
+            .friction(joint.dynamics.as_ref().map(|d| na::convert(d.friction)))
             .rotation(quaternion_from(&joint.origin.rpy))
             .translation(translation_from(&joint.origin.xyz))
             .finalize()
     }
 }
 
-impl<'a, T> From<&'a urdf_rs::Robot> for Chain<T>
+impl<T> From<&urdf_rs::Robot> for Chain<T>
 where
     T: RealField + SubsetOf<f64>,
 {
@@ -271,14 +295,10 @@ where
         for j in &robot.joints {
             let node = Node::<T>::new(j.into());
             child_link_name_to_node.insert(&j.child.link, node.clone());
-            if parent_link_name_to_node.get(&j.parent.link).is_some() {
-                parent_link_name_to_node
-                    .get_mut(&j.parent.link)
-                    .unwrap()
-                    .push(node.clone());
-            } else {
-                parent_link_name_to_node.insert(&j.parent.link, vec![node.clone()]);
-            }
+            parent_link_name_to_node
+                .entry(&j.parent.link)
+                .or_default()
+                .push(node.clone());
             ref_nodes.push(node.clone());
             joint_name_to_node.insert(j.name.clone(), node);
         }
@@ -316,7 +336,9 @@ where
             info!("set parent = {}, child = {}", root_node, rjn);
             rjn.set_parent(&root_node);
         }
-        Chain::from_root(root_node)
+        let mut chain = Chain::from_root(root_node);
+        chain.set_metadata(ChainMetadata::new(&robot.name));
+        chain
     }
 }
 
@@ -333,11 +355,639 @@ impl<T> Chain<T>
 where
     T: RealField + SubsetOf<f64>,
 {
+    /// Load the chain from URDF or xacro file
+    ///
+    /// The `<metadata>` elements of the file are loaded as `metadata()`, see `ChainMetadata`.
+    /// They are ignored if they are invalid.
     pub fn from_urdf_file<P>(path: P) -> Result<Self, urdf_rs::UrdfError>
     where
         P: AsRef<Path>,
     {
-        Ok(urdf_rs::utils::read_urdf_or_xacro(path)?.into())
+        let mut chain: Self = urdf_rs::utils::read_urdf_or_xacro(path.as_ref())?.into();
+        let metadata = std::fs::read_to_string(path.as_ref())
+            .ok()
+            .and_then(|string| ChainMetadata::from_urdf_str(&string).ok());
+        if let Some(metadata) = metadata {
+            chain.set_metadata(metadata);
+        }
+        Ok(chain)
+    }
+}
+
+/// Options to create `Chain` from URDF, used by `Chain::from_urdf_robot_with_options()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrdfLoadOptions {
+    /// Limits (lower, upper) given to the prismatic joints without `<limit>`.
+    /// `None` leaves them unbounded.
+    pub default_prismatic_limits: Option<(f64, f64)>,
+    /// Limits (lower, upper) given to the revolute joints without `<limit>`.
+    /// `None` leaves them unbounded. Continuous joints are not affected.
+    pub default_revolute_limits: Option<(f64, f64)>,
+    /// Flip the sign of the multiplier of the mimic joints whose positions
+    /// always violate their own limits, if the flipped one is consistent.
+    pub correct_mimic_sign: bool,
+}
+
+impl Default for UrdfLoadOptions {
+    /// Conservative limits: ±1.0 for prismatic and ±π for revolute joints
+    fn default() -> Self {
+        Self {
+            default_prismatic_limits: Some((-1.0, 1.0)),
+            default_revolute_limits: Some((-std::f64::consts::PI, std::f64::consts::PI)),
+            correct_mimic_sign: true,
+        }
+    }
+}
+
+/// Problem found while creating `Chain` from URDF
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum UrdfLoadWarning {
+    /// The revolute or prismatic joint has no `<limit>`
+    MissingLimits {
+        joint_name: String,
+        /// limits given by `UrdfLoadOptions`, `None` if it is left unbounded
+        synthesized: Option<(f64, f64)>,
+    },
+    /// The joint mimics a joint which does not exist, and the mimic is removed
+    MissingMimicJoint {
+        joint_name: String,
+        mimic_joint: String,
+    },
+    /// `multiplier * position + offset` of the mimic joint is out of its own limits
+    /// for any position of `mimic_joint` in the limits
+    InconsistentMimic {
+        joint_name: String,
+        mimic_joint: String,
+        /// the multiplier used instead of the original one, `None` if not corrected
+        corrected_multiplier: Option<f64>,
+    },
+    /// The type of the joint (spherical) is not supported,
+    /// and it is loaded as a fixed joint
+    UnsupportedJointType {
+        joint_name: String,
+        joint_type: String,
+    },
+    /// The parent or child link of the joint does not exist. The joint is attached
+    /// to the root if the parent is missing, and has no link if the child is missing.
+    MissingLink {
+        joint_name: String,
+        link_name: String,
+    },
+    /// The link is not a child of any joint, and it is not the root link.
+    /// It is ignored, and its child joints are attached to the root.
+    UnconnectedLink { link_name: String },
+}
+
+impl<T> Chain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create `Chain` from URDF with options, and return the warnings
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut robot = urdf_rs::read_file("urdf/sample.urdf").unwrap();
+    /// robot.joints[1].limit.lower = 0.0;
+    /// robot.joints[1].limit.upper = 0.0;
+    /// let (chain, warnings) = k::Chain::<f64>::from_urdf_robot_with_options(
+    ///     &robot,
+    ///     &k::urdf::UrdfLoadOptions::default(),
+    /// );
+    /// let missing_limits = warnings
+    ///     .iter()
+    ///     .filter(|w| matches!(w, k::urdf::UrdfLoadWarning::MissingLimits { .. }))
+    ///     .count();
+    /// assert_eq!(missing_limits, 1);
+    /// let limits = chain.find(&robot.joints[1].name).unwrap().joint().limits.unwrap();
+    /// assert_eq!(limits.max, std::f64::consts::PI);
+    /// ```
+    pub fn from_urdf_robot_with_options(
+        robot: &urdf_rs::Robot,
+        options: &UrdfLoadOptions,
+    ) -> (Self, Vec<UrdfLoadWarning>) {
+        let mut robot = robot.clone();
+        let mut warnings = Vec::new();
+        validate_structure(&mut robot, &mut warnings);
+        for joint in &mut robot.joints {
+            let default_limits = match joint.joint_type {
+                urdf_rs::JointType::Revolute => options.default_revolute_limits,
+                urdf_rs::JointType::Prismatic => options.default_prismatic_limits,
+                _ => continue,
+            };
+            // urdf_rs fills zeros if `<limit>` is missing
+            if joint.limit.upper - joint.limit.lower != 0.0 {
+                continue;
+            }
+            warn!("joint {} has no limits", joint.name);
+            if let Some((lower, upper)) = default_limits {
+                joint.limit.lower = lower;
+                joint.limit.upper = upper;
+            }
+            warnings.push(UrdfLoadWarning::MissingLimits {
+                joint_name: joint.name.clone(),
+                synthesized: default_limits,
+            });
+        }
+        validate_mimics(&mut robot, options, &mut warnings);
+        (Self::from(&robot), warnings)
+    }
+
+    /// Create `Chain` from URDF file with options, and return the warnings
+    pub fn from_urdf_file_with_options<P>(
+        path: P,
+        options: &UrdfLoadOptions,
+    ) -> Result<(Self, Vec<UrdfLoadWarning>), urdf_rs::UrdfError>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::from_urdf_robot_with_options(
+            &urdf_rs::utils::read_urdf_or_xacro(path)?,
+            options,
+        ))
+    }
+
+    /// Update the origins, the axes, the limits, the dynamics and the mimic parameters of the joints
+    /// with the same names as the URDF, in place
+    ///
+    /// The current positions, the links, and the nodes which are not in the URDF
+    /// (e.g. attached frames) are kept. The structure is not changed: new joints are not
+    /// added, and the joints changed between fixed and movable are not updated.
+    /// The positions are not clamped even if the new limits exclude them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut robot = urdf_rs::read_file("urdf/sample.urdf").unwrap();
+    /// let chain = k::Chain::<f64>::from(&robot);
+    /// chain.set_joint_positions(&[0.1; 12]).unwrap();
+    ///
+    /// // the model file is modified
+    /// let name = robot.joints[1].name.clone();
+    /// robot.joints[1].origin.xyz[2] += 0.01;
+    /// let report = chain.reload_urdf(&robot);
+    /// assert!(report.updated.contains(&name));
+    /// assert_eq!(chain.joint_positions(), vec![0.1; 12]);
+    /// let z = chain.find(&name).unwrap().joint().origin().translation.vector.z;
+    /// assert_eq!(z, robot.joints[1].origin.xyz[2]);
+    /// ```
+    pub fn reload_urdf(&self, robot: &urdf_rs::Robot) -> UrdfReloadReport {
+        let mut report = UrdfReloadReport::default();
+        for urdf_joint in &robot.joints {
+            let node = match self.find(&urdf_joint.name) {
+                Some(node) => node,
+                None => {
+                    report.not_in_chain.push(urdf_joint.name.clone());
+                    continue;
+                }
+            };
+            let new_joint = Joint::<T>::from(urdf_joint);
+            let mut node_impl = node.lock();
+            if node_impl.joint.num_positions() != new_joint.num_positions() {
+                report.type_changed.push(urdf_joint.name.clone());
+                continue;
+            }
+            let joint = &mut node_impl.joint;
+            joint.joint_type = new_joint.joint_type;
+            joint.set_origin(*new_joint.origin());
+            joint.limits = new_joint.limits;
+            joint.continuous = new_joint.continuous;
+            joint.velocity_limit = new_joint.velocity_limit;
+            joint.damping = new_joint.damping;
+            joint.friction = new_joint.friction;
+            if let (Some(urdf_mimic), true) = (&urdf_joint.mimic, node_impl.mimic.is_some()) {
+                node_impl.mimic = Some(urdf_mimic.into());
+            }
+            report.updated.push(urdf_joint.name.clone());
+        }
+        report
+    }
+}
+
+/// Result of `Chain::reload_urdf()`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UrdfReloadReport {
+    /// joints which are updated
+    pub updated: Vec<String>,
+    /// joints in the URDF which are not in the chain. They are not added.
+    pub not_in_chain: Vec<String>,
+    /// joints which are changed between fixed and movable. They are not updated,
+    /// because it changes the DoF of the chain.
+    pub type_changed: Vec<String>,
+}
+
+fn validate_mimics(
+    robot: &mut urdf_rs::Robot,
+    options: &UrdfLoadOptions,
+    warnings: &mut Vec<UrdfLoadWarning>,
+) {
+    let limits = robot
+        .joints
+        .iter()
+        .map(|j| {
+            let limited = matches!(
+                j.joint_type,
+                urdf_rs::JointType::Revolute | urdf_rs::JointType::Prismatic
+            ) && j.limit.upper - j.limit.lower != 0.0;
+            (j.name.clone(), (limited, j.limit.lower, j.limit.upper))
+        })
+        .collect::<HashMap<_, _>>();
+    for joint in &mut robot.joints {
+        let mimic = match joint.mimic {
+            Some(ref mut mimic) => mimic,
+            None => continue,
+        };
+        let (parent_limited, parent_lower, parent_upper) = match limits.get(&mimic.joint) {
+            Some(l) => *l,
+            None => {
+                warn!("{} mimics {} which is not found", joint.name, mimic.joint);
+                warnings.push(UrdfLoadWarning::MissingMimicJoint {
+                    joint_name: joint.name.clone(),
+                    mimic_joint: mimic.joint.clone(),
+                });
+                joint.mimic = None;
+                continue;
+            }
+        };
+        let (limited, lower, upper) = limits[&joint.name];
+        if !parent_limited || !limited {
+            continue;
+        }
+        let multiplier = mimic.multiplier.unwrap_or(1.0);
+        let offset = mimic.offset.unwrap_or(0.0);
+        // the range of the mimic positions is an interval because it is linear
+        let is_consistent = |multiplier: f64| {
+            let a = multiplier * parent_lower + offset;
+            let b = multiplier * parent_upper + offset;
+            a.min(b) <= upper && a.max(b) >= lower
+        };
+        if is_consistent(multiplier) {
+            continue;
+        }
+        let corrected_multiplier = if options.correct_mimic_sign && is_consistent(-multiplier) {
+            mimic.multiplier = Some(-multiplier);
+            Some(-multiplier)
+        } else {
+            None
+        };
+        warn!(
+            "mimic joint {} of {} is always out of its limits",
+            joint.name, mimic.joint
+        );
+        warnings.push(UrdfLoadWarning::InconsistentMimic {
+            joint_name: joint.name.clone(),
+            mimic_joint: mimic.joint.clone(),
+            corrected_multiplier,
+        });
+    }
+}
+
+/// Find the problems of the structure which `From<&urdf_rs::Robot>` handles silently
+fn validate_structure(robot: &mut urdf_rs::Robot, warnings: &mut Vec<UrdfLoadWarning>) {
+    for joint in &mut robot.joints {
+        if matches!(joint.joint_type, urdf_rs::JointType::Spherical) {
+            warn!(
+                "joint {} is {:?}, loaded as fixed",
+                joint.name, joint.joint_type
+            );
+            warnings.push(UrdfLoadWarning::UnsupportedJointType {
+                joint_name: joint.name.clone(),
+                joint_type: format!("{:?}", joint.joint_type).to_lowercase(),
+            });
+            joint.joint_type = urdf_rs::JointType::Fixed;
+        }
+    }
+    let link_names = robot
+        .links
+        .iter()
+        .map(|l| l.name.as_str())
+        .collect::<HashSet<_>>();
+    for joint in &robot.joints {
+        for link_name in &[&joint.parent.link, &joint.child.link] {
+            if !link_names.contains(link_name.as_str()) {
+                warn!("link {} of joint {} is not found", link_name, joint.name);
+                warnings.push(UrdfLoadWarning::MissingLink {
+                    joint_name: joint.name.clone(),
+                    link_name: link_name.to_string(),
+                });
+            }
+        }
+    }
+    let child_links = robot
+        .joints
+        .iter()
+        .map(|j| j.child.link.as_str())
+        .collect::<HashSet<_>>();
+    let root_links = robot
+        .links
+        .iter()
+        .filter(|l| !child_links.contains(l.name.as_str()))
+        .collect::<Vec<_>>();
+    // the last one is used as the root link
+    if let Some((_, ignored)) = root_links.split_last() {
+        for link in ignored {
+            warn!("link {} is not connected", link.name);
+            warnings.push(UrdfLoadWarning::UnconnectedLink {
+                link_name: link.name.clone(),
+            });
+        }
+    }
+}
+
+fn to_f64<T>(value: T) -> f64
+where
+    T: RealField + SubsetOf<f64>,
+{
+    na::convert(value)
+}
+
+fn to_f64_array3<T>(vector: &na::Vector3<T>) -> [f64; 3]
+where
+    T: RealField + SubsetOf<f64>,
+{
+    [to_f64(vector[0]), to_f64(vector[1]), to_f64(vector[2])]
+}
+
+/// Returns urdf_rs::Pose from nalgebra::Isometry3
+pub fn pose_from<T>(isometry: &Isometry3<T>) -> urdf_rs::Pose
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let [roll, pitch, yaw] = angles::rpy_from_quaternion(&isometry.rotation);
+    urdf_rs::Pose {
+        xyz: to_f64_array3(&isometry.translation.vector),
+        rpy: [to_f64(roll), to_f64(pitch), to_f64(yaw)],
+    }
+}
+
+impl<T> From<&Color<T>> for urdf_rs::Color
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn from(color: &Color<T>) -> Self {
+        urdf_rs::Color {
+            rgba: [
+                to_f64(color.r),
+                to_f64(color.g),
+                to_f64(color.b),
+                to_f64(color.a),
+            ],
+        }
+    }
+}
+
+impl<T> From<&Material<T>> for urdf_rs::Material
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn from(material: &Material<T>) -> Self {
+        urdf_rs::Material {
+            name: material.name.clone(),
+            color: Some((&material.color).into()),
+            texture: if material.texture.filename.is_empty() {
+                None
+            } else {
+                Some(urdf_rs::Texture {
+                    filename: material.texture.filename.clone(),
+                })
+            },
+        }
+    }
+}
+
+impl<T> From<&Geometry<T>> for urdf_rs::Geometry
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn from(geometry: &Geometry<T>) -> Self {
+        match geometry {
+            Geometry::Box {
+                depth,
+                width,
+                height,
+            } => urdf_rs::Geometry::Box {
+                size: [to_f64(*depth), to_f64(*width), to_f64(*height)],
+            },
+            Geometry::Cylinder { radius, length } => urdf_rs::Geometry::Cylinder {
+                radius: to_f64(*radius),
+                length: to_f64(*length),
+            },
+            Geometry::Capsule { radius, length } => urdf_rs::Geometry::Capsule {
+                radius: to_f64(*radius),
+                length: to_f64(*length),
+            },
+            Geometry::Sphere { radius } => urdf_rs::Geometry::Sphere {
+                radius: to_f64(*radius),
+            },
+            Geometry::Mesh { filename, scale } => urdf_rs::Geometry::Mesh {
+                filename: filename.clone(),
+                scale: Some(to_f64_array3(scale)),
+            },
+        }
+    }
+}
+
+impl<T> From<&Inertial<T>> for urdf_rs::Inertial
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn from(inertial: &Inertial<T>) -> Self {
+        let i = &inertial.inertia;
+        urdf_rs::Inertial {
+            origin: pose_from(inertial.origin()),
+            mass: urdf_rs::Mass {
+                value: to_f64(inertial.mass),
+            },
+            inertia: urdf_rs::Inertia {
+                ixx: to_f64(i[(0, 0)]),
+                ixy: to_f64(i[(0, 1)]),
+                ixz: to_f64(i[(0, 2)]),
+                iyy: to_f64(i[(1, 1)]),
+                iyz: to_f64(i[(1, 2)]),
+                izz: to_f64(i[(2, 2)]),
+            },
+        }
+    }
+}
+
+impl<T> From<&Link<T>> for urdf_rs::Link
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn from(link: &Link<T>) -> Self {
+        urdf_rs::Link {
+            name: link.name.clone(),
+            inertial: (&link.inertial).into(),
+            visual: link
+                .visuals
+                .iter()
+                .map(|v| urdf_rs::Visual {
+                    name: Some(v.name.clone()),
+                    origin: pose_from(v.origin()),
+                    geometry: (&v.geometry).into(),
+                    material: Some((&v.material).into()),
+                })
+                .collect(),
+            collision: link
+                .collisions
+                .iter()
+                .map(|c| urdf_rs::Collision {
+                    name: Some(c.name.clone()),
+                    origin: pose_from(c.origin()),
+                    geometry: (&c.geometry).into(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Name of the link of the node. If the node has no link, the joint name is used.
+fn link_name_of<T>(node: &Node<T>) -> String
+where
+    T: RealField + SubsetOf<f64>,
+{
+    let link_name = node.link().as_ref().map(|link| link.name.clone());
+    link_name.unwrap_or_else(|| node.joint().name.clone())
+}
+
+impl<T> From<&Chain<T>> for urdf_rs::Robot
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Export the chain as URDF structure
+    ///
+    /// The joint of the root node is not exported, because URDF does not have
+    /// a joint above the root link. Nodes without links are exported with
+    /// a link named after the joint. The effort limits are not stored in `k`,
+    /// so they are zero. URDF has no universal joint, so a `JointType::Universal`
+    /// is exported as two revolute joints `"<name>"` and `"<name>_second"` connected
+    /// by an empty link `"<name>_universal"`, which is loaded as two joints.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    /// let robot: urdf_rs::Robot = (&chain).into();
+    /// assert_eq!(robot.joints.len(), 12);
+    /// assert_eq!(robot.links.len(), 13);
+    /// let chain2: k::Chain<f64> = robot.into();
+    /// assert_eq!(chain2.dof(), 12);
+    /// ```
+    fn from(chain: &Chain<T>) -> Self {
+        let mut links = Vec::new();
+        let mut joints = Vec::new();
+        for node in chain.iter() {
+            let link_name = link_name_of(node);
+            links.push(match *node.link() {
+                Some(ref link) => link.into(),
+                None => urdf_rs::Link {
+                    name: link_name.clone(),
+                    inertial: Default::default(),
+                    visual: Vec::new(),
+                    collision: Vec::new(),
+                },
+            });
+            let parent = match node.parent() {
+                Some(parent) => parent,
+                None => continue,
+            };
+            let mimic = match (node.mimic_parent(), node.mimic()) {
+                (Some(mimic_parent), Some(mimic)) => Some(urdf_rs::Mimic {
+                    joint: mimic_parent.joint().name.clone(),
+                    multiplier: Some(to_f64(mimic.multiplier)),
+                    offset: Some(to_f64(mimic.origin)),
+                }),
+                _ => None,
+            };
+            let joint = node.joint();
+            let (joint_type, axis) = match joint.joint_type {
+                JointType::Fixed => (urdf_rs::JointType::Fixed, na::Vector3::x_axis()),
+                JointType::Rotational { axis } => {
+                    if joint.limits.is_some() && !joint.continuous {
+                        (urdf_rs::JointType::Revolute, axis)
+                    } else {
+                        (urdf_rs::JointType::Continuous, axis)
+                    }
+                }
+                JointType::Linear { axis } => (urdf_rs::JointType::Prismatic, axis),
+                JointType::Planar { normal } => (urdf_rs::JointType::Planar, normal),
+                JointType::Floating { .. } => (urdf_rs::JointType::Floating, na::Vector3::x_axis()),
+                JointType::Universal { first_axis, .. } => {
+                    if joint.limits.is_some() && !joint.continuous {
+                        (urdf_rs::JointType::Revolute, first_axis)
+                    } else {
+                        (urdf_rs::JointType::Continuous, first_axis)
+                    }
+                }
+            };
+            let mut limit = match joint.limits {
+                Some(ref range) if !joint.continuous => urdf_rs::JointLimit {
+                    lower: to_f64(range.min),
+                    upper: to_f64(range.max),
+                    ..Default::default()
+                },
+                _ => urdf_rs::JointLimit::default(),
+            };
+            if let Some(velocity_limit) = joint.velocity_limit {
+                limit.velocity = to_f64(velocity_limit);
+            }
+            let mut exported = urdf_rs::Joint {
+                name: joint.name.clone(),
+                joint_type,
+                origin: pose_from(joint.origin()),
+                parent: urdf_rs::LinkName {
+                    link: link_name_of(&parent),
+                },
+                child: urdf_rs::LinkName { link: link_name },
+                axis: urdf_rs::Axis {
+                    xyz: to_f64_array3(&axis),
+                },
+                limit,
+                dynamics: if joint.damping.is_some() || joint.friction.is_some() {
+                    Some(urdf_rs::Dynamics {
+                        damping: joint.damping.map(to_f64).unwrap_or(0.0),
+                        friction: joint.friction.map(to_f64).unwrap_or(0.0),
+                    })
+                } else {
+                    None
+                },
+                mimic,
+                safety_controller: None,
+            };
+            if let JointType::Universal { second_axis, .. } = joint.joint_type {
+                let middle_link_name = format!("{}_universal", joint.name);
+                links.push(urdf_rs::Link {
+                    name: middle_link_name.clone(),
+                    inertial: Default::default(),
+                    visual: Vec::new(),
+                    collision: Vec::new(),
+                });
+                let second = urdf_rs::Joint {
+                    name: format!("{}_second", joint.name),
+                    origin: urdf_rs::Pose::default(),
+                    parent: urdf_rs::LinkName {
+                        link: middle_link_name.clone(),
+                    },
+                    child: exported.child.clone(),
+                    axis: urdf_rs::Axis {
+                        xyz: to_f64_array3(&second_axis),
+                    },
+                    ..exported.clone()
+                };
+                exported.child.link = middle_link_name;
+                joints.push(exported);
+                joints.push(second);
+            } else {
+                joints.push(exported);
+            }
+        }
+        urdf_rs::Robot {
+            name: chain.metadata().name.clone(),
+            links,
+            joints,
+            materials: Vec::new(),
+        }
     }
 }
 
@@ -362,9 +1012,8 @@ pub fn link_to_joint_map(urdf_robot: &urdf_rs::Robot) -> HashMap<String, String>
         map.insert(j.child.link.to_owned(), j.name.to_owned());
     }
     for l in &urdf_robot.links {
-        if map.get(&l.name).is_none() {
-            map.insert(l.name.to_owned(), ROOT_JOINT_NAME.to_owned());
-        }
+        map.entry(l.name.to_owned())
+            .or_insert_with(|| ROOT_JOINT_NAME.to_owned());
     }
     map
 }
@@ -375,7 +1024,7 @@ pub fn joint_to_link_map(urdf_robot: &urdf_rs::Robot) -> HashMap<String, String>
         map.insert(j.name.to_owned(), j.child.link.to_owned());
     }
     for l in &urdf_robot.links {
-        if map.get(&l.name).is_none() {
+        if !map.contains_key(&l.name) {
             map.insert(ROOT_JOINT_NAME.to_owned(), l.name.to_owned());
         }
     }
@@ -408,3 +1057,271 @@ fn test_tree_from_file() {
     assert_eq!(names[0], "root");
     assert_eq!(names[1], "r_shoulder_yaw");
 }
+
+#[test]
+fn test_export_mimic() {
+    let j0 = NodeBuilder::<f64>::new()
+        .name("j0")
+        .joint_type(JointType::Linear {
+            axis: na::Vector3::z_axis(),
+        })
+        .into_node();
+    let j1 = NodeBuilder::new()
+        .name("j1")
+        .joint_type(JointType::Rotational {
+            axis: na::Vector3::y_axis(),
+        })
+        .limits(Some((-1.0..=1.0).into()))
+        .into_node();
+    let j2 = NodeBuilder::new()
+        .name("j2")
+        .joint_type(JointType::Rotational {
+            axis: na::Vector3::y_axis(),
+        })
+        .into_node();
+    j1.set_parent(&j0);
+    j2.set_parent(&j0);
+    j2.set_mimic_parent(&j1, Mimic::new(-2.0, 0.1));
+    let chain = Chain::from_root(j0);
+
+    let robot = urdf_rs::Robot::from(&chain);
+    assert_eq!(robot.joints.len(), 2);
+    let j2_urdf = robot.joints.iter().find(|j| j.name == "j2").unwrap();
+    assert_eq!(j2_urdf.joint_type, urdf_rs::JointType::Continuous);
+    let mimic = j2_urdf.mimic.as_ref().unwrap();
+    assert_eq!(mimic.joint, "j1");
+    assert_eq!(mimic.multiplier, Some(-2.0));
+    assert_eq!(mimic.offset, Some(0.1));
+
+    let loaded = Chain::<f64>::from(&robot);
+    let loaded_j2 = loaded.find("j2").unwrap();
+    assert_eq!(loaded_j2.mimic_parent().unwrap().joint().name, "j1");
+    assert_eq!(loaded_j2.mimic().unwrap().multiplier, -2.0);
+    loaded.find("j1").unwrap().set_joint_position(0.5).unwrap();
+    assert!((loaded_j2.joint_position().unwrap() + 0.9).abs() < 1e-10);
+}
+
+#[test]
+fn test_missing_prismatic_limits() {
+    let robot = urdf_rs::read_from_string(
+        r#"<robot name="slider">
+  <link name="base"/>
+  <link name="tip"/>
+  <joint name="slide" type="prismatic">
+    <parent link="base"/>
+    <child link="tip"/>
+    <axis xyz="1 0 0"/>
+  </joint>
+</robot>"#,
+    )
+    .unwrap();
+    let chain = Chain::<f64>::from(&robot);
+    assert!(chain.find("slide").unwrap().joint().limits.is_none());
+
+    let options = UrdfLoadOptions {
+        default_prismatic_limits: Some((-0.1, 0.2)),
+        ..Default::default()
+    };
+    let (chain, warnings) = Chain::<f64>::from_urdf_robot_with_options(&robot, &options);
+    let limits = chain.find("slide").unwrap().joint().limits.unwrap();
+    assert_eq!((limits.min, limits.max), (-0.1, 0.2));
+    assert_eq!(
+        warnings,
+        vec![UrdfLoadWarning::MissingLimits {
+            joint_name: "slide".to_owned(),
+            synthesized: Some((-0.1, 0.2)),
+        }]
+    );
+
+    let options = UrdfLoadOptions {
+        default_prismatic_limits: None,
+        ..Default::default()
+    };
+    let (chain, warnings) = Chain::<f64>::from_urdf_robot_with_options(&robot, &options);
+    assert!(chain.find("slide").unwrap().joint().limits.is_none());
+    assert_eq!(warnings.len(), 1);
+}
+
+#[test]
+fn test_inconsistent_mimic() {
+    let urdf = |mimic_joint: &str| {
+        format!(
+            r#"<robot name="gripper">
+  <link name="base"/>
+  <link name="l"/>
+  <link name="r"/>
+  <joint name="finger_l" type="prismatic">
+    <parent link="base"/>
+    <child link="l"/>
+    <axis xyz="0 1 0"/>
+    <limit lower="0.0" upper="0.04" effort="1" velocity="1"/>
+  </joint>
+  <joint name="finger_r" type="prismatic">
+    <parent link="base"/>
+    <child link="r"/>
+    <axis xyz="0 -1 0"/>
+    <limit lower="0.01" upper="0.04" effort="1" velocity="1"/>
+    <mimic joint="{}" multiplier="-1.0"/>
+  </joint>
+</robot>"#,
+            mimic_joint
+        )
+    };
+    let robot = urdf_rs::read_from_string(&urdf("finger_l")).unwrap();
+    let (chain, warnings) =
+        Chain::<f64>::from_urdf_robot_with_options(&robot, &UrdfLoadOptions::default());
+    assert_eq!(
+        warnings,
+        vec![UrdfLoadWarning::InconsistentMimic {
+            joint_name: "finger_r".to_owned(),
+            mimic_joint: "finger_l".to_owned(),
+            corrected_multiplier: Some(1.0),
+        }]
+    );
+    chain.set_joint_positions(&[0.03, 0.03]).unwrap();
+    assert_eq!(chain.find("finger_r").unwrap().joint_position(), Some(0.03));
+
+    let options = UrdfLoadOptions {
+        correct_mimic_sign: false,
+        ..Default::default()
+    };
+    let (_, warnings) = Chain::<f64>::from_urdf_robot_with_options(&robot, &options);
+    assert!(matches!(
+        warnings[0],
+        UrdfLoadWarning::InconsistentMimic {
+            corrected_multiplier: None,
+            ..
+        }
+    ));
+
+    let robot = urdf_rs::read_from_string(&urdf("finger_x")).unwrap();
+    let (chain, warnings) =
+        Chain::<f64>::from_urdf_robot_with_options(&robot, &UrdfLoadOptions::default());
+    assert_eq!(chain.dof(), 2);
+    assert!(matches!(
+        warnings[0],
+        UrdfLoadWarning::MissingMimicJoint { .. }
+    ));
+}
+
+#[test]
+fn test_structure_warnings() {
+    let robot = urdf_rs::read_from_string(
+        r#"<robot name="broken">
+  <link name="unused"/>
+  <link name="base"/>
+  <link name="tip"/>
+  <joint name="ball" type="spherical">
+    <parent link="base"/>
+    <child link="tip"/>
+  </joint>
+  <joint name="dangling" type="fixed">
+    <parent link="tip"/>
+    <child link="camera"/>
+  </joint>
+</robot>"#,
+    )
+    .unwrap();
+    let (chain, warnings) =
+        Chain::<f64>::from_urdf_robot_with_options(&robot, &UrdfLoadOptions::default());
+    assert_eq!(chain.dof(), 0);
+    assert_eq!(
+        warnings,
+        vec![
+            UrdfLoadWarning::UnsupportedJointType {
+                joint_name: "ball".to_owned(),
+                joint_type: "spherical".to_owned(),
+            },
+            UrdfLoadWarning::MissingLink {
+                joint_name: "dangling".to_owned(),
+                link_name: "camera".to_owned(),
+            },
+            UrdfLoadWarning::UnconnectedLink {
+                link_name: "unused".to_owned(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_floating_joint() {
+    let robot = urdf_rs::read_from_string(
+        r#"<robot name="drone">
+  <link name="world"/>
+  <link name="body"/>
+  <link name="camera"/>
+  <joint name="float" type="floating">
+    <origin xyz="0 0 1.0"/>
+    <parent link="world"/>
+    <child link="body"/>
+  </joint>
+  <joint name="camera_joint" type="fixed">
+    <origin xyz="0.2 0 0"/>
+    <parent link="body"/>
+    <child link="camera"/>
+  </joint>
+</robot>"#,
+    )
+    .unwrap();
+    let (chain, warnings) =
+        Chain::<f64>::from_urdf_robot_with_options(&robot, &UrdfLoadOptions::default());
+    assert!(warnings.is_empty());
+    assert_eq!(chain.dof(), 6);
+    let float = chain.find("float").unwrap();
+    assert_eq!(float.link().as_ref().unwrap().name, "body");
+    float
+        .set_floating_pose(&Isometry3::new(
+            na::Vector3::new(1.0, 0.0, 0.0),
+            na::Vector3::new(0.0, 0.0, std::f64::consts::FRAC_PI_2),
+        ))
+        .unwrap();
+    chain.update_transforms();
+    let camera = chain
+        .find("camera_joint")
+        .unwrap()
+        .world_transform()
+        .unwrap();
+    assert!((camera.translation.vector - na::Vector3::new(1.0, 0.2, 1.0)).norm() < 1e-10);
+
+    let exported = urdf_rs::Robot::from(&chain);
+    assert_eq!(exported.joints[0].joint_type, urdf_rs::JointType::Floating);
+    let report = chain.reload_urdf(&robot);
+    assert!(report.not_in_chain.is_empty());
+    assert!(report.type_changed.is_empty());
+}
+
+#[test]
+fn test_continuous_joint() {
+    let robot = urdf_rs::read_from_string(
+        r#"<robot name="wheel">
+  <link name="base"/>
+  <link name="wheel"/>
+  <joint name="axle" type="continuous">
+    <parent link="base"/>
+    <child link="wheel"/>
+    <axis xyz="0 1 0"/>
+  </joint>
+</robot>"#,
+    )
+    .unwrap();
+    let chain = Chain::<f64>::from(&robot);
+    let axle = chain.find("axle").unwrap();
+    assert!(axle.joint().continuous);
+    assert!(axle.joint().limits.is_none());
+    axle.set_joint_position(10.0).unwrap();
+    assert_eq!(axle.joint_position().unwrap(), 10.0);
+
+    let exported = urdf_rs::Robot::from(&chain);
+    assert_eq!(
+        exported.joints[0].joint_type,
+        urdf_rs::JointType::Continuous
+    );
+
+    let mut revolute = robot.clone();
+    revolute.joints[0].joint_type = urdf_rs::JointType::Revolute;
+    revolute.joints[0].limit.lower = -1.0;
+    revolute.joints[0].limit.upper = 1.0;
+    chain.reload_urdf(&revolute);
+    assert!(!axle.joint().continuous);
+    assert!(axle.set_joint_position(10.0).is_err());
+}
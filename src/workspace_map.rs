@@ -0,0 +1,490 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use super::orientation_reach::*;
+use na::{RealField, Vector3};
+use nalgebra as na;
+#[cfg(feature = "serde-serialize")]
+use serde::{Deserialize, Serialize};
+use simba::scalar::SubsetOf;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Magic bytes at the head of the files of `WorkspaceMap`, with the version of the format
+const MAGIC: &[u8; 8] = b"KWSMAP01";
+/// Length of the header: the magic, `min` and `voxel_size` as f64 and `dims` as u64
+const HEADER_LEN: usize = 8 + 4 * 8 + 3 * 8;
+
+/// Box divided into the voxels of a workspace map
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+struct VoxelGrid<T: RealField> {
+    min: Vector3<T>,
+    voxel_size: T,
+    dims: [usize; 3],
+}
+
+impl<T> VoxelGrid<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    fn num_voxels(&self) -> Option<usize> {
+        self.dims
+            .iter()
+            .try_fold(1usize, |num, dim| num.checked_mul(*dim))
+    }
+
+    /// Index of the voxel containing `point`, `None` if it is out of the box
+    fn index(&self, point: &Vector3<T>) -> Option<usize> {
+        let mut index = 0;
+        for axis in (0..3).rev() {
+            let offset = ((point[axis] - self.min[axis]) / self.voxel_size).floor();
+            if offset < T::zero() {
+                return None;
+            }
+            let offset = na::try_convert::<T, f64>(offset)? as usize;
+            if offset >= self.dims[axis] {
+                return None;
+            }
+            index = index * self.dims[axis] + offset;
+        }
+        Some(index)
+    }
+
+    fn center(&self, index: usize) -> Vector3<T> {
+        let x = index % self.dims[0];
+        let y = index / self.dims[0] % self.dims[1];
+        let z = index / (self.dims[0] * self.dims[1]);
+        let half: T = na::convert(0.5);
+        self.min
+            + Vector3::new(
+                na::convert::<f64, T>(x as f64) + half,
+                na::convert::<f64, T>(y as f64) + half,
+                na::convert::<f64, T>(z as f64) + half,
+            ) * self.voxel_size
+    }
+
+    fn header(&self) -> [u8; HEADER_LEN] {
+        let mut header = [0; HEADER_LEN];
+        header[..8].copy_from_slice(MAGIC);
+        let floats = [self.min.x, self.min.y, self.min.z, self.voxel_size];
+        for (i, value) in floats.iter().enumerate() {
+            let value = na::try_convert::<T, f64>(*value).unwrap_or_default();
+            header[8 + 8 * i..16 + 8 * i].copy_from_slice(&value.to_le_bytes());
+        }
+        for (i, dim) in self.dims.iter().enumerate() {
+            header[40 + 8 * i..48 + 8 * i].copy_from_slice(&(*dim as u64).to_le_bytes());
+        }
+        header
+    }
+
+    /// Parse the header, and check that `len` bytes of the voxels follow it
+    fn from_header(header: &[u8], len: usize) -> Result<Self, Error> {
+        if header.len() < HEADER_LEN || header[..8] != MAGIC[..] {
+            return Err(workspace_map_error("not a workspace map".to_owned()));
+        }
+        let float = |i: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&header[8 + 8 * i..16 + 8 * i]);
+            na::convert(f64::from_le_bytes(bytes))
+        };
+        let dim = |i: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&header[40 + 8 * i..48 + 8 * i]);
+            usize::try_from(u64::from_le_bytes(bytes))
+                .map_err(|_| workspace_map_error("too many voxels".to_owned()))
+        };
+        VoxelGrid {
+            min: Vector3::new(float(0), float(1), float(2)),
+            voxel_size: float(3),
+            dims: [dim(0)?, dim(1)?, dim(2)?],
+        }
+        .check(len)
+    }
+
+    /// Check the voxel size, and that there are `len` voxels
+    fn check(self, len: usize) -> Result<Self, Error> {
+        if self.voxel_size <= T::zero() {
+            return Err(workspace_map_error(format!(
+                "invalid voxel size {}",
+                self.voxel_size
+            )));
+        }
+        let num_voxels = self
+            .num_voxels()
+            .ok_or_else(|| workspace_map_error("too many voxels".to_owned()))?;
+        if num_voxels != len {
+            return Err(workspace_map_error(format!(
+                "{} voxels are expected, but there are {}",
+                num_voxels, len
+            )));
+        }
+        Ok(self)
+    }
+}
+
+fn workspace_map_error(reason: String) -> Error {
+    Error::WorkspaceMapError { reason }
+}
+
+fn reachability_of<T: RealField>(voxel: u8) -> T {
+    na::convert(f64::from(voxel) / 255.0)
+}
+
+/// Reachability of the voxels of a box around an arm, the capability map of the arm
+///
+/// Each voxel has the ratio of the tool directions reachable at its center, estimated by
+/// `reachable_orientations()` and quantized to `0..=255`. Generating it takes long, so
+/// it can be saved by `save()` and loaded by `load()`, or memory-mapped by
+/// `MappedWorkspaceMap` if the `mmap` feature is enabled. It is also serializable with
+/// serde if the `serde-serialize` feature is enabled, and the deserialized voxels are
+/// checked same as `load()`.
+///
+/// The file is the header followed by the voxels as `u8`, x first
+/// (`x + nx * (y + ny * z)`). The header is the magic `KWSMAP01`, the minimum corner
+/// (x, y, z) and the voxel size as `f64`, and the number of the voxels along x, y and z
+/// as `u64`, all of them little endian.
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let end = arm.end_transform().translation.vector;
+///
+/// let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 20);
+/// let half = k::Vector3::new(0.05, 0.05, 0.05);
+/// // a voxel around the end, with 8 directions
+/// let map = k::WorkspaceMap::generate(&solver, &arm, &(end - half), &(end + half), 0.1, 8)
+///     .unwrap();
+/// assert_eq!(map.dims(), [1, 1, 1]);
+/// assert!(map.reachability(&end).unwrap() > 0.0);
+/// assert!(map.reachability(&(end + k::Vector3::new(1.0, 0.0, 0.0))).is_none());
+///
+/// let path = std::env::temp_dir().join("k_workspace_map_example.bin");
+/// map.save(&path).unwrap();
+/// assert_eq!(k::WorkspaceMap::load(&path).unwrap(), map);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde-serialize",
+    serde(
+        try_from = "UncheckedWorkspaceMap<T>",
+        bound(deserialize = "T: RealField + SubsetOf<f64> + Deserialize<'de>")
+    )
+)]
+pub struct WorkspaceMap<T: RealField> {
+    grid: VoxelGrid<T>,
+    voxels: Vec<u8>,
+}
+
+/// `WorkspaceMap` deserialized by serde, before the checks of the voxels
+#[cfg(feature = "serde-serialize")]
+#[derive(Deserialize)]
+struct UncheckedWorkspaceMap<T: RealField> {
+    grid: VoxelGrid<T>,
+    voxels: Vec<u8>,
+}
+
+#[cfg(feature = "serde-serialize")]
+impl<T> TryFrom<UncheckedWorkspaceMap<T>> for WorkspaceMap<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    type Error = Error;
+
+    fn try_from(map: UncheckedWorkspaceMap<T>) -> Result<Self, Error> {
+        Ok(WorkspaceMap {
+            grid: map.grid.check(map.voxels.len())?,
+            voxels: map.voxels,
+        })
+    }
+}
+
+impl<T> WorkspaceMap<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Estimate the reachability of the voxels of the box from `min` to `max`
+    ///
+    /// The points are in the frame of the base of `arm`, same as `end_transform()`.
+    /// `num_directions` directions are tried at the center of each voxel by
+    /// `reachable_orientations()`. The box is extended to a multiple of `voxel_size`.
+    /// It returns `Error::InvalidParameterError` if `voxel_size` is not positive or
+    /// `max` is smaller than `min`.
+    pub fn generate(
+        solver: &JacobianIkSolver<T>,
+        arm: &SerialChain<T>,
+        min: &Vector3<T>,
+        max: &Vector3<T>,
+        voxel_size: T,
+        num_directions: usize,
+    ) -> Result<Self, Error> {
+        if voxel_size <= T::zero() || (0..3).any(|i| max[i] < min[i]) {
+            return Err(Error::InvalidParameterError {
+                reason: format!("invalid box {} - {} or voxel size {}", min, max, voxel_size),
+            });
+        }
+        let mut dims = [0; 3];
+        for (i, dim) in dims.iter_mut().enumerate() {
+            let num = ((max[i] - min[i]) / voxel_size).ceil().max(T::one());
+            *dim = na::try_convert::<T, f64>(num).unwrap_or(1.0) as usize;
+        }
+        let grid = VoxelGrid {
+            min: *min,
+            voxel_size,
+            dims,
+        };
+        let num_voxels = grid
+            .num_voxels()
+            .ok_or_else(|| Error::InvalidParameterError {
+                reason: format!("too many voxels {:?}", dims),
+            })?;
+        let voxels = (0..num_voxels)
+            .map(|index| {
+                let orientations =
+                    reachable_orientations(solver, arm, &grid.center(index), num_directions);
+                let fraction = na::try_convert::<T, f64>(orientations.fraction()).unwrap_or(0.0);
+                (fraction * 255.0).round() as u8
+            })
+            .collect();
+        Ok(WorkspaceMap { grid, voxels })
+    }
+
+    /// The minimum corner of the box
+    pub fn min(&self) -> Vector3<T> {
+        self.grid.min
+    }
+
+    /// Edge length of the voxels
+    pub fn voxel_size(&self) -> T {
+        self.grid.voxel_size
+    }
+
+    /// The number of the voxels along x, y and z
+    pub fn dims(&self) -> [usize; 3] {
+        self.grid.dims
+    }
+
+    /// Reachability of the voxels, 255 if all the directions are reachable
+    pub fn voxels(&self) -> &[u8] {
+        &self.voxels
+    }
+
+    /// Ratio of the reachable directions in the voxel containing `point`, `None` if
+    /// `point` is out of the box
+    pub fn reachability(&self, point: &Vector3<T>) -> Option<T> {
+        self.grid
+            .index(point)
+            .map(|index| reachability_of(self.voxels[index]))
+    }
+
+    /// Write the map in the format described in `WorkspaceMap`
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writer
+            .write_all(&self.grid.header())
+            .and_then(|_| writer.write_all(&self.voxels))
+            .map_err(|e| workspace_map_error(format!("failed to write: {}", e)))
+    }
+
+    /// Read the map written by `write_to()`
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut header = [0; HEADER_LEN];
+        let mut voxels = Vec::new();
+        reader
+            .read_exact(&mut header)
+            .and_then(|_| reader.read_to_end(&mut voxels))
+            .map_err(|e| workspace_map_error(format!("failed to read: {}", e)))?;
+        let grid = VoxelGrid::from_header(&header, voxels.len())?;
+        Ok(WorkspaceMap { grid, voxels })
+    }
+
+    /// Save the map to the file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = std::fs::File::create(path.as_ref()).map_err(|e| {
+            workspace_map_error(format!(
+                "failed to create {}: {}",
+                path.as_ref().display(),
+                e
+            ))
+        })?;
+        self.write_to(std::io::BufWriter::new(file))
+    }
+
+    /// Load the map from the file saved by `save()`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = std::fs::File::open(path.as_ref()).map_err(|e| {
+            workspace_map_error(format!("failed to open {}: {}", path.as_ref().display(), e))
+        })?;
+        Self::read_from(std::io::BufReader::new(file))
+    }
+}
+
+/// `WorkspaceMap` memory-mapped from the file, without reading all the voxels
+///
+/// The voxels are read from the file on demand by the OS, so large maps are loaded
+/// instantly and shared by the processes. It is available with the `mmap` feature.
+///
+/// # Examples
+///
+/// ```
+/// use k::nalgebra::Vector3;
+///
+/// let map = k::WorkspaceMap::<f64>::read_from(
+///     // 2 x 1 x 1 voxels of 0.5 from the origin, the first one is reachable
+///     [
+///         &b"KWSMAP01"[..],
+///         &0.0f64.to_le_bytes(), &0.0f64.to_le_bytes(), &0.0f64.to_le_bytes(),
+///         &0.5f64.to_le_bytes(),
+///         &2u64.to_le_bytes(), &1u64.to_le_bytes(), &1u64.to_le_bytes(),
+///         &[255, 0],
+///     ]
+///     .concat()
+///     .as_slice(),
+/// )
+/// .unwrap();
+/// let path = std::env::temp_dir().join("k_mapped_workspace_map_example.bin");
+/// map.save(&path).unwrap();
+///
+/// let mapped = k::MappedWorkspaceMap::<f64>::open(&path).unwrap();
+/// assert_eq!(mapped.reachability(&Vector3::new(0.2, 0.2, 0.2)), Some(1.0));
+/// assert_eq!(mapped.reachability(&Vector3::new(0.7, 0.2, 0.2)), Some(0.0));
+/// assert_eq!(mapped.reachability(&Vector3::new(1.2, 0.2, 0.2)), None);
+/// assert_eq!(mapped.to_workspace_map(), map);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct MappedWorkspaceMap<T: RealField> {
+    grid: VoxelGrid<T>,
+    mmap: memmap2::Mmap,
+}
+
+#[cfg(feature = "mmap")]
+impl<T> MappedWorkspaceMap<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Map the file saved by `WorkspaceMap::save()`
+    ///
+    /// The file must not be modified while it is mapped.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = std::fs::File::open(path.as_ref()).map_err(|e| {
+            workspace_map_error(format!("failed to open {}: {}", path.as_ref().display(), e))
+        })?;
+        // Safety: the file is not modified while mapped, as documented
+        let mmap = unsafe { memmap2::Mmap::map(&file) }.map_err(|e| {
+            workspace_map_error(format!("failed to map {}: {}", path.as_ref().display(), e))
+        })?;
+        let grid = VoxelGrid::from_header(&mmap, mmap.len().saturating_sub(HEADER_LEN))?;
+        Ok(MappedWorkspaceMap { grid, mmap })
+    }
+
+    /// The minimum corner of the box
+    pub fn min(&self) -> Vector3<T> {
+        self.grid.min
+    }
+
+    /// Edge length of the voxels
+    pub fn voxel_size(&self) -> T {
+        self.grid.voxel_size
+    }
+
+    /// The number of the voxels along x, y and z
+    pub fn dims(&self) -> [usize; 3] {
+        self.grid.dims
+    }
+
+    /// Reachability of the voxels in the file, see `WorkspaceMap::voxels()`
+    pub fn voxels(&self) -> &[u8] {
+        &self.mmap[HEADER_LEN..]
+    }
+
+    /// Same as `WorkspaceMap::reachability()`
+    pub fn reachability(&self, point: &Vector3<T>) -> Option<T> {
+        self.grid
+            .index(point)
+            .map(|index| reachability_of(self.voxels()[index]))
+    }
+
+    /// Copy the voxels into `WorkspaceMap`
+    pub fn to_workspace_map(&self) -> WorkspaceMap<T> {
+        WorkspaceMap {
+            grid: self.grid.clone(),
+            voxels: self.voxels().to_vec(),
+        }
+    }
+}
+
+#[test]
+fn test_workspace_map_errors() {
+    let map = WorkspaceMap::<f64> {
+        grid: VoxelGrid {
+            min: Vector3::new(-1.0, -1.0, 0.0),
+            voxel_size: 0.5,
+            dims: [4, 4, 2],
+        },
+        voxels: (0..32).collect(),
+    };
+    assert_eq!(map.reachability(&Vector3::new(-0.9, -0.9, 0.1)), Some(0.0));
+    assert_eq!(
+        map.reachability(&Vector3::new(0.9, 0.9, 0.9)),
+        Some(31.0 / 255.0)
+    );
+    assert_eq!(map.grid.index(&map.grid.center(13)), Some(13));
+    assert!(map.reachability(&Vector3::new(-1.1, 0.0, 0.0)).is_none());
+    assert!(map.reachability(&Vector3::new(0.0, 0.0, 1.0)).is_none());
+
+    let mut bytes = Vec::new();
+    map.write_to(&mut bytes).unwrap();
+    assert_eq!(bytes.len(), HEADER_LEN + 32);
+    assert_eq!(WorkspaceMap::read_from(bytes.as_slice()).unwrap(), map);
+    // truncated
+    assert!(matches!(
+        WorkspaceMap::<f64>::read_from(&bytes[..bytes.len() - 1]),
+        Err(Error::WorkspaceMapError { .. })
+    ));
+    assert!(WorkspaceMap::<f64>::read_from(&bytes[..10]).is_err());
+    // wrong magic
+    bytes[7] = b'2';
+    assert!(WorkspaceMap::<f64>::read_from(bytes.as_slice()).is_err());
+    assert!(WorkspaceMap::<f64>::load("not_exist.bin").is_err());
+
+    #[cfg(feature = "serde-serialize")]
+    {
+        let unchecked = |voxel_size, voxels| UncheckedWorkspaceMap::<f64> {
+            grid: VoxelGrid {
+                voxel_size,
+                ..map.grid.clone()
+            },
+            voxels,
+        };
+        assert_eq!(
+            WorkspaceMap::try_from(unchecked(0.5, (0..32).collect())).unwrap(),
+            map
+        );
+        assert!(WorkspaceMap::try_from(unchecked(0.5, (0..31).collect())).is_err());
+        assert!(WorkspaceMap::try_from(unchecked(0.0, (0..32).collect())).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::errors::*;
+use super::ik::*;
+use super::node::*;
+use na::{Isometry3, RealField};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// Container of named chains (robots and objects) placed in the same world frame
+///
+/// The placement of each chain is its `Chain::origin()`. A frame in the world is
+/// named `"<chain name>/<joint name>"`.
+///
+/// # Examples
+///
+/// ```
+/// use k::*;
+///
+/// let slot = NodeBuilder::new()
+///     .name("slot_3")
+///     .translation(Translation3::new(0.5, 0.0, 0.0))
+///     .into_node();
+/// let belt = NodeBuilder::new()
+///     .name("belt")
+///     .joint_type(JointType::Linear{axis: Vector3::x_axis()})
+///     .into_node();
+/// slot.set_parent(&belt);
+/// let conveyor = Chain::<f64>::from_root(belt);
+/// conveyor.set_origin(Isometry3::translation(1.0, 0.0, 0.0));
+///
+/// let mut world = World::new();
+/// world.add_chain("conveyor", conveyor);
+/// let pose = world.frame_transform("conveyor/slot_3").unwrap();
+/// assert_eq!(pose.translation.vector.x, 1.5);
+/// assert!(world.frame_transform("conveyor/none").is_err());
+/// ```
+#[derive(Debug, Default)]
+pub struct World<T: RealField> {
+    chains: Vec<(String, Chain<T>)>,
+}
+
+impl<T> World<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    pub fn new() -> Self {
+        Self { chains: Vec::new() }
+    }
+
+    /// Add a chain with the name. The chain with the same name is replaced.
+    pub fn add_chain(&mut self, name: &str, chain: Chain<T>) {
+        match self.chains.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = chain,
+            None => self.chains.push((name.to_owned(), chain)),
+        }
+    }
+
+    /// Get the chain by the name
+    pub fn chain(&self, name: &str) -> Option<&Chain<T>> {
+        self.chains.iter().find(|(n, _)| n == name).map(|(_, c)| c)
+    }
+
+    /// Iterate the names and the chains
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Chain<T>)> {
+        self.chains.iter().map(|(n, c)| (n.as_str(), c))
+    }
+
+    /// Find the node of the frame `"<chain name>/<joint name>"`
+    pub fn find_frame(&self, frame: &str) -> Result<&Node<T>, Error> {
+        let not_found = || Error::JointNotFoundError {
+            joint_name: frame.to_owned(),
+        };
+        let mut names = frame.splitn(2, '/');
+        let chain_name = names.next().ok_or_else(not_found)?;
+        let joint_name = names.next().ok_or_else(not_found)?;
+        self.chain(chain_name)
+            .and_then(|chain| chain.find(joint_name))
+            .ok_or_else(not_found)
+    }
+
+    /// Calculate the current world pose of the frame `"<chain name>/<joint name>"`
+    pub fn frame_transform(&self, frame: &str) -> Result<Isometry3<T>, Error> {
+        Ok(current_world_transform(self.find_frame(frame)?))
+    }
+}
+
+/// Calculate the world transform from the current positions, without the cache
+pub(crate) fn current_world_transform<T>(node: &Node<T>) -> Isometry3<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    node.iter_ancestors().fold(Isometry3::identity(), |acc, n| {
+        n.joint().local_transform() * acc
+    })
+}
+
+/// Calculate the world transform of the frame of `arm`, in which `end_transform()` is,
+/// from the current positions
+pub(crate) fn current_arm_root_transform<T>(arm: &SerialChain<T>) -> Isometry3<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    match arm.iter().next().and_then(|node| node.parent()) {
+        Some(parent) => current_world_transform(&parent),
+        None => Isometry3::identity(),
+    }
+}
+
+/// Target pose of IK, which can follow a frame of a `World`
+///
+/// # Examples
+///
+/// ```
+/// use k::prelude::*;
+/// use k::{Isometry3, Target, World};
+///
+/// let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+/// let mut world = World::new();
+/// world.add_chain("robot", chain.clone());
+///
+/// // the pose 0.05 below the current left wrist, which moves with the left arm
+/// let target = Target::relative_to(
+///     &world,
+///     "robot/l_wrist_pitch",
+///     Isometry3::translation(0.0, 0.0, -0.05),
+/// ).unwrap();
+/// let arm = k::SerialChain::from_end(chain.find("r_wrist_pitch").unwrap());
+/// arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3]).unwrap();
+/// let before = target.resolve();
+/// world.chain("robot").unwrap().find("l_elbow_pitch").unwrap().set_joint_position(-0.5).unwrap();
+/// assert!((target.resolve().translation.vector - before.translation.vector).norm() > 0.01);
+///
+/// let fixed: Target<f64> = arm.end_transform().into();
+/// fixed.solve(&k::JacobianIkSolver::default(), &arm, &k::Constraints::default()).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub enum Target<T: RealField> {
+    /// fixed pose in the world frame
+    Pose(Isometry3<T>),
+    /// `offset` from the frame of `node`, resolved at every use
+    Frame { node: Node<T>, offset: Isometry3<T> },
+}
+
+impl<T> Target<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Create target following the frame `"<chain name>/<joint name>"` of `world`
+    pub fn relative_to(world: &World<T>, frame: &str, offset: Isometry3<T>) -> Result<Self, Error> {
+        Ok(Target::Frame {
+            node: world.find_frame(frame)?.clone(),
+            offset,
+        })
+    }
+
+    /// Calculate the current pose in the world frame
+    pub fn resolve(&self) -> Isometry3<T> {
+        match self {
+            Target::Pose(pose) => *pose,
+            Target::Frame { node, offset } => current_world_transform(node) * offset,
+        }
+    }
+
+    /// Resolve the target and solve IK of `arm` with `solver`
+    ///
+    /// The target is in the world frame, so the origin of `arm` should be
+    /// placed in the world.
+    pub fn solve<S>(
+        &self,
+        solver: &S,
+        arm: &SerialChain<T>,
+        constraints: &Constraints,
+    ) -> Result<(), Error>
+    where
+        S: InverseKinematicsSolver<T>,
+    {
+        solver.solve_with_constraints(arm, &self.resolve(), constraints)
+    }
+}
+
+impl<T: RealField> From<Isometry3<T>> for Target<T> {
+    fn from(pose: Isometry3<T>) -> Self {
+        Target::Pose(pose)
+    }
+}
@@ -0,0 +1,165 @@
+/*
+  Copyright 2020 Takashi Ogura
+
+  Licensed under the Apache License, Version 2.0 (the "License");
+  you may not use this file except in compliance with the License.
+  You may obtain a copy of the License at
+
+      http://www.apache.org/licenses/LICENSE-2.0
+
+  Unless required by applicable law or agreed to in writing, software
+  distributed under the License is distributed on an "AS IS" BASIS,
+  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+  See the License for the specific language governing permissions and
+  limitations under the License.
+*/
+use super::chain::*;
+use super::joint::*;
+use na::{Isometry3, Matrix3, RealField, Vector3};
+use nalgebra as na;
+use simba::scalar::SubsetOf;
+
+/// If the distance between the wrist center and the axes is smaller than this value,
+/// the axes are regarded as intersecting.
+const WRIST_AXES_DISTANCE_TOLERANCE: f64 = 1e-6;
+
+/// Spherical wrist of a `SerialChain`, created by `SerialChain::detect_wrist_partition()`
+#[derive(Debug, Clone)]
+pub struct WristPartition<T: RealField> {
+    /// names of the last three rotational joints, which form the wrist
+    pub joint_names: Vec<String>,
+    /// index of the first wrist joint in `iter_joints()`
+    pub first_joint_index: usize,
+    /// the intersection point of the wrist axes, in the frame of the root of the chain
+    pub center: Vector3<T>,
+    /// the wrist center seen from the end of the chain, including the end effector.
+    ///
+    /// It does not depend on the positions of the wrist joints, so the wrist center
+    /// of the target pose is `target * offset`.
+    pub offset: Vector3<T>,
+}
+
+impl<T> SerialChain<T>
+where
+    T: RealField + SubsetOf<f64>,
+{
+    /// Detect the spherical wrist, whose last three rotational axes intersect at a point
+    ///
+    /// It returns `None` if the last three movable joints are not rotational
+    /// or their axes do not intersect at a point.
+    /// The result is calculated with the current joint positions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let elbow = NodeBuilder::new()
+    ///     .name("elbow")
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// let wrist_yaw = NodeBuilder::new()
+    ///     .name("wrist_yaw")
+    ///     .translation(Translation3::new(0.0, 0.0, 0.3))
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// let wrist_pitch = NodeBuilder::new()
+    ///     .name("wrist_pitch")
+    ///     .translation(Translation3::new(0.0, 0.0, 0.1))
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// let wrist_roll = NodeBuilder::new()
+    ///     .name("wrist_roll")
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// let tool = NodeBuilder::new()
+    ///     .name("tool")
+    ///     .translation(Translation3::new(0.0, 0.0, 0.05))
+    ///     .into_node();
+    /// connect![elbow => wrist_yaw => wrist_pitch => wrist_roll => tool];
+    /// let arm = SerialChain::<f64>::from_end(&tool);
+    /// arm.set_joint_positions(&[0.3, 0.2, -0.5, 1.0]).unwrap();
+    ///
+    /// let wrist = arm.detect_wrist_partition().unwrap();
+    /// assert_eq!(wrist.joint_names, vec!["wrist_yaw", "wrist_pitch", "wrist_roll"]);
+    /// assert_eq!(wrist.first_joint_index, 1);
+    /// assert!((wrist.offset - Vector3::new(0.0, 0.0, -0.05)).norm() < 1e-6);
+    /// let center = arm.end_transform() * nalgebra::Point3::from(wrist.offset);
+    /// assert!((center.coords - wrist.center).norm() < 1e-6);
+    /// ```
+    ///
+    /// If the axes do not intersect, it returns `None`.
+    ///
+    /// ```
+    /// use k::*;
+    ///
+    /// let j0 = NodeBuilder::new()
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// let j1 = NodeBuilder::new()
+    ///     .translation(Translation3::new(0.0, 0.0, 0.3))
+    ///     .joint_type(JointType::Rotational{axis: Vector3::z_axis()})
+    ///     .into_node();
+    /// let j2 = NodeBuilder::new()
+    ///     .translation(Translation3::new(0.0, 0.0, 0.3))
+    ///     .joint_type(JointType::Rotational{axis: Vector3::y_axis()})
+    ///     .into_node();
+    /// connect![j0 => j1 => j2];
+    /// let arm = SerialChain::<f64>::from_end(&j2);
+    /// assert!(arm.detect_wrist_partition().is_none());
+    /// ```
+    pub fn detect_wrist_partition(&self) -> Option<WristPartition<T>> {
+        let dof = self.dof();
+        if dof < 3 {
+            return None;
+        }
+        // axes of the wrist joints in the frame of the root, (point, direction)
+        let mut axes = Vec::with_capacity(3);
+        let mut joint_names = Vec::with_capacity(3);
+        let mut transform = Isometry3::identity();
+        let mut joint_index = 0;
+        for node in self.iter() {
+            let joint = node.joint();
+            let origin = transform * joint.origin();
+            transform *= joint.local_transform();
+            if !joint.is_movable() {
+                continue;
+            }
+            if joint_index + joint.num_positions() > dof - 3 {
+                match joint.joint_type {
+                    JointType::Rotational { axis } => {
+                        axes.push((origin.translation.vector, origin.rotation * axis));
+                        joint_names.push(joint.name.clone());
+                    }
+                    _ => return None,
+                }
+            }
+            joint_index += joint.num_positions();
+        }
+        // least squares point of the three lines
+        let mut a = Matrix3::zeros();
+        let mut b = Vector3::zeros();
+        for (point, direction) in &axes {
+            let projection = Matrix3::identity() - direction.into_inner() * direction.transpose();
+            a += projection;
+            b += projection * point;
+        }
+        let center = a.try_inverse()? * b;
+        let tolerance = na::convert(WRIST_AXES_DISTANCE_TOLERANCE);
+        for (point, direction) in &axes {
+            let diff = center - point;
+            if (diff - direction.into_inner() * diff.dot(direction)).norm() > tolerance {
+                return None;
+            }
+        }
+        Some(WristPartition {
+            joint_names,
+            first_joint_index: dof - 3,
+            center,
+            offset: self
+                .end_transform()
+                .inverse_transform_point(&center.into())
+                .coords,
+        })
+    }
+}
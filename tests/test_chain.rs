@@ -43,4 +43,246 @@ mod tests {
                 .collect::<Vec<_>>()
         );
     }
+
+    fn universal_arm() -> k::SerialChain<f64> {
+        use k::nalgebra::{Translation3, Vector3};
+        let root = k::NodeBuilder::new().name("root").into_node();
+        let gimbal = k::NodeBuilder::new()
+            .name("gimbal")
+            .translation(Translation3::new(0.0, 0.0, 0.5))
+            .joint_type(k::JointType::Universal {
+                first_axis: Vector3::z_axis(),
+                second_axis: Vector3::y_axis(),
+            })
+            .limits(Some((-2.0..=2.0).into()))
+            .into_node();
+        let elbow = k::NodeBuilder::new()
+            .name("elbow")
+            .translation(Translation3::new(0.3, 0.0, 0.0))
+            .joint_type(k::JointType::Rotational {
+                axis: Vector3::y_axis(),
+            })
+            .into_node();
+        let tip = k::NodeBuilder::new()
+            .name("tip")
+            .translation(Translation3::new(0.3, 0.0, 0.0))
+            .into_node();
+        k::connect![root => gimbal => elbow => tip];
+        k::SerialChain::new_unchecked(k::Chain::from_root(root))
+    }
+
+    #[test]
+    pub fn test_universal_joint() {
+        use k::nalgebra::{Translation3, Vector3};
+        use k::InverseKinematicsSolver;
+        let arm = universal_arm();
+        assert_eq!(arm.dof(), 3);
+        assert_eq!(arm.summary().num_universal, 1);
+        assert!(arm.set_joint_positions(&[0.3, 2.5, 0.1]).is_err());
+        arm.set_joint_positions(&[0.3, -0.4, 0.5]).unwrap();
+        let gimbal = arm.find("gimbal").unwrap();
+        assert_eq!(gimbal.joint().joint_positions(), &[0.3, -0.4]);
+        assert!(gimbal.joint().joint_position().is_none());
+
+        // the same as two stacked rotational joints
+        let yaw = k::NodeBuilder::new()
+            .translation(Translation3::new(0.0, 0.0, 0.5))
+            .joint_type(k::JointType::Rotational {
+                axis: Vector3::z_axis(),
+            })
+            .into_node();
+        let pitch = k::NodeBuilder::new()
+            .joint_type(k::JointType::Rotational {
+                axis: Vector3::y_axis(),
+            })
+            .into_node();
+        let elbow = k::NodeBuilder::new()
+            .translation(Translation3::new(0.3, 0.0, 0.0))
+            .joint_type(k::JointType::Rotational {
+                axis: Vector3::y_axis(),
+            })
+            .into_node();
+        let tip = k::NodeBuilder::new()
+            .translation(Translation3::new(0.3, 0.0, 0.0))
+            .into_node();
+        k::connect![yaw => pitch => elbow => tip];
+        let stacked = k::SerialChain::new_unchecked(k::Chain::from_root(yaw));
+        stacked.set_joint_positions(&[0.3, -0.4, 0.5]).unwrap();
+        let pose = arm.end_transform();
+        let stacked_pose = stacked.end_transform();
+        assert!((pose.to_homogeneous() - stacked_pose.to_homogeneous()).norm() < 1e-10);
+        let jacobian = k::jacobian(&arm);
+        assert!((jacobian - k::jacobian(&stacked)).norm() < 1e-10);
+
+        let target = pose;
+        arm.set_joint_positions(&[0.0, 0.0, 0.2]).unwrap();
+        let constraints = k::Constraints {
+            rotation_x: false,
+            rotation_y: false,
+            rotation_z: false,
+            ..Default::default()
+        };
+        let solver = k::JacobianIkSolver::default();
+        solver
+            .solve_with_constraints(&arm, &target, &constraints)
+            .unwrap();
+        let solved = arm.end_transform();
+        assert!((solved.translation.vector - target.translation.vector).norm() < 1e-3);
+
+        let robot: urdf_rs::Robot = (&*arm).into();
+        assert_eq!(robot.joints.len(), 4);
+        let loaded: k::Chain<f64> = robot.into();
+        assert_eq!(loaded.dof(), 3);
+    }
+
+    #[test]
+    pub fn test_universal_joint_rerooted() {
+        let arm = universal_arm();
+        arm.set_joint_positions(&[0.3, -0.4, 0.5]).unwrap();
+        arm.update_transforms();
+        let root_pose = arm.find("root").unwrap().world_transform().unwrap();
+        let rerooted = arm.rerooted(arm.find("tip").unwrap()).unwrap();
+        assert_eq!(rerooted.dof(), 3);
+        let gimbal = rerooted.find("gimbal").unwrap();
+        assert_eq!(gimbal.joint().joint_positions(), &[-0.4, 0.3]);
+        rerooted.update_transforms();
+        let old_root = rerooted
+            .find("root_link")
+            .unwrap()
+            .world_transform()
+            .unwrap();
+        assert!((old_root.to_homogeneous() - root_pose.to_homogeneous()).norm() < 1e-10);
+    }
+
+    #[test]
+    pub fn test_planar_joint() {
+        use k::nalgebra::{Isometry3, Translation3, Vector3};
+        use k::InverseKinematicsSolver;
+        let world = k::NodeBuilder::new().name("world").into_node();
+        let base = k::NodeBuilder::new()
+            .name("base")
+            .joint_type(k::JointType::Planar {
+                normal: Vector3::z_axis(),
+            })
+            .into_node();
+        let shoulder = k::NodeBuilder::new()
+            .name("shoulder")
+            .translation(Translation3::new(0.0, 0.0, 0.5))
+            .joint_type(k::JointType::Rotational {
+                axis: Vector3::z_axis(),
+            })
+            .into_node();
+        let hand = k::NodeBuilder::new()
+            .name("hand")
+            .translation(Translation3::new(0.5, 0.0, 0.0))
+            .into_node();
+        k::connect![world => base => shoulder => hand];
+        let arm = k::SerialChain::from_end(&hand);
+        assert_eq!(arm.dof(), 4);
+        assert_eq!(arm.summary().num_planar, 1);
+
+        arm.set_joint_positions(&[1.0, 2.0, std::f64::consts::FRAC_PI_2, 0.0])
+            .unwrap();
+        let pose = arm.end_transform();
+        assert!((pose.translation.vector - Vector3::new(1.0, 2.5, 0.5)).norm() < 1e-10);
+
+        // far beyond the reach of the arm, so the base must move
+        arm.set_joint_positions(&[0.0; 4]).unwrap();
+        let target = Isometry3::translation(2.0, 1.0, 0.5);
+        let constraints = k::Constraints {
+            rotation_x: false,
+            rotation_y: false,
+            rotation_z: false,
+            ..Default::default()
+        };
+        k::JacobianIkSolver::new(0.001, 0.01, 0.5, 100)
+            .solve_with_constraints(&arm, &target, &constraints)
+            .unwrap();
+        let positions = base.joint().joint_positions().to_vec();
+        assert!((positions[0].powi(2) + positions[1].powi(2)).sqrt() > 1.5);
+        assert!(arm.rerooted(&hand).is_err());
+
+        let robot: urdf_rs::Robot = (&*arm).into();
+        assert_eq!(robot.joints[0].joint_type, urdf_rs::JointType::Planar);
+        let loaded: k::Chain<f64> = robot.into();
+        assert_eq!(loaded.dof(), 4);
+    }
+
+    fn floating_arm(rotation: k::angles::RotationParameterization) -> k::SerialChain<f64> {
+        use k::nalgebra::{Translation3, Vector3};
+        let world = k::NodeBuilder::new().name("world").into_node();
+        let base = k::NodeBuilder::new()
+            .name("base")
+            .joint_type(k::JointType::Floating { rotation })
+            .into_node();
+        let arm_joint = k::NodeBuilder::new()
+            .name("arm")
+            .translation(Translation3::new(0.2, 0.0, 0.0))
+            .joint_type(k::JointType::Rotational {
+                axis: Vector3::y_axis(),
+            })
+            .into_node();
+        let hand = k::NodeBuilder::new()
+            .name("hand")
+            .translation(Translation3::new(0.5, 0.0, 0.0))
+            .into_node();
+        k::connect![world => base => arm_joint => hand];
+        k::SerialChain::from_end(&hand)
+    }
+
+    #[test]
+    pub fn test_floating_joint() {
+        use k::angles::RotationParameterization;
+        use k::nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+        use k::InverseKinematicsSolver;
+        for rotation in [
+            RotationParameterization::Quaternion,
+            RotationParameterization::ExponentialCoordinates,
+            RotationParameterization::Rpy,
+        ]
+        .iter()
+        {
+            let arm = floating_arm(*rotation);
+            assert_eq!(arm.dof(), 4 + rotation.num_parameters());
+            assert_eq!(arm.summary().num_floating, 1);
+            let base = arm.find("base").unwrap();
+            assert_eq!(base.floating_pose().unwrap(), Isometry3::identity());
+
+            let base_pose =
+                Isometry3::new(Vector3::new(0.1, -0.2, 0.3), Vector3::new(0.4, -0.5, 0.6));
+            base.set_floating_pose(&base_pose).unwrap();
+            arm.find("arm").unwrap().set_joint_position(0.7).unwrap();
+            let positions = arm.joint_positions();
+            let pose = arm.end_transform();
+            let expected = base_pose
+                * Translation3::new(0.2, 0.0, 0.0)
+                * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.7)
+                * Translation3::new(0.5, 0.0, 0.0);
+            assert!((pose.to_homogeneous() - expected.to_homogeneous()).norm() < 1e-10);
+
+            // compare with the finite difference
+            let jacobi = k::jacobian(&arm);
+            let eps = 1e-6;
+            for i in 0..positions.len() {
+                let mut moved = positions.clone();
+                moved[i] += eps;
+                arm.set_joint_positions(&moved).unwrap();
+                let moved_pose = arm.end_transform();
+                let linear = (moved_pose.translation.vector - pose.translation.vector) / eps;
+                let angular = (moved_pose.rotation * pose.rotation.inverse()).scaled_axis() / eps;
+                assert!((linear - jacobi.fixed_slice::<3, 1>(0, i)).norm() < 1e-4);
+                assert!((angular - jacobi.fixed_slice::<3, 1>(3, i)).norm() < 1e-4);
+            }
+
+            arm.set_joint_positions(&positions).unwrap();
+            let target = Isometry3::new(Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.5, 1.5, -0.5));
+            k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100)
+                .solve(&arm, &target)
+                .unwrap();
+            let solved = arm.end_transform();
+            assert!((solved.translation.vector - target.translation.vector).norm() < 1e-3);
+            assert!(solved.rotation.angle_to(&target.rotation) < 1e-3);
+            assert!(arm.rerooted(arm.find("hand").unwrap()).is_err());
+        }
+    }
 }
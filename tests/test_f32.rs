@@ -0,0 +1,75 @@
+use k::prelude::*;
+use nalgebra as na;
+
+/// Solve the targets generated from deterministic positions, and count the successes
+fn count_solved<T>(solver: &k::JacobianIkSolver<T>) -> usize
+where
+    T: k::RealField + k::SubsetOf<f64>,
+{
+    let chain = k::Chain::<T>::from_urdf_file("urdf/sample.urdf").unwrap();
+    let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    let mut solved = 0;
+    for i in 0..50 {
+        let positions = |offset: f64| {
+            (0..6)
+                .map(|j| na::convert(((i as f64 * 0.37 + j as f64 * 1.3).sin()) * 0.8 + offset))
+                .collect::<Vec<T>>()
+        };
+        arm.set_joint_positions(&positions(0.0)).unwrap();
+        let target = arm.end_transform();
+        arm.set_joint_positions_clamped(&positions(0.15));
+        if solver.solve(&arm, &target).is_ok() {
+            solved += 1;
+        }
+    }
+    solved
+}
+
+#[test]
+fn ik_f32_default() {
+    let solved_f32 = count_solved(&k::JacobianIkSolver::<f32>::default());
+    let solved_f64 = count_solved(&k::JacobianIkSolver::<f64>::default());
+    assert!(solved_f32 >= solved_f64);
+}
+
+#[test]
+fn ik_f32_too_small_tolerance() {
+    // smaller than the precision of f32, it is raised to `Tolerances::convergence_floor()`
+    let solver_f32 = k::JacobianIkSolver::<f32>::new(1e-7, 1e-7, 0.5, 30);
+    let solver_f64 = k::JacobianIkSolver::<f64>::new(1e-7, 1e-7, 0.5, 30);
+    assert!(count_solved(&solver_f32) >= count_solved(&solver_f64));
+}
+
+#[test]
+fn servo_f32() {
+    let chain = k::ChainF32::from_urdf_file("urdf/sample.urdf").unwrap();
+    let arm = k::SerialChainF32::from_end(chain.find("l_wrist_pitch").unwrap());
+    arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3])
+        .unwrap();
+    let mut target = arm.end_transform();
+    target.translation.vector.z += 0.05;
+    let mut servo = k::CartesianServo::default();
+    for _ in 0..500 {
+        servo.update(&arm, &target, 0.01).unwrap();
+    }
+    let end = arm.end_transform();
+    assert!((end.translation.vector - target.translation.vector).norm() < 0.001);
+}
+
+#[test]
+fn axis_alignment_f32() {
+    let chain = k::ChainF32::from_urdf_file("urdf/sample.urdf").unwrap();
+    let arm = k::SerialChainF32::from_end(chain.find("r_wrist_pitch").unwrap());
+    arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3])
+        .unwrap();
+    let end = arm.end_transform();
+    let position = end.translation.vector + na::Vector3::new(-0.05, 0.0, 0.02);
+    let direction =
+        na::UnitQuaternion::from_euler_angles(0.2, 0.0, 0.0) * end.rotation * na::Vector3::z();
+    let solver = k::JacobianIkSolver::default();
+    solver
+        .solve_with_axis_alignment(&arm, &position, &na::Unit::new_normalize(direction))
+        .unwrap();
+    let end = arm.end_transform();
+    assert!((end.translation.vector - position).norm() < 0.001);
+}
@@ -6,6 +6,7 @@ mod tests {
     use super::*;
     use k::prelude::*;
     use na::{Translation3, Vector3};
+    use std::f64::consts::PI;
     pub fn create_joint_with_link_array6() -> k::SerialChain<f64> {
         let l0: k::Node<f64> = k::NodeBuilder::new()
             .name("shoulder_pitch")
@@ -126,7 +127,7 @@ mod tests {
         let poses = arm.update_transforms();
         let init_pose = poses.last().unwrap();
         let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
-        solver.solve(&arm, &init_pose).unwrap();
+        solver.solve(&arm, init_pose).unwrap();
         let end_angles = arm.joint_positions();
         for (init, end) in angles.iter().zip(end_angles.iter()) {
             assert!((init - end).abs() < 0.001);
@@ -144,11 +145,388 @@ mod tests {
         // set different angles
         arm.set_joint_positions(&[0.4, 0.1, 0.1, -1.0, 0.1, 0.1])
             .unwrap();
-        solver.solve(&arm, &init_pose).unwrap();
+        solver.solve(&arm, init_pose).unwrap();
         let end_angles = arm.joint_positions();
         println!("{:?}", end_angles);
         for (init, end) in angles.iter().zip(end_angles.iter()) {
             assert!((init - end).abs() < 0.002);
         }
     }
+
+    #[test]
+    pub fn fabrik_long_chain() {
+        let mut nodes: Vec<k::Node<f64>> = Vec::new();
+        for i in 0..10 {
+            let node = k::NodeBuilder::new()
+                .translation(Translation3::new(if i == 0 { 0.0 } else { 0.1 }, 0.0, 0.0))
+                .joint_type(k::JointType::Rotational {
+                    axis: Vector3::z_axis(),
+                })
+                .into_node();
+            if let Some(parent) = nodes.last() {
+                node.set_parent(parent);
+            }
+            nodes.push(node);
+        }
+        let end = k::NodeBuilder::new()
+            .translation(Translation3::new(0.1, 0.0, 0.0))
+            .into_node();
+        end.set_parent(nodes.last().unwrap());
+        let arm = k::SerialChain::from_end(&end);
+        arm.set_joint_positions(&[0.1; 10]).unwrap();
+        let target = na::Isometry3::translation(0.3, 0.5, 0.0);
+        let solver = k::FabrikIkSolver::new(0.0001, 20);
+        solver.solve(&arm, &target).unwrap();
+        let diff = arm.end_transform().translation.vector - target.translation.vector;
+        assert!(diff.norm() < 0.0001);
+    }
+
+    #[test]
+    pub fn fabrik_end_effector() {
+        let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+        let mut arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+        arm.set_end_effector("tcp", na::Isometry3::translation(0.0, 0.0, -0.15));
+        arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3])
+            .unwrap();
+        let mut target = arm.end_transform();
+        target.translation.vector.x += 0.05;
+        let solver = k::FabrikIkSolver::<f64>::default();
+        solver.solve(&arm, &target).unwrap();
+        let diff = arm.end_transform().translation.vector - target.translation.vector;
+        assert!(diff.norm() < 0.001);
+    }
+
+    #[test]
+    pub fn jacobian_transpose_default_converges() {
+        let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+        let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+        let solver = k::JacobianTransposeSolver::default();
+        for start in &[
+            [0.1, 0.2, 0.0, -0.5, 0.0, -0.3],
+            [0.5, -0.3, 0.4, -1.0, 0.3, 0.2],
+        ] {
+            for diff in &[
+                Vector3::new(0.05, 0.0, 0.0),
+                Vector3::new(0.0, 0.0, 0.05),
+                Vector3::new(0.03, -0.03, 0.03),
+            ] {
+                arm.set_joint_positions(start).unwrap();
+                let mut target = arm.end_transform();
+                target.translation.vector += diff;
+                solver.solve(&arm, &target).unwrap();
+                let end = arm.end_transform();
+                assert!((end.translation.vector - target.translation.vector).norm() < 0.005);
+                assert!(end.rotation.angle_to(&target.rotation) < 0.05);
+            }
+        }
+    }
+
+    #[test]
+    pub fn damped_near_singularity() {
+        let arm = create_joint_with_link_array6();
+        // the elbow is almost stretched
+        let angles = vec![0.0, 0.0, 0.0, -0.001, 0.0, 0.0];
+        arm.set_joint_positions(&angles).unwrap();
+        let mut target = arm.end_transform();
+        target.translation.vector.z += 0.1;
+        let constraints = k::Constraints {
+            rotation_x: false,
+            rotation_y: false,
+            rotation_z: false,
+            ..Default::default()
+        };
+        let mut solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+        // the pseudo inverse jumps to the far away solution
+        solver
+            .solve_with_constraints(&arm, &target, &constraints)
+            .unwrap();
+        assert!(arm.joint_positions().iter().any(|q| q.abs() > 10.0));
+
+        arm.set_joint_positions(&angles).unwrap();
+        solver.set_damping(k::IkDamping::Adaptive {
+            max: 0.1,
+            threshold: 0.1,
+        });
+        solver
+            .solve_with_constraints(&arm, &target, &constraints)
+            .unwrap();
+        let diff = arm.end_transform().translation.vector - target.translation.vector;
+        assert!(diff.norm() < 0.001);
+        assert!(arm.joint_positions().iter().all(|q| q.abs() < 3.0));
+    }
+
+    #[test]
+    pub fn point_on_plane() {
+        let arm = create_joint_with_link_array6();
+        arm.set_joint_positions(&[0.8, 0.2, 0.0, -1.2, 0.0, 0.1])
+            .unwrap();
+        let end = arm.end_transform().translation.vector;
+        let point = end + Vector3::new(0.03, 0.0, 0.05);
+        let normal = na::Unit::new_normalize(Vector3::new(1.0, 0.0, 1.0));
+        let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+        solver
+            .solve_geometric(&arm, &k::GeometricTarget::PointOnPlane { point, normal })
+            .unwrap();
+        let end = arm.end_transform().translation.vector;
+        assert!(normal.dot(&(end - point)).abs() < 0.001);
+    }
+
+    #[test]
+    pub fn random_restart_keeps_best() {
+        let arm = create_joint_with_link_array6();
+        arm.set_joint_positions(&[0.8, 0.2, 0.0, -1.2, 0.0, 0.1])
+            .unwrap();
+        // out of reach
+        let target = na::Isometry3::from_parts(
+            Translation3::new(10.0, 0.0, 0.0),
+            na::UnitQuaternion::identity(),
+        );
+        let mut solver = k::RandomRestartIkSolver::new(
+            k::JacobianIkSolver::default(),
+            std::time::Duration::from_secs(10),
+        );
+        solver.max_attempts = Some(5);
+        let result = solver.solve(&arm, &target);
+        let best = match result {
+            Err(k::Error::NotConvergedError {
+                num_tried,
+                position_diff,
+                ..
+            }) => {
+                assert_eq!(num_tried, 10);
+                position_diff.norm()
+            }
+            _ => panic!("unexpected result {:?}", result),
+        };
+        let diff = arm.end_transform().translation.vector - target.translation.vector;
+        assert!((diff.norm() - best).abs() < 1e-6);
+    }
+
+    #[test]
+    pub fn limit_avoidance() {
+        let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+        let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+        // l_elbow_pitch is near the upper limit 3.0
+        let start = [0.1, 0.2, 0.0, 2.8, 0.0, -0.3];
+        arm.set_joint_positions(&start).unwrap();
+        let mut target = arm.end_transform();
+        target.translation.vector.z += 0.02;
+        let constraints = k::Constraints {
+            rotation_x: false,
+            rotation_y: false,
+            rotation_z: false,
+            ..Default::default()
+        };
+        let mut solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+        solver
+            .solve_with_constraints(&arm, &target, &constraints)
+            .unwrap();
+        let without = arm.joint_positions()[3];
+
+        arm.set_joint_positions(&start).unwrap();
+        solver.set_nullspace_objective(Some(k::NullspaceObjective::limit_avoidance(&arm, 0.5)));
+        solver
+            .solve_with_constraints(&arm, &target, &constraints)
+            .unwrap();
+        let diff = arm.end_transform().translation.vector - target.translation.vector;
+        assert!(diff.norm() < 0.001);
+        assert!(arm.joint_positions()[3] < without);
+    }
+
+    #[test]
+    pub fn solve_from_restores_on_failure() {
+        let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+        let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+        let start = [0.1, 0.2, 0.0, -0.5, 0.0, -0.3];
+        arm.set_joint_positions(&start).unwrap();
+        let mut target = arm.end_transform();
+        target.translation.vector.z += 10.0;
+        let solver = k::JacobianIkSolver::default();
+        let seed = [0.0, 0.1, 0.0, -0.3, 0.0, -0.1];
+        assert!(solver.solve_from(&arm, &seed, &target).is_err());
+        assert_eq!(arm.joint_positions(), start);
+        // invalid seed
+        assert!(solver.solve_from(&arm, &[0.0], &target).is_err());
+        assert_eq!(arm.joint_positions(), start);
+    }
+
+    #[test]
+    pub fn solve_with_frame_of_other_branch() {
+        let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+        chain.set_origin(k::Isometry3::translation(1.0, 0.0, 0.5));
+        let wrist = chain.find("l_wrist_pitch").unwrap();
+        let arm = k::SerialChain::from_end(wrist);
+        arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3])
+            .unwrap();
+        chain.update_transforms();
+        let reference = chain.find("r_elbow_pitch").unwrap();
+        let mut goal = wrist.world_transform().unwrap();
+        goal.translation.vector.z += 0.02;
+        let target = reference.world_transform().unwrap().inverse() * goal;
+        let solver = k::JacobianIkSolver::default();
+        solver
+            .solve_with_frame(&arm, &target, reference, &k::Constraints::default())
+            .unwrap();
+        chain.update_transforms();
+        let diff = wrist.world_transform().unwrap().translation.vector - goal.translation.vector;
+        assert!(diff.norm() < 0.001);
+    }
+
+    #[test]
+    pub fn solve_respects_coupled_limits() {
+        let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+        let mut arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+        let names = arm
+            .iter_joints()
+            .take(2)
+            .map(|joint| joint.name.clone())
+            .collect::<Vec<_>>();
+        let limit = k::CoupledLimit::ellipsoid(names, vec![0.0, 0.0], vec![0.3, 0.3]).unwrap();
+        arm.add_coupled_limit(limit.clone()).unwrap();
+        arm.set_joint_positions(&[0.1, 0.1, 0.0, -0.5, 0.0, -0.3])
+            .unwrap();
+        let mut target = arm.end_transform();
+        target.translation.vector.y += 0.1;
+        target.translation.vector.z += 0.1;
+        let solver = k::JacobianIkSolver::default();
+        let _ = solver.solve_with_constraints(
+            &arm,
+            &target,
+            &k::Constraints {
+                rotation_x: false,
+                rotation_y: false,
+                rotation_z: false,
+                ..Default::default()
+            },
+        );
+        let positions = arm.joint_positions();
+        assert!(limit.violation(&positions[..2]) < 1e-6);
+
+        // the multi-chain and the prioritized solvers set the positions through the chain
+        let solver = k::JacobianIkSolver::new(0.001, 0.01, 0.5, 100);
+        let task_constraints = k::Constraints {
+            rotation_x: false,
+            rotation_y: false,
+            rotation_z: false,
+            ..Default::default()
+        };
+        let task = k::MultiChainTask {
+            arm: &arm,
+            target,
+            constraints: task_constraints.clone(),
+        };
+        arm.set_joint_positions(&[0.1, 0.1, 0.0, -0.5, 0.0, -0.3])
+            .unwrap();
+        let _ = solver.solve_multiple(std::slice::from_ref(&task));
+        let positions = arm.joint_positions();
+        assert!(limit.violation(&positions[..2]) < 1e-6);
+
+        arm.set_joint_positions(&[0.1, 0.1, 0.0, -0.5, 0.0, -0.3])
+            .unwrap();
+        let _ = solver.solve_prioritized(&[vec![k::PriorityTask::Pose(task)]]);
+        let positions = arm.joint_positions();
+        assert!(limit.violation(&positions[..2]) < 1e-6);
+
+        arm.set_joint_positions(&[0.1, 0.1, 0.0, -0.5, 0.0, -0.3])
+            .unwrap();
+        let transpose = k::JacobianTransposeSolver::default();
+        let _ = transpose.solve_with_constraints(&arm, &target, &task_constraints);
+        let positions = arm.joint_positions();
+        assert!(limit.violation(&positions[..2]) < 1e-6);
+    }
+
+    #[test]
+    pub fn solve_prioritized_keeps_higher_level() {
+        let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+        let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+        let start = [0.1, 0.2, 0.0, -0.5, 0.0, -0.3];
+        arm.set_joint_positions(&start).unwrap();
+        let mut target = arm.end_transform();
+        target.translation.vector.z += 0.02;
+        let position_only = k::Constraints {
+            rotation_x: false,
+            rotation_y: false,
+            rotation_z: false,
+            ..Default::default()
+        };
+        let task = k::MultiChainTask {
+            arm: &arm,
+            target,
+            constraints: position_only.clone(),
+        };
+        // the posture conflicts with the target, but it has the lower priority
+        let levels = vec![
+            vec![k::PriorityTask::Pose(task.clone())],
+            vec![k::PriorityTask::Posture {
+                chain: &arm,
+                positions: vec![1.0; 6],
+            }],
+        ];
+        let solver = k::JacobianIkSolver::new(0.001, 0.01, 0.5, 100);
+        solver.solve_prioritized(&levels).unwrap();
+        let diff = arm.end_transform().translation.vector - target.translation.vector;
+        assert!(diff.norm() < 0.001);
+
+        // the lower pose can not be reached without moving the higher one
+        arm.set_joint_positions(&start).unwrap();
+        let mut far = target;
+        far.translation.vector.z += 0.1;
+        let levels = vec![
+            vec![k::PriorityTask::Pose(task)],
+            vec![k::PriorityTask::Pose(k::MultiChainTask {
+                arm: &arm,
+                target: far,
+                constraints: position_only,
+            })],
+        ];
+        assert!(solver.solve_prioritized(&levels).is_err());
+        assert_eq!(arm.joint_positions(), start);
+    }
+
+    #[test]
+    pub fn solve_continuous_joint_takes_shortest_way() {
+        let l0: k::Node<f64> = k::NodeBuilder::new()
+            .name("shoulder_pitch")
+            .joint_type(k::JointType::Rotational {
+                axis: Vector3::y_axis(),
+            })
+            .into_node();
+        let l1: k::Node<f64> = k::NodeBuilder::new()
+            .name("elbow_pitch")
+            .joint_type(k::JointType::Rotational {
+                axis: Vector3::y_axis(),
+            })
+            .translation(Translation3::new(0.0, 0.0, -0.3))
+            .into_node();
+        let l2: k::Node<f64> = k::NodeBuilder::new()
+            .name("wrist_roll")
+            .joint_type(k::JointType::Rotational {
+                axis: Vector3::z_axis(),
+            })
+            .translation(Translation3::new(0.0, 0.0, -0.3))
+            .limits(Some(k::joint::Range::new(-PI, PI)))
+            .continuous(true)
+            .into_node();
+        connect![l0 => l1 => l2];
+        let arm = k::SerialChain::from_end(&l2);
+        arm.set_joint_positions(&[0.2, -0.5, -2.9]).unwrap();
+        let target = arm.end_transform();
+        // the wrist reaches the target by turning 0.38 rad over π
+        arm.set_joint_positions(&[0.2, -0.5, 3.0]).unwrap();
+        let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+        solver
+            .solve_with_constraints(
+                &arm,
+                &target,
+                &k::Constraints {
+                    position_y: false,
+                    rotation_x: false,
+                    rotation_y: false,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let wrist = l2.joint_position().unwrap();
+        assert!((wrist - (2.0 * PI - 2.9)).abs() < 0.01);
+    }
 }
@@ -0,0 +1,212 @@
+use k::prelude::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Allocator counting the allocations of the current thread while it is enabled
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = Cell::new(false);
+    static NUM_ALLOCATIONS: Cell<usize> = Cell::new(0);
+}
+
+fn count_allocation() {
+    if COUNTING.with(|counting| counting.get()) {
+        NUM_ALLOCATIONS.with(|num| num.set(num.get() + 1));
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The number of the allocations in `f` on this thread
+fn count_allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    NUM_ALLOCATIONS.with(|num| num.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    let re = f();
+    COUNTING.with(|counting| counting.set(false));
+    (re, NUM_ALLOCATIONS.with(|num| num.get()))
+}
+
+fn sample_arm() -> (k::Chain<f64>, k::SerialChain<f64>) {
+    let chain = k::Chain::<f64>::from_urdf_file("urdf/sample.urdf").unwrap();
+    let arm = k::SerialChain::from_end(chain.find("l_wrist_pitch").unwrap());
+    arm.set_joint_positions(&[0.1, 0.2, 0.0, -0.5, 0.0, -0.3])
+        .unwrap();
+    (chain, arm)
+}
+
+#[test]
+fn solve_without_allocations() {
+    let (_chain, arm) = sample_arm();
+    let start = arm.end_transform();
+    let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+    let mut target = start;
+    target.translation.vector.z += 0.01;
+    // the normal solve allocates
+    let (re, num_allocations) = count_allocations(|| solver.solve(&arm, &target));
+    re.unwrap();
+    assert!(num_allocations > 0);
+
+    solver.preallocate(&arm);
+    for i in 0..10 {
+        let mut target = start;
+        target.translation.vector.x += 0.005 * i as f64;
+        target.translation.vector.z += 0.01 * i as f64;
+        let (re, num_allocations) = count_allocations(|| solver.solve(&arm, &target));
+        re.unwrap();
+        assert_eq!(num_allocations, 0);
+        let end = arm.end_transform();
+        assert!((end.translation.vector - target.translation.vector).norm() < 0.001);
+    }
+}
+
+#[test]
+fn solve_with_constraints_without_allocations() {
+    let (_chain, arm) = sample_arm();
+    let mut target = arm.end_transform();
+    target.translation.vector.z += 0.02;
+    let constraints = k::Constraints {
+        rotation_z: false,
+        frame: k::ConstraintFrame::End,
+        ..Default::default()
+    };
+    let mut solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+    solver.set_damping(k::IkDamping::Constant(0.01));
+    solver.set_joint_weights(Some(vec![10.0, 1.0, 1.0, 1.0, 1.0, 1.0]));
+    solver.preallocate(&arm);
+    let (re, num_allocations) =
+        count_allocations(|| solver.solve_with_constraints(&arm, &target, &constraints));
+    re.unwrap();
+    assert_eq!(num_allocations, 0);
+    let end = arm.end_transform();
+    assert!((end.translation.vector - target.translation.vector).norm() < 0.001);
+}
+
+#[test]
+fn solve_failure_restores_positions() {
+    let (_chain, arm) = sample_arm();
+    let positions = arm.joint_positions();
+    let mut target = arm.end_transform();
+    target.translation.vector.z += 10.0;
+    let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 10);
+    solver.preallocate(&arm);
+    let (re, num_allocations) = count_allocations(|| solver.solve(&arm, &target));
+    assert!(matches!(re, Err(k::Error::NotConvergedError { .. })));
+    assert_eq!(num_allocations, 0);
+    assert_eq!(arm.joint_positions(), positions);
+}
+
+#[test]
+fn solve_at_singular_pose_like_normal_solve() {
+    // planar arm: the jacobian has no z rows, so J Jᵀ is singular
+    let nodes = (0..4)
+        .map(|i| {
+            k::NodeBuilder::new()
+                .joint_type(k::JointType::Rotational {
+                    axis: k::Vector3::z_axis(),
+                })
+                .translation(k::Translation3::new(
+                    if i == 0 { 0.0 } else { 0.3 },
+                    0.0,
+                    0.0,
+                ))
+                .into_node()
+        })
+        .collect::<Vec<k::Node<f64>>>();
+    for pair in nodes.windows(2) {
+        pair[1].set_parent(&pair[0]);
+    }
+    let arm = k::SerialChain::from_end(&nodes[3]);
+    let start = [0.1, 0.2, 0.3, 0.4];
+    arm.set_joint_positions(&start).unwrap();
+    let mut target = arm.end_transform();
+    target.translation.vector.x -= 0.05;
+    target.translation.vector.y += 0.05;
+    let constraints = k::Constraints {
+        rotation_x: false,
+        rotation_y: false,
+        rotation_z: false,
+        ..Default::default()
+    };
+    let solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+    solver
+        .solve_with_constraints(&arm, &target, &constraints)
+        .unwrap();
+    let solved = arm.joint_positions();
+
+    arm.set_joint_positions(&start).unwrap();
+    solver.preallocate(&arm);
+    let (re, num_allocations) =
+        count_allocations(|| solver.solve_with_constraints(&arm, &target, &constraints));
+    re.unwrap();
+    assert_eq!(num_allocations, 0);
+    for (position, expected) in arm.joint_positions().iter().zip(&solved) {
+        assert!((position - expected).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn solve_with_profiler_uses_normal_solve() {
+    let (_chain, arm) = sample_arm();
+    let mut target = arm.end_transform();
+    target.translation.vector.z += 0.02;
+    let mut solver = k::JacobianIkSolver::new(0.001, 0.001, 0.5, 100);
+    let profiler = k::Profiler::new();
+    solver.set_profiler(Some(profiler.clone()));
+    solver.preallocate(&arm);
+    solver.solve(&arm, &target).unwrap();
+    assert!(profiler.entry("ik/jacobian").is_some());
+}
+
+#[test]
+fn transpose_solve_without_allocations() {
+    let (_chain, arm) = sample_arm();
+    let mut target = arm.end_transform();
+    target.translation.vector.x += 0.02;
+    target.translation.vector.z += 0.02;
+    let constraints = k::Constraints {
+        rotation_x: false,
+        rotation_y: false,
+        rotation_z: false,
+        ..Default::default()
+    };
+    let solver = k::JacobianTransposeSolver::new(0.003, 0.03, 1.0, 1000);
+    solver.preallocate(&arm);
+    let (re, num_allocations) =
+        count_allocations(|| solver.solve_with_constraints(&arm, &target, &constraints));
+    re.unwrap();
+    assert_eq!(num_allocations, 0);
+    let end = arm.end_transform();
+    assert!((end.translation.vector - target.translation.vector).norm() < 0.003);
+
+    // failure restores the positions without allocations
+    let positions = arm.joint_positions();
+    target.translation.vector.z += 10.0;
+    let (re, num_allocations) =
+        count_allocations(|| solver.solve_with_constraints(&arm, &target, &constraints));
+    assert!(re.is_err());
+    assert_eq!(num_allocations, 0);
+    assert_eq!(arm.joint_positions(), positions);
+}